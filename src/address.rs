@@ -0,0 +1,364 @@
+use std::fmt::{self, Write};
+
+use crate::decode::{parts, Cursor};
+use crate::decode_error::DecodeError;
+use crate::format::{self, FormatOptions};
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+use crate::Result;
+
+/// The fixed high bits (0000) of an MSP430X address instruction word
+pub(crate) const ADDRESS_PREFIX: u16 = 0;
+pub(crate) const ADDRESS_MASK: u16 = 0b1111_0000_0000_0000;
+
+const SOURCE_MASK: u16 = 0b1111_0000_0000;
+const FORM_MASK: u16 = 0b1111_0000;
+const DESTINATION_MASK: u16 = 0b1111;
+
+// mova only forms
+const INDIRECT: u16 = 0;
+const AUTOINCREMENT: u16 = 1;
+const ABSOLUTE_SOURCE: u16 = 2;
+const INDEXED_SOURCE: u16 = 3;
+const ABSOLUTE_DESTINATION: u16 = 6;
+const INDEXED_DESTINATION: u16 = 7;
+
+// forms shared by every address instruction. The low two bits select the
+// instruction
+const IMMEDIATE: u16 = 0b1000;
+const REGISTER: u16 = 0b1100;
+
+const MOVA_OPCODE: u16 = 0;
+const CMPA_OPCODE: u16 = 1;
+const ADDA_OPCODE: u16 = 2;
+const SUBA_OPCODE: u16 = 3;
+
+/// All address instructions implement this trait to provide a common
+/// interface and polymorphism. Address instructions always operate on 20
+/// bit values
+pub trait AddressInstruction {
+    /// Return the mnemonic for the instruction
+    fn mnemonic(&self) -> &str;
+    /// Returns the source operand. Immediate and absolute operands hold the
+    /// low 16 bits of their value
+    fn source(&self) -> &Operand;
+    /// Returns the destination operand. Absolute operands hold the low 16
+    /// bits of their value
+    fn destination(&self) -> &Operand;
+    /// Returns the bits 19:16 of an immediate or absolute operand which are
+    /// stored in the instruction word
+    fn high(&self) -> u8;
+
+    /// Returns the size of the instruction (in bytes)
+    fn size(&self) -> usize {
+        2 + self.source().size() + self.destination().size()
+    }
+
+    /// Returns the 20 bit value of an immediate or absolute source
+    fn source_value(&self) -> Option<u32> {
+        value(self.source(), self.high())
+    }
+
+    /// Returns the 20 bit value of an absolute destination
+    fn destination_value(&self) -> Option<u32> {
+        value(self.destination(), self.high())
+    }
+}
+
+fn value(operand: &Operand, high: u8) -> Option<u32> {
+    match operand {
+        Operand::Immediate(low) | Operand::Absolute(low) => Some((high as u32) << 16 | *low as u32),
+        _ => None,
+    }
+}
+
+/// Returns the additional word of an operand
+fn additional_word(operand: &Operand) -> Option<u16> {
+    match operand {
+        Operand::Immediate(low) | Operand::Absolute(low) => Some(*low),
+        Operand::Indexed { offset, .. } => Some(*offset as u16),
+        _ => None,
+    }
+}
+
+/// Returns the instruction word of an address instruction. The operands
+/// are always one of the forms produced by decode
+fn instruction_word(opcode: u16, source: &Operand, destination: &Operand, high: u8) -> u16 {
+    let high = high as u16;
+    let (form, source, destination) = match (source, destination) {
+        (Operand::RegisterIndirect(s), Operand::RegisterDirect(d)) => (INDIRECT, *s as u16, *d),
+        (Operand::RegisterIndirectAutoIncrement(s), Operand::RegisterDirect(d)) => {
+            (AUTOINCREMENT, *s as u16, *d)
+        }
+        (Operand::Absolute(_), Operand::RegisterDirect(d)) => (ABSOLUTE_SOURCE, high, *d),
+        (Operand::Indexed { register: s, .. }, Operand::RegisterDirect(d)) => {
+            (INDEXED_SOURCE, *s as u16, *d)
+        }
+        (Operand::RegisterDirect(s), Operand::Absolute(_)) => {
+            return (*s as u16) << 8 | ABSOLUTE_DESTINATION << 4 | high;
+        }
+        (Operand::RegisterDirect(s), Operand::Indexed { register: d, .. }) => {
+            (INDEXED_DESTINATION, *s as u16, *d)
+        }
+        (Operand::Immediate(_), Operand::RegisterDirect(d)) => (IMMEDIATE | opcode, high, *d),
+        (Operand::RegisterDirect(s), Operand::RegisterDirect(d)) => {
+            (REGISTER | opcode, *s as u16, *d)
+        }
+        _ => unreachable!("address instructions are only built by the decoder"),
+    };
+
+    source << 8 | form << 4 | destination as u16
+}
+
+macro_rules! address_instruction {
+    ($t:ident, $n:expr, $opcode:expr) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+        pub struct $t {
+            source: Operand,
+            destination: Operand,
+            high: u8,
+        }
+
+        impl $t {
+            pub(crate) fn new(source: Operand, destination: Operand, high: u8) -> $t {
+                $t {
+                    source,
+                    destination,
+                    high,
+                }
+            }
+
+            /// Returns the instruction word followed by the additional word
+            /// of an operand if it has one
+            pub fn words(&self) -> impl Iterator<Item = u16> {
+                let word = instruction_word($opcode, &self.source, &self.destination, self.high);
+                let additional =
+                    additional_word(&self.source).or(additional_word(&self.destination));
+                std::iter::once(word).chain(additional)
+            }
+        }
+
+        impl AddressInstruction for $t {
+            fn mnemonic(&self) -> &str {
+                $n
+            }
+
+            fn source(&self) -> &Operand {
+                &self.source
+            }
+
+            fn destination(&self) -> &Operand {
+                &self.destination
+            }
+
+            fn high(&self) -> u8 {
+                self.high
+            }
+        }
+
+        impl fmt::Display for $t {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write(f, self, &FormatOptions::default())
+            }
+        }
+    };
+}
+
+address_instruction!(Mova, "mova", MOVA_OPCODE);
+address_instruction!(Cmpa, "cmpa", CMPA_OPCODE);
+address_instruction!(Adda, "adda", ADDA_OPCODE);
+address_instruction!(Suba, "suba", SUBA_OPCODE);
+
+/// Parses an address instruction whose instruction word has already been
+/// parsed. Only mova has forms other than immediate and register sources.
+/// The rotate instructions that share the group (rrcm, rram, rlam and rrum)
+/// are rejected as an invalid opcode
+pub(crate) fn decode(word: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    let source = ((word & SOURCE_MASK) >> 8) as u8;
+    let form = (word & FORM_MASK) >> 4;
+    let destination = (word & DESTINATION_MASK) as u8;
+
+    let additional = |input, missing| parts::instruction_word(input).map_err(|_| missing);
+
+    let (input, source, destination, high) = match form {
+        INDIRECT => (
+            input,
+            Operand::RegisterIndirect(source),
+            Operand::RegisterDirect(destination),
+            0,
+        ),
+        AUTOINCREMENT => (
+            input,
+            Operand::RegisterIndirectAutoIncrement(source),
+            Operand::RegisterDirect(destination),
+            0,
+        ),
+        ABSOLUTE_SOURCE => {
+            let (input, low) = additional(input, DecodeError::MissingSource)?;
+            (
+                input,
+                Operand::Absolute(low),
+                Operand::RegisterDirect(destination),
+                source,
+            )
+        }
+        INDEXED_SOURCE => {
+            let (input, offset) = additional(input, DecodeError::MissingSource)?;
+            (
+                input,
+                Operand::indexed(source, offset as i16),
+                Operand::RegisterDirect(destination),
+                0,
+            )
+        }
+        ABSOLUTE_DESTINATION => {
+            let (input, low) = additional(input, DecodeError::MissingDestination)?;
+            (
+                input,
+                Operand::RegisterDirect(source),
+                Operand::Absolute(low),
+                destination,
+            )
+        }
+        INDEXED_DESTINATION => {
+            let (input, offset) = additional(input, DecodeError::MissingDestination)?;
+            (
+                input,
+                Operand::RegisterDirect(source),
+                Operand::indexed(destination, offset as i16),
+                0,
+            )
+        }
+        IMMEDIATE..REGISTER => {
+            let (input, low) = additional(input, DecodeError::MissingSource)?;
+            (
+                input,
+                Operand::Immediate(low),
+                Operand::RegisterDirect(destination),
+                source,
+            )
+        }
+        REGISTER.. => (
+            input,
+            Operand::RegisterDirect(source),
+            Operand::RegisterDirect(destination),
+            0,
+        ),
+        _ => return Err(DecodeError::InvalidOpcode(form)),
+    };
+
+    let inst = match form {
+        IMMEDIATE.. => match form & 0b11 {
+            MOVA_OPCODE => Instruction::Mova(Mova::new(source, destination, high)),
+            CMPA_OPCODE => Instruction::Cmpa(Cmpa::new(source, destination, high)),
+            ADDA_OPCODE => Instruction::Adda(Adda::new(source, destination, high)),
+            _ => Instruction::Suba(Suba::new(source, destination, high)),
+        },
+        _ => Instruction::Mova(Mova::new(source, destination, high)),
+    };
+
+    Ok((input, inst))
+}
+
+fn write_operand<W: Write>(
+    w: &mut W,
+    operand: &Operand,
+    value: Option<u32>,
+    options: &FormatOptions,
+) -> fmt::Result {
+    match (operand, value) {
+        (Operand::Immediate(_), Some(value)) => write!(w, "#{:#x}", value),
+        (Operand::Absolute(_), Some(value)) => write!(w, "&{:#x}", value),
+        _ => format::write_operand(w, operand, options),
+    }
+}
+
+/// Writes an address instruction with its 20 bit operands. This backs both
+/// Display and format::write_instruction
+pub(crate) fn write<W: Write, T: AddressInstruction + ?Sized>(
+    w: &mut W,
+    inst: &T,
+    options: &FormatOptions,
+) -> fmt::Result {
+    if options.uppercase {
+        for c in inst.mnemonic().chars() {
+            w.write_char(c.to_ascii_uppercase())?;
+        }
+    } else {
+        w.write_str(inst.mnemonic())?;
+    }
+
+    w.write_char(' ')?;
+    write_operand(w, inst.source(), inst.source_value(), options)?;
+    w.write_str(", ")?;
+    write_operand(w, inst.destination(), inst.destination_value(), options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    #[test]
+    fn mova_forms() {
+        let cases: [(&[u8], &str); 8] = [
+            (&[0x04, 0x05], "mova @r5, r4"),
+            (&[0x14, 0x05], "mova @r5+, r4"),
+            (&[0x24, 0x01, 0x34, 0x12], "mova &0x11234, r4"),
+            (&[0x34, 0x05, 0xfe, 0xff], "mova -0x2(r5), r4"),
+            (&[0x61, 0x05, 0x34, 0x12], "mova r5, &0x11234"),
+            (&[0x74, 0x05, 0x04, 0x00], "mova r5, 0x4(r4)"),
+            (&[0x84, 0x0f, 0x78, 0x56], "mova #0xf5678, r4"),
+            (&[0xc4, 0x05], "mova r5, r4"),
+        ];
+
+        for (data, expected) in cases {
+            let inst = decode(data).unwrap();
+            assert_eq!(inst.to_string(), expected);
+            assert_eq!(inst.size(), data.len());
+            assert_eq!(inst.encode().unwrap(), data.to_vec(), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        // cmpa #0x10000, r15; adda r4, sp; suba #0x2, r4
+        let cmpa = decode(&[0x9f, 0x01, 0x00, 0x00]).unwrap();
+        assert_eq!(cmpa.to_string(), "cmpa #0x10000, r15");
+        assert_eq!(cmpa.mnemonic(), "cmpa");
+        let Instruction::Cmpa(inner) = cmpa else {
+            panic!("{} is not cmpa", cmpa);
+        };
+        assert_eq!(inner.source_value(), Some(0x10000));
+        assert_eq!(inner.destination_value(), None);
+
+        assert_eq!(decode(&[0xe1, 0x04]).unwrap().to_string(), "adda r4, sp");
+        assert_eq!(
+            decode(&[0xb4, 0x00, 0x02, 0x00]).unwrap().to_string(),
+            "suba #0x2, r4"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        // rrcm and rram aren't decoded yet
+        assert_eq!(decode(&[0x44, 0x00]), Err(DecodeError::InvalidOpcode(4)));
+        assert_eq!(decode(&[0x54, 0x00]), Err(DecodeError::InvalidOpcode(5)));
+        assert_eq!(decode(&[0x24, 0x01]), Err(DecodeError::MissingSource));
+        assert_eq!(decode(&[0x61, 0x05]), Err(DecodeError::MissingDestination));
+    }
+
+    #[test]
+    fn uppercase() {
+        let inst = decode(&[0x84, 0x0f, 0x78, 0x56]).unwrap();
+        let options = FormatOptions {
+            uppercase: true,
+            numeric_registers: true,
+            ..Default::default()
+        };
+        let mut s = String::new();
+        format::write_instruction(&mut s, &inst, &options).unwrap();
+        assert_eq!(s, "MOVA #0xf5678, r4");
+    }
+}
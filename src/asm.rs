@@ -0,0 +1,2123 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use crate::address::{Adda, Cmpa, Mova, Suba};
+use crate::encode::{EncodeError, EncodeOptions};
+use crate::extended::{self, Repetition};
+use crate::instruction::{emulated, Instruction};
+use crate::jxx::*;
+use crate::listing::Line;
+use crate::operand::{Operand, OperandWidth};
+use crate::provenance::Provenance;
+use crate::single_operand::*;
+use crate::target::{self, TargetError};
+use crate::two_operand::*;
+use crate::writer::{self, Segment};
+
+/// Errors that can occur while parsing assembly
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// Present when the mnemonic isn't an instruction. Contains the mnemonic
+    UnknownMnemonic(String),
+    /// Present when the mnemonic has a width suffix the instruction doesn't
+    /// support. Contains the mnemonic
+    InvalidWidth(String),
+    /// Present when an instruction has the wrong number of operands.
+    /// Contains the expected and actual number of operands
+    OperandCount((usize, usize)),
+    /// Present when an operand can't be parsed. Contains the operand
+    InvalidOperand(String),
+    /// Present when the operands parse but the instruction can't be encoded
+    /// (eg. an immediate destination)
+    Unencodable(EncodeError),
+    /// Present when an operand refers to a label that isn't defined.
+    /// Contains the label
+    UndefinedSymbol(String),
+    /// Present when a jump's label is too far away for the 10 bit offset.
+    /// Contains the label and the distance in bytes
+    JumpOutOfRange((String, i64)),
+}
+
+impl ParseError {
+    /// Returns a stable numeric code for the error. Parse errors use the
+    /// 500-599 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::UnknownMnemonic(_) => 500,
+            Self::InvalidWidth(_) => 501,
+            Self::OperandCount(_) => 502,
+            Self::InvalidOperand(_) => 503,
+            Self::Unencodable(_) => 504,
+            Self::UndefinedSymbol(_) => 505,
+            Self::JumpOutOfRange(_) => 506,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic {}", mnemonic),
+            Self::InvalidWidth(mnemonic) => write!(f, "invalid width for {}", mnemonic),
+            Self::OperandCount((expected, found)) => {
+                write!(f, "expected {} operands but found {}", expected, found)
+            }
+            Self::InvalidOperand(operand) => write!(f, "invalid operand {}", operand),
+            Self::Unencodable(e) => write!(f, "{}", e),
+            Self::UndefinedSymbol(label) => write!(f, "undefined symbol {}", label),
+            Self::JumpOutOfRange((label, distance)) => {
+                write!(f, "jump to {} is out of range ({} bytes)", label, distance)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unencodable(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a number in decimal or hex (0x) with an optional minus sign
+fn number(text: &str) -> Option<i32> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+
+    // the sign only comes before the radix prefix (eg. -0x5 but not 0x-5)
+    // and from_str_radix would accept one after it
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) if !hex.starts_with(['+', '-']) => i32::from_str_radix(hex, 16).ok()?,
+        None if !text.starts_with(['+', '-']) => text.parse::<i32>().ok()?,
+        _ => return None,
+    };
+
+    if value > 0xffff {
+        return None;
+    }
+
+    Some(if negative { -value } else { value })
+}
+
+/// Parses a number that fits in a word, either signed or unsigned
+fn word(text: &str) -> Option<u16> {
+    number(text)
+        .filter(|value| (i16::MIN as i32..=u16::MAX as i32).contains(value))
+        .map(|value| value as u16)
+}
+
+/// Returns whether text can be a label
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// The address of the instruction being parsed and the labels operands may
+/// refer to
+struct Context<'a> {
+    address: u32,
+    /// None while sizing instructions before all labels are known in which
+    /// case every label resolves to the current address
+    symbols: Option<&'a BTreeMap<String, u32>>,
+    /// Whether numeric immediates are kept as an additional word rather
+    /// than using the constant generator
+    literal_immediates: bool,
+}
+
+impl Context<'_> {
+    /// Evaluates an expression of labels and numbers joined by + and - (eg.
+    /// `buffer+2` or `TOP-4`). Returns None if the expression doesn't refer
+    /// to a label
+    fn symbol(&self, text: &str) -> Result<Option<i64>, ParseError> {
+        let mut terms = vec![];
+        let (mut start, mut sign) = (0, 1);
+        for (i, c) in text.char_indices() {
+            // a sign before the first term or after an operator is part of
+            // the number that follows it
+            if (c == '+' || c == '-') && !text[start..i].trim().is_empty() {
+                terms.push((sign, text[start..i].trim()));
+                sign = if c == '-' { -1 } else { 1 };
+                start = i + 1;
+            }
+        }
+        terms.push((sign, text[start..].trim()));
+
+        // negated labels (-buffer) are a term subtracted from zero
+        for (sign, term) in terms.iter_mut() {
+            if let Some(label) = term.strip_prefix('-').filter(|l| is_identifier(l.trim())) {
+                *sign = -*sign;
+                *term = label.trim();
+            }
+        }
+
+        if !terms.iter().any(|(_, term)| is_identifier(term)) {
+            return Ok(None);
+        }
+
+        let mut value = 0;
+        for (sign, term) in terms {
+            let term = match (is_identifier(term), self.symbols) {
+                (true, None) => self.address as i64,
+                (true, Some(symbols)) => match symbols.get(term) {
+                    Some(value) => *value as i64,
+                    None => return Err(ParseError::UndefinedSymbol(term.to_string())),
+                },
+                (false, _) => {
+                    number(term).ok_or_else(|| ParseError::InvalidOperand(text.to_string()))? as i64
+                }
+            };
+            value += sign * term;
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Resolves an expression with labels to a word operand. Labels are
+    /// always encoded with an additional word so the size of an instruction
+    /// doesn't depend on where its labels end up
+    fn symbol_word(&self, text: &str) -> Result<Option<u16>, ParseError> {
+        match self.symbol(text)? {
+            // the value isn't known while sizing so it isn't range checked
+            Some(_) if self.symbols.is_none() => Ok(Some(0)),
+            Some(value) => u16::try_from(value)
+                .or_else(|_| i16::try_from(value).map(|value| value as u16))
+                .map(Some)
+                .map_err(|_| ParseError::InvalidOperand(text.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the target of a jump which is either an offset in words (as
+    /// displayed) or an expression with a label
+    fn offset(&self, text: &str) -> Result<i16, ParseError> {
+        let invalid = || ParseError::InvalidOperand(text.to_string());
+        let target = match self.symbol(text)? {
+            // jumps are always one word so the offset isn't needed to size
+            // them
+            Some(_) if self.symbols.is_none() => return Ok(0),
+            Some(target) => target,
+            None => {
+                let offset = text.strip_prefix('#').unwrap_or(text);
+                return number(offset)
+                    .and_then(|offset| i16::try_from(offset).ok())
+                    .ok_or_else(invalid);
+            }
+        };
+
+        // offsets are relative to the word after the jump
+        let distance = target - (self.address as i64 + 2);
+        match offset_from_displacement(distance) {
+            Ok(offset) => Ok(offset),
+            Err(_) if distance % 2 != 0 => Err(invalid()),
+            Err(_) => Err(ParseError::JumpOutOfRange((text.to_string(), distance))),
+        }
+    }
+}
+
+fn register(text: &str) -> Option<u8> {
+    match text {
+        "pc" => Some(0),
+        "sp" => Some(1),
+        "sr" => Some(2),
+        "cg" => Some(3),
+        _ => text.strip_prefix('r')?.parse().ok().filter(|r| *r < 16),
+    }
+}
+
+/// Parses an operand in the syntax used by Operand's Display. Immediates
+/// the constant generator can produce are parsed as constants unless the
+/// context keeps literal immediates
+fn operand(text: &str, context: &Context) -> Result<Operand, ParseError> {
+    let invalid = || ParseError::InvalidOperand(text.to_string());
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(label) = text.strip_prefix('#') {
+        if let Some(value) = context.symbol_word(label)? {
+            return Ok(Operand::Immediate(value));
+        }
+    } else if let Some(label) = text.strip_prefix('&') {
+        if let Some(value) = context.symbol_word(label)? {
+            return Ok(Operand::Absolute(value));
+        }
+    }
+
+    let operand = if let Some(value) = lower.strip_prefix('#') {
+        match value.strip_suffix("(pc)") {
+            Some(offset) => Operand::Symbolic(word(offset).ok_or_else(invalid)? as i16),
+            None => match word(value).ok_or_else(invalid)? {
+                value if context.literal_immediates => Operand::Immediate(value),
+                0 => Operand::Constant(0),
+                1 => Operand::Constant(1),
+                2 => Operand::Constant(2),
+                4 => Operand::Constant(4),
+                8 => Operand::Constant(8),
+                0xffff => Operand::Constant(-1),
+                value => Operand::Immediate(value),
+            },
+        }
+    } else if let Some(address) = lower.strip_prefix('&') {
+        Operand::Absolute(word(address).ok_or_else(invalid)?)
+    } else if let Some(indirect) = lower.strip_prefix('@') {
+        match indirect.strip_suffix('+') {
+            Some(r) => Operand::RegisterIndirectAutoIncrement(register(r).ok_or_else(invalid)?),
+            None => Operand::RegisterIndirect(register(indirect).ok_or_else(invalid)?),
+        }
+    } else if let Some((offset, r)) = text.strip_suffix(')').and_then(|o| o.rsplit_once('(')) {
+        let offset = match context.symbol_word(offset)? {
+            Some(offset) => offset as i16,
+            None => word(&offset.to_ascii_lowercase()).ok_or_else(invalid)? as i16,
+        };
+        match register(&r.to_ascii_lowercase()).ok_or_else(invalid)? {
+            0 => Operand::Symbolic(offset),
+            r => Operand::indexed(r, offset),
+        }
+    } else {
+        Operand::RegisterDirect(register(&lower).ok_or_else(invalid)?)
+    };
+
+    Ok(operand)
+}
+
+/// Builds a two operand instruction, returning the emulated instruction
+/// when the decoder would show it as one
+fn two_operand(
+    name: &str,
+    source: Operand,
+    width: OperandWidth,
+    destination: Operand,
+) -> Instruction {
+    match name {
+        "mov" => emulated(Mov::new(source, width, destination), Instruction::Mov),
+        "add" => emulated(Add::new(source, width, destination), Instruction::Add),
+        "addc" => emulated(Addc::new(source, width, destination), Instruction::Addc),
+        "subc" => emulated(Subc::new(source, width, destination), Instruction::Subc),
+        "sub" => emulated(Sub::new(source, width, destination), Instruction::Sub),
+        "cmp" => emulated(Cmp::new(source, width, destination), Instruction::Cmp),
+        "dadd" => emulated(Dadd::new(source, width, destination), Instruction::Dadd),
+        "bit" => Instruction::Bit(Bit::new(source, width, destination)),
+        "bic" => emulated(Bic::new(source, width, destination), Instruction::Bic),
+        "bis" => emulated(Bis::new(source, width, destination), Instruction::Bis),
+        "xor" => emulated(Xor::new(source, width, destination), Instruction::Xor),
+        _ => Instruction::And(And::new(source, width, destination)),
+    }
+}
+
+/// Parses a 20 bit number in decimal or hex (0x) with an optional minus
+/// sign. Negative numbers are returned in 20 bit two's complement
+fn long(text: &str) -> Option<u32> {
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+
+    let value = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) if !hex.starts_with(['+', '-']) => u32::from_str_radix(hex, 16).ok()?,
+        None if !text.starts_with(['+', '-']) => text.parse::<u32>().ok()?,
+        _ => return None,
+    };
+
+    match negative {
+        false => (value <= 0xfffff).then_some(value),
+        true => (value <= 0x80000).then_some((0x100000 - value) & 0xfffff),
+    }
+}
+
+/// Parses an operand of an MSP430X instruction whose immediate, absolute,
+/// indexed and symbolic values are 20 bits. Returns the operand holding the
+/// low 16 bits of the value and the upper four bits
+fn long_operand(text: &str, context: &Context) -> Result<(Operand, u8), ParseError> {
+    let invalid = || ParseError::InvalidOperand(text.to_string());
+    let lower = text.to_ascii_lowercase();
+    let split = |value: u32| (value as u16, (value >> 16) as u8);
+
+    // labels are always a literal word so they don't change the size
+    let label = |label: &str| -> Result<Option<u32>, ParseError> {
+        match context.symbol(label)? {
+            Some(_) if context.symbols.is_none() => Ok(Some(0)),
+            Some(value) => u32::try_from(value)
+                .ok()
+                .filter(|value| *value <= 0xfffff)
+                .map(Some)
+                .ok_or_else(invalid),
+            None => Ok(None),
+        }
+    };
+
+    if let Some(value) = lower.strip_prefix('#') {
+        if let Some(offset) = value.strip_suffix("(pc)") {
+            let (low, high) = split(long(offset).ok_or_else(invalid)?);
+            return Ok((Operand::Symbolic(low as i16), high));
+        }
+        if let Some(value) = label(&text[1..])? {
+            let (low, high) = split(value);
+            return Ok((Operand::Immediate(low), high));
+        }
+
+        let number = long(value).ok_or_else(invalid)?;
+        if !context.literal_immediates {
+            match number {
+                0 | 1 | 2 | 4 | 8 => return Ok((Operand::Constant(number as i8), 0)),
+                0xfffff if value.starts_with('-') => return Ok((Operand::Constant(-1), 0)),
+                _ => {}
+            }
+        }
+        let (low, high) = split(number);
+        Ok((Operand::Immediate(low), high))
+    } else if let Some(address) = lower.strip_prefix('&') {
+        let value = match label(&text[1..])? {
+            Some(value) => value,
+            None => long(address).ok_or_else(invalid)?,
+        };
+        let (low, high) = split(value);
+        Ok((Operand::Absolute(low), high))
+    } else if let Some((offset, r)) = text.strip_suffix(')').and_then(|o| o.rsplit_once('(')) {
+        let offset = match label(offset)? {
+            Some(offset) => offset,
+            None => long(&offset.to_ascii_lowercase()).ok_or_else(invalid)?,
+        };
+        let (low, high) = split(offset);
+        match register(&r.to_ascii_lowercase()).ok_or_else(invalid)? {
+            0 => Ok((Operand::Symbolic(low as i16), high)),
+            r => Ok((Operand::indexed(r, low as i16), high)),
+        }
+    } else {
+        Ok((operand(text, context)?, 0))
+    }
+}
+
+/// Parses an MSP430X instruction (eg. `movx.a #0x12345, r15`) by building
+/// the instruction it extends from the low 16 bits of its operands. The
+/// repetition comes from a `rpt` prefix
+fn extended(
+    mnemonic: &str,
+    operands: &[&str],
+    repetition: Option<Repetition>,
+    context: &Context,
+) -> Result<Instruction, ParseError> {
+    let lower = mnemonic.to_ascii_lowercase();
+    let unknown = || ParseError::UnknownMnemonic(mnemonic.to_string());
+    let (name, width) = match lower.split_once('.') {
+        Some((name, width)) => (name, Some(width)),
+        None => (lower.as_str(), None),
+    };
+    let name = name.strip_suffix('x').ok_or_else(unknown)?;
+    let (name, zero_carry) = match name {
+        "rru" => ("rrc", true),
+        name => (name, false),
+    };
+
+    // the A/L bit is cleared for address width which swpb and sxt select
+    // without the B/W bit
+    let no_bw = name == "swpb" || name == "sxt";
+    let (al, byte) = match width {
+        None | Some("w") => (true, false),
+        Some("b") if !no_bw => (true, true),
+        Some("a") => (false, !no_bw),
+        Some(_) => return Err(ParseError::InvalidWidth(mnemonic.to_string())),
+    };
+    let width = if byte {
+        OperandWidth::Byte
+    } else {
+        OperandWidth::Word
+    };
+
+    let expected = match name {
+        "mov" | "add" | "addc" | "subc" | "sub" | "cmp" | "dadd" | "bit" | "bic" | "bis"
+        | "xor" | "and" => 2,
+        "rrc" | "swpb" | "rra" | "sxt" | "push" => 1,
+        _ => return Err(unknown()),
+    };
+    if operands.len() != expected {
+        return Err(ParseError::OperandCount((expected, operands.len())));
+    }
+
+    let (inst, high) = if expected == 2 {
+        let (source, source_high) = long_operand(operands[0], context)?;
+        let (destination, destination_high) = long_operand(operands[1], context)?;
+        let inst = two_operand(name, source, width, destination);
+        (inst, (source_high, destination_high))
+    } else {
+        // format II instructions keep the high bits of their only operand
+        // in the destination bits
+        let (source, high) = long_operand(operands[0], context)?;
+        let inst = match name {
+            "rrc" => Instruction::Rrc(Rrc::new(source, Some(width))),
+            "rra" => Instruction::Rra(Rra::new(source, Some(width))),
+            "push" => Instruction::Push(Push::new(source, Some(width))),
+            "swpb" => Instruction::Swpb(Swpb::new(source, None)),
+            _ => Instruction::Sxt(Sxt::new(source, None)),
+        };
+        (inst, (0, high))
+    };
+
+    inst.encode().map_err(ParseError::Unencodable)?;
+    extended::extend(&inst, al, high, repetition, zero_carry)
+        .map(Instruction::Extended)
+        .ok_or_else(|| ParseError::InvalidOperand(operands.join(", ")))
+}
+
+/// Parses an address instruction (mova, cmpa, adda or suba) whose
+/// immediate and absolute operands are 20 bits. Only mova has forms other
+/// than immediate and register sources
+fn address(name: &str, operands: &[&str], context: &Context) -> Result<Instruction, ParseError> {
+    if operands.len() != 2 {
+        return Err(ParseError::OperandCount((2, operands.len())));
+    }
+
+    let invalid = |text: &str| ParseError::InvalidOperand(text.to_string());
+    let parse = |text: &str| -> Result<(Operand, u8), ParseError> {
+        if !text.starts_with(['#', '&']) {
+            // indexed offsets are 16 bits and pc is indexed rather than
+            // symbolic
+            return match operand(text, context)? {
+                Operand::Symbolic(offset) => Ok((Operand::indexed(0, offset), 0)),
+                operand => Ok((operand, 0)),
+            };
+        }
+
+        // immediates never use the constant generator
+        match long_operand(text, context)? {
+            (Operand::Constant(_), _) => {
+                let value = long(&text[1..]).ok_or_else(|| invalid(text))?;
+                Ok((Operand::Immediate(value as u16), (value >> 16) as u8))
+            }
+            (Operand::Symbolic(_), _) => Err(invalid(text)),
+            operand => Ok(operand),
+        }
+    };
+    let (source, source_high) = parse(operands[0])?;
+    let (destination, destination_high) = parse(operands[1])?;
+
+    let register = |operand: &Operand| matches!(operand, Operand::RegisterDirect(_));
+    let valid = match (&source, &destination) {
+        (Operand::Immediate(_) | Operand::RegisterDirect(_), Operand::RegisterDirect(_)) => true,
+        (_, Operand::RegisterDirect(_)) => name == "mova",
+        (Operand::RegisterDirect(_), Operand::Absolute(_) | Operand::Indexed { .. }) => {
+            name == "mova"
+        }
+        _ => false,
+    };
+    if !valid {
+        let error = match register(&destination) {
+            true => EncodeError::InvalidSource(source),
+            false => EncodeError::InvalidDestination(destination),
+        };
+        return Err(ParseError::Unencodable(error));
+    }
+
+    let high = source_high.max(destination_high);
+    Ok(match name {
+        "mova" => Instruction::Mova(Mova::new(source, destination, high)),
+        "cmpa" => Instruction::Cmpa(Cmpa::new(source, destination, high)),
+        "adda" => Instruction::Adda(Adda::new(source, destination, high)),
+        _ => Instruction::Suba(Suba::new(source, destination, high)),
+    })
+}
+
+/// Parses a single instruction in the syntax Display produces (eg.
+/// `mov.b #0x10, r15`, `jnz #-0x6` or `push @r4+`). Mnemonics and
+/// registers are case insensitive and numbers may be decimal or hex.
+/// Instructions that the decoder would show as an emulated instruction are
+/// returned as the emulated instruction so parsing the display of a decoded
+/// instruction returns an equivalent instruction. Emulated mnemonics (eg.
+/// `ret` or `inc.b r15`) are accepted and lowered to the instruction they
+/// emulate the same way TI's assembler does, as are the jump synonyms jne,
+/// jeq, jnc and jhs. MSP430X address instructions (eg. `mova &0x12345, r4`)
+/// and extended instructions with their `rpt` prefix (eg. `rpt #4 rrax r15`)
+/// are parsed with 20 bit operands
+pub fn parse(line: &str) -> Result<Instruction, ParseError> {
+    let symbols = BTreeMap::new();
+    let context = Context {
+        address: 0,
+        symbols: Some(&symbols),
+        literal_immediates: false,
+    };
+    parse_in(line, &context)
+}
+
+impl std::str::FromStr for Instruction {
+    type Err = ParseError;
+
+    /// Parses a single instruction the same way as [`parse`]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s)
+    }
+}
+
+/// Splits an instruction into its mnemonic and operands
+fn split(line: &str) -> (&str, Vec<&str>) {
+    let line = line.trim();
+    let (mnemonic, operands) = match line.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, operands.trim()),
+        None => (line, ""),
+    };
+    let operands = if operands.is_empty() {
+        vec![]
+    } else {
+        operands.split(',').map(str::trim).collect()
+    };
+
+    (mnemonic, operands)
+}
+
+fn parse_in(line: &str, context: &Context) -> Result<Instruction, ParseError> {
+    let (mnemonic, operands) = split(line);
+    let lower = mnemonic.to_ascii_lowercase();
+
+    // msp430x instructions have 20 bit operands and an address width so
+    // they are parsed separately
+    if lower == "rpt" {
+        let rest = line.trim()[mnemonic.len()..].trim();
+        let (count, rest) = rest
+            .split_once(char::is_whitespace)
+            .ok_or(ParseError::OperandCount((2, operands.len())))?;
+        let repetition = match count.strip_prefix('#') {
+            Some(count) => number(count)
+                .filter(|count| (1..=16).contains(count))
+                .map(|count| Repetition::Count(count as u8)),
+            None => register(&count.to_ascii_lowercase()).map(Repetition::Register),
+        }
+        .ok_or_else(|| ParseError::InvalidOperand(count.to_string()))?;
+        let (mnemonic, operands) = split(rest);
+        return extended(mnemonic, &operands, Some(repetition), context);
+    }
+    match lower
+        .split_once('.')
+        .map_or(lower.as_str(), |(name, _)| name)
+    {
+        "mova" | "cmpa" | "adda" | "suba" => {
+            if lower.contains('.') {
+                return Err(ParseError::InvalidWidth(mnemonic.to_string()));
+            }
+            return address(&lower, &operands, context);
+        }
+        name if name.ends_with('x') => return extended(mnemonic, &operands, None, context),
+        _ => {}
+    }
+
+    let (name, width) = match lower.split_once('.') {
+        Some((name, "b")) => (name, Some(OperandWidth::Byte)),
+        Some((name, "w")) => (name, Some(OperandWidth::Word)),
+        Some(_) => return Err(ParseError::InvalidWidth(mnemonic.to_string())),
+        None => (lower.as_str(), None),
+    };
+
+    let count = |expected: usize| {
+        if operands.len() == expected {
+            Ok(())
+        } else {
+            Err(ParseError::OperandCount((expected, operands.len())))
+        }
+    };
+    let no_width = || match width {
+        Some(OperandWidth::Byte) => Err(ParseError::InvalidWidth(mnemonic.to_string())),
+        _ => Ok(()),
+    };
+    let word = width.unwrap_or(OperandWidth::Word);
+
+    let inst = match name {
+        "reti" => {
+            no_width()?;
+            count(0)?;
+            Instruction::Reti(Reti::new())
+        }
+        "rrc" | "swpb" | "rra" | "sxt" | "push" | "call" => {
+            count(1)?;
+            let source = operand(operands[0], context)?;
+            match name {
+                "rrc" => Instruction::Rrc(Rrc::new(source, Some(word))),
+                "rra" => Instruction::Rra(Rra::new(source, Some(word))),
+                "push" => Instruction::Push(Push::new(source, Some(word))),
+                _ => {
+                    no_width()?;
+                    match name {
+                        "swpb" => Instruction::Swpb(Swpb::new(source, None)),
+                        "sxt" => Instruction::Sxt(Sxt::new(source, None)),
+                        _ => Instruction::Call(Call::new(source, None)),
+                    }
+                }
+            }
+        }
+        "jnz" | "jne" | "jz" | "jeq" | "jlo" | "jnc" | "jc" | "jhs" | "jn" | "jge" | "jl"
+        | "jmp" => {
+            if width.is_some() {
+                return Err(ParseError::InvalidWidth(mnemonic.to_string()));
+            }
+            count(1)?;
+            let offset = context.offset(operands[0])?;
+            match name {
+                "jnz" | "jne" => Instruction::Jnz(Jnz::new(offset)),
+                "jz" | "jeq" => Instruction::Jz(Jz::new(offset)),
+                "jlo" | "jnc" => Instruction::Jlo(Jlo::new(offset)),
+                "jc" | "jhs" => Instruction::Jc(Jc::new(offset)),
+                "jn" => Instruction::Jn(Jn::new(offset)),
+                "jge" => Instruction::Jge(Jge::new(offset)),
+                "jl" => Instruction::Jl(Jl::new(offset)),
+                _ => Instruction::Jmp(Jmp::new(offset)),
+            }
+        }
+        "mov" | "add" | "addc" | "subc" | "sub" | "cmp" | "dadd" | "bit" | "bic" | "bis"
+        | "xor" | "and" => {
+            count(2)?;
+            let source = operand(operands[0], context)?;
+            let destination = operand(operands[1], context)?;
+            two_operand(name, source, word, destination)
+        }
+        // emulated instructions are lowered to the instruction they emulate
+        // which always uses the constant generator
+        "ret" | "nop" | "clrc" | "clrn" | "clrz" | "setc" | "setn" | "setz" | "dint" | "eint" => {
+            no_width()?;
+            count(0)?;
+            let (name, source, destination) = match name {
+                "ret" => ("mov", Operand::RegisterIndirectAutoIncrement(1), 0),
+                "nop" => ("mov", Operand::Constant(0), 3),
+                "clrc" => ("bic", Operand::Constant(1), 2),
+                "clrn" => ("bic", Operand::Constant(4), 2),
+                "clrz" => ("bic", Operand::Constant(2), 2),
+                "setc" => ("bis", Operand::Constant(1), 2),
+                "setn" => ("bis", Operand::Constant(4), 2),
+                "setz" => ("bis", Operand::Constant(2), 2),
+                "dint" => ("bic", Operand::Constant(8), 2),
+                _ => ("bis", Operand::Constant(8), 2),
+            };
+            two_operand(name, source, word, Operand::RegisterDirect(destination))
+        }
+        "br" => {
+            no_width()?;
+            count(1)?;
+            let source = operand(operands[0], context)?;
+            two_operand("mov", source, word, Operand::RegisterDirect(0))
+        }
+        "adc" | "clr" | "dadc" | "dec" | "decd" | "inc" | "incd" | "inv" | "pop" | "rla"
+        | "rlc" | "sbc" | "tst" => {
+            count(1)?;
+            let destination = operand(operands[0], context)?;
+            let (name, source) = match name {
+                "adc" => ("addc", Operand::Constant(0)),
+                "clr" => ("mov", Operand::Constant(0)),
+                "dadc" => ("dadd", Operand::Constant(0)),
+                "dec" => ("sub", Operand::Constant(1)),
+                "decd" => ("sub", Operand::Constant(2)),
+                "inc" => ("add", Operand::Constant(1)),
+                "incd" => ("add", Operand::Constant(2)),
+                "inv" => ("xor", Operand::Constant(-1)),
+                "pop" => ("mov", Operand::RegisterIndirectAutoIncrement(1)),
+                "rla" => ("add", destination),
+                "rlc" => ("addc", destination),
+                "sbc" => ("subc", Operand::Constant(0)),
+                _ => ("cmp", Operand::Constant(0)),
+            };
+            two_operand(name, source, word, destination)
+        }
+        _ => return Err(ParseError::UnknownMnemonic(mnemonic.to_string())),
+    };
+
+    inst.encode().map_err(ParseError::Unencodable)?;
+    Ok(inst)
+}
+
+/// A line of a program once its labels are removed
+enum Statement {
+    Instruction(Instruction),
+    /// A jump to a label that is out of range relaxed to a branch
+    Relaxed(Vec<Instruction>),
+    /// Bytes emitted by a data directive
+    Data(Vec<u8>),
+    /// Moves the location counter to an address
+    Org(u32),
+}
+
+/// Parses a directive (.org, .byte, .word, .space or .align) or an
+/// instruction
+fn statement(text: &str, context: &Context) -> Result<Statement, ParseError> {
+    let (directive, operands) = match text.split_once(char::is_whitespace) {
+        Some((directive, operands)) => (directive, operands.trim()),
+        None => (text, ""),
+    };
+    if !directive.starts_with('.') {
+        return parse_in(text, context).map(Statement::Instruction);
+    }
+
+    let operands: Vec<&str> = if operands.is_empty() {
+        vec![]
+    } else {
+        operands.split(',').map(str::trim).collect()
+    };
+    let invalid = |text: &str| ParseError::InvalidOperand(text.to_string());
+    let count = |expected: usize| {
+        if operands.len() == expected {
+            Ok(())
+        } else {
+            Err(ParseError::OperandCount((expected, operands.len())))
+        }
+    };
+    let size = |text: &str| {
+        number(text)
+            .filter(|n| *n >= 0)
+            .map(|n| n as u32)
+            .ok_or_else(|| invalid(text))
+    };
+
+    let statement = match directive.to_ascii_lowercase().as_str() {
+        ".org" => {
+            count(1)?;
+            Statement::Org(size(operands[0])?)
+        }
+        ".byte" => Statement::Data(
+            operands
+                .iter()
+                .map(|text| {
+                    number(text)
+                        .filter(|value| (i8::MIN as i32..=u8::MAX as i32).contains(value))
+                        .map(|value| value as u8)
+                        .ok_or_else(|| invalid(text))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        ".word" => {
+            let mut data = vec![];
+            for text in &operands {
+                let value = match context.symbol_word(text)? {
+                    Some(value) => value,
+                    None => word(text).ok_or_else(|| invalid(text))?,
+                };
+                data.extend(value.to_le_bytes());
+            }
+            Statement::Data(data)
+        }
+        ".space" => {
+            let fill = match operands.len() {
+                1 => 0,
+                _ => {
+                    count(2)?;
+                    number(operands[1])
+                        .filter(|value| (i8::MIN as i32..=u8::MAX as i32).contains(value))
+                        .ok_or_else(|| invalid(operands[1]))? as u8
+                }
+            };
+            Statement::Data(vec![
+                fill;
+                size(operands.first().copied().unwrap_or_default())?
+                    as usize
+            ])
+        }
+        ".align" => {
+            count(1)?;
+            let alignment = size(operands[0])?;
+            if alignment == 0 {
+                return Err(invalid(operands[0]));
+            }
+            let padding = (alignment - context.address % alignment) % alignment;
+            Statement::Data(vec![0; padding as usize])
+        }
+        _ => return Err(ParseError::UnknownMnemonic(directive.to_string())),
+    };
+
+    Ok(statement)
+}
+
+/// Relaxes a jump to a label that is out of range to a branch. Conditional
+/// jumps become the inverted jump over `br #label` and jn, which has no
+/// inverse, jumps over a jmp that skips the branch
+fn relax(text: &str, context: &Context) -> Result<Vec<Instruction>, ParseError> {
+    let (mnemonic, operands) = split(text);
+    let target = context
+        .symbol_word(operands[0])?
+        .ok_or_else(|| ParseError::InvalidOperand(operands[0].to_string()))?;
+    // labels are always encoded with an additional word so the size of the
+    // branch doesn't depend on the target
+    let branch = two_operand(
+        "mov",
+        Operand::Immediate(target),
+        OperandWidth::Word,
+        Operand::RegisterDirect(0),
+    );
+
+    // the inverted jump skips the two words of the branch
+    let mut relaxed = match mnemonic.to_ascii_lowercase().as_str() {
+        "jnz" | "jne" => vec![Instruction::Jz(Jz::new(2))],
+        "jz" | "jeq" => vec![Instruction::Jnz(Jnz::new(2))],
+        "jlo" | "jnc" => vec![Instruction::Jc(Jc::new(2))],
+        "jc" | "jhs" => vec![Instruction::Jlo(Jlo::new(2))],
+        "jge" => vec![Instruction::Jl(Jl::new(2))],
+        "jl" => vec![Instruction::Jge(Jge::new(2))],
+        "jn" => vec![Instruction::Jn(Jn::new(1)), Instruction::Jmp(Jmp::new(2))],
+        _ => vec![],
+    };
+    relaxed.push(branch);
+    Ok(relaxed)
+}
+
+/// Errors that can occur while assembling a program
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AssembleError {
+    /// Present when a line can't be parsed. Contains the line and column
+    /// numbers (starting at 1) and the error
+    InvalidLine((usize, usize, ParseError)),
+    /// Present when a label is defined more than once. Contains the line
+    /// number (starting at 1) and the label
+    DuplicateLabel((usize, String)),
+    /// Present when an instruction would start at an odd address. Contains
+    /// the line number (starting at 1) and the address
+    UnalignedInstruction((usize, u32)),
+    /// Present when .org would move the location counter backwards.
+    /// Contains the line number (starting at 1) and the address
+    InvalidOrigin((usize, u32)),
+    /// Present when a .macro has no matching .endm. Contains the line
+    /// number (starting at 1) of the .macro
+    UnterminatedMacro(usize),
+    /// Present when a macro is invoked with the wrong number of arguments.
+    /// Contains the line number (starting at 1) of the invocation and the
+    /// expected and actual number of arguments
+    MacroArguments((usize, usize, usize)),
+    /// Present when a line expanded from a macro can't be parsed. Contains
+    /// the line numbers (starting at 1) of the invocation and of the line
+    /// in the macro's definition and the error
+    InvalidExpansion((usize, usize, ParseError)),
+    /// Present when macros invoke each other too deeply (eg. a macro that
+    /// invokes itself). Contains the line number (starting at 1) of the
+    /// invocation and the macro
+    RecursiveMacro((usize, String)),
+}
+
+impl AssembleError {
+    /// Returns a stable numeric code for the error. Assemble errors use the
+    /// 600-699 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InvalidLine(_) => 600,
+            Self::DuplicateLabel(_) => 601,
+            Self::UnalignedInstruction(_) => 602,
+            Self::InvalidOrigin(_) => 603,
+            Self::UnterminatedMacro(_) => 604,
+            Self::MacroArguments(_) => 605,
+            Self::InvalidExpansion(_) => 606,
+            Self::RecursiveMacro(_) => 607,
+        }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLine((line, column, e)) => {
+                write!(f, "line {}, column {}: {}", line, column, e)
+            }
+            Self::DuplicateLabel((line, label)) => {
+                write!(f, "line {}: duplicate label {}", line, label)
+            }
+            Self::UnalignedInstruction((line, address)) => {
+                write!(
+                    f,
+                    "line {}: instruction at odd address {:#x}",
+                    line, address
+                )
+            }
+            Self::InvalidOrigin((line, address)) => {
+                write!(f, "line {}: .org {:#x} moves backwards", line, address)
+            }
+            Self::UnterminatedMacro(line) => write!(f, "line {}: .macro without .endm", line),
+            Self::MacroArguments((line, expected, found)) => write!(
+                f,
+                "line {}: expected {} macro arguments but found {}",
+                line, expected, found
+            ),
+            Self::InvalidExpansion((line, definition, e)) => write!(
+                f,
+                "line {}: in macro expansion from line {}: {}",
+                line, definition, e
+            ),
+            Self::RecursiveMacro((line, name)) => {
+                write!(f, "line {}: macro {} is nested too deeply", line, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidLine((_, _, e)) | Self::InvalidExpansion((_, _, e)) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A line of an assembler listing
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListingRow {
+    /// The line in the source. Lines expanded from a macro have the line of
+    /// the invocation
+    pub line: usize,
+    /// The address the line was assembled at
+    pub address: u32,
+    /// The bytes emitted by the line
+    pub bytes: Vec<u8>,
+    /// The text of the line after macro expansion
+    pub source: String,
+    /// The line in the macro definition the line was expanded from
+    pub definition: Option<usize>,
+}
+
+/// The number of words shown on each row of a rendered listing. Longer
+/// data continues on the following rows
+const LISTING_WORDS: usize = 3;
+
+/// Formats bytes as little endian words separated by spaces. A trailing odd
+/// byte is shown on its own
+fn words(bytes: &[u8]) -> String {
+    let words: Vec<String> = bytes
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [low, high] => format!("{:04x}", u16::from_le_bytes([*low, *high])),
+            _ => format!("{:02x}", chunk[0]),
+        })
+        .collect();
+    words.join(" ")
+}
+
+/// An assembled program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    /// The address of the first instruction
+    pub origin: u32,
+    /// The instructions and their addresses
+    pub instructions: Vec<(u32, Instruction)>,
+    /// The bytes emitted by data directives and their addresses
+    pub data: Vec<(u32, Vec<u8>)>,
+    /// The address of every label
+    pub symbols: BTreeMap<String, u32>,
+    /// One row per line of the source in the order it was assembled
+    pub listing: Vec<ListingRow>,
+    /// The line number (starting at 1) and address of every jump that was
+    /// relaxed to a branch because its label is out of range
+    pub relaxed: Vec<(usize, u32)>,
+}
+
+impl Program {
+    /// Returns the encoded instructions and data with their addresses in
+    /// address order. Operands are encoded exactly as they were assembled so
+    /// every instruction has its size
+    fn encoded(&self) -> Vec<(u32, Vec<u8>)> {
+        let options = EncodeOptions {
+            literal_immediates: true,
+            ..Default::default()
+        };
+        let mut encoded: Vec<(u32, Vec<u8>)> = self
+            .instructions
+            .iter()
+            .map(|(address, inst)| {
+                let bytes = inst
+                    .encode_with(&options)
+                    .expect("parsed instructions always encode");
+                (*address, bytes)
+            })
+            .chain(self.data.iter().cloned())
+            .collect();
+        encoded.sort_by_key(|(address, _)| *address);
+        encoded
+    }
+
+    /// Returns the machine code and data for the program starting at the
+    /// origin. Gaps left by .org are filled with zeros
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut output = vec![];
+        for (address, bytes) in self.encoded() {
+            let start = (address - self.origin) as usize;
+            if output.len() < start + bytes.len() {
+                output.resize(start + bytes.len(), 0);
+            }
+            output[start..start + bytes.len()].copy_from_slice(&bytes);
+        }
+
+        output
+    }
+
+    /// Returns the machine code and data for the program as contiguous
+    /// segments. Gaps left by .org start a new segment rather than being
+    /// filled so they aren't written when the image is flashed
+    pub fn segments(&self) -> Vec<Segment> {
+        let mut segments: Vec<Segment> = vec![];
+        for (address, bytes) in self.encoded() {
+            if bytes.is_empty() {
+                continue;
+            }
+
+            match segments.last_mut() {
+                Some(last) if last.address + last.data.len() as u32 == address => {
+                    last.data.extend(bytes)
+                }
+                _ => segments.push(Segment::new(address, bytes)),
+            }
+        }
+
+        segments
+    }
+
+    /// Serializes the program to Intel HEX
+    pub fn intel_hex(&self) -> String {
+        writer::intel_hex(&self.segments())
+    }
+
+    /// Serializes the program to TI-TXT
+    pub fn ti_txt(&self) -> String {
+        writer::ti_txt(&self.segments())
+    }
+
+    /// Renders the listing with one row per line showing the line number,
+    /// the address, the emitted bytes as little endian words and the source.
+    /// Lines that don't emit anything leave the address and words blank
+    pub fn render_listing(&self) -> String {
+        let mut output = String::new();
+        for row in &self.listing {
+            let mut chunks = row.bytes.chunks(LISTING_WORDS * 2);
+            let first = chunks.next();
+            let address = match first {
+                Some(_) => format!("{:04x}", row.address),
+                None => String::new(),
+            };
+            let text = format!(
+                "{:>5}  {:<4}  {:<14}  {}",
+                row.line,
+                address,
+                words(first.unwrap_or_default()),
+                row.source
+            );
+            let _ = writeln!(output, "{}", text.trim_end());
+
+            let mut address = row.address;
+            for chunk in chunks {
+                address += (LISTING_WORDS * 2) as u32;
+                let _ = writeln!(output, "{:>5}  {:04x}  {}", "", address, words(chunk));
+            }
+        }
+
+        output
+    }
+
+    /// Returns the instructions as listing lines tagged with the line they
+    /// were assembled from. Instructions expanded from a macro are also
+    /// tagged with the macro rule and relaxed jumps with the relax rule
+    pub fn lines(&self) -> Vec<Line> {
+        self.instructions
+            .iter()
+            .map(|(address, inst)| {
+                let row = self.listing.iter().find(|row| {
+                    (row.address..row.address + row.bytes.len() as u32).contains(address)
+                });
+                let provenance = row.map(|row| {
+                    let mut provenance = Provenance::assembled(row.line);
+                    if row.definition.is_some() {
+                        provenance = provenance.with_rule("macro");
+                    }
+                    if self.relaxed.contains(&(row.line, row.address)) {
+                        provenance = provenance.with_rule("relax");
+                    }
+                    provenance
+                });
+                Line {
+                    address: *address,
+                    size: inst.size(),
+                    instruction: Ok(*inst),
+                    hits: 0,
+                    provenance,
+                }
+            })
+            .collect()
+    }
+
+    /// Validates the target of every jump, call and branch with a known
+    /// target against the executable memory of the device. The errors are
+    /// returned with the address of the instruction
+    pub fn invalid_targets(&self, executable: &[Range<u32>]) -> Vec<(u32, TargetError)> {
+        target::validate_listing(&self.instructions, executable)
+    }
+}
+
+/// Splits a line into its labels and the instruction text. Comments start
+/// with ; and run to the end of the line
+fn split_labels(line: &str) -> (Vec<&str>, &str) {
+    let mut text = line.split(';').next().unwrap_or_default().trim();
+    let mut labels = vec![];
+    while let Some((label, rest)) = text.split_once(':') {
+        if !is_identifier(label.trim()) {
+            break;
+        }
+
+        labels.push(label.trim());
+        text = rest.trim();
+    }
+
+    (labels, text)
+}
+
+/// The maximum depth macros are expanded to which stops macros that invoke
+/// themselves
+const MACRO_DEPTH: usize = 16;
+
+/// A macro defined with .macro and .endm
+struct Macro {
+    parameters: Vec<String>,
+    /// The lines of the body and their line numbers
+    body: Vec<(usize, String)>,
+}
+
+/// A line of source once macros are expanded
+struct SourceLine {
+    text: String,
+    /// The line number (starting at 1) of the line or of the invocation it
+    /// was expanded from
+    line: usize,
+    /// The line number (starting at 1) of the line in the macro definition
+    /// it was expanded from
+    definition: Option<usize>,
+}
+
+/// Returns the operands of a statement when its first word is the directive
+fn directive<'a>(statement: &'a str, directive: &str) -> Option<&'a str> {
+    let (name, operands) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    name.eq_ignore_ascii_case(directive)
+        .then(|| operands.trim())
+}
+
+/// Expands a line, recursively expanding it when it invokes a macro.
+/// Parameters are substituted where they're written as `\name` and `\@` is
+/// replaced by a number unique to each expansion so labels in macros can
+/// be made unique
+fn invoke(
+    macros: &BTreeMap<String, Macro>,
+    source: SourceLine,
+    depth: usize,
+    expansions: &mut usize,
+    output: &mut Vec<SourceLine>,
+) -> Result<(), AssembleError> {
+    let (labels, statement) = split_labels(&source.text);
+    let (name, arguments) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+    let Some(definition) = macros.get(name) else {
+        output.push(source);
+        return Ok(());
+    };
+
+    if depth == MACRO_DEPTH {
+        return Err(AssembleError::RecursiveMacro((
+            source.line,
+            name.to_string(),
+        )));
+    }
+
+    let arguments: Vec<&str> = match arguments.trim() {
+        "" => vec![],
+        arguments => arguments.split(',').map(str::trim).collect(),
+    };
+    if arguments.len() != definition.parameters.len() {
+        return Err(AssembleError::MacroArguments((
+            source.line,
+            definition.parameters.len(),
+            arguments.len(),
+        )));
+    }
+
+    // labels on the invocation refer to the start of the expansion
+    if !labels.is_empty() {
+        output.push(SourceLine {
+            text: labels.iter().map(|label| format!("{}: ", label)).collect(),
+            line: source.line,
+            definition: source.definition,
+        });
+    }
+
+    // longer parameters are substituted first so \ab isn't replaced by \a
+    let mut substitutions: Vec<(&String, &str)> =
+        definition.parameters.iter().zip(arguments).collect();
+    substitutions.sort_by_key(|(parameter, _)| std::cmp::Reverse(parameter.len()));
+
+    *expansions += 1;
+    let expansion = expansions.to_string();
+    for (line, text) in &definition.body {
+        let mut text = text.replace("\\@", &expansion);
+        for (parameter, argument) in &substitutions {
+            text = text.replace(&format!("\\{}", parameter), argument);
+        }
+
+        let line = SourceLine {
+            text,
+            line: source.line,
+            definition: Some(*line),
+        };
+        invoke(macros, line, depth + 1, expansions, output)?;
+    }
+
+    Ok(())
+}
+
+/// Collects the macros defined in the source and expands their invocations
+fn expand(source: &str) -> Result<Vec<SourceLine>, AssembleError> {
+    let mut macros = BTreeMap::new();
+    let mut expansions = 0;
+    let mut output = vec![];
+    let mut lines = source.lines().enumerate();
+
+    while let Some((i, text)) = lines.next() {
+        let statement = split_labels(text).1;
+        let Some(signature) = directive(statement, ".macro") else {
+            let line = SourceLine {
+                text: text.to_string(),
+                line: i + 1,
+                definition: None,
+            };
+            invoke(&macros, line, 0, &mut expansions, &mut output)?;
+            continue;
+        };
+
+        let mut words = signature
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|word| !word.is_empty());
+        // a macro without a name would expand on every blank line
+        let name = match words.next() {
+            Some(name) if is_identifier(name) => name.to_string(),
+            name => {
+                let e = match name {
+                    Some(name) => ParseError::InvalidOperand(name.to_string()),
+                    None => ParseError::OperandCount((1, 0)),
+                };
+                return Err(AssembleError::InvalidLine((
+                    i + 1,
+                    column(text, statement, &e),
+                    e,
+                )));
+            }
+        };
+        let parameters = words.map(str::to_string).collect();
+
+        let mut body = vec![];
+        loop {
+            match lines.next() {
+                None => return Err(AssembleError::UnterminatedMacro(i + 1)),
+                Some((_, text)) if directive(split_labels(text).1, ".endm").is_some() => break,
+                Some((j, text)) => body.push((j + 1, text.to_string())),
+            }
+        }
+
+        macros.insert(name, Macro { parameters, body });
+    }
+
+    Ok(output)
+}
+
+/// Returns the column (starting at 1) of an error in a statement on a line.
+/// Errors about an operand or label point at it and other errors point at
+/// the start of the statement
+fn column(line: &str, statement: &str, error: &ParseError) -> usize {
+    // the statement is a slice of the line
+    let start = statement.as_ptr() as usize - line.as_ptr() as usize;
+    let needle = match error {
+        ParseError::InvalidOperand(text)
+        | ParseError::UndefinedSymbol(text)
+        | ParseError::JumpOutOfRange((text, _)) => Some(text.as_str()),
+        _ => None,
+    };
+
+    let is_identifier_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+    let operands = statement
+        .find(char::is_whitespace)
+        .unwrap_or(statement.len());
+    let offset = needle.and_then(|needle| {
+        statement[operands..]
+            .match_indices(needle)
+            .map(|(i, _)| operands + i)
+            .find(|i| {
+                let before = statement[..*i].chars().next_back();
+                let after = statement[i + needle.len()..].chars().next();
+                !before.into_iter().chain(after).any(is_identifier_char)
+            })
+    });
+
+    line[..start + offset.unwrap_or(0)].chars().count() + 1
+}
+
+/// Assembles a program with one instruction per line starting at origin.
+/// Lines may start with any number of labels (`loop:`) which can be used as
+/// jump targets (`jnz loop`), immediates (`call #func`) and absolute
+/// addresses (`mov &counter, r15`), on their own or in expressions that add
+/// or subtract numbers and other labels (`mov buffer+2(r4), r15`). Labels
+/// are resolved in a second pass so they may be used before they are
+/// defined. Immediates the constant generator can produce use the constant
+/// generator and errors report the line and column they occurred at
+///
+/// Data is emitted with directives which move the location counter the
+/// same way as instructions:
+///
+/// - `.org 0x4400` moves the location counter forward to an address
+/// - `.byte 1, 0xff` and `.word 0x1234, label` emit bytes and words
+/// - `.space 16, 0xff` emits a number of bytes of an optional fill value
+/// - `.align 2` pads with zeros to a multiple of the alignment
+///
+/// Macros are defined between `.macro name param, ...` and `.endm` and are
+/// invoked like an instruction (`name r4, #1`). Parameters are written as
+/// `\param` in the body and `\@` is replaced by a number that is unique to
+/// each expansion
+///
+/// Jumps to a label more than 512 words away are relaxed to a branch and
+/// reported in the program's relaxed jumps
+pub fn assemble(source: &str, origin: u32) -> Result<Program, AssembleError> {
+    assemble_with(source, origin, &EncodeOptions::default())
+}
+
+/// Assembles a program the same way as assemble using the encode options
+/// to choose between constants and literal immediates and whether jumps
+/// are relaxed
+pub fn assemble_with(
+    source: &str,
+    origin: u32,
+    options: &EncodeOptions,
+) -> Result<Program, AssembleError> {
+    let expanded = expand(source)?;
+    let lines: Vec<(&SourceLine, (Vec<&str>, &str))> = expanded
+        .iter()
+        .map(|source| (source, split_labels(&source.text)))
+        .collect();
+    let mut program = Program {
+        origin,
+        instructions: vec![],
+        data: vec![],
+        symbols: BTreeMap::new(),
+        listing: vec![],
+        relaxed: vec![],
+    };
+
+    // the lines with a jump that is relaxed. Relaxing a jump moves the
+    // labels after it so the program is laid out again until every jump is
+    // in range or relaxed
+    let mut relaxed = BTreeSet::new();
+    let literal = EncodeOptions {
+        literal_immediates: true,
+        ..Default::default()
+    };
+    'layout: loop {
+        program.instructions.clear();
+        program.data.clear();
+        program.symbols.clear();
+        program.listing.clear();
+        program.relaxed.clear();
+
+        // the first pass finds the address of every label which is possible
+        // because the size of a line doesn't depend on its labels
+        for pass in 0..2 {
+            let mut address = origin;
+            for (index, (source, (labels, text))) in lines.iter().enumerate() {
+                let line = source.line;
+                if pass == 0 {
+                    for label in labels {
+                        if program.symbols.insert(label.to_string(), address).is_some() {
+                            return Err(AssembleError::DuplicateLabel((line, label.to_string())));
+                        }
+                    }
+                }
+
+                if text.is_empty() {
+                    if pass == 1 {
+                        program.listing.push(ListingRow {
+                            line,
+                            address,
+                            bytes: vec![],
+                            source: source.text.clone(),
+                            definition: source.definition,
+                        });
+                    }
+                    continue;
+                }
+
+                let context = Context {
+                    address,
+                    symbols: (pass == 1).then_some(&program.symbols),
+                    literal_immediates: options.literal_immediates,
+                };
+                let statement = if relaxed.contains(&index) {
+                    relax(text, &context).map(Statement::Relaxed)
+                } else {
+                    statement(text, &context)
+                };
+                let statement = match statement {
+                    Err(ParseError::JumpOutOfRange(_)) if !options.strict_jumps => {
+                        relaxed.insert(index);
+                        continue 'layout;
+                    }
+                    statement => statement.map_err(|e| match source.definition {
+                        Some(definition) => AssembleError::InvalidExpansion((line, definition, e)),
+                        None => {
+                            AssembleError::InvalidLine((line, column(&source.text, text, &e), e))
+                        }
+                    })?,
+                };
+                let start = address;
+                let mut bytes = vec![];
+                match statement {
+                    Statement::Instruction(_) | Statement::Relaxed(_)
+                        if !address.is_multiple_of(2) =>
+                    {
+                        return Err(AssembleError::UnalignedInstruction((line, address)));
+                    }
+                    Statement::Instruction(inst) => {
+                        let size = inst.size() as u32;
+                        if pass == 1 {
+                            bytes = inst
+                                .encode_with(&literal)
+                                .expect("parsed instructions always encode");
+                            program.instructions.push((address, inst));
+                        }
+                        address += size;
+                    }
+                    Statement::Relaxed(instructions) => {
+                        if pass == 1 {
+                            program.relaxed.push((line, address));
+                        }
+                        for inst in instructions {
+                            let size = inst.size() as u32;
+                            if pass == 1 {
+                                bytes.extend(
+                                    inst.encode_with(&literal)
+                                        .expect("relaxed jumps always encode"),
+                                );
+                                program.instructions.push((address, inst));
+                            }
+                            address += size;
+                        }
+                    }
+                    Statement::Data(data) => {
+                        let size = data.len() as u32;
+                        if pass == 1 && size > 0 {
+                            bytes = data.clone();
+                            program.data.push((address, data));
+                        }
+                        address += size;
+                    }
+                    Statement::Org(target) if target < address => {
+                        return Err(AssembleError::InvalidOrigin((line, target)));
+                    }
+                    Statement::Org(target) => address = target,
+                }
+
+                if pass == 1 {
+                    program.listing.push(ListingRow {
+                        line,
+                        address: start,
+                        bytes,
+                        source: source.text.clone(),
+                        definition: source.definition,
+                    });
+                }
+            }
+        }
+
+        break;
+    }
+
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::provenance;
+
+    #[test]
+    fn from_str() {
+        let inst: Instruction = "add.b @r5+, 4(r6)".parse().unwrap();
+        assert_eq!(inst.encode(), Ok(vec![0xf6, 0x55, 0x04, 0x00]));
+        assert_eq!("ret".parse::<Instruction>(), parse("ret"));
+        assert!("add.b".parse::<Instruction>().is_err());
+    }
+
+    #[test]
+    fn jump_synonyms() {
+        for (synonym, name) in [("jne", "jnz"), ("jeq", "jz"), ("jnc", "jlo"), ("jhs", "jc")] {
+            assert_eq!(
+                parse(&format!("{} #-0x2", synonym)),
+                parse(&format!("{} #-0x2", name))
+            );
+        }
+        assert_eq!(parse("JNE #0x4"), Ok(Instruction::Jnz(Jnz::new(4))));
+    }
+
+    #[test]
+    fn display_round_trip() {
+        // every first word, including msp430x extension words and address
+        // instructions, followed by words that give every form its operands
+        // and by a register only instruction so extension words repeat
+        for trailer in [
+            [0xfa, 0xff, 0x00, 0x44, 0x34, 0x12],
+            [0x0e, 0x4f, 0, 0, 0, 0],
+        ] {
+            for word in 0..=u16::MAX {
+                let mut data = [0; 8];
+                data[..2].copy_from_slice(&word.to_le_bytes());
+                data[2..].copy_from_slice(&trailer);
+                let inst = match decode(&data) {
+                    Ok(inst) => inst,
+                    Err(_) => continue,
+                };
+
+                let text = inst.to_string();
+                let parsed =
+                    parse(&text).unwrap_or_else(|e| panic!("{:#06x} {}: {}", word, text, e));
+                assert_eq!(
+                    parsed.normalize(),
+                    inst.normalize(),
+                    "{:#06x} {}",
+                    word,
+                    text
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(
+            parse("mov.b #0x10, r15"),
+            Ok(Instruction::Mov(Mov::new(
+                Operand::Immediate(0x10),
+                OperandWidth::Byte,
+                Operand::RegisterDirect(15)
+            )))
+        );
+        assert_eq!(
+            parse("ADD @R5+, 4(r6)"),
+            Ok(Instruction::Add(Add::new(
+                Operand::RegisterIndirectAutoIncrement(5),
+                OperandWidth::Word,
+                Operand::Indexed {
+                    register: 6,
+                    offset: 4
+                }
+            )))
+        );
+        assert_eq!(
+            parse("push @r4+"),
+            Ok(Instruction::Push(Push::new(
+                Operand::RegisterIndirectAutoIncrement(4),
+                Some(OperandWidth::Word)
+            )))
+        );
+        assert_eq!(
+            parse("call &0x4410"),
+            Ok(Instruction::Call(Call::new(
+                Operand::Absolute(0x4410),
+                None
+            )))
+        );
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(
+            parse("cmp #-1, r15").unwrap().source(),
+            Some(&Operand::Constant(-1))
+        );
+        assert_eq!(
+            parse("cmp #0x4, r15").unwrap().source(),
+            Some(&Operand::Constant(4))
+        );
+    }
+
+    #[test]
+    fn emulated_mnemonics() {
+        let source = "
+            ret
+            nop
+            clrz
+            setn
+            eint
+            br #0x4400
+            pop r11
+            inc.b r15
+            inv &0x0200
+            rla r14
+            tst r13
+        ";
+        assert_eq!(
+            assemble(source, 0).unwrap().bytes(),
+            vec![
+                0x30, 0x41, 0x03, 0x43, 0x22, 0xc3, 0x22, 0xd2, 0x32, 0xd2, 0x30, 0x40, 0x00, 0x44,
+                0x3b, 0x41, 0x5f, 0x53, 0xb2, 0xe3, 0x00, 0x02, 0x0e, 0x5e, 0x0d, 0x93
+            ]
+        );
+        assert_eq!("setn".parse::<Instruction>().unwrap().to_string(), "setn");
+        assert_eq!(parse("ret r15"), Err(ParseError::OperandCount((0, 1))));
+        assert_eq!(
+            parse("br.b r15"),
+            Err(ParseError::InvalidWidth("br.b".to_string()))
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(parse("jnz #-0x6"), Ok(Instruction::Jnz(Jnz::new(-6))));
+        assert_eq!(parse("jmp 2"), Ok(Instruction::Jmp(Jmp::new(2))));
+        assert_eq!(
+            parse("jmp #0x200"),
+            Err(ParseError::Unencodable(EncodeError::JumpOutOfRange(0x200)))
+        );
+    }
+
+    #[test]
+    fn msp430x() {
+        let words = |text: &str| parse(text).unwrap().encode().unwrap();
+        // movx.a #0x12345, r15
+        assert_eq!(
+            words("movx.a #0x12345, r15"),
+            [0x80, 0x18, 0x7f, 0x40, 0x45, 0x23]
+        );
+        // rpt #4 rrax r15 and rrux r15
+        assert_eq!(words("rpt #4 rrax r15"), [0x43, 0x18, 0x0f, 0x11]);
+        assert_eq!(words("rrux r15"), [0x40, 0x19, 0x0f, 0x10]);
+        // mova &0x12345, r4 and adda #0xfffff, sp
+        assert_eq!(words("mova &0x12345, r4"), [0x24, 0x01, 0x45, 0x23]);
+        assert_eq!(words("adda #0xfffff, sp"), [0xa1, 0x0f, 0xff, 0xff]);
+
+        assert_eq!(
+            parse("rpt #4 movx @r4, r5"),
+            Err(ParseError::InvalidOperand("@r4, r5".to_string()))
+        );
+        assert_eq!(
+            parse("cmpa @r4, r5"),
+            Err(ParseError::Unencodable(EncodeError::InvalidSource(
+                Operand::RegisterIndirect(4)
+            )))
+        );
+        assert_eq!(
+            parse("swpbx.b r4"),
+            Err(ParseError::InvalidWidth("swpbx.b".to_string()))
+        );
+        assert_eq!(
+            parse("jmpx #0x2"),
+            Err(ParseError::UnknownMnemonic("jmpx".to_string()))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("mvo r4, r5"),
+            Err(ParseError::UnknownMnemonic("mvo".to_string()))
+        );
+        assert_eq!(
+            parse("swpb.b r4"),
+            Err(ParseError::InvalidWidth("swpb.b".to_string()))
+        );
+        assert_eq!(parse("mov r4"), Err(ParseError::OperandCount((2, 1))));
+        assert_eq!(
+            parse("mov r16, r4"),
+            Err(ParseError::InvalidOperand("r16".to_string()))
+        );
+        assert_eq!(
+            parse("mov #0x-5, r4"),
+            Err(ParseError::InvalidOperand("#0x-5".to_string()))
+        );
+        assert_eq!(
+            parse("mov #--5, r4"),
+            Err(ParseError::InvalidOperand("#--5".to_string()))
+        );
+        assert_eq!(
+            parse("mov r4, #0x10"),
+            Err(ParseError::Unencodable(EncodeError::InvalidDestination(
+                Operand::Immediate(0x10)
+            )))
+        );
+
+        let error = parse("mov r4, #0x10").unwrap_err();
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(
+            source.to_string(),
+            EncodeError::InvalidDestination(Operand::Immediate(0x10)).to_string()
+        );
+        assert!(std::error::Error::source(&ParseError::OperandCount((2, 1))).is_none());
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            start:  mov #0x4400, sp
+                    call #func      ; forward reference
+            loop:   sub #1, r15
+                    jnz loop
+                    jmp start
+            func:   mov &start, r14
+        ";
+        let program = assemble(source, 0x4400).unwrap();
+        assert_eq!(program.symbols["func"], 0x440e);
+        assert_eq!(
+            program
+                .instructions
+                .iter()
+                .map(|(address, inst)| format!("{:04x} {}", address, inst))
+                .collect::<Vec<_>>(),
+            vec![
+                "4400 mov #0x4400, sp",
+                "4404 call #0x440e",
+                "4408 dec r15",
+                "440a jnz #-0x2",
+                "440c jmp #-0x7",
+                "440e mov &0x4400, r14",
+            ]
+        );
+        assert_eq!(&program.bytes()[4..8], &[0xb0, 0x12, 0x0e, 0x44]);
+    }
+
+    #[test]
+    fn labels_small_values() {
+        // labels are always encoded with a word even if the value could come
+        // from the constant generator
+        let program = assemble("zero: mov #zero, r15", 0).unwrap();
+        assert_eq!(program.bytes(), vec![0x3f, 0x40, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn literal_immediates() {
+        let source = "mov #4, r15\nmov #0x5, r15";
+        assert_eq!(
+            assemble(source, 0).unwrap().bytes(),
+            vec![0x2f, 0x42, 0x3f, 0x40, 0x05, 0x00]
+        );
+
+        let options = EncodeOptions {
+            literal_immediates: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            assemble_with(source, 0, &options).unwrap().bytes(),
+            vec![0x3f, 0x40, 0x04, 0x00, 0x3f, 0x40, 0x05, 0x00]
+        );
+    }
+
+    #[test]
+    fn directives() {
+        let source = "
+                    mov &table, r14
+                    mov @sp+, pc
+            table:  .word 0x1234, end
+                    .byte 1, -1
+            name:   .byte 0x41
+                    .align 2
+                    .space 2, 0xff
+                    .org 0x10
+            end:    reti
+        ";
+        let program = assemble(source, 0).unwrap();
+        assert_eq!(program.symbols["table"], 0x6);
+        assert_eq!(program.symbols["name"], 0xc);
+        assert_eq!(program.symbols["end"], 0x10);
+        assert_eq!(
+            program.bytes(),
+            vec![
+                0x1e, 0x42, 0x06, 0x00, 0x30, 0x41, 0x34, 0x12, 0x10, 0x00, 0x01, 0xff, 0x41, 0x00,
+                0xff, 0xff, 0x00, 0x13
+            ]
+        );
+    }
+
+    #[test]
+    fn segments() {
+        let source = "
+                    mov @sp+, pc
+                    .org 0xfffe
+                    .word 0x4400
+        ";
+        let program = assemble(source, 0x4400).unwrap();
+        assert_eq!(
+            program.segments(),
+            vec![
+                Segment::new(0x4400, vec![0x30, 0x41]),
+                Segment::new(0xfffe, vec![0x00, 0x44]),
+            ]
+        );
+        assert_eq!(program.ti_txt(), "@4400\n30 41\n@FFFE\n00 44\nq\n");
+        assert_eq!(
+            program.intel_hex(),
+            ":02440000304149\n:02FFFE000044BD\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let source = "start:  mov #0x4400, sp ; stack
+        .space 8, 0xff
+
+        .byte 1
+        ";
+        let program = assemble(source, 0x4400).unwrap();
+        assert_eq!(
+            program.listing[0],
+            ListingRow {
+                line: 1,
+                address: 0x4400,
+                bytes: vec![0x31, 0x40, 0x00, 0x44],
+                source: "start:  mov #0x4400, sp ; stack".to_string(),
+                definition: None,
+            }
+        );
+        assert_eq!(
+            program.render_listing(),
+            concat!(
+                "    1  4400  4031 4400       start:  mov #0x4400, sp ; stack\n",
+                "    2  4404  ffff ffff ffff          .space 8, 0xff\n",
+                "       440a  ffff\n",
+                "    3\n",
+                "    4  440c  01                      .byte 1\n",
+                "    5\n",
+            )
+        );
+    }
+
+    #[test]
+    fn directive_errors() {
+        assert_eq!(
+            assemble(".byte 1\nreti", 0),
+            Err(AssembleError::UnalignedInstruction((2, 1)))
+        );
+        assert_eq!(
+            assemble(".org 0x10\n.org 0x8", 0),
+            Err(AssembleError::InvalidOrigin((2, 8)))
+        );
+        assert_eq!(
+            assemble(".byte 0x100", 0),
+            Err(AssembleError::InvalidLine((
+                1,
+                7,
+                ParseError::InvalidOperand("0x100".to_string())
+            )))
+        );
+        assert_eq!(
+            assemble(".long 1", 0),
+            Err(AssembleError::InvalidLine((
+                1,
+                1,
+                ParseError::UnknownMnemonic(".long".to_string())
+            )))
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let source = "
+                    mov #buffer+2, r15
+                    mov TOP-4(r4), &buffer - 0x2
+                    jmp end-2
+                    reti
+            end:    .word buffer+1, -buffer
+            buffer: .space 4
+            TOP:
+        ";
+        let program = assemble(source, 0x200).unwrap();
+        assert_eq!(
+            program
+                .instructions
+                .iter()
+                .map(|(_, inst)| inst.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "mov #0x214, r15",
+                "mov 0x212(r4), &0x210",
+                "jmp #0x0",
+                "reti"
+            ]
+        );
+        assert_eq!(program.data[0], (0x20e, vec![0x13, 0x02, 0xee, 0xfd]));
+    }
+
+    #[test]
+    fn error_columns() {
+        let error = assemble("start: mov #start, r4\n  mov &start+2, &bufer", 0).unwrap_err();
+        assert_eq!(
+            error,
+            AssembleError::InvalidLine((2, 18, ParseError::UndefinedSymbol("bufer".to_string())))
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 2, column 18: undefined symbol bufer"
+        );
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "undefined symbol bufer");
+
+        assert_eq!(
+            assemble("r4: mov r4, r16", 0),
+            Err(AssembleError::InvalidLine((
+                1,
+                13,
+                ParseError::InvalidOperand("r16".to_string())
+            )))
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = r"
+            .macro  set_bits bits, register
+                    bis.b #\bits, &\register
+            .endm
+            .macro  wait count
+                    mov #\count, r15
+            w\@:    dec r15
+                    jnz w\@
+            .endm
+            start:  set_bits 0x41, 0x22
+                    wait 10
+                    wait 20
+        ";
+        let program = assemble(source, 0x4400).unwrap();
+        assert_eq!(program.symbols["start"], 0x4400);
+        assert_eq!(program.symbols["w2"], 0x440a);
+        assert_eq!(program.symbols["w3"], 0x4412);
+        assert_eq!(
+            program
+                .instructions
+                .iter()
+                .map(|(_, inst)| inst.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "bis.b #0x41, &0x22",
+                "mov #0xa, r15",
+                "dec r15",
+                "jnz #-0x2",
+                "mov #0x14, r15",
+                "dec r15",
+                "jnz #-0x2",
+            ]
+        );
+    }
+
+    #[test]
+    fn provenance() {
+        let source = ".macro zero r\nclr \\r\n.endm\nstart: nop\nzero r15";
+        let program = assemble(source, 0x4400).unwrap();
+        assert_eq!(
+            provenance::export(&program.lines()),
+            "4400  from line 4\n4402  from line 5 by macro\n"
+        );
+    }
+
+    #[test]
+    fn macro_errors() {
+        let source = ".macro push2 a, b\npush \\a\npush \\b\n.endm\npush2 r4, r55";
+        let error = assemble(source, 0).unwrap_err();
+        assert_eq!(
+            error,
+            AssembleError::InvalidExpansion((5, 3, ParseError::InvalidOperand("r55".to_string())))
+        );
+        assert_eq!(
+            error.to_string(),
+            "line 5: in macro expansion from line 3: invalid operand r55"
+        );
+
+        assert_eq!(
+            assemble(".macro push2 a, b\n.endm\npush2 r4", 0),
+            Err(AssembleError::MacroArguments((3, 2, 1)))
+        );
+        assert_eq!(
+            assemble("nop\n.macro forever\nreti", 0),
+            Err(AssembleError::UnterminatedMacro(2))
+        );
+        assert_eq!(
+            assemble(".macro forever\nforever\n.endm\nforever", 0),
+            Err(AssembleError::RecursiveMacro((4, "forever".to_string())))
+        );
+        assert_eq!(
+            assemble(".macro\nnop\n.endm\n\nnop\n", 0),
+            Err(AssembleError::InvalidLine((
+                1,
+                1,
+                ParseError::OperandCount((1, 0))
+            )))
+        );
+        assert_eq!(
+            assemble(".macro 2x\nnop\n.endm", 0),
+            Err(AssembleError::InvalidLine((
+                1,
+                8,
+                ParseError::InvalidOperand("2x".to_string())
+            )))
+        );
+    }
+
+    #[test]
+    fn invalid_targets() {
+        let program = assemble("call #0x4401\nbr #0x0200\nloop: jmp loop", 0x4400).unwrap();
+        assert_eq!(
+            program.invalid_targets(&[0x4400..0xff80, 0x10000..0x14400]),
+            vec![
+                (0x4400, TargetError::Unaligned(0x4401)),
+                (0x4404, TargetError::NotExecutable(0x0200))
+            ]
+        );
+    }
+
+    #[test]
+    fn relaxed_jumps() {
+        let source = format!(
+            "jnz far\njn far\n{}far: reti\njmp far",
+            "mov #0x4400, r15\n".repeat(300)
+        );
+        let program = assemble(&source, 0x4400).unwrap();
+        assert_eq!(program.symbols["far"], 0x48be);
+        assert_eq!(program.relaxed, vec![(1, 0x4400), (2, 0x4406)]);
+        // jz #0x4; br #0x48be; jn #0x2; jmp #0x4; br #0x48be
+        assert_eq!(
+            program.bytes()[..14],
+            [0x02, 0x24, 0x30, 0x40, 0xbe, 0x48, 0x01, 0x30, 0x02, 0x3c, 0x30, 0x40, 0xbe, 0x48]
+        );
+        // the jump back to far is in range
+        assert_eq!(program.bytes()[0x4be..], [0x00, 0x13, 0xfe, 0x3f]);
+        assert_eq!(
+            program.lines()[1].provenance,
+            Some(Provenance::assembled(1).with_rule("relax"))
+        );
+
+        let program = assemble(&source.replace("jnz", "jmp"), 0x4400).unwrap();
+        assert_eq!(program.bytes()[..4], [0x30, 0x40, 0xbc, 0x48]);
+    }
+
+    #[test]
+    fn label_errors() {
+        assert_eq!(
+            assemble("jmp nowhere", 0x4400),
+            Err(AssembleError::InvalidLine((
+                1,
+                5,
+                ParseError::UndefinedSymbol("nowhere".to_string())
+            )))
+        );
+        assert_eq!(
+            assemble("a: nop_line:\na: reti", 0x4400),
+            Err(AssembleError::DuplicateLabel((2, "a".to_string())))
+        );
+
+        let far = format!("jmp far\n{}far: reti", "mov #0x4400, r15\n".repeat(300));
+        let strict = EncodeOptions {
+            strict_jumps: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            assemble_with(&far, 0x4400, &strict),
+            Err(AssembleError::InvalidLine((
+                1,
+                5,
+                ParseError::JumpOutOfRange(("far".to_string(), 1200))
+            )))
+        );
+    }
+}
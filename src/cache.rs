@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+
+use crate::decode_error::DecodeError;
+use crate::instruction::Instruction;
+use crate::Result;
+
+/// The most words an instruction can be encoded in (an MSP430X extension
+/// word followed by a format I instruction with two additional words)
+const MAX_WORDS: usize = 4;
+
+/// Memoizes decode for analyses that decode the same addresses or encodings
+/// repeatedly (eg. speculative decoding or gadget search). An instruction
+/// only depends on the words it is encoded in so it is cached under just
+/// those (the first word and any extension words) whatever follows them.
+/// Errors can depend on how much data is left (eg. a missing source word)
+/// so they are cached under every available word up to the four an
+/// instruction can use
+#[derive(Debug, Clone, Default)]
+pub struct CachedDecoder {
+    instructions: HashMap<([u16; MAX_WORDS], usize), Instruction>,
+    errors: HashMap<([u16; MAX_WORDS], usize), DecodeError>,
+    hits: usize,
+    misses: usize,
+}
+
+impl CachedDecoder {
+    pub fn new() -> CachedDecoder {
+        CachedDecoder::default()
+    }
+
+    /// Decodes the next instruction in data, returning the cached result if
+    /// the same words have been decoded before
+    pub fn decode(&mut self, data: &[u8]) -> Result<Instruction> {
+        let available = (data.len() / 2).min(MAX_WORDS);
+        let mut words = [0; MAX_WORDS];
+        for (word, bytes) in words.iter_mut().zip(data.chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        // the first len words with the rest cleared so keys of the same
+        // length only differ by the words they use
+        let key = |len: usize| {
+            let mut key = [0; MAX_WORDS];
+            key[..len].copy_from_slice(&words[..len]);
+            (key, len)
+        };
+
+        // an instruction cached under a prefix of the words decodes the
+        // same whatever follows it
+        let cached = (1..=available).find_map(|len| self.instructions.get(&key(len)));
+        if let Some(inst) = cached {
+            self.hits += 1;
+            return Ok(*inst);
+        }
+        if let Some(e) = self.errors.get(&key(available)) {
+            self.hits += 1;
+            return Err(*e);
+        }
+
+        self.misses += 1;
+        let result = crate::decode(&data[..available * 2]);
+        match result {
+            Ok(inst) => {
+                self.instructions.insert(key(inst.size() / 2), inst);
+            }
+            Err(e) => {
+                self.errors.insert(key(available), e);
+            }
+        }
+        result
+    }
+
+    /// Returns the number of decodes that were answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Returns the number of decodes that had to be decoded
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Returns the fraction of decodes that were answered from the cache
+    pub fn hit_rate(&self) -> f32 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f32 / total as f32,
+        }
+    }
+
+    /// Returns the number of distinct encodings in the cache
+    pub fn len(&self) -> usize {
+        self.instructions.len() + self.errors.len()
+    }
+
+    /// Returns whether nothing has been cached yet (or since the last clear)
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty() && self.errors.is_empty()
+    }
+
+    /// Empties the cache and resets the statistics
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+        self.errors.clear();
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hits() {
+        let mut decoder = CachedDecoder::new();
+        // mov #0x4400, sp followed by different trailing data
+        let first = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let second = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41, 0xff];
+        assert_eq!(decoder.decode(&first), crate::decode(&first));
+        assert_eq!(decoder.decode(&second), crate::decode(&second));
+        assert_eq!(decoder.decode(&first[4..]), crate::decode(&first[4..]));
+        assert_eq!((decoder.hits(), decoder.misses()), (1, 2));
+        assert_eq!(decoder.len(), 2);
+        assert!((decoder.hit_rate() - 1.0 / 3.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn unused_words() {
+        let mut decoder = CachedDecoder::new();
+        // ret followed by different words and movx 0x2(r5), 0x4(r4) whose
+        // words are all used
+        assert!(decoder.decode(&[0x30, 0x41, 0x00, 0x00]).is_ok());
+        assert!(decoder
+            .decode(&[0x30, 0x41, 0xff, 0xff, 0x12, 0x34])
+            .is_ok());
+        let movx = [0x40, 0x18, 0x94, 0x45, 0x02, 0x00, 0x04, 0x00];
+        assert!(decoder.decode(&movx).is_ok());
+        assert!(decoder
+            .decode(&[movx.as_slice(), &[0x30, 0x41]].concat())
+            .is_ok());
+        assert_eq!((decoder.hits(), decoder.misses()), (2, 2));
+        assert_eq!(decoder.len(), 2);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut decoder = CachedDecoder::new();
+        // mov #0x4400, sp without the immediate is a different result
+        assert!(decoder.decode(&[0x31, 0x40, 0x00, 0x44]).is_ok());
+        assert_eq!(
+            decoder.decode(&[0x31, 0x40]),
+            Err(DecodeError::MissingSource)
+        );
+        assert_eq!(decoder.hits(), 0);
+    }
+
+    #[test]
+    fn extended() {
+        let mut decoder = CachedDecoder::new();
+        // movx 0x2(r5), 0x4(r4)
+        let data = [0x40, 0x18, 0x94, 0x45, 0x02, 0x00, 0x04, 0x00];
+        let inst = decoder.decode(&data).unwrap();
+        assert_eq!(Ok(inst), crate::decode(&data));
+        assert_eq!(inst.size(), 8);
+    }
+
+    #[test]
+    fn clear() {
+        let mut decoder = CachedDecoder::new();
+        let _ = decoder.decode(&[0x30, 0x41]);
+        let _ = decoder.decode(&[0x30, 0x41]);
+        decoder.clear();
+        assert!(decoder.is_empty());
+        assert_eq!(decoder.hit_rate(), 0.0);
+    }
+}
@@ -0,0 +1,195 @@
+//! A versioned text serialization of instructions for golden files and
+//! snapshot tests. Unlike Display, the output for a given VERSION never
+//! changes. Changes to the format are made under a new version
+//!
+//! Format version 1:
+//!
+//! - mnemonics are lower case and instructions with operands always carry
+//!   an explicit .w or .b width
+//! - registers are always r0-r15
+//! - immediates are unsigned with four hex digits (#0x00ff) and constant
+//!   generator values are signed decimal (#-1) so the two encodings differ
+//! - absolute addresses are written &0x0200 and symbolic operands are
+//!   written as indexed from r0 (0x10(r0))
+//! - jump offsets are signed decimal words (jnz -2)
+
+use std::fmt::Write;
+
+use crate::format::operands;
+use crate::instruction::Instruction;
+use crate::listing::Line;
+use crate::operand::{Operand, OperandWidth};
+
+/// The current version of the canonical format
+pub const VERSION: u32 = 1;
+
+/// The prefix of the header line that starts a canonical listing
+const HEADER: &str = "; msp430-asm canonical v";
+
+fn operand(output: &mut String, operand: &Operand) {
+    let _ = match operand {
+        Operand::RegisterDirect(r) => write!(output, "r{}", r),
+        Operand::Indexed {
+            register: r,
+            offset: i,
+        } if *i < 0 => write!(output, "-{:#x}(r{})", i.unsigned_abs(), r),
+        Operand::Indexed {
+            register: r,
+            offset: i,
+        } => write!(output, "{:#x}(r{})", i, r),
+        Operand::RegisterIndirect(r) => write!(output, "@r{}", r),
+        Operand::RegisterIndirectAutoIncrement(r) => write!(output, "@r{}+", r),
+        Operand::Symbolic(i) if *i < 0 => write!(output, "-{:#x}(r0)", i.unsigned_abs()),
+        Operand::Symbolic(i) => write!(output, "{:#x}(r0)", i),
+        Operand::Immediate(i) => write!(output, "#0x{:04x}", i),
+        Operand::Absolute(a) => write!(output, "&0x{:04x}", a),
+        Operand::Constant(i) => write!(output, "#{}", i),
+    };
+}
+
+/// Serializes an instruction in the current canonical format
+pub fn canonical(inst: &Instruction) -> String {
+    let mut output = String::from(inst.mnemonic().trim_end_matches(".b"));
+
+    if let Some(offset) = inst.jump_offset() {
+        let _ = write!(output, " {}", offset);
+        return output;
+    }
+
+    let (source, destination) = operands(inst);
+    if source.is_none() && destination.is_none() {
+        return output;
+    }
+
+    // emulated instructions don't always carry a width of their own so the
+    // width of the original instruction is used
+    let width = match inst.operand_width() {
+        Some(OperandWidth::Byte) => ".b",
+        _ => ".w",
+    };
+    output.push_str(width);
+
+    if let Some(source) = source {
+        output.push(' ');
+        operand(&mut output, source);
+    }
+
+    if let Some(destination) = destination {
+        output.push_str(if source.is_some() { ", " } else { " " });
+        operand(&mut output, destination);
+    }
+
+    output
+}
+
+/// Serializes a listing in the current canonical format. The first line is
+/// a header with the format version followed by one line per instruction.
+/// Lines that failed to decode are written with the stable error code
+/// rather than the error message
+pub fn listing(lines: &[Line]) -> String {
+    let mut output = format!("{}{}\n", HEADER, VERSION);
+    for line in lines {
+        let _ = match &line.instruction {
+            Ok(inst) => writeln!(output, "{:08x}  {}", line.address, canonical(inst)),
+            Err(e) => writeln!(output, "{:08x}  ; error {}", line.address, e.code()),
+        };
+    }
+
+    output
+}
+
+/// Returns the format version of a canonical listing or None when the text
+/// doesn't start with a header
+pub fn version(text: &str) -> Option<u32> {
+    text.lines().next()?.strip_prefix(HEADER)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::listing::sweep;
+
+    fn serialize(data: &[u8]) -> String {
+        canonical(&decode(data).unwrap())
+    }
+
+    #[test]
+    fn two_operand() {
+        // mov #0x4400, sp
+        assert_eq!(serialize(&[0x31, 0x40, 0x00, 0x44]), "mov.w #0x4400, r1");
+        // mov.b @r15+, 0x4(r14)
+        assert_eq!(
+            serialize(&[0xfe, 0x4f, 0x04, 0x00]),
+            "mov.b @r15+, 0x4(r14)"
+        );
+        // add #-1, r15
+        assert_eq!(serialize(&[0x3f, 0x53]), "add.w #-1, r15");
+        // cmp #-1, r15 encoded as an immediate
+        assert_eq!(serialize(&[0x3f, 0x90, 0xff, 0xff]), "cmp.w #0xffff, r15");
+        // mov &0x0120, r9
+        assert_eq!(serialize(&[0x19, 0x42, 0x20, 0x01]), "mov.w &0x0120, r9");
+    }
+
+    #[test]
+    fn emulated() {
+        // ret
+        assert_eq!(serialize(&[0x30, 0x41]), "ret");
+        // inc.b r15
+        assert_eq!(serialize(&[0x5f, 0x53]), "inc.b r15");
+        // pop r11
+        assert_eq!(serialize(&[0x3b, 0x41]), "pop.w r11");
+    }
+
+    #[test]
+    fn every_emulated() {
+        // every emulated mnemonic in the stable format
+        let cases = [
+            ([0x0f, 0x63], "adc.w r15"),
+            ([0x00, 0x4f], "br.w r15"),
+            ([0x0f, 0x43], "clr.w r15"),
+            ([0x12, 0xc3], "clrc"),
+            ([0x22, 0xc2], "clrn"),
+            ([0x22, 0xc3], "clrz"),
+            ([0x0f, 0xa3], "dadc.w r15"),
+            ([0x1f, 0x83], "dec.w r15"),
+            ([0x2f, 0x83], "decd.w r15"),
+            ([0x32, 0xc2], "dint"),
+            ([0x32, 0xd2], "eint"),
+            ([0x1f, 0x53], "inc.w r15"),
+            ([0x2f, 0x53], "incd.w r15"),
+            ([0x3f, 0xe3], "inv.w r15"),
+            ([0x03, 0x43], "nop"),
+            ([0x3b, 0x41], "pop.w r11"),
+            ([0x30, 0x41], "ret"),
+            ([0x0f, 0x5f], "rla.w r15"),
+            ([0x0f, 0x6f], "rlc.w r15"),
+            ([0x0f, 0x73], "sbc.w r15"),
+            ([0x12, 0xd3], "setc"),
+            ([0x22, 0xd2], "setn"),
+            ([0x22, 0xd3], "setz"),
+            ([0x0f, 0x93], "tst.w r15"),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(serialize(&data), expected);
+        }
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(serialize(&[0xfe, 0x23]), "jnz -2");
+        assert_eq!(serialize(&[0x01, 0x3c]), "jmp 1");
+    }
+
+    #[test]
+    fn versioned_listing() {
+        // push r11; <invalid>
+        let text = listing(&sweep(&[0x0b, 0x12, 0x80, 0x13], 0x4400));
+        assert_eq!(
+            text,
+            "; msp430-asm canonical v1\n00004400  push.w r11\n00004402  ; error 6\n"
+        );
+        assert_eq!(version(&text), Some(VERSION));
+        assert_eq!(version("00004400  push.w r11\n"), None);
+    }
+}
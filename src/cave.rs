@@ -0,0 +1,144 @@
+/// A run of unused flash (erased 0xff or zeroed 0x00 bytes) that is large
+/// enough to host injected code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cave {
+    /// The address of the first byte of the cave. This is always word
+    /// aligned so that instructions can be placed at it directly
+    pub address: u32,
+    /// The size of the cave (in bytes). This is always a multiple of the
+    /// word size
+    pub size: usize,
+    /// The byte value that fills the cave
+    pub fill: u8,
+}
+
+/// Scans an image for runs of 0xff or 0x00 bytes that are at least
+/// min_size bytes long and returns them as candidate caves. base is the
+/// address the first byte of the image is loaded at.
+///
+/// Caves are ranked with the largest first. Erased flash (0xff) is preferred
+/// over zeroed flash when two caves are the same size because a run of 0x00
+/// is more likely to be initialized data than unused space. Any remaining
+/// ties are broken by address
+pub fn find_caves(image: &[u8], base: u32, min_size: usize) -> Vec<Cave> {
+    let mut caves = vec![];
+    let mut start = 0;
+
+    while start < image.len() {
+        let fill = image[start];
+        let end = image[start..]
+            .iter()
+            .position(|b| *b != fill)
+            .map_or(image.len(), |len| start + len);
+
+        if fill == 0xff || fill == 0x00 {
+            // instructions must be word aligned so trim the run down to the
+            // largest aligned region it contains
+            let address = base as usize + start;
+            let aligned_start = start + (address & 1);
+            let size = end.saturating_sub(aligned_start) & !1;
+            if size > 0 && size >= min_size {
+                caves.push(Cave {
+                    address: (base as usize + aligned_start) as u32,
+                    size,
+                    fill,
+                });
+            }
+        }
+
+        start = end;
+    }
+
+    caves.sort_by(|a, b| {
+        b.size
+            .cmp(&a.size)
+            .then_with(|| b.fill.cmp(&a.fill))
+            .then_with(|| a.address.cmp(&b.address))
+    });
+
+    caves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_image() {
+        assert_eq!(find_caves(&[], 0x4400, 2), vec![]);
+    }
+
+    #[test]
+    fn no_caves() {
+        let image = [0x31, 0x40, 0x00, 0x44];
+        assert_eq!(find_caves(&image, 0x4400, 2), vec![]);
+    }
+
+    #[test]
+    fn ranked_by_size() {
+        let image = [
+            0x31, 0x40, 0xff, 0xff, 0x00, 0x44, 0xff, 0xff, 0xff, 0xff, 0x30, 0x41,
+        ];
+        assert_eq!(
+            find_caves(&image, 0x4400, 2),
+            vec![
+                Cave {
+                    address: 0x4406,
+                    size: 4,
+                    fill: 0xff
+                },
+                Cave {
+                    address: 0x4402,
+                    size: 2,
+                    fill: 0xff
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn erased_preferred() {
+        let image = [0x00, 0x00, 0x30, 0x41, 0xff, 0xff];
+        assert_eq!(
+            find_caves(&image, 0x4400, 2),
+            vec![
+                Cave {
+                    address: 0x4404,
+                    size: 2,
+                    fill: 0xff
+                },
+                Cave {
+                    address: 0x4400,
+                    size: 2,
+                    fill: 0x00
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn minimum_size() {
+        let image = [0xff, 0xff, 0x30, 0x41, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(
+            find_caves(&image, 0x4400, 4),
+            vec![Cave {
+                address: 0x4404,
+                size: 4,
+                fill: 0xff
+            }]
+        );
+    }
+
+    #[test]
+    fn unaligned_run() {
+        let image = [0x30, 0xff, 0xff, 0xff, 0xff, 0x41];
+        assert_eq!(
+            find_caves(&image, 0x4400, 2),
+            vec![Cave {
+                address: 0x4402,
+                size: 2,
+                fill: 0xff
+            }]
+        );
+    }
+}
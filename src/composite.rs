@@ -0,0 +1,273 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::operand::{Operand, OperandWidth};
+use crate::two_operand::TwoOperand;
+
+/// The operation a composite implements
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompositeKind {
+    Add,
+    Sub,
+    /// A compare of the least significant words followed by subc of the
+    /// others, which sets the flags for the whole value
+    Cmp,
+}
+
+/// A multi-word operation implemented by a chain of instructions that
+/// propagate the carry (eg. add followed by addc for 32-bit addition or cmp
+/// followed by subc for a 32-bit compare)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composite {
+    /// The address of the first instruction in the chain
+    pub address: u32,
+    pub kind: CompositeKind,
+    /// The source and destination of each instruction in the chain from the
+    /// least significant word to the most significant
+    pub parts: Vec<(Operand, Operand)>,
+}
+
+impl Composite {
+    /// Returns the width of the operation in bits
+    pub fn bits(&self) -> usize {
+        self.parts.len() * 16
+    }
+}
+
+impl fmt::Display for Composite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mnemonic = match self.kind {
+            CompositeKind::Add => "add",
+            CompositeKind::Sub => "sub",
+            CompositeKind::Cmp => "cmp",
+        };
+
+        write!(f, "{}{} ", mnemonic, self.bits())?;
+        for (i, (source, _)) in self.parts.iter().rev().enumerate() {
+            let separator = if i == 0 { "" } else { ":" };
+            write!(f, "{}{}", separator, source)?;
+        }
+
+        write!(f, ", ")?;
+        for (i, (_, destination)) in self.parts.iter().rev().enumerate() {
+            let separator = if i == 0 { "" } else { ":" };
+            write!(f, "{}{}", separator, destination)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the kind, whether the instruction consumes the carry and the
+/// operands for word sized instructions that can be part of a chain.
+/// Emulated adc and sbc are carry only adds of #0
+fn link(inst: &Instruction) -> Option<(CompositeKind, bool, Operand, Operand)> {
+    let (kind, carry, inst): (_, _, &dyn TwoOperand) = match inst {
+        Instruction::Add(inst) => (CompositeKind::Add, false, inst),
+        Instruction::Addc(inst) => (CompositeKind::Add, true, inst),
+        Instruction::Adc(inst) => (CompositeKind::Add, true, inst.original()),
+        Instruction::Sub(inst) => (CompositeKind::Sub, false, inst),
+        Instruction::Subc(inst) => (CompositeKind::Sub, true, inst),
+        Instruction::Sbc(inst) => (CompositeKind::Sub, true, inst.original()),
+        Instruction::Cmp(inst) => (CompositeKind::Cmp, false, inst),
+        _ => return None,
+    };
+
+    match inst.operand_width() {
+        OperandWidth::Word => Some((kind, carry, *inst.source(), *inst.destination())),
+        OperandWidth::Byte => None,
+    }
+}
+
+/// Returns whether next is the word after previous in a multi-word value.
+/// Registers hold consecutive words in consecutive registers (r12 and r13)
+/// and memory holds them at consecutive addresses. Any immediate can be
+/// the next word (eg. the #0 of adc extending a 16-bit value)
+fn next_word(previous: &Operand, next: &Operand) -> bool {
+    match (previous, next) {
+        (_, Operand::Immediate(_) | Operand::Constant(_)) => true,
+        (Operand::RegisterDirect(a), Operand::RegisterDirect(b)) => *b == a + 1,
+        (
+            Operand::Indexed {
+                register: a,
+                offset: i,
+            },
+            Operand::Indexed {
+                register: b,
+                offset: j,
+            },
+        ) => a == b && i.checked_add(2) == Some(*j),
+        (
+            Operand::RegisterIndirect(a),
+            Operand::Indexed {
+                register: b,
+                offset: 2,
+            },
+        ) => a == b,
+        (Operand::RegisterIndirectAutoIncrement(a), Operand::RegisterIndirectAutoIncrement(b)) => {
+            a == b
+        }
+        (Operand::Absolute(a), Operand::Absolute(b)) => a.checked_add(2) == Some(*b),
+        _ => false,
+    }
+}
+
+/// Finds chains of an add, sub or cmp immediately followed by one or more
+/// addc (after add) or subc (after sub and cmp) whose operands hold the
+/// following words of the same values
+pub fn find(listing: &[(u32, Instruction)]) -> Vec<Composite> {
+    let mut composites = vec![];
+    let mut i = 0;
+
+    while i < listing.len() {
+        let (address, inst) = &listing[i];
+        i += 1;
+
+        let (kind, source, destination) = match link(inst) {
+            Some((kind, false, source, destination)) => (kind, source, destination),
+            _ => continue,
+        };
+
+        let mut parts = vec![(source, destination)];
+        while let Some((next, true, source, destination)) =
+            listing.get(i).and_then(|(_, inst)| link(inst))
+        {
+            let (previous_source, previous_destination) = parts[parts.len() - 1];
+            let continues =
+                next == kind || (kind == CompositeKind::Cmp && next == CompositeKind::Sub);
+            if !continues
+                || !next_word(&previous_source, &source)
+                || !next_word(&previous_destination, &destination)
+            {
+                break;
+            }
+
+            parts.push((source, destination));
+            i += 1;
+        }
+
+        if parts.len() > 1 {
+            composites.push(Composite {
+                address: *address,
+                kind,
+                parts,
+            });
+        }
+    }
+
+    composites
+}
+
+/// Renders a listing as text with every composite collapsed into a single
+/// pseudo-instruction
+pub fn collapse(listing: &[(u32, Instruction)]) -> Vec<(u32, String)> {
+    let composites = find(listing);
+    let mut lines = vec![];
+    let mut composites = composites.iter().peekable();
+    let mut skip = 0;
+
+    for (address, inst) in listing {
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+
+        match composites.next_if(|c| c.address == *address) {
+            Some(composite) => {
+                lines.push((*address, composite.to_string()));
+                skip = composite.parts.len() - 1;
+            }
+            None => lines.push((*address, inst.to_string())),
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::listing;
+
+    #[test]
+    fn add32() {
+        // add r14, r12; addc r15, r13; ret
+        let listing = listing(&[0x0c, 0x5e, 0x0d, 0x6f, 0x30, 0x41], 0x4400);
+        let composites = find(&listing);
+        assert_eq!(composites.len(), 1);
+        assert_eq!(composites[0].bits(), 32);
+        assert_eq!(composites[0].to_string(), "add32 r15:r14, r13:r12");
+        assert_eq!(
+            collapse(&listing),
+            vec![
+                (0x4400, "add32 r15:r14, r13:r12".to_string()),
+                (0x4404, "ret".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn sub64() {
+        // sub r8, r12; subc r9, r13; subc r10, r14; subc r11, r15
+        let listing = listing(&[0x0c, 0x88, 0x0d, 0x79, 0x0e, 0x7a, 0x0f, 0x7b], 0x4400);
+        let composites = find(&listing);
+        assert_eq!(composites.len(), 1);
+        assert_eq!(composites[0].kind, CompositeKind::Sub);
+        assert_eq!(composites[0].bits(), 64);
+    }
+
+    #[test]
+    fn carry_only() {
+        // add r10, r12; adc r13
+        let listing = listing(&[0x0c, 0x5a, 0x0d, 0x63], 0x4400);
+        assert_eq!(find(&listing)[0].to_string(), "add32 #0x0:r10, r13:r12");
+    }
+
+    #[test]
+    fn not_chained() {
+        // add r14, r12; mov r12, r11; addc r15, r13
+        assert_eq!(
+            find(&listing(&[0x0c, 0x5e, 0x0b, 0x4c, 0x0d, 0x6f], 0x4400)),
+            vec![]
+        );
+        // add r14, r12; subc r15, r13
+        assert_eq!(find(&listing(&[0x0c, 0x5e, 0x0d, 0x7f], 0x4400)), vec![]);
+        // add r14, r12; addc r15, r10
+        assert_eq!(find(&listing(&[0x0c, 0x5e, 0x0a, 0x6f], 0x4400)), vec![]);
+        // add r14, r12; addc r11, r13
+        assert_eq!(find(&listing(&[0x0c, 0x5e, 0x0d, 0x6b], 0x4400)), vec![]);
+    }
+
+    #[test]
+    fn cmp32() {
+        // cmp r14, r12; subc r15, r13
+        let listing = listing(&[0x0c, 0x9e, 0x0d, 0x7f], 0x4400);
+        let composites = find(&listing);
+        assert_eq!(composites.len(), 1);
+        assert_eq!(composites[0].kind, CompositeKind::Cmp);
+        assert_eq!(composites[0].to_string(), "cmp32 r15:r14, r13:r12");
+    }
+
+    #[test]
+    fn cmp_not_chained() {
+        // cmp r14, r12; subc r11, r13
+        assert_eq!(find(&listing(&[0x0c, 0x9e, 0x0d, 0x7b], 0x4400)), vec![]);
+        // cmp r14, r12; addc r15, r13
+        assert_eq!(find(&listing(&[0x0c, 0x9e, 0x0d, 0x6f], 0x4400)), vec![]);
+        // cmp r14, r12; cmp r15, r13
+        assert_eq!(find(&listing(&[0x0c, 0x9e, 0x0d, 0x9f], 0x4400)), vec![]);
+    }
+
+    #[test]
+    fn memory_words() {
+        // add r14, &0x0200; addc r15, &0x0202
+        let adjacent = listing(&[0x82, 0x5e, 0x00, 0x02, 0x82, 0x6f, 0x02, 0x02], 0x4400);
+        assert_eq!(
+            find(&adjacent)[0].to_string(),
+            "add32 r15:r14, &0x202:&0x200"
+        );
+        // add r14, &0x0200; addc r15, &0x0204
+        let apart = listing(&[0x82, 0x5e, 0x00, 0x02, 0x82, 0x6f, 0x04, 0x02], 0x4400);
+        assert_eq!(find(&apart), vec![]);
+    }
+}
@@ -0,0 +1,230 @@
+use std::fmt;
+
+use crate::listing::{self, Line};
+use crate::writer::Segment;
+
+/// Errors that can occur while unpacking an update container
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ContainerError {
+    /// Present when the container ends before its header or table is
+    /// complete. Contains the number of bytes that were needed
+    Truncated(usize),
+    /// Present when an entry of an offset table points outside of the
+    /// container. Contains the index of the entry
+    InvalidEntry(usize),
+}
+
+impl ContainerError {
+    /// Returns a stable numeric code for the error. Container errors use the
+    /// 800-899 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Truncated(_) => 800,
+            Self::InvalidEntry(_) => 801,
+        }
+    }
+}
+
+impl fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated(needed) => {
+                write!(f, "container is truncated, {} bytes are needed", needed)
+            }
+            Self::InvalidEntry(index) => {
+                write!(f, "entry {} is outside of the container", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ContainerError {}
+
+/// Extracts the code and data from an update container so it can be
+/// disassembled like any other image
+pub trait Unwrapper {
+    /// Returns the name of the container format
+    fn name(&self) -> &str;
+    /// Returns the segments stored in the container
+    fn unpack(&self, data: &[u8]) -> Result<Vec<Segment>, ContainerError>;
+}
+
+/// XORs data with a repeating key. The key starts over at the start of data
+fn xor(data: &[u8], key: &[u8]) -> Vec<u8> {
+    if key.is_empty() {
+        return data.to_vec();
+    }
+
+    data.iter()
+        .zip(key.iter().cycle())
+        .map(|(byte, key)| byte ^ key)
+        .collect()
+}
+
+fn word(data: &[u8], offset: usize) -> Result<u16, ContainerError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or(ContainerError::Truncated(offset + 2))
+}
+
+/// A single payload that is XORed with a repeating key and loaded at a fixed
+/// address. An empty key leaves the payload as is
+#[derive(Debug, Clone, PartialEq)]
+pub struct Xor {
+    /// The offset of the payload in the container (eg. to skip a header)
+    pub offset: usize,
+    /// The address the payload is loaded at
+    pub address: u32,
+    pub key: Vec<u8>,
+}
+
+impl Unwrapper for Xor {
+    fn name(&self) -> &str {
+        "xor"
+    }
+
+    fn unpack(&self, data: &[u8]) -> Result<Vec<Segment>, ContainerError> {
+        let payload = data
+            .get(self.offset..)
+            .ok_or(ContainerError::Truncated(self.offset))?;
+        Ok(vec![Segment::new(self.address, xor(payload, &self.key))])
+    }
+}
+
+/// A table of segments at the start of the container. The table is a little
+/// endian word holding the number of entries followed by one entry of three
+/// little endian words per segment: the address it is loaded at, the offset
+/// of its data in the container and its length. The data of every segment
+/// is XORed with the key which starts over for each segment
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OffsetTable {
+    pub key: Vec<u8>,
+}
+
+impl Unwrapper for OffsetTable {
+    fn name(&self) -> &str {
+        "offset-table"
+    }
+
+    fn unpack(&self, data: &[u8]) -> Result<Vec<Segment>, ContainerError> {
+        let count = word(data, 0)? as usize;
+        (0..count)
+            .map(|index| {
+                let entry = 2 + index * 6;
+                let address = word(data, entry)?;
+                let offset = word(data, entry + 2)? as usize;
+                let len = word(data, entry + 4)? as usize;
+                let payload = data
+                    .get(offset..offset + len)
+                    .ok_or(ContainerError::InvalidEntry(index))?;
+                Ok(Segment::new(address as u32, xor(payload, &self.key)))
+            })
+            .collect()
+    }
+}
+
+/// Unpacks data with the first unwrapper that accepts it. Returns the name
+/// of the unwrapper along with the segments or the error of the last
+/// unwrapper when none of them accept it
+pub fn unpack<'a>(
+    unwrappers: &[&'a dyn Unwrapper],
+    data: &[u8],
+) -> Option<Result<(&'a str, Vec<Segment>), ContainerError>> {
+    let mut last = None;
+    for unwrapper in unwrappers {
+        match unwrapper.unpack(data) {
+            Ok(segments) => return Some(Ok((unwrapper.name(), segments))),
+            Err(e) => last = Some(Err(e)),
+        }
+    }
+
+    last
+}
+
+/// Sweeps every segment in address order and returns the lines of all of
+/// them
+pub fn sweep(segments: &[Segment]) -> Vec<Line> {
+    let mut segments: Vec<&Segment> = segments.iter().collect();
+    segments.sort_by_key(|segment| segment.address);
+    segments
+        .into_iter()
+        .flat_map(|segment| listing::sweep(&segment.data, segment.address))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xor_payload() {
+        // a four byte header followed by mov #0x4400, sp XORed with 0xa5 0x5a
+        let data = [0x01, 0x00, 0x00, 0x00, 0x94, 0x1a, 0xa5, 0x1e];
+        let unwrapper = Xor {
+            offset: 4,
+            address: 0x4400,
+            key: vec![0xa5, 0x5a],
+        };
+        let segments = unwrapper.unpack(&data).unwrap();
+        assert_eq!(
+            segments,
+            vec![Segment::new(0x4400, vec![0x31, 0x40, 0x00, 0x44])]
+        );
+        assert_eq!(
+            listing::render(&sweep(&segments)),
+            "       0  4400:  mov #0x4400, sp\n"
+        );
+        assert_eq!(
+            Xor {
+                offset: 9,
+                ..unwrapper
+            }
+            .unpack(&data),
+            Err(ContainerError::Truncated(9))
+        );
+    }
+
+    #[test]
+    fn offset_table() {
+        // two entries: ret at 0xc000 and the reset vector at 0xfffe
+        let data = [
+            0x02, 0x00, //
+            0x00, 0xc0, 0x0e, 0x00, 0x02, 0x00, //
+            0xfe, 0xff, 0x10, 0x00, 0x02, 0x00, //
+            0x30, 0x41, 0x00, 0xc0,
+        ];
+        let unwrapper = OffsetTable::default();
+        assert_eq!(
+            unwrapper.unpack(&data),
+            Ok(vec![
+                Segment::new(0xc000, vec![0x30, 0x41]),
+                Segment::new(0xfffe, vec![0x00, 0xc0]),
+            ])
+        );
+        assert_eq!(
+            unwrapper.unpack(&data[..6]),
+            Err(ContainerError::Truncated(8))
+        );
+        assert_eq!(
+            unwrapper.unpack(&data[..16]),
+            Err(ContainerError::InvalidEntry(1))
+        );
+    }
+
+    #[test]
+    fn first_accepted() {
+        // a container too short for a table falls back to a plain payload
+        let data = [0x30, 0x41];
+        let table = OffsetTable::default();
+        let plain = Xor {
+            offset: 0,
+            address: 0xc000,
+            key: vec![],
+        };
+        let (name, segments) = unpack(&[&table, &plain], &data).unwrap().unwrap();
+        assert_eq!(name, "xor");
+        assert_eq!(segments, vec![Segment::new(0xc000, vec![0x30, 0x41])]);
+        assert_eq!(unpack(&[], &data), None);
+    }
+}
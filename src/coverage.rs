@@ -0,0 +1,246 @@
+use std::fmt;
+use std::ops::Range;
+
+/// The size of a basic block entry in a drcov file
+const DRCOV_BB_SIZE: usize = 8;
+
+/// Errors that can occur while parsing a coverage file
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum CoverageError {
+    /// Present when an address can't be parsed as hex. Contains the line
+    /// number (starting at 1) and the value
+    InvalidAddress((usize, String)),
+    /// Present when an address doesn't fit in the 32 bit address space or
+    /// is the last address of it. Contains the line number (starting at 1)
+    /// and the address
+    AddressOutOfRange((usize, u64)),
+    /// Present when a drcov file is malformed. Contains a description of the
+    /// problem
+    InvalidDrcov(String),
+}
+
+impl CoverageError {
+    /// Returns a stable numeric code for the error. Coverage errors use the
+    /// 200-299 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InvalidAddress(_) => 200,
+            Self::InvalidDrcov(_) => 201,
+            Self::AddressOutOfRange(_) => 202,
+        }
+    }
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress((line, value)) => {
+                write!(f, "line {}: invalid address {}", line, value)
+            }
+            Self::InvalidDrcov(reason) => {
+                write!(f, "invalid drcov file: {}", reason)
+            }
+            Self::AddressOutOfRange((line, address)) => {
+                write!(f, "line {}: address {:#x} is out of range", line, address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoverageError {}
+
+fn parse_hex(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u64::from_str_radix(value, 16).ok()
+}
+
+/// Parses a coverage file that lists one hexadecimal address per line into
+/// the covered ranges. Empty lines and lines starting with # are ignored
+pub fn parse_addresses(coverage: &str) -> Result<Vec<Range<u32>>, CoverageError> {
+    let mut ranges = vec![];
+    for (i, line) in coverage.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let address = parse_hex(line)
+            .ok_or_else(|| CoverageError::InvalidAddress((i + 1, line.to_string())))?;
+        let start = u32::try_from(address)
+            .map_err(|_| CoverageError::AddressOutOfRange((i + 1, address)))?;
+        let end = start
+            .checked_add(1)
+            .ok_or(CoverageError::AddressOutOfRange((i + 1, address)))?;
+        ranges.push(start..end);
+    }
+
+    Ok(ranges)
+}
+
+/// Parses a drcov coverage file (as produced by DynamoRIO and most
+/// emulators) into the covered ranges. Basic block offsets are relative to
+/// the base of the module they belong to so the module table is used to
+/// convert them back into addresses
+pub fn parse_drcov(coverage: &[u8]) -> Result<Vec<Range<u32>>, CoverageError> {
+    let invalid = |reason: &str| CoverageError::InvalidDrcov(reason.to_string());
+
+    let mut bases = vec![];
+    let mut base_column = None;
+    let mut offset = 0;
+    let blocks = loop {
+        let end = coverage[offset..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(|| invalid("missing basic block table"))?;
+        let line = std::str::from_utf8(&coverage[offset..offset + end])
+            .map_err(|_| invalid("header is not text"))?
+            .trim();
+        offset += end + 1;
+
+        if let Some(columns) = line.strip_prefix("Columns:") {
+            base_column = columns
+                .split(',')
+                .position(|c| matches!(c.trim(), "base" | "start"));
+        } else if let Some(table) = line.strip_prefix("BB Table:") {
+            let count = table
+                .split_whitespace()
+                .next()
+                .and_then(|c| c.parse::<usize>().ok())
+                .ok_or_else(|| invalid("invalid basic block count"))?;
+            break count;
+        } else if let Some(column) = base_column {
+            // everything between the columns and the basic block table is a
+            // module entry
+            let base = line
+                .split(',')
+                .nth(column)
+                .and_then(parse_hex)
+                .ok_or_else(|| invalid("invalid module entry"))?;
+            bases.push(base as u32);
+        }
+    };
+
+    let table = &coverage[offset..];
+    if blocks
+        .checked_mul(DRCOV_BB_SIZE)
+        .is_none_or(|size| table.len() < size)
+    {
+        return Err(invalid("truncated basic block table"));
+    }
+
+    table
+        .chunks_exact(DRCOV_BB_SIZE)
+        .take(blocks)
+        .map(|bb| {
+            let start = u32::from_le_bytes([bb[0], bb[1], bb[2], bb[3]]);
+            let size = u16::from_le_bytes([bb[4], bb[5]]) as u32;
+            let module = u16::from_le_bytes([bb[6], bb[7]]) as usize;
+            let base = bases
+                .get(module)
+                .ok_or_else(|| invalid("basic block references an unknown module"))?;
+            let start = base.wrapping_add(start);
+            Ok(start..start.wrapping_add(size))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing::{count_range_hits, render, sweep};
+
+    fn drcov(blocks: &[(u32, u16, u16)]) -> Vec<u8> {
+        let mut data = b"DRCOV VERSION: 2\nDRCOV FLAVOR: drcov\nModule Table: version 2, count 1\nColumns: id, base, end, entry, path\n 0, 0x4400, 0x10000, 0x0, firmware.elf\n".to_vec();
+        data.extend(format!("BB Table: {} bbs\n", blocks.len()).as_bytes());
+        for (start, size, module) in blocks {
+            data.extend(start.to_le_bytes());
+            data.extend(size.to_le_bytes());
+            data.extend(module.to_le_bytes());
+        }
+
+        data
+    }
+
+    #[test]
+    fn addresses() {
+        let coverage = "# covered\n0x4400\n4404\n";
+        assert_eq!(
+            parse_addresses(coverage),
+            Ok(vec![0x4400..0x4401, 0x4404..0x4405])
+        );
+    }
+
+    #[test]
+    fn addresses_invalid() {
+        assert_eq!(
+            parse_addresses("0x4400\nnope\n"),
+            Err(CoverageError::InvalidAddress((2, "nope".to_string())))
+        );
+    }
+
+    #[test]
+    fn addresses_out_of_range() {
+        assert_eq!(
+            parse_addresses(
+                "ffffffff
+"
+            ),
+            Err(CoverageError::AddressOutOfRange((1, 0xffffffff)))
+        );
+        assert_eq!(
+            parse_addresses(
+                "0x4400
+1ffffffff0
+"
+            ),
+            Err(CoverageError::AddressOutOfRange((2, 0x1ffffffff0)))
+        );
+    }
+
+    #[test]
+    fn drcov_blocks() {
+        let data = drcov(&[(0, 4, 0), (4, 2, 0)]);
+        assert_eq!(parse_drcov(&data), Ok(vec![0x4400..0x4404, 0x4404..0x4406]));
+    }
+
+    #[test]
+    fn drcov_unknown_module() {
+        let data = drcov(&[(0, 4, 1)]);
+        assert_eq!(
+            parse_drcov(&data),
+            Err(CoverageError::InvalidDrcov(
+                "basic block references an unknown module".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn drcov_truncated() {
+        let mut data = drcov(&[(0, 4, 0)]);
+        data.pop();
+        assert_eq!(
+            parse_drcov(&data),
+            Err(CoverageError::InvalidDrcov(
+                "truncated basic block table".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn listing() {
+        // mov #0x4400, sp; ret
+        let image = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let mut lines = sweep(&image, 0x4400);
+        count_range_hits(&mut lines, &parse_drcov(&drcov(&[(0, 6, 0)])).unwrap());
+        count_range_hits(&mut lines, &parse_addresses("0x4404").unwrap());
+        assert_eq!(
+            render(&lines),
+            "       1  4400:  mov #0x4400, sp\n       2  4404:  ret\n"
+        );
+    }
+}
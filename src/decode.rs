@@ -0,0 +1,143 @@
+pub mod parts;
+
+use crate::decode_error::DecodeError;
+use crate::extended;
+
+/// A position in an input stream that the parsers in parts operate on. Each
+/// parser takes a cursor and returns a new cursor that has been advanced
+/// past everything it consumed along with the parsed value. This allows the
+/// parsers to be composed to decode formats that embed instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(data: &'a [u8]) -> Cursor<'a> {
+        Cursor { data, offset: 0 }
+    }
+
+    /// Returns the data that has not been consumed yet
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.data[self.offset..]
+    }
+
+    /// Returns the number of bytes that have been consumed
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns whether all of the data has been consumed
+    pub fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+
+    /// Returns a new cursor that has been advanced by len bytes
+    pub fn advance(&self, len: usize) -> Cursor<'a> {
+        Cursor {
+            data: self.data,
+            offset: (self.offset + len).min(self.data.len()),
+        }
+    }
+}
+
+/// A first word whose decoding is inconsistent with the decode tables
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub word: u16,
+    pub reason: String,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#06x}: {}", self.word, self.reason)
+    }
+}
+
+/// Checks every possible first word against the structural guarantees of
+/// the decoder and returns the words that violate them:
+///
+/// - decoding never panics
+/// - when the additional words are present decoding either succeeds or
+///   fails because the encoding is invalid, never because data is missing
+/// - the size of a decoded instruction is the number of bytes consumed
+/// - when only the first word is present decoding fails with a missing
+///   operand error if and only if the instruction needs additional words
+/// - when only an msp430x extension word is present decoding fails with a
+///   missing instruction error
+///
+/// This is run by the tests but is public so it can be used to check
+/// decoders built from the parts
+pub fn verify_tables() -> Vec<Violation> {
+    let mut violations = vec![];
+    let mut violation = |word, reason: String| violations.push(Violation { word, reason });
+
+    for word in 0..=u16::MAX {
+        let [a, b] = word.to_le_bytes();
+        let data = [a, b, 0x34, 0x12, 0x78, 0x56];
+
+        let decoded = std::panic::catch_unwind(|| {
+            let full = parts::instruction(Cursor::new(&data));
+            let truncated = parts::instruction(Cursor::new(&data[..2]));
+            (full, truncated)
+        });
+        let (full, truncated) = match decoded {
+            Ok(decoded) => decoded,
+            Err(_) => {
+                violation(word, "decoding panicked".to_string());
+                continue;
+            }
+        };
+
+        match (full, truncated) {
+            (
+                Err(
+                    e @ (DecodeError::MissingSource
+                    | DecodeError::MissingDestination
+                    | DecodeError::MissingInstruction),
+                ),
+                _,
+            ) => violation(word, format!("unclassified error with full data: {}", e)),
+            (Ok((input, inst)), _) if input.offset() != inst.size() => violation(
+                word,
+                format!(
+                    "{} consumed {} bytes but has size {}",
+                    inst,
+                    input.offset(),
+                    inst.size()
+                ),
+            ),
+            (Ok((_, inst)), Ok(_)) if inst.size() > 2 => violation(
+                word,
+                format!("{} decoded without its additional words", inst),
+            ),
+            (Ok((_, inst)), Err(DecodeError::MissingSource | DecodeError::MissingDestination))
+                if inst.size() > 2 => {}
+            (Ok((_, inst)), Ok((_, short))) if inst == short => {}
+            (Err(e), Err(short)) if e == short => {}
+            (_, Err(DecodeError::MissingInstruction))
+                if word & extended::EXTENSION_MASK == extended::EXTENSION_PREFIX => {}
+            (full, truncated) => violation(
+                word,
+                format!(
+                    "decoded as {:?} but as {:?} without additional words",
+                    full.map(|(_, inst)| inst),
+                    truncated.map(|(_, inst)| inst)
+                ),
+            ),
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables() {
+        assert_eq!(verify_tables(), vec![]);
+    }
+}
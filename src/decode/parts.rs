@@ -0,0 +1,371 @@
+use crate::address::{ADDRESS_MASK, ADDRESS_PREFIX};
+use crate::decode::Cursor;
+use crate::decode_error::DecodeError;
+use crate::emulate::Emulate;
+use crate::extended::{EXTENSION_MASK, EXTENSION_PREFIX};
+use crate::instruction::Instruction;
+use crate::jxx::*;
+use crate::operand::{self, Operand, OperandWidth};
+use crate::single_operand::*;
+use crate::two_operand::*;
+use crate::Result;
+
+pub(crate) const RRC_OPCODE: u16 = 0;
+pub(crate) const SWPB_OPCODE: u16 = 1;
+pub(crate) const RRA_OPCODE: u16 = 2;
+pub(crate) const SXT_OPCODE: u16 = 3;
+pub(crate) const PUSH_OPCODE: u16 = 4;
+pub(crate) const CALL_OPCODE: u16 = 5;
+pub(crate) const RETI_OPCODE: u16 = 6;
+
+pub(crate) const MOV_OPCODE: u16 = 4;
+pub(crate) const ADD_OPCODE: u16 = 5;
+pub(crate) const ADDC_OPCODE: u16 = 6;
+pub(crate) const SUBC_OPCODE: u16 = 7;
+pub(crate) const SUB_OPCODE: u16 = 8;
+pub(crate) const CMP_OPCODE: u16 = 9;
+pub(crate) const DADD_OPCODE: u16 = 10;
+pub(crate) const BIT_OPCODE: u16 = 11;
+pub(crate) const BIC_OPCODE: u16 = 12;
+pub(crate) const BIS_OPCODE: u16 = 13;
+pub(crate) const XOR_OPCODE: u16 = 14;
+pub(crate) const AND_OPCODE: u16 = 15;
+
+/// The fixed high bits (000100) of a single operand instruction word
+pub(crate) const SINGLE_OPERAND_PREFIX: u16 = 0b0001_0000_0000_0000;
+
+const SINGLE_OPERAND_REGISTER_MASK: u16 = 0b1111;
+
+const SINGLE_OPERAND_OPCODE_MASK: u16 = 0b0000_0011_1000_0000;
+
+const SINGLE_OPERAND_SOURCE_MASK: u16 = 0b11_0000;
+
+pub(crate) const SINGLE_OPERAND_WIDTH_MASK: u16 = 0b100_0000;
+
+/// The bit that selects the MSP430X pushm and popm instructions
+/// (0x1400-0x17ff) in the single operand group
+const MULTIPLE_MASK: u16 = 0b0000_0100_0000_0000;
+
+/// The bit of a reti instruction word that selects the MSP430X calla
+/// instruction (0x1340-0x137f)
+const CALLA_MASK: u16 = 0b0100_0000;
+
+pub(crate) const INST_TYPE_MASK: u16 = 0b1110_0000_0000_0000;
+
+pub(crate) const SINGLE_OPERAND_INSTRUCTION: u16 = 0b0000_0000_0000_0000;
+
+/// JMP_MASK masks off the high three bits to check whether the pattern 001
+/// is present. This describes a JMP instruction
+pub(crate) const JMP_INSTRUCTION: u16 = 0b0010_0000_0000_0000;
+
+/// JMP_CONDITION_MASK masks off the three bits used to denote the Jxx condition
+const JMP_CONDITION_MASK: u16 = 0b0001_1100_0000_0000;
+
+/// JMP_OFFSET masks off the lower 10 bits used to represent the offset.
+/// This can be a negative offset and it represented as such in one's
+/// compliment
+pub(crate) const JMP_OFFSET: u16 = 0b0000001111111111;
+
+const TWO_OPERAND_OPCODE_MASK: u16 = 0b1111_0000_0000_0000;
+const TWO_OPERAND_SOURCE_MASK: u16 = 0b1111_0000_0000;
+const TWO_OPERAND_AD_MASK: u16 = 0b1000_0000;
+const TWO_OPERAND_WIDTH: u16 = 0b100_0000;
+const TWO_OPERAND_AS: u16 = 0b11_0000;
+const TWO_OPERAND_DESTINATION: u16 = 0b1111;
+
+/// Parses the first word of an instruction
+pub fn instruction_word(input: Cursor) -> Result<(Cursor, u16)> {
+    let data = input.remaining();
+    if data.len() < 2 {
+        return Err(DecodeError::MissingInstruction);
+    }
+
+    let word = u16::from_le_bytes([data[0], data[1]]);
+    Ok((input.advance(2), word))
+}
+
+/// Parses a source operand for the register and AS (source addressing
+/// mode) that were decoded from the instruction word, consuming the
+/// additional word if the operand has one
+pub fn source(register: u8, addressing: u8, input: Cursor) -> Result<(Cursor, Operand)> {
+    let operand = operand::source(register, addressing, input.remaining())?;
+    Ok((input.advance(operand.size()), operand))
+}
+
+/// Parses a destination operand for the register and AD (destination
+/// addressing mode) that were decoded from the instruction word, consuming
+/// the additional word if the operand has one
+pub fn destination(register: u8, addressing: u8, input: Cursor) -> Result<(Cursor, Operand)> {
+    let operand = operand::destination(register, addressing, input.remaining())?;
+    Ok((input.advance(operand.size()), operand))
+}
+
+/// Parses the remainder of a single operand instruction (format II) whose
+/// instruction word has already been parsed
+pub fn single_operand(first_word: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    let opcode = (SINGLE_OPERAND_OPCODE_MASK & first_word) >> 7;
+    let register = (SINGLE_OPERAND_REGISTER_MASK & first_word) as u8;
+    let source_addressing = ((SINGLE_OPERAND_SOURCE_MASK & first_word) >> 4) as u8;
+    let operand_width = OperandWidth::from(((SINGLE_OPERAND_WIDTH_MASK & first_word) >> 6) as u8);
+
+    // the bits the CPU ignores are kept (xored with the canonical word) so
+    // the instruction encodes to its original word
+    let used = match opcode {
+        RETI_OPCODE => SINGLE_OPERAND_OPCODE_MASK,
+        RRC_OPCODE | RRA_OPCODE | PUSH_OPCODE => {
+            SINGLE_OPERAND_OPCODE_MASK
+                | SINGLE_OPERAND_WIDTH_MASK
+                | SINGLE_OPERAND_SOURCE_MASK
+                | SINGLE_OPERAND_REGISTER_MASK
+        }
+        _ => SINGLE_OPERAND_OPCODE_MASK | SINGLE_OPERAND_SOURCE_MASK | SINGLE_OPERAND_REGISTER_MASK,
+    };
+    let reserved = (first_word & !used) ^ SINGLE_OPERAND_PREFIX;
+
+    // pushm, popm and calla aren't decoded so they are rejected rather than
+    // shown as a format II instruction with reserved bits. The upper byte
+    // of the instruction word is reported as their opcode
+    if first_word & MULTIPLE_MASK != 0 || (opcode == RETI_OPCODE && first_word & CALLA_MASK != 0) {
+        return Err(DecodeError::InvalidOpcode(first_word >> 8));
+    }
+
+    // reti has no operand so the operand bits never consume a word and
+    // the opcode is checked first so truncated data fails the same way
+    match opcode {
+        RETI_OPCODE => {
+            let reti = Reti::new().with_reserved(reserved);
+            return Ok((input, Instruction::Reti(reti)));
+        }
+        RRC_OPCODE..RETI_OPCODE => {}
+        _ => return Err(DecodeError::InvalidOpcode(opcode)),
+    }
+
+    let (input, source) = source(register, source_addressing, input)?;
+
+    let inst = match opcode {
+        RRC_OPCODE => {
+            Instruction::Rrc(Rrc::new(source, Some(operand_width)).with_reserved(reserved))
+        }
+        SWPB_OPCODE => Instruction::Swpb(Swpb::new(source, None).with_reserved(reserved)),
+        RRA_OPCODE => {
+            Instruction::Rra(Rra::new(source, Some(operand_width)).with_reserved(reserved))
+        }
+        SXT_OPCODE => Instruction::Sxt(Sxt::new(source, None).with_reserved(reserved)),
+        PUSH_OPCODE => {
+            Instruction::Push(Push::new(source, Some(operand_width)).with_reserved(reserved))
+        }
+        _ => Instruction::Call(Call::new(source, None).with_reserved(reserved)),
+    };
+
+    Ok((input, inst))
+}
+
+/// Parses a jxx instruction (format III) whose instruction word has already
+/// been parsed. Jxx instructions never have additional words so the input
+/// is returned unchanged
+pub fn jxx(first_word: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    let condition = (first_word & JMP_CONDITION_MASK) >> 10;
+    let offset = sign_extend_offset(first_word & JMP_OFFSET);
+
+    let inst = match condition {
+        0 => Ok(Instruction::Jnz(Jnz::new(offset))),
+        1 => Ok(Instruction::Jz(Jz::new(offset))),
+        2 => Ok(Instruction::Jlo(Jlo::new(offset))),
+        3 => Ok(Instruction::Jc(Jc::new(offset))),
+        4 => Ok(Instruction::Jn(Jn::new(offset))),
+        5 => Ok(Instruction::Jge(Jge::new(offset))),
+        6 => Ok(Instruction::Jl(Jl::new(offset))),
+        7 => Ok(Instruction::Jmp(Jmp::new(offset))),
+        _ => Err(DecodeError::InvalidJumpCondition(condition)),
+    }?;
+
+    Ok((input, inst))
+}
+
+/// Parses the remainder of a two operand instruction (format I) whose
+/// instruction word has already been parsed
+pub fn two_operand(first_word: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    let opcode = (first_word & TWO_OPERAND_OPCODE_MASK) >> 12;
+    let source_register = ((first_word & TWO_OPERAND_SOURCE_MASK) >> 8) as u8;
+    let ad = ((first_word & TWO_OPERAND_AD_MASK) >> 7) as u8;
+    let operand_width = OperandWidth::from(((first_word & TWO_OPERAND_WIDTH) >> 6) as u8);
+    let source_addressing = ((first_word & TWO_OPERAND_AS) >> 4) as u8;
+    let destination_register = (first_word & TWO_OPERAND_DESTINATION) as u8;
+
+    // if source has an additional word it is encoded before the destination
+    let (input, source) = source(source_register, source_addressing, input)?;
+    let (input, destination) = destination(destination_register, ad, input)?;
+
+    let inst = match opcode {
+        MOV_OPCODE => {
+            let inst = Mov::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Mov(inst)),
+            }
+        }
+        ADD_OPCODE => {
+            let inst = Add::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Add(inst)),
+            }
+        }
+        ADDC_OPCODE => {
+            let inst = Addc::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Addc(inst)),
+            }
+        }
+        SUBC_OPCODE => {
+            let inst = Subc::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Subc(inst)),
+            }
+        }
+        SUB_OPCODE => {
+            let inst = Sub::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Sub(inst)),
+            }
+        }
+        CMP_OPCODE => {
+            let inst = Cmp::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Cmp(inst)),
+            }
+        }
+        DADD_OPCODE => {
+            let inst = Dadd::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Dadd(inst)),
+            }
+        }
+        BIT_OPCODE => Ok(Instruction::Bit(Bit::new(
+            source,
+            operand_width,
+            destination,
+        ))),
+        BIC_OPCODE => {
+            let inst = Bic::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Bic(inst)),
+            }
+        }
+        BIS_OPCODE => {
+            let inst = Bis::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Bis(inst)),
+            }
+        }
+        XOR_OPCODE => {
+            let inst = Xor::new(source, operand_width, destination);
+            match inst.emulate() {
+                Some(inst) => Ok(inst),
+                None => Ok(Instruction::Xor(inst)),
+            }
+        }
+        AND_OPCODE => Ok(Instruction::And(And::new(
+            source,
+            operand_width,
+            destination,
+        ))),
+        _ => Err(DecodeError::InvalidOpcode(opcode)),
+    }?;
+
+    Ok((input, inst))
+}
+
+/// Parses the remainder of an MSP430X extended instruction whose extension
+/// word has already been parsed
+pub fn extended(extension: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    let inst = crate::extended::decode(extension, input.remaining())?;
+    Ok((input.advance(inst.size() - 2), Instruction::Extended(inst)))
+}
+
+/// Parses an MSP430X address instruction (mova, cmpa, adda or suba) whose
+/// instruction word has already been parsed
+pub fn address(first_word: u16, input: Cursor) -> Result<(Cursor, Instruction)> {
+    crate::address::decode(first_word, input)
+}
+
+/// Parses a complete instruction
+pub fn instruction(input: Cursor) -> Result<(Cursor, Instruction)> {
+    let (input, first_word) = instruction_word(input)?;
+
+    if first_word & EXTENSION_MASK == EXTENSION_PREFIX {
+        return extended(first_word, input);
+    }
+
+    if first_word & ADDRESS_MASK == ADDRESS_PREFIX {
+        return address(first_word, input);
+    }
+
+    match first_word & INST_TYPE_MASK {
+        SINGLE_OPERAND_INSTRUCTION => single_operand(first_word, input),
+        JMP_INSTRUCTION => jxx(first_word, input),
+        // The opcode is the first four bits for this type of instruction so
+        // there isn't a simple mask we can check. If it doesn't match a
+        // single operand or jmp instuction we'll fall through to here and
+        // attempt to match a two operand
+        _ => two_operand(first_word, input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_word_missing() {
+        let data = [0x30];
+        assert_eq!(
+            instruction_word(Cursor::new(&data)),
+            Err(DecodeError::MissingInstruction)
+        );
+    }
+
+    #[test]
+    fn source_consumes_word() {
+        let data = [0x00, 0x44, 0x30, 0x41];
+        let (input, operand) = source(0, 3, Cursor::new(&data)).unwrap();
+        assert_eq!(operand, Operand::Immediate(0x4400));
+        assert_eq!(input.offset(), 2);
+    }
+
+    #[test]
+    fn destination_consumes_word() {
+        let data = [0x00, 0x02];
+        let (input, operand) = destination(2, 1, Cursor::new(&data)).unwrap();
+        assert_eq!(operand, Operand::Absolute(0x0200));
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn instruction_stream() {
+        // mov #0x4400, sp; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let (input, first) = instruction(Cursor::new(&data)).unwrap();
+        assert_eq!(first.to_string(), "mov #0x4400, sp");
+        assert_eq!(input.offset(), 4);
+        let (input, second) = instruction(input).unwrap();
+        assert_eq!(second.to_string(), "ret");
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn composed() {
+        // a record with a one byte tag followed by an instruction
+        let data = [0x7f, 0x30, 0x41];
+        let input = Cursor::new(&data).advance(1);
+        let (_, inst) = instruction(input).unwrap();
+        assert_eq!(inst.to_string(), "ret");
+    }
+}
@@ -1,6 +1,10 @@
+use crate::operand::Operand;
+
 /// Catch all error type that contains any error that can occur during the
-/// decoding process
+/// decoding process. New variants may be added so matches must include a
+/// wildcard arm; code can be used to identify a variant across versions
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
 pub enum DecodeError {
     /// Present when an instruction expects an additional source argument
     /// (after the instruction) but none is present
@@ -10,17 +14,92 @@ pub enum DecodeError {
     MissingDestination,
     /// Present when the combination of the AS (source addressing mode) field
     /// and the register are an invalid combination
-    InvalidSource((u16, u8)),
+    InvalidSource { addressing: u16, register: u8 },
     /// Present when the combination of the AD (destination addressing mode) field
     /// and the register are an invalid combination
-    InvalidDestination((u16, u8)),
+    InvalidDestination { addressing: u16, register: u8 },
     /// Present when there is not instruction available to read
     MissingInstruction,
-    /// Present when the opcode specified for a type 1 or type 2 instruction
-    /// is invalid
+    /// Present when the opcode specified for a type 1, type 2 or address
+    /// instruction is invalid
     InvalidOpcode(u16),
     /// Present when the condition of a jxx instruction is invalid
     InvalidJumpCondition(u16),
+    /// Present when an instruction is decoded at an odd address which the
+    /// hardware can't fetch from. Contains the address
+    UnalignedAddress(u32),
+    /// Present when an instruction is built with an operand that can't be
+    /// used as a destination (eg. an immediate). Contains the operand
+    IllegalDestination(Operand),
+    /// Present when an MSP430X extension word is followed by an instruction
+    /// that can't be extended or selects a reserved width. Contains the
+    /// extension word
+    InvalidExtension(u16),
+}
+
+impl DecodeError {
+    /// Returns a stable numeric code for the error. Codes are never reused
+    /// or changed once assigned so they are safe to expose over FFI or as
+    /// process exit statuses. Decode errors use the 1-99 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::MissingSource => 1,
+            Self::MissingDestination => 2,
+            Self::InvalidSource { .. } => 3,
+            Self::InvalidDestination { .. } => 4,
+            Self::MissingInstruction => 5,
+            Self::InvalidOpcode(_) => 6,
+            Self::InvalidJumpCondition(_) => 7,
+            Self::UnalignedAddress(_) => 8,
+            Self::IllegalDestination(_) => 9,
+            Self::InvalidExtension(_) => 10,
+        }
+    }
+
+    /// Returns an InvalidSource error from the (addressing, register) tuple
+    /// it held before it had named fields
+    #[deprecated(
+        since = "0.3.0",
+        note = "use DecodeError::InvalidSource { addressing, register }"
+    )]
+    pub fn invalid_source((addressing, register): (u16, u8)) -> DecodeError {
+        Self::InvalidSource {
+            addressing,
+            register,
+        }
+    }
+
+    /// Returns an InvalidDestination error from the (addressing, register)
+    /// tuple it held before it had named fields
+    #[deprecated(
+        since = "0.3.0",
+        note = "use DecodeError::InvalidDestination { addressing, register }"
+    )]
+    pub fn invalid_destination((addressing, register): (u16, u8)) -> DecodeError {
+        Self::InvalidDestination {
+            addressing,
+            register,
+        }
+    }
+
+    /// Returns the addressing mode of an invalid source or destination
+    pub fn addressing(&self) -> Option<u16> {
+        match self {
+            Self::InvalidSource { addressing, .. }
+            | Self::InvalidDestination { addressing, .. } => Some(*addressing),
+            _ => None,
+        }
+    }
+
+    /// Returns the register of an invalid source or destination
+    pub fn register(&self) -> Option<u8> {
+        match self {
+            Self::InvalidSource { register, .. } | Self::InvalidDestination { register, .. } => {
+                Some(*register)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for DecodeError {
@@ -32,18 +111,24 @@ impl std::fmt::Display for DecodeError {
             Self::MissingDestination => {
                 write!(f, "destination operand is missing")
             }
-            Self::InvalidSource((source, register)) => {
+            Self::InvalidSource {
+                addressing,
+                register,
+            } => {
                 write!(
                     f,
                     "source addressing mode ({}) for register ({}) is invalid",
-                    source, register
+                    addressing, register
                 )
             }
-            Self::InvalidDestination((source, register)) => {
+            Self::InvalidDestination {
+                addressing,
+                register,
+            } => {
                 write!(
                     f,
                     "destination addressing mode ({}) for register ({}) is invalid",
-                    source, register
+                    addressing, register
                 )
             }
             Self::MissingInstruction => {
@@ -55,8 +140,63 @@ impl std::fmt::Display for DecodeError {
             Self::InvalidJumpCondition(condition) => {
                 write!(f, "invalid jump condition {}", condition)
             }
+            Self::UnalignedAddress(address) => {
+                write!(f, "address {:#x} is not word aligned", address)
+            }
+            Self::IllegalDestination(operand) => {
+                write!(f, "{} can't be used as a destination", operand)
+            }
+            Self::InvalidExtension(extension) => {
+                write!(f, "invalid extension word {:#06x}", extension)
+            }
         }
     }
 }
 
 impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        let errors = [
+            DecodeError::MissingSource,
+            DecodeError::MissingDestination,
+            DecodeError::InvalidSource {
+                addressing: 1,
+                register: 3,
+            },
+            DecodeError::InvalidDestination {
+                addressing: 1,
+                register: 3,
+            },
+            DecodeError::MissingInstruction,
+            DecodeError::InvalidOpcode(7),
+            DecodeError::InvalidJumpCondition(8),
+            DecodeError::UnalignedAddress(0x4401),
+            DecodeError::IllegalDestination(Operand::Immediate(0x10)),
+            DecodeError::InvalidExtension(0x1800),
+        ];
+        assert_eq!(
+            errors.iter().map(|e| e.code()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]
+        );
+    }
+
+    #[test]
+    fn invalid_operand_fields() {
+        let e = DecodeError::InvalidDestination {
+            addressing: 3,
+            register: 9,
+        };
+        assert_eq!(e.addressing(), Some(3));
+        assert_eq!(e.register(), Some(9));
+        assert_eq!(DecodeError::MissingSource.register(), None);
+
+        #[allow(deprecated)]
+        let old = DecodeError::invalid_destination((3, 9));
+        assert_eq!(old, e);
+    }
+}
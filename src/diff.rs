@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::mem::{discriminant, Discriminant};
+
+use crate::instruction::Instruction;
+pub use crate::operand::Equality;
+
+/// The length of the opcode sequences that are compared
+const NGRAM_SIZE: usize = 3;
+
+/// Computes how similar two functions are based on the instructions they
+/// contain. The result is between 0.0 (nothing in common) and 1.0
+/// (identical opcode sequences).
+///
+/// Functions are compared by the multiset of opcode n-grams they contain
+/// rather than the exact instructions so that operands (registers,
+/// addresses and offsets) that commonly change between builds of the same
+/// code don't affect the score. Functions shorter than the n-gram size are
+/// compared by their whole opcode sequence
+pub fn similarity(f1: &[Instruction], f2: &[Instruction]) -> f32 {
+    similarity_by(f1, f2, Equality::Structural)
+}
+
+/// Computes similarity the same way as similarity but compares
+/// instructions using equality
+pub fn similarity_by(f1: &[Instruction], f2: &[Instruction], equality: Equality) -> f32 {
+    let n = NGRAM_SIZE.min(f1.len()).min(f2.len()).max(1);
+    let a = ngrams(f1, n, equality);
+    let b = ngrams(f2, n, equality);
+
+    let mut intersection = 0;
+    let mut union = 0;
+    for (ngram, count) in a.iter() {
+        let other = b.get(ngram).copied().unwrap_or(0);
+        intersection += (*count).min(other);
+        union += (*count).max(other);
+    }
+
+    for (ngram, count) in b.iter() {
+        if !a.contains_key(ngram) {
+            union += count;
+        }
+    }
+
+    if union == 0 {
+        return 1.0;
+    }
+
+    intersection as f32 / union as f32
+}
+
+fn ngrams(
+    f: &[Instruction],
+    n: usize,
+    equality: Equality,
+) -> HashMap<Vec<Discriminant<Instruction>>, usize> {
+    let mut counts = HashMap::new();
+    let opcodes: Vec<_> = f
+        .iter()
+        .map(|inst| match equality {
+            Equality::Structural => discriminant(inst),
+            Equality::Semantic => discriminant(&inst.normalize()),
+        })
+        .collect();
+    for window in opcodes.windows(n) {
+        *counts.entry(window.to_vec()).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jxx::Jnz;
+    use crate::operand::{Operand, OperandWidth};
+    use crate::single_operand::Push;
+    use crate::two_operand::{Add, Mov};
+
+    fn push(r: u8) -> Instruction {
+        Instruction::Push(Push::new(
+            Operand::RegisterDirect(r),
+            Some(OperandWidth::Word),
+        ))
+    }
+
+    fn mov(r: u8) -> Instruction {
+        Instruction::Mov(Mov::new(
+            Operand::RegisterDirect(r),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ))
+    }
+
+    fn add(r: u8) -> Instruction {
+        Instruction::Add(Add::new(
+            Operand::RegisterDirect(r),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ))
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(similarity(&[], &[]), 1.0);
+    }
+
+    #[test]
+    fn one_empty() {
+        assert_eq!(similarity(&[push(11)], &[]), 0.0);
+    }
+
+    #[test]
+    fn identical_ignoring_operands() {
+        let f1 = [push(11), mov(12), add(13), Instruction::Jnz(Jnz::new(-3))];
+        let f2 = [push(10), mov(9), add(8), Instruction::Jnz(Jnz::new(-5))];
+        assert_eq!(similarity(&f1, &f2), 1.0);
+    }
+
+    #[test]
+    fn different() {
+        let f1 = [push(11), push(11), push(11)];
+        let f2 = [add(11), add(11), add(11)];
+        assert_eq!(similarity(&f1, &f2), 0.0);
+    }
+
+    #[test]
+    fn partial() {
+        let f1 = [push(11), mov(12), add(13), add(13)];
+        let f2 = [push(11), mov(12), add(13), mov(13)];
+        assert_eq!(similarity(&f1, &f2), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn symmetric() {
+        let f1 = [push(11), mov(12), add(13), add(13), mov(1)];
+        let f2 = [push(11), mov(12), add(13), mov(13)];
+        assert_eq!(similarity(&f1, &f2), similarity(&f2, &f1));
+    }
+
+    #[test]
+    fn semantic() {
+        // add #1, r15 with an immediate word isn't emulated as inc
+        let add_immediate = Instruction::Add(Add::new(
+            Operand::Immediate(1),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ));
+        let inc = crate::decode(&[0x1f, 0x53]).unwrap();
+        let f1 = [push(11), add_immediate];
+        let f2 = [push(11), inc];
+        assert_eq!(similarity(&f1, &f2), 0.0);
+        assert_eq!(similarity_by(&f1, &f2, Equality::Semantic), 1.0);
+    }
+}
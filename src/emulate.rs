@@ -1,5 +1,5 @@
 use crate::instruction::Instruction;
-use crate::operand::{Operand, OperandWidth};
+use crate::operand::{Equality, Operand, OperandWidth};
 
 use crate::two_operand::*;
 use std::fmt;
@@ -8,7 +8,17 @@ use std::fmt;
 /// that the decoding step can determine if a decoded instruction emulates
 /// another
 pub trait Emulate {
-    fn emulate(&self) -> Option<Instruction>;
+    /// Returns the emulated instruction when the operands are exactly the
+    /// ones the emulated instruction is encoded with. This is what the
+    /// decoder uses
+    fn emulate(&self) -> Option<Instruction> {
+        self.emulate_by(Equality::Structural)
+    }
+
+    /// Returns the emulated instruction comparing the operands with
+    /// equality. Semantic equality also detects aliases written with an
+    /// immediate word (eg. `add #1, r15` is inc)
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction>;
 }
 
 /// All emulated instructions implement this trait to provide a common
@@ -29,6 +39,7 @@ pub trait Emulated {
 macro_rules! emulated {
     ($t:ident, $n:expr, $o:ident) => {
         #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
         pub struct $t {
             destination: Option<Operand>,
             operand_width: Option<OperandWidth>,
@@ -53,6 +64,11 @@ macro_rules! emulated {
                     original,
                 }
             }
+
+            /// Returns the instruction that this instruction emulates
+            pub fn original(&self) -> &$o {
+                &self.original
+            }
         }
 
         impl Emulated for $t {
@@ -78,10 +94,9 @@ macro_rules! emulated {
 
         impl fmt::Display for $t {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                if self.destination.is_none() && self.operand_width.is_none() {
-                    write!(f, "{}", $n)
-                } else {
-                    write!(f, "{} {}", self.mnemonic(), self.destination.unwrap())
+                match &self.destination {
+                    Some(destination) => write!(f, "{} {}", self.mnemonic(), destination),
+                    None => write!(f, "{}", self.mnemonic()),
                 }
             }
         }
@@ -109,6 +124,6 @@ emulated!(Rla, "rla", Add);
 emulated!(Rlc, "rlc", Addc);
 emulated!(Sbc, "sbc", Subc);
 emulated!(Setc, "setc", Bis);
-emulated!(Setn, "Setn", Bis);
+emulated!(Setn, "setn", Bis);
 emulated!(Setz, "setz", Bis);
 emulated!(Tst, "tst", Cmp);
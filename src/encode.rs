@@ -0,0 +1,431 @@
+use std::fmt;
+
+use crate::decode::parts::*;
+use crate::instruction::Instruction;
+use crate::jxx::{Jxx, OFFSET_RANGE};
+use crate::operand::{Operand, OperandWidth};
+use crate::single_operand::SingleOperand;
+use crate::two_operand::TwoOperand;
+
+/// Controls how instructions are encoded. The default options are the ones
+/// the encoder and the assembler use: immediates of 0, 1, 2, 4, 8 and -1
+/// are encoded with the constant generator and literal_immediates forces a
+/// literal word instead. Instruction::encode doesn't use the defaults
+/// because it guarantees that decoded instructions re-encode to their
+/// original bytes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EncodeOptions {
+    /// Encodes immediates as an additional word even when the constant
+    /// generator can produce the value. By default the constant generator
+    /// is used which is the shortest encoding and matches compiler output
+    pub literal_immediates: bool,
+    /// Keeps the instruction word bits the CPU ignores that were set when
+    /// the instruction was decoded. By default they are cleared
+    pub reserved_bits: bool,
+    /// Makes the assembler report a jump to a label that is out of range as
+    /// an error. By default the jump is relaxed to a branch
+    pub strict_jumps: bool,
+}
+
+/// Errors that can occur while encoding an instruction. Decoded
+/// instructions always encode but instructions that were built by hand may
+/// contain operands that have no encoding
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum EncodeError {
+    /// Present when an operand can't be encoded as a source (eg. a constant
+    /// the constant generator can't produce)
+    InvalidSource(Operand),
+    /// Present when an operand can't be encoded as a destination (eg. an
+    /// immediate)
+    InvalidDestination(Operand),
+    /// Present when a jxx offset (in words) doesn't fit in 10 bits
+    JumpOutOfRange(i16),
+    /// Present when a jump displacement (in bytes) is odd or too far for a
+    /// jxx instruction
+    InvalidDisplacement(i64),
+}
+
+impl EncodeError {
+    /// Returns a stable numeric code for the error. Encode errors use the
+    /// 400-499 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InvalidSource(_) => 400,
+            Self::InvalidDestination(_) => 401,
+            Self::JumpOutOfRange(_) => 402,
+            Self::InvalidDisplacement(_) => 403,
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSource(operand) => {
+                write!(f, "{} can't be encoded as a source", operand)
+            }
+            Self::InvalidDestination(operand) => {
+                write!(f, "{} can't be encoded as a destination", operand)
+            }
+            Self::JumpOutOfRange(offset) => {
+                write!(f, "jump offset {} is out of range", offset)
+            }
+            Self::InvalidDisplacement(displacement) if displacement % 2 != 0 => {
+                write!(f, "jump displacement {} is not word aligned", displacement)
+            }
+            Self::InvalidDisplacement(displacement) => {
+                write!(f, "jump displacement {} is out of range", displacement)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+fn width_bit(width: Option<OperandWidth>) -> u16 {
+    match width {
+        Some(OperandWidth::Byte) => 1,
+        Some(OperandWidth::Word) | None => 0,
+    }
+}
+
+/// The machine code of an instruction which is at most four words (three
+/// for the MSP430 and one more for an MSP430X extension word). This lives on
+/// the stack so encoding doesn't allocate
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Encoding {
+    bytes: [u8; 8],
+    len: usize,
+}
+
+impl Encoding {
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+fn words(words: &[Option<u16>]) -> Encoding {
+    let mut encoding = Encoding {
+        bytes: [0; 8],
+        len: 0,
+    };
+    for word in words.iter().flatten() {
+        encoding.bytes[encoding.len..encoding.len + 2].copy_from_slice(&word.to_le_bytes());
+        encoding.len += 2;
+    }
+
+    encoding
+}
+
+/// Encodes an MSP430X instruction from the words it was decoded from
+fn msp430x(instruction_words: impl Iterator<Item = u16>) -> Encoding {
+    let mut slots = [None; 4];
+    for (slot, word) in slots.iter_mut().zip(instruction_words) {
+        *slot = Some(word);
+    }
+    words(&slots)
+}
+
+fn single_operand(
+    opcode: u16,
+    inst: &dyn SingleOperand,
+    reserved: u16,
+) -> Result<Encoding, EncodeError> {
+    let (addressing, word, register) = inst.source().encode(true)?;
+    let first = SINGLE_OPERAND_PREFIX
+        | opcode << 7
+        | width_bit(*inst.operand_width()) << 6
+        | addressing << 4
+        | register as u16;
+
+    Ok(words(&[Some(first ^ reserved), word]))
+}
+
+fn jxx(condition: u16, inst: &dyn Jxx) -> Result<Encoding, EncodeError> {
+    let offset = inst.offset();
+    if !OFFSET_RANGE.contains(&offset) {
+        return Err(EncodeError::JumpOutOfRange(offset));
+    }
+
+    let word = JMP_INSTRUCTION | condition << 10 | (offset as u16 & JMP_OFFSET);
+    Ok(words(&[Some(word)]))
+}
+
+fn two_operand(opcode: u16, inst: &dyn TwoOperand) -> Result<Encoding, EncodeError> {
+    let (source_addressing, source_word, source_register) = inst.source().encode(true)?;
+    let (destination_addressing, destination_word, destination_register) =
+        inst.destination().encode(false)?;
+    let first = opcode << 12
+        | (source_register as u16) << 8
+        | destination_addressing << 7
+        | width_bit(Some(*inst.operand_width())) << 6
+        | source_addressing << 4
+        | destination_register as u16;
+
+    // the source's additional word comes before the destination's
+    Ok(words(&[Some(first), source_word, destination_word]))
+}
+
+/// Encodes an instruction to its machine code. Emulated instructions are
+/// encoded as the instruction they emulate. reserved is xored with the
+/// instruction word of single operand instructions (see
+/// SingleOperand::reserved)
+pub(crate) fn instruction(inst: &Instruction, reserved: u16) -> Result<Encoding, EncodeError> {
+    match inst {
+        Instruction::Rrc(inst) => single_operand(RRC_OPCODE, inst, reserved),
+        Instruction::Swpb(inst) => single_operand(SWPB_OPCODE, inst, reserved),
+        Instruction::Rra(inst) => single_operand(RRA_OPCODE, inst, reserved),
+        Instruction::Sxt(inst) => single_operand(SXT_OPCODE, inst, reserved),
+        Instruction::Push(inst) => single_operand(PUSH_OPCODE, inst, reserved),
+        Instruction::Call(inst) => single_operand(CALL_OPCODE, inst, reserved),
+        Instruction::Reti(_) => Ok(words(&[Some(
+            (SINGLE_OPERAND_PREFIX | RETI_OPCODE << 7) ^ reserved,
+        )])),
+        Instruction::Jnz(inst) => jxx(0, inst),
+        Instruction::Jz(inst) => jxx(1, inst),
+        Instruction::Jlo(inst) => jxx(2, inst),
+        Instruction::Jc(inst) => jxx(3, inst),
+        Instruction::Jn(inst) => jxx(4, inst),
+        Instruction::Jge(inst) => jxx(5, inst),
+        Instruction::Jl(inst) => jxx(6, inst),
+        Instruction::Jmp(inst) => jxx(7, inst),
+        Instruction::Mov(inst) => two_operand(MOV_OPCODE, inst),
+        Instruction::Add(inst) => two_operand(ADD_OPCODE, inst),
+        Instruction::Addc(inst) => two_operand(ADDC_OPCODE, inst),
+        Instruction::Subc(inst) => two_operand(SUBC_OPCODE, inst),
+        Instruction::Sub(inst) => two_operand(SUB_OPCODE, inst),
+        Instruction::Cmp(inst) => two_operand(CMP_OPCODE, inst),
+        Instruction::Dadd(inst) => two_operand(DADD_OPCODE, inst),
+        Instruction::Bit(inst) => two_operand(BIT_OPCODE, inst),
+        Instruction::Bic(inst) => two_operand(BIC_OPCODE, inst),
+        Instruction::Bis(inst) => two_operand(BIS_OPCODE, inst),
+        Instruction::Xor(inst) => two_operand(XOR_OPCODE, inst),
+        Instruction::And(inst) => two_operand(AND_OPCODE, inst),
+        Instruction::Adc(inst) => two_operand(ADDC_OPCODE, inst.original()),
+        Instruction::Br(inst) => two_operand(MOV_OPCODE, inst.original()),
+        Instruction::Clr(inst) => two_operand(MOV_OPCODE, inst.original()),
+        Instruction::Clrc(inst) => two_operand(BIC_OPCODE, inst.original()),
+        Instruction::Clrn(inst) => two_operand(BIC_OPCODE, inst.original()),
+        Instruction::Clrz(inst) => two_operand(BIC_OPCODE, inst.original()),
+        Instruction::Dadc(inst) => two_operand(DADD_OPCODE, inst.original()),
+        Instruction::Dec(inst) => two_operand(SUB_OPCODE, inst.original()),
+        Instruction::Decd(inst) => two_operand(SUB_OPCODE, inst.original()),
+        Instruction::Dint(inst) => two_operand(BIC_OPCODE, inst.original()),
+        Instruction::Eint(inst) => two_operand(BIS_OPCODE, inst.original()),
+        Instruction::Inc(inst) => two_operand(ADD_OPCODE, inst.original()),
+        Instruction::Incd(inst) => two_operand(ADD_OPCODE, inst.original()),
+        Instruction::Inv(inst) => two_operand(XOR_OPCODE, inst.original()),
+        Instruction::Nop(inst) => two_operand(MOV_OPCODE, inst.original()),
+        Instruction::Pop(inst) => two_operand(MOV_OPCODE, inst.original()),
+        Instruction::Ret(inst) => two_operand(MOV_OPCODE, inst.original()),
+        Instruction::Rla(inst) => two_operand(ADD_OPCODE, inst.original()),
+        Instruction::Rlc(inst) => two_operand(ADDC_OPCODE, inst.original()),
+        Instruction::Sbc(inst) => two_operand(SUBC_OPCODE, inst.original()),
+        Instruction::Setc(inst) => two_operand(BIS_OPCODE, inst.original()),
+        Instruction::Setn(inst) => two_operand(BIS_OPCODE, inst.original()),
+        Instruction::Setz(inst) => two_operand(BIS_OPCODE, inst.original()),
+        Instruction::Tst(inst) => two_operand(CMP_OPCODE, inst.original()),
+        Instruction::Extended(inst) => Ok(msp430x(inst.words())),
+        Instruction::Mova(inst) => Ok(msp430x(inst.words())),
+        Instruction::Cmpa(inst) => Ok(msp430x(inst.words())),
+        Instruction::Adda(inst) => Ok(msp430x(inst.words())),
+        Instruction::Suba(inst) => Ok(msp430x(inst.words())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::jxx::*;
+    use crate::single_operand::Reti;
+    use crate::two_operand::{Cmp, Mov};
+
+    const LITERAL: EncodeOptions = EncodeOptions {
+        literal_immediates: true,
+        reserved_bits: false,
+        strict_jumps: false,
+    };
+
+    #[test]
+    fn round_trip() {
+        for word in 0..=u16::MAX {
+            let [a, b] = word.to_le_bytes();
+            let data = [a, b, 0x34, 0x12, 0x78, 0x56];
+            let inst = match decode(&data) {
+                Ok(inst) => inst,
+                Err(_) => continue,
+            };
+
+            assert_eq!(
+                inst.original_encoding(),
+                Ok(data[..inst.size()].to_vec()),
+                "{:#06x} {}",
+                word,
+                inst
+            );
+            assert_eq!(inst.encode(), inst.original_encoding());
+        }
+    }
+
+    #[test]
+    fn reserved_bits() {
+        // swpb r15 with the width bit set and reti with its operand bits set
+        let swpb = decode(&[0xcf, 0x10]).unwrap();
+        assert_eq!(swpb.to_string(), "swpb r15");
+        assert_eq!(swpb.original_encoding(), Ok(vec![0xcf, 0x10]));
+        assert_eq!(swpb.canonical_encoding(), Ok(vec![0x8f, 0x10]));
+        let options = EncodeOptions {
+            reserved_bits: true,
+            ..Default::default()
+        };
+        assert_eq!(swpb.encode_with(&options), Ok(vec![0xcf, 0x10]));
+        assert_eq!(swpb.encode_with(&LITERAL), Ok(vec![0x8f, 0x10]));
+
+        let reti = decode(&[0x0f, 0x13]).unwrap();
+        assert_eq!(reti.original_encoding(), Ok(vec![0x0f, 0x13]));
+        assert_eq!(reti.canonical_encoding(), Ok(vec![0x00, 0x13]));
+        assert!(reti.semantically_eq(&Instruction::Reti(Reti::new())));
+    }
+
+    #[test]
+    fn hand_built() {
+        let inst = Instruction::Mov(Mov::new(
+            Operand::Immediate(0x4400),
+            OperandWidth::Word,
+            Operand::RegisterDirect(1),
+        ));
+        assert_eq!(inst.encode(), Ok(vec![0x31, 0x40, 0x00, 0x44]));
+    }
+
+    #[test]
+    fn constant_generator() {
+        // mov.b #0x104, r15 only uses the low byte which is a constant
+        let inst = Instruction::Mov(Mov::new(
+            Operand::Immediate(0x104),
+            OperandWidth::Byte,
+            Operand::RegisterDirect(15),
+        ));
+        assert_eq!(inst.canonical_encoding(), Ok(vec![0x6f, 0x42]));
+        assert_eq!(inst.encode(), Ok(vec![0x7f, 0x40, 0x04, 0x01]));
+
+        let inst = Instruction::Cmp(Cmp::new(
+            Operand::Immediate(0xffff),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ));
+        assert_eq!(inst.canonical_encoding(), Ok(vec![0x3f, 0x93]));
+    }
+
+    #[test]
+    fn constant_generator_default() {
+        // mov #1, r15
+        let inst = Instruction::Mov(Mov::new(
+            Operand::Immediate(1),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ));
+        assert_eq!(
+            inst.encode_with(&EncodeOptions::default()),
+            Ok(vec![0x1f, 0x43])
+        );
+        assert_eq!(inst.encode_with(&LITERAL), Ok(vec![0x3f, 0x40, 0x01, 0x00]));
+
+        // a decoded literal word stays a literal word with encode
+        let data = [0x3f, 0x40, 0x01, 0x00];
+        let decoded = decode(&data).unwrap();
+        assert_eq!(decoded.encode(), Ok(data.to_vec()));
+        assert_eq!(decoded.canonical_encoding(), Ok(vec![0x1f, 0x43]));
+    }
+
+    #[test]
+    fn invalid_destination() {
+        let inst = Instruction::Mov(Mov::new(
+            Operand::RegisterDirect(4),
+            OperandWidth::Word,
+            Operand::Immediate(0x4400),
+        ));
+        assert_eq!(
+            inst.encode(),
+            Err(EncodeError::InvalidDestination(Operand::Immediate(0x4400)))
+        );
+    }
+
+    #[test]
+    fn invalid_constant() {
+        let inst = Instruction::Mov(Mov::new(
+            Operand::Constant(3),
+            OperandWidth::Word,
+            Operand::RegisterDirect(4),
+        ));
+        assert_eq!(
+            inst.encode(),
+            Err(EncodeError::InvalidSource(Operand::Constant(3)))
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        // jnz, jz, jlo, jc, jn, jge, jl and jmp to the jump itself
+        let jumps = [
+            Instruction::Jnz(Jnz::new(-1)),
+            Instruction::Jz(Jz::new(-1)),
+            Instruction::Jlo(Jlo::new(-1)),
+            Instruction::Jc(Jc::new(-1)),
+            Instruction::Jn(Jn::new(-1)),
+            Instruction::Jge(Jge::new(-1)),
+            Instruction::Jl(Jl::new(-1)),
+            Instruction::Jmp(Jmp::new(-1)),
+        ];
+        for (condition, jump) in jumps.iter().enumerate() {
+            let word = 0x23ff | (condition as u16) << 10;
+            assert_eq!(jump.encode(), Ok(word.to_le_bytes().to_vec()), "{}", jump);
+        }
+    }
+
+    #[test]
+    fn into_buffers() {
+        // mov #0x4400, sp; ret
+        let program = [
+            decode(&[0x31, 0x40, 0x00, 0x44]).unwrap(),
+            decode(&[0x30, 0x41]).unwrap(),
+        ];
+        let mut output = vec![0xff];
+        for inst in &program {
+            inst.encode_into(&mut output, &LITERAL).unwrap();
+        }
+        assert_eq!(output, vec![0xff, 0x31, 0x40, 0x00, 0x44, 0x30, 0x41]);
+
+        let invalid = Instruction::Mov(Mov::new(
+            Operand::RegisterDirect(4),
+            OperandWidth::Word,
+            Operand::Immediate(0x10),
+        ));
+        assert_eq!(
+            invalid.encode_into(&mut output, &LITERAL),
+            Err(EncodeError::InvalidDestination(Operand::Immediate(0x10)))
+        );
+        assert_eq!(output.len(), 7);
+
+        let mut writer = std::io::Cursor::new(vec![]);
+        program[0].encode_to_writer(&mut writer, &LITERAL).unwrap();
+        assert_eq!(writer.into_inner(), vec![0x31, 0x40, 0x00, 0x44]);
+        let e = invalid
+            .encode_to_writer(&mut std::io::sink(), &LITERAL)
+            .unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn jump_out_of_range() {
+        assert_eq!(
+            Instruction::Jmp(Jmp::new(-512)).encode(),
+            Ok(vec![0x00, 0x3e])
+        );
+        assert_eq!(
+            Instruction::Jmp(Jmp::new(512)).encode(),
+            Err(EncodeError::JumpOutOfRange(512))
+        );
+    }
+}
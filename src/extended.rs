@@ -0,0 +1,483 @@
+use std::fmt::{self, Write};
+
+use crate::decode::{parts, Cursor};
+use crate::decode_error::DecodeError;
+use crate::format::{self, FormatOptions};
+use crate::instruction::Instruction;
+use crate::isa_coverage::Form;
+use crate::operand::{Operand, OperandWidth};
+use crate::Result;
+
+/// The fixed high bits (00011) of an MSP430X extension word
+pub(crate) const EXTENSION_PREFIX: u16 = 0b0001_1000_0000_0000;
+pub(crate) const EXTENSION_MASK: u16 = 0b1111_1000_0000_0000;
+
+const AL: u16 = 0b100_0000;
+const ZC: u16 = 0b1_0000_0000;
+const REPEAT_REGISTER: u16 = 0b1000_0000;
+const REPEAT: u16 = 0b1111;
+const SOURCE_HIGH: u16 = 0b111_1000_0000;
+const DESTINATION_HIGH: u16 = 0b1111;
+
+/// The width of an extended instruction which is selected by the A/L bit of
+/// the extension word together with the B/W bit of the instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExtendedWidth {
+    Byte,
+    Word,
+    /// A 20 bit address
+    Address,
+}
+
+/// How many times an extended instruction whose operands are all registers
+/// is executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Repetition {
+    /// Executed the given number of times (2-16)
+    Count(u8),
+    /// Executed the number of times held in the low four bits of the
+    /// register plus one
+    Register(u8),
+}
+
+/// An MSP430X instruction made up of an extension word (0x1800-0x1fff)
+/// followed by a format I or format II instruction. The extension word
+/// supplies the upper four bits of 20 bit operands, the address width and
+/// for register operands a repetition count
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Extended {
+    extension: u16,
+    // the words of the instruction are kept rather than the Instruction
+    // itself which would make Instruction a recursive type
+    words: [u16; 3],
+    len: u8,
+}
+
+impl Extended {
+    /// Returns the extension word
+    pub fn extension(&self) -> u16 {
+        self.extension
+    }
+
+    /// Returns the instruction that follows the extension word with its 16
+    /// bit operands
+    pub fn instruction(&self) -> Instruction {
+        let mut data = [0; 6];
+        for (bytes, word) in data.chunks_exact_mut(2).zip(&self.words) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+
+        // the words were validated when the instruction was decoded and
+        // decoding is pure so this can't fail
+        crate::decode(&data[..self.len as usize * 2]).expect("extended instruction is valid")
+    }
+
+    /// Returns the size of the instruction (in bytes) including the
+    /// extension word
+    pub fn size(&self) -> usize {
+        2 + self.len as usize * 2
+    }
+
+    /// Returns the extension word followed by the words of the instruction
+    pub fn words(&self) -> impl Iterator<Item = u16> + '_ {
+        std::iter::once(self.extension).chain(self.words[..self.len as usize].iter().copied())
+    }
+
+    /// Returns whether every operand is a register in which case the
+    /// extension word holds a repetition count rather than operand bits
+    pub fn register_mode(&self) -> bool {
+        register_mode(&self.instruction())
+    }
+
+    pub fn width(&self) -> ExtendedWidth {
+        width(self.extension, &self.instruction()).expect("extended width was validated")
+    }
+
+    /// Returns how many times the instruction is repeated or None if it is
+    /// executed once
+    pub fn repetition(&self) -> Option<Repetition> {
+        if !self.register_mode() {
+            return None;
+        }
+
+        let repeat = (self.extension & REPEAT) as u8;
+        match self.extension & REPEAT_REGISTER {
+            0 if repeat == 0 => None,
+            0 => Some(Repetition::Count(repeat + 1)),
+            _ => Some(Repetition::Register(repeat)),
+        }
+    }
+
+    /// Returns whether the carry is treated as zero (eg. rrux)
+    pub fn zero_carry(&self) -> bool {
+        self.register_mode() && self.extension & ZC != 0
+    }
+
+    /// Returns the 20 bit value of the source when it has an additional
+    /// word (immediates, indexed, symbolic and absolute operands)
+    pub fn source_value(&self) -> Option<u32> {
+        let high = (self.extension & SOURCE_HIGH) >> 7;
+        value(self.instruction().source()?, high)
+    }
+
+    /// Returns the 20 bit value of the destination when it has an
+    /// additional word (indexed, symbolic and absolute operands). Format II
+    /// instructions use the destination bits of the extension word for
+    /// their only operand
+    pub fn destination_value(&self) -> Option<u32> {
+        let high = self.extension & DESTINATION_HIGH;
+        let inst = self.instruction();
+        match inst.destination() {
+            Some(destination) => value(destination, high),
+            None => value(inst.source()?, high),
+        }
+    }
+
+    /// Returns the instruction with the extension word bits the CPU ignores
+    /// cleared. These are the reserved bits, the high bits of operands
+    /// without an additional word and the zero carry bit of instructions
+    /// that don't use the carry
+    pub(crate) fn normalize(&self) -> Extended {
+        let inst = self.instruction();
+        let carry = Form::of(&inst)
+            .is_some_and(|form| matches!(form.opcode, "addc" | "subc" | "dadd" | "rrc"));
+        let high = |value: Option<u32>| value.map_or(0, |value| (value >> 16) as u8);
+        let source_high = match inst.destination() {
+            Some(_) => high(self.source_value()),
+            None => 0,
+        };
+        let high = (source_high, high(self.destination_value()));
+
+        extend(
+            &inst,
+            self.extension & AL != 0,
+            high,
+            self.repetition(),
+            carry && self.zero_carry(),
+        )
+        .expect("extended instruction is valid")
+    }
+
+    /// Returns the mnemonic including the width (eg. movx.a)
+    pub fn mnemonic(&self) -> &'static str {
+        let opcode = Form::of(&self.instruction())
+            .map(|form| form.opcode)
+            .unwrap_or_default();
+        let opcode = match opcode {
+            "rrc" if self.zero_carry() => "rru",
+            _ => opcode,
+        };
+
+        macro_rules! mnemonics {
+            ($($name:literal),*) => {
+                match (opcode, self.width()) {
+                    $(
+                        ($name, ExtendedWidth::Byte) => concat!($name, "x.b"),
+                        ($name, ExtendedWidth::Word) => concat!($name, "x"),
+                        ($name, ExtendedWidth::Address) => concat!($name, "x.a"),
+                    )*
+                    _ => "",
+                }
+            };
+        }
+
+        mnemonics!(
+            "mov", "add", "addc", "subc", "sub", "cmp", "dadd", "bit", "bic", "bis", "xor", "and",
+            "rrc", "rru", "swpb", "rra", "sxt", "push"
+        )
+    }
+}
+
+/// Returns the value of an operand's additional word extended to 20 bits
+/// with the high bits from the extension word
+fn value(operand: &Operand, high: u16) -> Option<u32> {
+    let low = match operand {
+        Operand::Indexed { offset: i, .. } | Operand::Symbolic(i) => *i as u16,
+        Operand::Immediate(i) | Operand::Absolute(i) => *i,
+        _ => return None,
+    };
+
+    Some((high as u32) << 16 | low as u32)
+}
+
+fn register_mode(inst: &Instruction) -> bool {
+    let register =
+        |operand: Option<&Operand>| matches!(operand, None | Some(Operand::RegisterDirect(_)));
+    register(inst.source()) && register(inst.destination())
+}
+
+/// Returns the width selected by the A/L bit and the B/W bit of the
+/// instruction. swpb and sxt have no B/W bit and A/L alone selects between
+/// word and address
+fn width(extension: u16, inst: &Instruction) -> Option<ExtendedWidth> {
+    let al = extension & AL != 0;
+    let byte = inst.operand_width() == Some(OperandWidth::Byte);
+    match (inst, al, byte) {
+        (Instruction::Swpb(_) | Instruction::Sxt(_), true, _) => Some(ExtendedWidth::Word),
+        (Instruction::Swpb(_) | Instruction::Sxt(_), false, _) => Some(ExtendedWidth::Address),
+        (_, true, false) => Some(ExtendedWidth::Word),
+        (_, true, true) => Some(ExtendedWidth::Byte),
+        (_, false, true) => Some(ExtendedWidth::Address),
+        (_, false, false) => None,
+    }
+}
+
+/// Parses the instruction that follows an extension word that has already
+/// been parsed. Jumps, call, reti, address instructions and another
+/// extension word can't be extended
+pub(crate) fn decode(extension: u16, data: &[u8]) -> Result<Extended> {
+    let (_, first_word) = parts::instruction_word(Cursor::new(data))?;
+    if first_word & EXTENSION_MASK == EXTENSION_PREFIX {
+        return Err(DecodeError::InvalidExtension(extension));
+    }
+
+    let (input, inst) = parts::instruction(Cursor::new(data))?;
+    let extendable = !matches!(
+        inst,
+        Instruction::Call(_)
+            | Instruction::Reti(_)
+            | Instruction::Mova(_)
+            | Instruction::Cmpa(_)
+            | Instruction::Adda(_)
+            | Instruction::Suba(_)
+    ) && inst.jump_offset().is_none();
+    if !extendable || width(extension, &inst).is_none() {
+        return Err(DecodeError::InvalidExtension(extension));
+    }
+
+    let mut words = [0; 3];
+    for (word, bytes) in words.iter_mut().zip(data[..input.offset()].chunks_exact(2)) {
+        *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+    }
+
+    Ok(Extended {
+        extension,
+        words,
+        len: (input.offset() / 2) as u8,
+    })
+}
+
+/// Builds an extended instruction from the instruction it extends, whose
+/// operands hold the low 16 bits of their values, and the fields of the
+/// extension word. The high bits are ignored when every operand is a
+/// register and the repetition and zero carry are only allowed then.
+/// Returns None when they don't make a valid extended instruction
+pub(crate) fn extend(
+    inst: &Instruction,
+    al: bool,
+    high: (u8, u8),
+    repetition: Option<Repetition>,
+    zero_carry: bool,
+) -> Option<Extended> {
+    let mut extension = EXTENSION_PREFIX;
+    if al {
+        extension |= AL;
+    }
+
+    if register_mode(inst) {
+        if zero_carry {
+            extension |= ZC;
+        }
+        extension |= match repetition {
+            None => 0,
+            Some(Repetition::Count(count @ 1..=16)) => count as u16 - 1,
+            Some(Repetition::Register(r @ 0..=15)) => REPEAT_REGISTER | r as u16,
+            Some(_) => return None,
+        };
+    } else if repetition.is_some() || zero_carry || high.0 > 0xf || high.1 > 0xf {
+        return None;
+    } else {
+        extension |= (high.0 as u16) << 7 | high.1 as u16;
+    }
+
+    let data = inst.encode().ok()?;
+    decode(extension, &data).ok()
+}
+
+fn write_operand<W: Write>(
+    w: &mut W,
+    operand: &Operand,
+    value: Option<u32>,
+    options: &FormatOptions,
+) -> fmt::Result {
+    let Some(value) = value else {
+        return format::write_operand(w, operand, options);
+    };
+
+    // indexed and symbolic offsets are signed 20 bit values
+    let signed = |w: &mut W| {
+        if value & 0x80000 == 0 {
+            write!(w, "{:#x}", value)
+        } else {
+            write!(w, "-{:#x}", 0x100000 - value)
+        }
+    };
+
+    match operand {
+        Operand::Indexed { register: r, .. } => {
+            signed(w)?;
+            w.write_char('(')?;
+            format::write_operand(w, &Operand::RegisterDirect(*r), options)?;
+            w.write_char(')')
+        }
+        Operand::Symbolic(_) => {
+            w.write_char('#')?;
+            signed(w)?;
+            w.write_str("(pc)")
+        }
+        Operand::Immediate(_) => write!(w, "#{:#x}", value),
+        _ => write!(w, "&{:#x}", value),
+    }
+}
+
+impl Extended {
+    /// Writes the instruction with the repetition prefix and 20 bit
+    /// operands. This backs both Display and format::write_instruction
+    pub(crate) fn write<W: Write>(&self, w: &mut W, options: &FormatOptions) -> fmt::Result {
+        match self.repetition() {
+            Some(Repetition::Count(count)) => write!(w, "rpt #{} ", count)?,
+            Some(Repetition::Register(r)) => write!(w, "rpt r{} ", r)?,
+            None => {}
+        }
+
+        if options.uppercase {
+            for c in self.mnemonic().chars() {
+                w.write_char(c.to_ascii_uppercase())?;
+            }
+        } else {
+            w.write_str(self.mnemonic())?;
+        }
+
+        let inst = self.instruction();
+        match (inst.source(), inst.destination()) {
+            (Some(source), Some(destination)) => {
+                w.write_char(' ')?;
+                write_operand(w, source, self.source_value(), options)?;
+                w.write_str(", ")?;
+                write_operand(w, destination, self.destination_value(), options)
+            }
+            (Some(source), None) => {
+                w.write_char(' ')?;
+                write_operand(w, source, self.destination_value(), options)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Extended {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &FormatOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode;
+    use crate::instruction::Instruction;
+
+    use super::*;
+
+    fn extended(data: &[u8]) -> Extended {
+        match decode(data).unwrap() {
+            Instruction::Extended(inst) => inst,
+            inst => panic!("{} is not extended", inst),
+        }
+    }
+
+    #[test]
+    fn address_operands() {
+        // movx.a #0x12345, &0x23456
+        let inst = extended(&[0x82, 0x18, 0xf2, 0x40, 0x45, 0x23, 0x56, 0x34]);
+        assert_eq!(inst.width(), ExtendedWidth::Address);
+        assert_eq!(inst.source_value(), Some(0x12345));
+        assert_eq!(inst.destination_value(), Some(0x23456));
+        assert_eq!(inst.size(), 8);
+        assert_eq!(inst.to_string(), "movx.a #0x12345, &0x23456");
+
+        let options = FormatOptions {
+            uppercase: true,
+            ..Default::default()
+        };
+        let mut s = String::new();
+        inst.write(&mut s, &options).unwrap();
+        assert_eq!(s, "MOVX.A #0x12345, &0x23456");
+    }
+
+    #[test]
+    fn widths() {
+        // movx r4, r5; movx.b r4, r5; movx.a r4, r5
+        assert_eq!(
+            extended(&[0x40, 0x18, 0x05, 0x44]).to_string(),
+            "movx r4, r5"
+        );
+        assert_eq!(
+            extended(&[0x40, 0x18, 0x45, 0x44]).to_string(),
+            "movx.b r4, r5"
+        );
+        assert_eq!(
+            extended(&[0x00, 0x18, 0x45, 0x44]).to_string(),
+            "movx.a r4, r5"
+        );
+        // swpbx.a r5
+        assert_eq!(
+            extended(&[0x00, 0x18, 0x85, 0x10]).to_string(),
+            "swpbx.a r5"
+        );
+    }
+
+    #[test]
+    fn single_operand() {
+        // pushx.a &0x12345
+        let inst = extended(&[0x01, 0x18, 0x52, 0x12, 0x45, 0x23]);
+        assert_eq!(inst.destination_value(), Some(0x12345));
+        assert_eq!(inst.to_string(), "pushx.a &0x12345");
+        // pushx -0x2(r4) with the offset sign extended to 20 bits
+        let inst = extended(&[0x4f, 0x18, 0x14, 0x12, 0xfe, 0xff]);
+        assert_eq!(inst.to_string(), "pushx -0x2(r4)");
+    }
+
+    #[test]
+    fn repetition() {
+        // rpt #4 rrax r5
+        let inst = extended(&[0x43, 0x18, 0x05, 0x11]);
+        assert_eq!(inst.repetition(), Some(Repetition::Count(4)));
+        assert_eq!(inst.to_string(), "rpt #4 rrax r5");
+        // rpt r7 rrux r5
+        let inst = extended(&[0xc7, 0x19, 0x05, 0x10]);
+        assert_eq!(inst.repetition(), Some(Repetition::Register(7)));
+        assert!(inst.zero_carry());
+        assert_eq!(inst.to_string(), "rpt r7 rrux r5");
+    }
+
+    #[test]
+    fn invalid() {
+        // an extended jmp, call, mova and a reserved width
+        assert_eq!(
+            decode(&[0x40, 0x18, 0xff, 0x3f]),
+            Err(DecodeError::InvalidExtension(0x1840))
+        );
+        assert_eq!(
+            decode(&[0x40, 0x18, 0x85, 0x12]),
+            Err(DecodeError::InvalidExtension(0x1840))
+        );
+        assert_eq!(
+            decode(&[0x40, 0x18, 0xc4, 0x05]),
+            Err(DecodeError::InvalidExtension(0x1840))
+        );
+        assert_eq!(
+            decode(&[0x00, 0x18, 0x05, 0x44]),
+            Err(DecodeError::InvalidExtension(0x1800))
+        );
+        assert_eq!(decode(&[0x40, 0x18]), Err(DecodeError::MissingInstruction));
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = [0x82, 0x18, 0xf2, 0x40, 0x45, 0x23, 0x56, 0x34];
+        assert_eq!(decode(&data).unwrap().encode(), Ok(data.to_vec()));
+    }
+}
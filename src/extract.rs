@@ -0,0 +1,259 @@
+use std::fmt;
+
+use crate::decode_at;
+use crate::decode_error::DecodeError;
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+use crate::target;
+
+/// Errors that can occur while extracting a function
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ExtractError {
+    /// Present when the range isn't contained within the image. Contains
+    /// the start and end of the range
+    OutOfRange((u32, u32)),
+    /// Present when an instruction in the range doesn't decode
+    Decode(DecodeError),
+}
+
+impl ExtractError {
+    /// Returns a stable numeric code for the error. Extract errors use the
+    /// 1000-1099 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::OutOfRange(_) => 1000,
+            Self::Decode(_) => 1001,
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange((start, end)) => {
+                write!(f, "range {:#x}-{:#x} is outside of the image", start, end)
+            }
+            Self::Decode(e) => write!(f, "function: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Describes how an external reference is encoded which determines how it
+/// needs to be fixed up when the blob is moved
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RelocationKind {
+    /// An immediate call target (`call #addr`)
+    Call,
+    /// An immediate branch target (`br #addr`)
+    Branch,
+    /// An absolute data reference (`&addr`)
+    Absolute,
+    /// A PC relative data reference (`addr(pc)`) to an address outside of the
+    /// function
+    Symbolic,
+    /// A jxx instruction whose target is outside of the function
+    Jump,
+}
+
+/// A reference from a function to an address that needs to be fixed up if
+/// the function is re-injected at another address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Relocation {
+    /// The offset into the blob of the word that encodes the reference. For
+    /// jumps this is the instruction word itself
+    pub offset: usize,
+    /// The address that is referenced
+    pub target: u32,
+    pub kind: RelocationKind,
+}
+
+/// The bytes of a function extracted from an image along with all of its
+/// external references
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blob {
+    /// The address the function was extracted from
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// Extracts the function that spans the address range [start, end) from an
+/// image that is loaded at base. The range is decoded linearly so it must
+/// only contain code. Absolute references (calls, branches and data) are
+/// always reported because they are encoded independent of the location of
+/// the function. Relative references (jumps and symbolic operands) are only
+/// reported when they point outside of the function
+pub fn extract(image: &[u8], base: u32, start: u32, end: u32) -> Result<Blob, ExtractError> {
+    let range = start
+        .checked_sub(base)
+        .zip(end.checked_sub(base))
+        .and_then(|(from, to)| image.get(from as usize..to as usize));
+    let bytes = range.ok_or(ExtractError::OutOfRange((start, end)))?;
+    let mut relocations = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = start + offset as u32;
+        let inst = decode_at(&bytes[offset..], address).map_err(ExtractError::Decode)?;
+
+        if let Some(jump) = inst.jump_offset() {
+            let target = target::jump_target(address, jump);
+            if target < start || target >= end {
+                relocations.push(Relocation {
+                    offset,
+                    target,
+                    kind: RelocationKind::Jump,
+                });
+            }
+        }
+
+        // extension words follow the instruction word with the source
+        // word first, if present
+        let mut word_offset = offset + 2;
+        let operands = [inst.source(), inst.destination()];
+        for (i, operand) in operands.iter().enumerate() {
+            let operand = match operand {
+                Some(operand) => operand,
+                None => continue,
+            };
+
+            let word_address = start + word_offset as u32;
+            let reference = match operand {
+                Operand::Immediate(target) => match (&inst, i) {
+                    (Instruction::Call(_), 0) => Some((*target as u32, RelocationKind::Call)),
+                    (Instruction::Br(_), 0) => Some((*target as u32, RelocationKind::Branch)),
+                    _ => None,
+                },
+                Operand::Absolute(target) => Some((*target as u32, RelocationKind::Absolute)),
+                Operand::Symbolic(index) => {
+                    let target = (word_address as u16).wrapping_add(*index as u16) as u32;
+                    if target < start || target >= end {
+                        Some((target, RelocationKind::Symbolic))
+                    } else {
+                        None
+                    }
+                }
+                _ => None,
+            };
+
+            if let Some((target, kind)) = reference {
+                relocations.push(Relocation {
+                    offset: word_offset,
+                    target,
+                    kind,
+                });
+            }
+
+            word_offset += operand.size();
+        }
+
+        offset += inst.size();
+    }
+
+    Ok(Blob {
+        address: start,
+        bytes: bytes.to_vec(),
+        relocations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_relocations() {
+        // push r11; pop r11; ret
+        let image = [0x0b, 0x12, 0x3b, 0x41, 0x30, 0x41];
+        let blob = extract(&image, 0x4400, 0x4400, 0x4406).unwrap();
+        assert_eq!(blob.bytes, image.to_vec());
+        assert_eq!(blob.relocations, vec![]);
+    }
+
+    #[test]
+    fn call_and_absolute() {
+        // ret; call #0x4400; mov &0x0200, r15; ret
+        let image = [
+            0x30, 0x41, 0xb0, 0x12, 0x00, 0x44, 0x1f, 0x42, 0x00, 0x02, 0x30, 0x41,
+        ];
+        let blob = extract(&image, 0x4400, 0x4402, 0x440c).unwrap();
+        assert_eq!(blob.address, 0x4402);
+        assert_eq!(blob.bytes, image[2..].to_vec());
+        assert_eq!(
+            blob.relocations,
+            vec![
+                Relocation {
+                    offset: 2,
+                    target: 0x4400,
+                    kind: RelocationKind::Call
+                },
+                Relocation {
+                    offset: 6,
+                    target: 0x0200,
+                    kind: RelocationKind::Absolute
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        // jmp $-2 (outside); jmp $+2 (inside); ret
+        let image = [0xfe, 0x3f, 0x00, 0x3c, 0x30, 0x41];
+        let blob = extract(&image, 0x4400, 0x4400, 0x4406).unwrap();
+        assert_eq!(
+            blob.relocations,
+            vec![Relocation {
+                offset: 0,
+                target: 0x43fe,
+                kind: RelocationKind::Jump
+            }]
+        );
+    }
+
+    #[test]
+    fn out_of_range() {
+        let image = [0x30, 0x41];
+        assert_eq!(
+            extract(&image, 0x4400, 0x4400, 0x4410),
+            Err(ExtractError::OutOfRange((0x4400, 0x4410)))
+        );
+        assert_eq!(
+            extract(&image, 0x4400, 0x43fe, 0x4402),
+            Err(ExtractError::OutOfRange((0x43fe, 0x4402)))
+        );
+        assert_eq!(
+            extract(&image, 0x4400, 0x4402, 0x4400),
+            Err(ExtractError::OutOfRange((0x4402, 0x4400)))
+        );
+        assert_eq!(
+            extract(&[0x80, 0x13], 0x4400, 0x4400, 0x4402),
+            Err(ExtractError::Decode(DecodeError::InvalidOpcode(7)))
+        );
+    }
+
+    #[test]
+    fn symbolic() {
+        // mov 0x10(pc), r15
+        let image = [0x1f, 0x40, 0x10, 0x00];
+        let blob = extract(&image, 0x4400, 0x4400, 0x4404).unwrap();
+        assert_eq!(
+            blob.relocations,
+            vec![Relocation {
+                offset: 2,
+                target: 0x4412,
+                kind: RelocationKind::Symbolic
+            }]
+        );
+    }
+}
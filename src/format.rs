@@ -0,0 +1,206 @@
+use std::fmt::{self, Write};
+
+use crate::address;
+use crate::emulate::Emulated;
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+
+/// Controls how instructions are rendered by write_instruction. The default
+/// matches the Display implementation of Instruction
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct FormatOptions {
+    /// Renders mnemonics in upper case (eg. MOV.B)
+    pub uppercase: bool,
+    /// Renders pc, sp, sr and cg as r0-r3
+    pub numeric_registers: bool,
+    /// Renders jnz, jz, jlo and jc with their synonyms jne, jeq, jnc and jhs
+    pub alternate_jumps: bool,
+}
+
+/// Returns the mnemonic of an instruction with the jump synonyms selected
+/// by options
+fn mnemonic<'a>(inst: &'a Instruction, options: &FormatOptions) -> &'a str {
+    if !options.alternate_jumps {
+        return inst.mnemonic();
+    }
+
+    match inst {
+        Instruction::Jnz(_) => "jne",
+        Instruction::Jz(_) => "jeq",
+        Instruction::Jlo(_) => "jnc",
+        Instruction::Jc(_) => "jhs",
+        _ => inst.mnemonic(),
+    }
+}
+
+/// Returns the operands in the order they are displayed. Emulated
+/// instructions only display their destination
+pub(crate) fn operands(inst: &Instruction) -> (Option<&Operand>, Option<&Operand>) {
+    match inst {
+        Instruction::Adc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Br(inst) => (None, inst.destination().as_ref()),
+        Instruction::Clr(inst) => (None, inst.destination().as_ref()),
+        Instruction::Clrc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Clrn(inst) => (None, inst.destination().as_ref()),
+        Instruction::Clrz(inst) => (None, inst.destination().as_ref()),
+        Instruction::Dadc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Dec(inst) => (None, inst.destination().as_ref()),
+        Instruction::Decd(inst) => (None, inst.destination().as_ref()),
+        Instruction::Dint(inst) => (None, inst.destination().as_ref()),
+        Instruction::Eint(inst) => (None, inst.destination().as_ref()),
+        Instruction::Inc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Incd(inst) => (None, inst.destination().as_ref()),
+        Instruction::Inv(inst) => (None, inst.destination().as_ref()),
+        Instruction::Nop(inst) => (None, inst.destination().as_ref()),
+        Instruction::Pop(inst) => (None, inst.destination().as_ref()),
+        Instruction::Ret(inst) => (None, inst.destination().as_ref()),
+        Instruction::Rla(inst) => (None, inst.destination().as_ref()),
+        Instruction::Rlc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Sbc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Setc(inst) => (None, inst.destination().as_ref()),
+        Instruction::Setn(inst) => (None, inst.destination().as_ref()),
+        Instruction::Setz(inst) => (None, inst.destination().as_ref()),
+        Instruction::Tst(inst) => (None, inst.destination().as_ref()),
+        _ => (inst.source(), inst.destination()),
+    }
+}
+
+pub(crate) fn write_operand<W: Write>(
+    w: &mut W,
+    operand: &Operand,
+    options: &FormatOptions,
+) -> fmt::Result {
+    if !options.numeric_registers {
+        return write!(w, "{}", operand);
+    }
+
+    match operand {
+        Operand::RegisterDirect(r) => write!(w, "r{}", r),
+        Operand::Indexed {
+            register: r,
+            offset: i,
+        } if *i < 0 => write!(w, "-{:#x}(r{})", i.unsigned_abs(), r),
+        Operand::Indexed {
+            register: r,
+            offset: i,
+        } => write!(w, "{:#x}(r{})", i, r),
+        Operand::RegisterIndirect(r) => write!(w, "@r{}", r),
+        Operand::RegisterIndirectAutoIncrement(r) => write!(w, "@r{}+", r),
+        _ => write!(w, "{}", operand),
+    }
+}
+
+/// Writes an instruction to any fmt::Write without allocating
+pub fn write_instruction<W: Write>(
+    w: &mut W,
+    inst: &Instruction,
+    options: &FormatOptions,
+) -> fmt::Result {
+    match inst {
+        Instruction::Extended(inst) => return inst.write(w, options),
+        Instruction::Mova(inst) => return address::write(w, inst, options),
+        Instruction::Cmpa(inst) => return address::write(w, inst, options),
+        Instruction::Adda(inst) => return address::write(w, inst, options),
+        Instruction::Suba(inst) => return address::write(w, inst, options),
+        _ => {}
+    }
+
+    let mnemonic = mnemonic(inst, options);
+    if options.uppercase {
+        for c in mnemonic.chars() {
+            w.write_char(c.to_ascii_uppercase())?;
+        }
+    } else {
+        w.write_str(mnemonic)?;
+    }
+
+    if let Some(offset) = inst.jump_offset() {
+        return if offset < 0 {
+            write!(w, " #-{:#x}", offset.unsigned_abs())
+        } else {
+            write!(w, " #{:#x}", offset)
+        };
+    }
+
+    let (source, destination) = operands(inst);
+    if let Some(source) = source {
+        w.write_char(' ')?;
+        write_operand(w, source, options)?;
+    }
+
+    if let Some(destination) = destination {
+        w.write_str(if source.is_some() { ", " } else { " " })?;
+        write_operand(w, destination, options)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    fn format(data: &[u8], options: FormatOptions) -> String {
+        let mut output = String::new();
+        write_instruction(&mut output, &decode(data).unwrap(), &options).unwrap();
+        output
+    }
+
+    #[test]
+    fn default_matches_display() {
+        for word in 0..=u16::MAX {
+            let [a, b] = word.to_le_bytes();
+            if let Ok(inst) = decode(&[a, b, 0xfa, 0xff, 0x00, 0x44]) {
+                let mut output = String::new();
+                write_instruction(&mut output, &inst, &FormatOptions::default()).unwrap();
+                assert_eq!(output, inst.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn uppercase() {
+        let options = FormatOptions {
+            uppercase: true,
+            ..Default::default()
+        };
+        // mov.b @r15+, 0x4(r14)
+        assert_eq!(
+            format(&[0xfe, 0x4f, 0x04, 0x00], options),
+            "MOV.B @r15+, 0x4(r14)"
+        );
+    }
+
+    #[test]
+    fn numeric_registers() {
+        let options = FormatOptions {
+            numeric_registers: true,
+            ..Default::default()
+        };
+        // mov #0x4400, sp
+        assert_eq!(
+            format(&[0x31, 0x40, 0x00, 0x44], options),
+            "mov #0x4400, r1"
+        );
+        // ret
+        assert_eq!(format(&[0x30, 0x41], options), "ret");
+        // pop r9 with the stack pointer as the source of the original
+        assert_eq!(format(&[0x39, 0x41], options), "pop r9");
+        // push -0x6(sp)
+        assert_eq!(format(&[0x11, 0x12, 0xfa, 0xff], options), "push -0x6(r1)");
+    }
+
+    #[test]
+    fn alternate_jumps() {
+        let options = FormatOptions {
+            alternate_jumps: true,
+            ..Default::default()
+        };
+        assert_eq!(format(&[0xfe, 0x23], options), "jne #-0x2");
+        assert_eq!(format(&[0xfe, 0x27], options), "jeq #-0x2");
+        assert_eq!(format(&[0xfe, 0x2b], options), "jnc #-0x2");
+        assert_eq!(format(&[0xfe, 0x2f], options), "jhs #-0x2");
+        assert_eq!(format(&[0xfe, 0x33], options), "jn #-0x2");
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::operand::{Operand, OperandWidth};
+use crate::rules::writes_destination;
+
+/// The inferred type of a global variable. Later variants are more
+/// specific and take precedence when a global is used in several ways
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GlobalType {
+    /// Only accessed with byte instructions
+    Byte,
+    /// Accessed with word instructions
+    Word,
+    /// Loaded into a register that is then dereferenced
+    DataPointer,
+    /// Used as the target of a call or branch
+    CodePointer,
+}
+
+impl fmt::Display for GlobalType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Byte => write!(f, "byte"),
+            Self::Word => write!(f, "word"),
+            Self::DataPointer => write!(f, "data pointer"),
+            Self::CodePointer => write!(f, "code pointer"),
+        }
+    }
+}
+
+/// A global variable at an absolute address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub address: u16,
+    pub ty: GlobalType,
+    /// The number of instructions that read the global
+    pub reads: usize,
+    /// The number of instructions that write the global
+    pub writes: usize,
+}
+
+/// Returns whether the instruction ends straight line execution
+fn transfers_control(inst: &Instruction) -> bool {
+    inst.jump_offset().is_some()
+        || matches!(
+            inst,
+            Instruction::Call(_) | Instruction::Reti(_) | Instruction::Ret(_) | Instruction::Br(_)
+        )
+        || inst.destination() == Some(&Operand::RegisterDirect(0))
+}
+
+/// Returns whether the operand dereferences the register
+fn dereferences(operand: &Operand, register: u8) -> bool {
+    match operand {
+        Operand::Indexed { register: r, .. }
+        | Operand::RegisterIndirect(r)
+        | Operand::RegisterIndirectAutoIncrement(r) => *r == register,
+        _ => false,
+    }
+}
+
+/// Returns whether the register loaded by the instruction at index is
+/// dereferenced before it is overwritten or execution leaves the block
+fn loads_pointer(listing: &[(u32, Instruction)], index: usize, register: u8) -> bool {
+    for (_, inst) in &listing[index + 1..] {
+        let operands = [inst.source(), inst.destination()];
+        if operands
+            .iter()
+            .flatten()
+            .any(|operand| dereferences(operand, register))
+        {
+            return true;
+        }
+
+        if (writes_destination(inst)
+            && inst.destination() == Some(&Operand::RegisterDirect(register)))
+            || transfers_control(inst)
+        {
+            return false;
+        }
+    }
+
+    false
+}
+
+fn global(globals: &mut Vec<Global>, address: u16) -> &mut Global {
+    let index = match globals.iter().position(|g| g.address == address) {
+        Some(index) => index,
+        None => {
+            globals.push(Global {
+                address,
+                ty: GlobalType::Byte,
+                reads: 0,
+                writes: 0,
+            });
+            globals.len() - 1
+        }
+    };
+
+    &mut globals[index]
+}
+
+/// Infers the type of every absolute addressed global accessed in the
+/// listing from the access widths and how the values are used. The
+/// globals are returned ordered by address
+pub fn infer(listing: &[(u32, Instruction)]) -> Vec<Global> {
+    let mut globals = vec![];
+
+    for (index, (_, inst)) in listing.iter().enumerate() {
+        let width = match inst.operand_width() {
+            Some(OperandWidth::Byte) => GlobalType::Byte,
+            _ => GlobalType::Word,
+        };
+
+        if let Some(Operand::Absolute(address)) = inst.source() {
+            let ty = match (inst, inst.destination()) {
+                (Instruction::Call(_) | Instruction::Br(_), _) => GlobalType::CodePointer,
+                (Instruction::Mov(_), Some(Operand::RegisterDirect(r)))
+                    if width == GlobalType::Word && loads_pointer(listing, index, *r) =>
+                {
+                    GlobalType::DataPointer
+                }
+                _ => width,
+            };
+
+            let global = global(&mut globals, *address);
+            global.reads += 1;
+            global.ty = global.ty.max(ty);
+        }
+
+        if let Some(Operand::Absolute(address)) = inst.destination() {
+            let global = global(&mut globals, *address);
+            if writes_destination(inst) {
+                global.writes += 1;
+            }
+
+            // read-modify-write instructions also read the destination
+            if !matches!(
+                inst,
+                Instruction::Mov(_) | Instruction::Clr(_) | Instruction::Pop(_)
+            ) {
+                global.reads += 1;
+            }
+
+            global.ty = global.ty.max(width);
+        }
+    }
+
+    globals.sort_by_key(|g| g.address);
+    globals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::listing;
+
+    #[test]
+    fn widths() {
+        // mov.b #0x1, &0x0200; add &0x0202, r12; inc.b &0x0202
+        let globals = infer(&listing(
+            &[
+                0xd2, 0x43, 0x00, 0x02, 0x1c, 0x52, 0x02, 0x02, 0xd2, 0x53, 0x02, 0x02,
+            ],
+            0x4400,
+        ));
+        assert_eq!(
+            globals,
+            vec![
+                Global {
+                    address: 0x0200,
+                    ty: GlobalType::Byte,
+                    reads: 0,
+                    writes: 1
+                },
+                Global {
+                    address: 0x0202,
+                    ty: GlobalType::Word,
+                    reads: 2,
+                    writes: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pointers() {
+        // mov &0x0200, r15; mov @r15, r14; call &0x0204
+        let globals = infer(&listing(
+            &[0x1f, 0x42, 0x00, 0x02, 0x2e, 0x4f, 0x92, 0x12, 0x04, 0x02],
+            0x4400,
+        ));
+        assert_eq!(
+            globals
+                .iter()
+                .map(|g| (g.address, g.ty))
+                .collect::<Vec<_>>(),
+            vec![
+                (0x0200, GlobalType::DataPointer),
+                (0x0204, GlobalType::CodePointer)
+            ]
+        );
+    }
+
+    #[test]
+    fn overwritten_before_dereference() {
+        // mov &0x0200, r15; mov #0x0, r15; mov @r15, r14
+        let globals = infer(&listing(
+            &[0x1f, 0x42, 0x00, 0x02, 0x0f, 0x43, 0x2e, 0x4f],
+            0x4400,
+        ));
+        assert_eq!(globals[0].ty, GlobalType::Word);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::decode;
+use crate::instruction::Instruction;
+use crate::isa_coverage::Form;
+use crate::operand::Operand;
+
+/// Returns the fraction of data that decodes as instructions in a linear
+/// sweep from the start. Words that fail to decode are skipped. A trailing
+/// odd byte is ignored
+pub fn decode_rate(data: &[u8]) -> f64 {
+    let len = data.len() & !1;
+    if len == 0 {
+        return 0.0;
+    }
+
+    let mut offset = 0;
+    let mut decoded = 0;
+    while offset < len {
+        match decode(&data[offset..len]) {
+            Ok(inst) => {
+                decoded += inst.size();
+                offset += inst.size();
+            }
+            Err(_) => offset += 2,
+        }
+    }
+
+    decoded as f64 / len as f64
+}
+
+/// Returns how likely an instruction is to appear in compiled code from 0
+/// (never) to 1. Nearly every word decodes as some instruction so the
+/// opcode and operands are weighed rather than whether it decodes at all
+pub fn instruction_plausibility(inst: &Instruction) -> f64 {
+    let Some(form) = Form::of(inst) else {
+        return 0.0;
+    };
+
+    // writing to the constant generator only happens in nop
+    let destination = match inst {
+        Instruction::Nop(_) => None,
+        _ => inst.destination(),
+    };
+    if destination == Some(&Operand::RegisterDirect(3)) {
+        return 0.0;
+    }
+
+    // byte operations on pc, sp and sr clear their upper byte
+    let special = matches!(destination, Some(Operand::RegisterDirect(0..=2)));
+    if special && form.byte == Some(true) {
+        return 0.0;
+    }
+
+    let weight = match form.opcode {
+        "mov" | "call" | "push" | "jmp" | "jnz" | "jz" => 1.0,
+        "add" | "sub" | "cmp" | "bit" | "bic" | "bis" | "and" | "xor" => 0.8,
+        "jlo" | "jc" | "jn" | "jge" | "jl" => 0.8,
+        "addc" | "subc" | "rrc" | "rra" | "swpb" | "sxt" => 0.5,
+        _ => 0.2,
+    };
+
+    // pc is only written by branches and returns in most code
+    if destination == Some(&Operand::RegisterDirect(0)) && form.opcode != "mov" {
+        return weight * 0.2;
+    }
+
+    weight
+}
+
+/// Returns the plausibility of data as code from 0 to 1. This is the
+/// plausibility of every instruction in a linear sweep from the start
+/// weighed by its size. Words that fail to decode count as 0
+pub fn plausibility(data: &[u8]) -> f64 {
+    let len = data.len() & !1;
+    if len == 0 {
+        return 0.0;
+    }
+
+    let mut offset = 0;
+    let mut total = 0.0;
+    while offset < len {
+        match decode(&data[offset..len]) {
+            Ok(inst) => {
+                total += instruction_plausibility(&inst) * inst.size() as f64;
+                offset += inst.size();
+            }
+            Err(_) => offset += 2,
+        }
+    }
+
+    total / len as f64
+}
+
+/// Returns the Shannon entropy of data in bits per byte from 0 to 8. Code
+/// is usually around 5 to 6, erased flash is 0 and compressed or encrypted
+/// data is close to 8
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Metrics for a window of an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    /// The address of the first byte of the window
+    pub address: u32,
+    pub size: usize,
+    /// The entropy in bits per byte
+    pub entropy: f64,
+    /// The fraction of the window that decodes as instructions
+    pub decode_rate: f64,
+    /// The plausibility of the window as code
+    pub plausibility: f64,
+}
+
+/// Splits an image into windows of size bytes (the last may be shorter) and
+/// returns the metrics of each one so they can be plotted. base is the
+/// address the first byte of the image is loaded at. Code has a high
+/// plausibility and moderate entropy, data has a low plausibility and
+/// encrypted or compressed data has a high entropy
+pub fn windows(image: &[u8], base: u32, size: usize) -> Vec<Window> {
+    if size == 0 {
+        return vec![];
+    }
+
+    image
+        .chunks(size)
+        .enumerate()
+        .map(|(i, window)| Window {
+            address: base + (i * size) as u32,
+            size: window.len(),
+            entropy: entropy(window),
+            decode_rate: decode_rate(window),
+            plausibility: plausibility(window),
+        })
+        .collect()
+}
+
+/// A repeating XOR key and the plausibility of data as code after it is
+/// applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCandidate {
+    pub key: Vec<u8>,
+    pub score: f64,
+}
+
+/// The number of bytes every key is scored on before the best keys are
+/// rescored on all of the data
+const XOR_SAMPLE: usize = 256;
+
+/// The number of the best keys on the sample that are rescored on all of
+/// the data (or count if more are asked for)
+const XOR_RESCORE: usize = 256;
+
+/// Tries every repeating XOR key of key_len bytes over data and returns the
+/// count keys whose output is the most plausible code. The key starts at
+/// the first byte of data so a key that is out of phase with the data shows
+/// up as a rotation of the real key. Only keys of one or two bytes are
+/// searched, other lengths return no candidates. Every key is scored on the
+/// first 256 bytes and only the best are rescored on all of the data so
+/// large inputs aren't decoded 65536 times. Ties are broken by the key so
+/// the order is stable
+pub fn search_xor(data: &[u8], key_len: usize, count: usize) -> Vec<KeyCandidate> {
+    let keys: u32 = match key_len {
+        1 => 0x100,
+        2 => 0x10000,
+        _ => return vec![],
+    };
+
+    let mut buffer = Vec::with_capacity(data.len());
+    let mut score = |key: &[u8], data: &[u8]| {
+        buffer.clear();
+        buffer.extend(data.iter().zip(key.iter().cycle()).map(|(b, k)| b ^ k));
+        plausibility(&buffer)
+    };
+    let sort = |candidates: &mut Vec<KeyCandidate>| {
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.key.cmp(&b.key)))
+    };
+
+    let sample = &data[..data.len().min(XOR_SAMPLE)];
+    let mut candidates: Vec<KeyCandidate> = (0..keys)
+        .map(|key| {
+            let key = key.to_le_bytes()[..key_len].to_vec();
+            KeyCandidate {
+                score: score(&key, sample),
+                key,
+            }
+        })
+        .collect();
+    sort(&mut candidates);
+
+    if sample.len() < data.len() {
+        candidates.truncate(count.max(XOR_RESCORE));
+        for candidate in candidates.iter_mut() {
+            candidate.score = score(&candidate.key, data);
+        }
+        sort(&mut candidates);
+    }
+
+    candidates.truncate(count);
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // mov #0x4400, sp; mov #0x5a80, &0x0120; call #0x4410; jmp #-0x1
+    const CODE: [u8; 16] = [
+        0x31, 0x40, 0x00, 0x44, 0xb2, 0x40, 0x80, 0x5a, 0x20, 0x01, 0xb0, 0x12, 0x10, 0x44, 0xff,
+        0x3f,
+    ];
+
+    #[test]
+    fn rates() {
+        assert_eq!(decode_rate(&CODE), 1.0);
+        // two invalid words and ret
+        let data = [0x80, 0x13, 0x80, 0x13, 0x30, 0x41, 0x30];
+        assert_eq!(decode_rate(&data), 1.0 / 3.0);
+        assert_eq!(decode_rate(&[0x30]), 0.0);
+        assert_eq!(plausibility(&CODE), 1.0);
+        // mov r15, r3; dadd r4, r5
+        assert_eq!(plausibility(&[0x03, 0x4f, 0x05, 0xa4]), 0.1);
+    }
+
+    #[test]
+    fn entropies() {
+        assert_eq!(entropy(&[]), 0.0);
+        assert_eq!(entropy(&[0xff; 64]), 0.0);
+        assert_eq!(entropy(&[0x00, 0x01, 0x02, 0x03]), 2.0);
+        let all: Vec<u8> = (0..=255).collect();
+        assert_eq!(entropy(&all), 8.0);
+    }
+
+    #[test]
+    fn image_windows() {
+        let mut image = CODE.to_vec();
+        image.extend([0xff; 16]);
+        image.extend([0x30, 0x41]);
+        let windows = windows(&image, 0xc000, 16);
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0].address, 0xc000);
+        assert_eq!(windows[0].plausibility, 1.0);
+        assert_eq!(windows[1].address, 0xc010);
+        assert_eq!(windows[1].entropy, 0.0);
+        assert_eq!(windows[2].size, 2);
+        assert_eq!(windows[2].decode_rate, 1.0);
+    }
+
+    #[test]
+    fn single_byte_key() {
+        let encrypted: Vec<u8> = CODE.iter().map(|b| b ^ 0xa7).collect();
+        let candidates = search_xor(&encrypted, 1, 3);
+        assert_eq!(candidates.len(), 3);
+        assert_eq!(candidates[0].key, vec![0xa7]);
+        assert_eq!(candidates[0].score, 1.0);
+        assert!(candidates.windows(2).all(|w| w[0].score >= w[1].score));
+        assert_eq!(search_xor(&encrypted, 3, 3), vec![]);
+    }
+
+    #[test]
+    fn two_byte_key() {
+        let encrypted: Vec<u8> = CODE
+            .iter()
+            .zip([0x3c, 0xa7].iter().cycle())
+            .map(|(b, k)| b ^ k)
+            .collect();
+        // registers are weighed evenly so the low byte of the key isn't
+        // unique but the key is always among the best
+        let candidates = search_xor(&encrypted, 2, usize::MAX);
+        assert_eq!(candidates.len(), 0x10000);
+        let best = candidates[0].score;
+        assert!(candidates
+            .iter()
+            .any(|c| c.key == vec![0x3c, 0xa7] && c.score == best));
+    }
+
+    #[test]
+    fn sampled_key() {
+        // code for the whole sample followed by invalid words so the keys
+        // that are best on the sample score lower once they are rescored
+        let mut plain = CODE.repeat(XOR_SAMPLE / CODE.len());
+        plain.extend([0x80, 0x13].repeat(128));
+        let encrypted: Vec<u8> = plain
+            .iter()
+            .zip([0x3c, 0xa7].iter().cycle())
+            .map(|(b, k)| b ^ k)
+            .collect();
+        let candidates = search_xor(&encrypted, 2, 16);
+        assert_eq!(candidates.len(), 16);
+        assert!(candidates[0].score < 1.0);
+        assert!(candidates[0].score >= plausibility(&plain));
+    }
+}
@@ -0,0 +1,484 @@
+use crate::instruction::Instruction;
+use crate::jxx::Jxx;
+use crate::operand::{Operand, OperandWidth};
+use crate::single_operand::SingleOperand;
+use crate::target;
+use crate::two_operand::TwoOperand;
+
+/// The operation performed by a two operand instruction
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Mov,
+    Add,
+    Addc,
+    Subc,
+    Sub,
+    Cmp,
+    Dadd,
+    Bit,
+    Bic,
+    Bis,
+    Xor,
+    And,
+}
+
+fn register(r: u8) -> String {
+    match r {
+        0 => "pc".to_string(),
+        1 => "sp".to_string(),
+        2 => "sr".to_string(),
+        _ => format!("r{}", r),
+    }
+}
+
+/// Returns the mask that covers all bits for the width and the number of
+/// bits
+fn mask(width: OperandWidth) -> (u16, u8) {
+    match width {
+        OperandWidth::Byte => (0xff, 8),
+        OperandWidth::Word => (0xffff, 16),
+    }
+}
+
+/// Builds the statements for one instruction. Extension words are consumed
+/// in encoding order so symbolic operands resolve to absolute addresses
+struct Lifter {
+    address: u32,
+    extension: u32,
+    temps: usize,
+    statements: Vec<String>,
+}
+
+impl Lifter {
+    fn new(address: u32) -> Lifter {
+        Lifter {
+            address,
+            extension: address + 2,
+            temps: 0,
+            statements: vec![],
+        }
+    }
+
+    fn emit(&mut self, statement: String) {
+        self.statements.push(statement);
+    }
+
+    /// Assigns an expression to a new temporary and returns its name
+    fn temp(&mut self, expression: String) -> String {
+        let name = format!("t{}", self.temps);
+        self.temps += 1;
+        self.emit(format!("{} = {}", name, expression));
+        name
+    }
+
+    /// Returns the value of a register. PC reads as the address of the word
+    /// after the instruction word
+    fn register_value(&self, r: u8) -> String {
+        match r {
+            0 => format!("{:#x}", (self.address + 2) as u16),
+            _ => register(r),
+        }
+    }
+
+    /// Returns the address expression for operands that live in memory,
+    /// consuming an extension word if the operand has one
+    fn memory(&mut self, operand: &Operand) -> Option<String> {
+        let extension = self.extension;
+        self.extension += operand.size() as u32;
+
+        match operand {
+            Operand::Indexed {
+                register: r,
+                offset: i,
+            } if *i < 0 => Some(format!(
+                "{} - {:#x}",
+                self.register_value(*r),
+                i.unsigned_abs()
+            )),
+            Operand::Indexed {
+                register: r,
+                offset: i,
+            } => Some(format!("{} + {:#x}", self.register_value(*r), i)),
+            Operand::RegisterIndirect(r) | Operand::RegisterIndirectAutoIncrement(r) => {
+                Some(self.register_value(*r))
+            }
+            Operand::Symbolic(i) => {
+                Some(format!("{:#x}", (extension as u16).wrapping_add(*i as u16)))
+            }
+            Operand::Absolute(a) => Some(format!("{:#x}", a)),
+            _ => None,
+        }
+    }
+
+    fn load(address: &str, width: OperandWidth) -> String {
+        format!("load{}({})", mask(width).1, address)
+    }
+
+    /// Reads an operand into a temporary. The address of memory operands is
+    /// returned so the result can be written back to it
+    fn read(&mut self, operand: &Operand, width: OperandWidth) -> (String, Option<String>) {
+        let (mask, _) = mask(width);
+        match self.memory(operand) {
+            Some(address) => {
+                let value = self.temp(Lifter::load(&address, width));
+                (value, Some(address))
+            }
+            None => {
+                let value = match operand {
+                    Operand::RegisterDirect(r) if width == OperandWidth::Byte => {
+                        format!("{} & 0xff", self.register_value(*r))
+                    }
+                    Operand::RegisterDirect(r) => self.register_value(*r),
+                    Operand::Immediate(i) => format!("{:#x}", i & mask),
+                    Operand::Constant(c) => format!("{:#x}", *c as i16 as u16 & mask),
+                    _ => unreachable!("memory operands are handled above"),
+                };
+                (self.temp(value), None)
+            }
+        }
+    }
+
+    /// Increments the register of an autoincrement operand
+    fn increment(&mut self, operand: &Operand, width: OperandWidth) {
+        if let Operand::RegisterIndirectAutoIncrement(r) = operand {
+            let step = match (width, r) {
+                (OperandWidth::Byte, 1) | (OperandWidth::Word, _) => 2,
+                (OperandWidth::Byte, _) => 1,
+            };
+            self.emit(format!("{} = {} + {}", register(*r), register(*r), step));
+        }
+    }
+
+    /// Writes a value to a register or to the memory address of an operand
+    fn write(
+        &mut self,
+        operand: &Operand,
+        address: Option<String>,
+        width: OperandWidth,
+        value: &str,
+    ) {
+        match (address, operand) {
+            (Some(address), _) => {
+                self.emit(format!("store{}({}, {})", mask(width).1, address, value))
+            }
+            (None, Operand::RegisterDirect(r)) => {
+                self.emit(format!("{} = {}", register(*r), value))
+            }
+            // writes to immediates and constants are discarded by the CPU
+            _ => {}
+        }
+    }
+
+    fn result_flags(&mut self, result: &str, width: OperandWidth) {
+        let (_, bits) = mask(width);
+        self.emit(format!("sr.n = {} >> {} & 1", result, bits - 1));
+        self.emit(format!("sr.z = {} == 0", result));
+    }
+
+    fn two_operand(&mut self, op: Op, inst: &dyn TwoOperand) {
+        let width = *inst.operand_width();
+        let (mask, bits) = mask(width);
+        let (source, _) = self.read(inst.source(), width);
+        self.increment(inst.source(), width);
+
+        if op == Op::Mov {
+            let address = self.memory(inst.destination());
+            self.write(inst.destination(), address, width, &source);
+            return;
+        }
+
+        let (destination, address) = self.read(inst.destination(), width);
+        let writes = !matches!(op, Op::Cmp | Op::Bit);
+
+        let result = match op {
+            Op::Add | Op::Addc | Op::Sub | Op::Subc | Op::Cmp => {
+                let operand = match op {
+                    Op::Sub | Op::Subc | Op::Cmp => self.temp(format!("~{} & {:#x}", source, mask)),
+                    _ => source,
+                };
+                let carry = match op {
+                    Op::Add => "",
+                    Op::Sub | Op::Cmp => " + 1",
+                    _ => " + sr.c",
+                };
+                let sum = self.temp(format!("{} + {}{}", destination, operand, carry));
+                self.emit(format!("sr.c = {} >> {} & 1", sum, bits));
+                self.emit(format!(
+                    "sr.v = (~({} ^ {}) & ({} ^ {})) >> {} & 1",
+                    destination,
+                    operand,
+                    destination,
+                    sum,
+                    bits - 1
+                ));
+                let result = self.temp(format!("{} & {:#x}", sum, mask));
+                self.result_flags(&result, width);
+                result
+            }
+            Op::Dadd => {
+                let result = self.temp(format!(
+                    "bcd_add{}({}, {}, sr.c)",
+                    bits, destination, source
+                ));
+                self.emit(format!(
+                    "sr.c = bcd_carry{}({}, {}, sr.c)",
+                    bits, destination, source
+                ));
+                self.result_flags(&result, width);
+                result
+            }
+            Op::Bic => self.temp(format!("{} & ~{} & {:#x}", destination, source, mask)),
+            Op::Bis => self.temp(format!("{} | {}", destination, source)),
+            Op::Xor | Op::And | Op::Bit => {
+                let operator = if op == Op::Xor { "^" } else { "&" };
+                let result = self.temp(format!("{} {} {}", destination, operator, source));
+                self.result_flags(&result, width);
+                self.emit(format!("sr.c = {} != 0", result));
+                match op {
+                    Op::Xor => self.emit(format!(
+                        "sr.v = ({} & {}) >> {} & 1",
+                        destination,
+                        source,
+                        bits - 1
+                    )),
+                    _ => self.emit("sr.v = 0".to_string()),
+                }
+                result
+            }
+            Op::Mov => unreachable!("mov is handled above"),
+        };
+
+        if writes {
+            self.write(inst.destination(), address, width, &result);
+        }
+    }
+
+    fn single_operand(&mut self, inst: &Instruction) {
+        let (operand, width) = match inst {
+            Instruction::Rrc(inst) => (
+                *inst.source(),
+                inst.operand_width().unwrap_or(OperandWidth::Word),
+            ),
+            Instruction::Rra(inst) => (
+                *inst.source(),
+                inst.operand_width().unwrap_or(OperandWidth::Word),
+            ),
+            Instruction::Swpb(inst) => (*inst.source(), OperandWidth::Word),
+            Instruction::Sxt(inst) => (*inst.source(), OperandWidth::Word),
+            Instruction::Push(inst) => (
+                *inst.source(),
+                inst.operand_width().unwrap_or(OperandWidth::Word),
+            ),
+            Instruction::Call(inst) => (*inst.source(), OperandWidth::Word),
+            _ => return,
+        };
+        let (mask, bits) = mask(width);
+        let (value, address) = self.read(&operand, width);
+
+        let result = match inst {
+            Instruction::Rrc(_) | Instruction::Rra(_) => {
+                let high = match inst {
+                    Instruction::Rrc(_) => format!("sr.c << {}", bits - 1),
+                    _ => format!("{} & {:#x}", value, 1 << (bits - 1)),
+                };
+                let result = self.temp(format!("{} | {} >> 1", high, value));
+                self.emit(format!("sr.c = {} & 1", value));
+                self.emit("sr.v = 0".to_string());
+                self.result_flags(&result, width);
+                result
+            }
+            Instruction::Swpb(_) => {
+                self.temp(format!("({} >> 8 | {} << 8) & {:#x}", value, value, mask))
+            }
+            Instruction::Sxt(_) => {
+                let result = self.temp(format!("sext8({} & 0xff)", value));
+                self.result_flags(&result, width);
+                self.emit(format!("sr.c = {} != 0", result));
+                self.emit("sr.v = 0".to_string());
+                result
+            }
+            Instruction::Push(_) => {
+                self.increment(&operand, width);
+                self.emit("sp = sp - 2".to_string());
+                self.emit(format!("store{}(sp, {})", bits, value));
+                return;
+            }
+            Instruction::Call(_) => {
+                self.increment(&operand, width);
+                let next = self.extension;
+                self.emit("sp = sp - 2".to_string());
+                self.emit(format!("store16(sp, {:#x})", next as u16));
+                self.emit(format!("pc = {}", value));
+                return;
+            }
+            _ => return,
+        };
+
+        self.write(&operand, address, width, &result);
+        self.increment(&operand, width);
+    }
+
+    fn jump(&mut self, offset: i16, condition: Option<&str>) {
+        let target = target::jump_target(self.address, offset) as u16;
+        match condition {
+            Some(condition) => self.emit(format!("if {} pc = {:#x}", condition, target)),
+            None => self.emit(format!("pc = {:#x}", target)),
+        }
+    }
+}
+
+/// Lifts an instruction decoded at address to a textual pcode-like IL so
+/// that other frameworks can consume its semantics. The result is a list
+/// of statements separated by "; " that are executed in order.
+///
+/// tN are temporaries with unbounded precision, registers are named pc,
+/// sp, sr and r3-r15 and status register flags are sr.c, sr.z, sr.n and
+/// sr.v. Memory is accessed with load8/load16 and store8/store16 and
+/// addresses wrap at 16 bits. Decimal addition uses the bcd_add and
+/// bcd_carry intrinsics and sign extension of a byte uses sext8. Emulated
+/// instructions are lifted as the instruction they emulate and MSP430X
+/// extended instructions are lifted as unimplemented
+pub fn lift(address: u32, inst: &Instruction) -> String {
+    let mut lifter = Lifter::new(address);
+
+    match inst {
+        Instruction::Rrc(_)
+        | Instruction::Swpb(_)
+        | Instruction::Rra(_)
+        | Instruction::Sxt(_)
+        | Instruction::Push(_)
+        | Instruction::Call(_) => lifter.single_operand(inst),
+        Instruction::Reti(_) => {
+            lifter.emit("sr = load16(sp)".to_string());
+            lifter.emit("sp = sp + 2".to_string());
+            lifter.emit("pc = load16(sp)".to_string());
+            lifter.emit("sp = sp + 2".to_string());
+        }
+        Instruction::Jnz(inst) => lifter.jump(inst.offset(), Some("!sr.z")),
+        Instruction::Jz(inst) => lifter.jump(inst.offset(), Some("sr.z")),
+        Instruction::Jlo(inst) => lifter.jump(inst.offset(), Some("!sr.c")),
+        Instruction::Jc(inst) => lifter.jump(inst.offset(), Some("sr.c")),
+        Instruction::Jn(inst) => lifter.jump(inst.offset(), Some("sr.n")),
+        Instruction::Jge(inst) => lifter.jump(inst.offset(), Some("sr.n == sr.v")),
+        Instruction::Jl(inst) => lifter.jump(inst.offset(), Some("sr.n != sr.v")),
+        Instruction::Jmp(inst) => lifter.jump(inst.offset(), None),
+        Instruction::Mov(inst) => lifter.two_operand(Op::Mov, inst),
+        Instruction::Add(inst) => lifter.two_operand(Op::Add, inst),
+        Instruction::Addc(inst) => lifter.two_operand(Op::Addc, inst),
+        Instruction::Subc(inst) => lifter.two_operand(Op::Subc, inst),
+        Instruction::Sub(inst) => lifter.two_operand(Op::Sub, inst),
+        Instruction::Cmp(inst) => lifter.two_operand(Op::Cmp, inst),
+        Instruction::Dadd(inst) => lifter.two_operand(Op::Dadd, inst),
+        Instruction::Bit(inst) => lifter.two_operand(Op::Bit, inst),
+        Instruction::Bic(inst) => lifter.two_operand(Op::Bic, inst),
+        Instruction::Bis(inst) => lifter.two_operand(Op::Bis, inst),
+        Instruction::Xor(inst) => lifter.two_operand(Op::Xor, inst),
+        Instruction::And(inst) => lifter.two_operand(Op::And, inst),
+        Instruction::Adc(inst) => lifter.two_operand(Op::Addc, inst.original()),
+        Instruction::Br(inst) => lifter.two_operand(Op::Mov, inst.original()),
+        Instruction::Clr(inst) => lifter.two_operand(Op::Mov, inst.original()),
+        Instruction::Clrc(inst) => lifter.two_operand(Op::Bic, inst.original()),
+        Instruction::Clrn(inst) => lifter.two_operand(Op::Bic, inst.original()),
+        Instruction::Clrz(inst) => lifter.two_operand(Op::Bic, inst.original()),
+        Instruction::Dadc(inst) => lifter.two_operand(Op::Dadd, inst.original()),
+        Instruction::Dec(inst) => lifter.two_operand(Op::Sub, inst.original()),
+        Instruction::Decd(inst) => lifter.two_operand(Op::Sub, inst.original()),
+        Instruction::Dint(inst) => lifter.two_operand(Op::Bic, inst.original()),
+        Instruction::Eint(inst) => lifter.two_operand(Op::Bis, inst.original()),
+        Instruction::Inc(inst) => lifter.two_operand(Op::Add, inst.original()),
+        Instruction::Incd(inst) => lifter.two_operand(Op::Add, inst.original()),
+        Instruction::Inv(inst) => lifter.two_operand(Op::Xor, inst.original()),
+        Instruction::Nop(inst) => lifter.two_operand(Op::Mov, inst.original()),
+        Instruction::Pop(inst) => lifter.two_operand(Op::Mov, inst.original()),
+        Instruction::Ret(inst) => lifter.two_operand(Op::Mov, inst.original()),
+        Instruction::Rla(inst) => lifter.two_operand(Op::Add, inst.original()),
+        Instruction::Rlc(inst) => lifter.two_operand(Op::Addc, inst.original()),
+        Instruction::Sbc(inst) => lifter.two_operand(Op::Subc, inst.original()),
+        Instruction::Setc(inst) => lifter.two_operand(Op::Bis, inst.original()),
+        Instruction::Setn(inst) => lifter.two_operand(Op::Bis, inst.original()),
+        Instruction::Setz(inst) => lifter.two_operand(Op::Bis, inst.original()),
+        Instruction::Tst(inst) => lifter.two_operand(Op::Cmp, inst.original()),
+        // 20 bit operations aren't modelled
+        Instruction::Extended(_)
+        | Instruction::Mova(_)
+        | Instruction::Cmpa(_)
+        | Instruction::Adda(_)
+        | Instruction::Suba(_) => lifter.emit("unimplemented".to_string()),
+    }
+
+    lifter.statements.join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    fn lift_bytes(data: &[u8]) -> String {
+        lift(0x4400, &decode(data).unwrap())
+    }
+
+    #[test]
+    fn mov() {
+        // mov.b @r15+, 0x4(r14)
+        assert_eq!(
+            lift_bytes(&[0xfe, 0x4f, 0x04, 0x00]),
+            "t0 = load8(r15); r15 = r15 + 1; store8(r14 + 0x4, t0)"
+        );
+        // mov #0x4400, sp
+        assert_eq!(
+            lift_bytes(&[0x31, 0x40, 0x00, 0x44]),
+            "t0 = 0x4400; sp = t0"
+        );
+    }
+
+    #[test]
+    fn add() {
+        // add r15, r14
+        assert_eq!(
+            lift_bytes(&[0x0e, 0x5f]),
+            "t0 = r15; t1 = r14; t2 = t1 + t0; sr.c = t2 >> 16 & 1; \
+             sr.v = (~(t1 ^ t0) & (t1 ^ t2)) >> 15 & 1; t3 = t2 & 0xffff; \
+             sr.n = t3 >> 15 & 1; sr.z = t3 == 0; r14 = t3"
+        );
+    }
+
+    #[test]
+    fn cmp_symbolic() {
+        // cmp.b #0x1, 0x10(pc) resolves the destination to an address
+        assert_eq!(
+            lift_bytes(&[0xd0, 0x93, 0x10, 0x00]),
+            "t0 = 0x1; t1 = load8(0x4412); t2 = ~t0 & 0xff; t3 = t1 + t2 + 1; \
+             sr.c = t3 >> 8 & 1; sr.v = (~(t1 ^ t2) & (t1 ^ t3)) >> 7 & 1; \
+             t4 = t3 & 0xff; sr.n = t4 >> 7 & 1; sr.z = t4 == 0"
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        // jnz #-0x6, ret, call #0x4410, reti
+        assert_eq!(lift_bytes(&[0xfa, 0x23]), "if !sr.z pc = 0x43f6");
+        assert_eq!(
+            lift_bytes(&[0x30, 0x41]),
+            "t0 = load16(sp); sp = sp + 2; pc = t0"
+        );
+        assert_eq!(
+            lift_bytes(&[0xb0, 0x12, 0x10, 0x44]),
+            "t0 = 0x4410; sp = sp - 2; store16(sp, 0x4404); pc = t0"
+        );
+        assert_eq!(
+            lift_bytes(&[0x00, 0x13]),
+            "sr = load16(sp); sp = sp + 2; pc = load16(sp); sp = sp + 2"
+        );
+    }
+
+    #[test]
+    fn every_instruction() {
+        for word in 0..=u16::MAX {
+            let [a, b] = word.to_le_bytes();
+            if let Ok(inst) = decode(&[a, b, 0x00, 0x80, 0xff, 0xff]) {
+                assert!(!lift(0xfffa, &inst).is_empty(), "{}", inst);
+            }
+        }
+    }
+}
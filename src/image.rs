@@ -0,0 +1,491 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::patch::{self, PatchError, PatchRecord};
+use crate::writer::{self, Segment};
+
+const DATA_RECORD: u8 = 0x00;
+const END_OF_FILE_RECORD: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS_RECORD: u8 = 0x02;
+const START_SEGMENT_ADDRESS_RECORD: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS_RECORD: u8 = 0x04;
+const START_LINEAR_ADDRESS_RECORD: u8 = 0x05;
+
+/// The program header type of a loadable ELF segment
+const PT_LOAD: u32 = 1;
+
+/// The file formats an image can be opened from and written back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    IntelHex,
+    TiTxt,
+    /// A 32 bit little endian ELF file. Segments are loaded at their
+    /// physical address which is where they are stored in flash
+    Elf,
+}
+
+/// Errors that can occur while opening or patching an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ImageError {
+    /// Present when the file isn't Intel HEX, TI-TXT or ELF
+    UnknownFormat,
+    /// Present when a line of an Intel HEX or TI-TXT file can't be parsed.
+    /// Contains the line number (starting at 1)
+    InvalidLine(usize),
+    /// Present when the checksum of an Intel HEX record doesn't match its
+    /// contents. Contains the line number (starting at 1)
+    Checksum(usize),
+    /// Present when an ELF file is truncated or isn't 32 bit little endian
+    InvalidElf,
+    /// Present when a patch is at an address the image doesn't contain.
+    /// Contains the address
+    Unmapped(u32),
+    /// Present when the instruction can't be patched
+    Patch(PatchError),
+}
+
+impl ImageError {
+    /// Returns a stable numeric code for the error. Image errors use the
+    /// 1000-1099 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::UnknownFormat => 1000,
+            Self::InvalidLine(_) => 1001,
+            Self::Checksum(_) => 1002,
+            Self::InvalidElf => 1003,
+            Self::Unmapped(_) => 1004,
+            Self::Patch(_) => 1005,
+        }
+    }
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "not an Intel HEX, TI-TXT or ELF file"),
+            Self::InvalidLine(line) => write!(f, "line {}: invalid record", line),
+            Self::Checksum(line) => write!(f, "line {}: checksum mismatch", line),
+            Self::InvalidElf => write!(f, "invalid or truncated ELF file"),
+            Self::Unmapped(address) => write!(f, "{:#x} isn't in the image", address),
+            Self::Patch(e) => write!(f, "patch: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Patch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Bytes loaded from a file and where they are stored in it
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    address: u32,
+    data: Vec<u8>,
+    /// The line index of the record (Intel HEX and TI-TXT) or the file
+    /// offset of the data (ELF)
+    location: usize,
+}
+
+/// Parses pairs of hex digits
+fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn intel_hex(text: &str) -> Result<Vec<Chunk>, ImageError> {
+    let mut chunks = vec![];
+    let mut upper = 0;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let bytes = line
+            .strip_prefix(':')
+            .and_then(hex_bytes)
+            .filter(|bytes| bytes.len() >= 5 && bytes.len() == bytes[0] as usize + 5)
+            .ok_or(ImageError::InvalidLine(i + 1))?;
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(ImageError::Checksum(i + 1));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match (bytes[3], data) {
+            (DATA_RECORD, _) => chunks.push(Chunk {
+                address: upper + address,
+                data: data.to_vec(),
+                location: i,
+            }),
+            (END_OF_FILE_RECORD, _) => break,
+            (EXTENDED_SEGMENT_ADDRESS_RECORD, [high, low]) => {
+                upper = (u16::from_be_bytes([*high, *low]) as u32) << 4
+            }
+            (EXTENDED_LINEAR_ADDRESS_RECORD, [high, low]) => {
+                upper = (u16::from_be_bytes([*high, *low]) as u32) << 16
+            }
+            (START_SEGMENT_ADDRESS_RECORD | START_LINEAR_ADDRESS_RECORD, _) => {}
+            _ => return Err(ImageError::InvalidLine(i + 1)),
+        }
+    }
+
+    Ok(chunks)
+}
+
+fn ti_txt(text: &str) -> Result<Vec<Chunk>, ImageError> {
+    let mut chunks = vec![];
+    let mut address = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let invalid = ImageError::InvalidLine(i + 1);
+        if line.is_empty() {
+            continue;
+        }
+        if line.eq_ignore_ascii_case("q") {
+            break;
+        }
+        if let Some(start) = line.strip_prefix('@') {
+            address = Some(u32::from_str_radix(start, 16).map_err(|_| invalid)?);
+            continue;
+        }
+
+        let address = address.as_mut().ok_or(invalid)?;
+        let data: Vec<u8> = line
+            .split_whitespace()
+            .map(|byte| {
+                (byte.len() == 2)
+                    .then(|| u8::from_str_radix(byte, 16).ok())
+                    .flatten()
+            })
+            .collect::<Option<_>>()
+            .ok_or(invalid)?;
+        let len = data.len() as u32;
+        chunks.push(Chunk {
+            address: *address,
+            data,
+            location: i,
+        });
+        *address += len;
+    }
+
+    Ok(chunks)
+}
+
+fn elf(data: &[u8]) -> Result<Vec<Chunk>, ImageError> {
+    let bytes =
+        |offset: usize, len: usize| data.get(offset..offset + len).ok_or(ImageError::InvalidElf);
+    let half = |offset| bytes(offset, 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let word = |offset| bytes(offset, 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    // 32 bit (ELFCLASS32) and little endian (ELFDATA2LSB)
+    if bytes(4, 2)? != [1, 1] {
+        return Err(ImageError::InvalidElf);
+    }
+
+    let (phoff, phentsize, phnum) = (word(0x1c)? as usize, half(0x2a)?, half(0x2c)?);
+    let mut chunks = vec![];
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        let (offset, size) = (word(header + 4)? as usize, word(header + 16)? as usize);
+        if word(header)? != PT_LOAD || size == 0 {
+            continue;
+        }
+
+        chunks.push(Chunk {
+            address: word(header + 12)?,
+            data: bytes(offset, size)?.to_vec(),
+            location: offset,
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// Merges chunks into contiguous segments in address order. Where chunks
+/// overlap the bytes of the later one in address order are kept
+fn segments(chunks: &[Chunk]) -> Vec<Segment> {
+    let mut chunks: Vec<&Chunk> = chunks.iter().collect();
+    chunks.sort_by_key(|chunk| chunk.address);
+
+    let mut segments: Vec<Segment> = vec![];
+    for chunk in chunks {
+        match segments.last_mut() {
+            Some(last) if chunk.address <= last.address + last.data.len() as u32 => {
+                let start = (chunk.address - last.address) as usize;
+                let end = start + chunk.data.len();
+                if last.data.len() < end {
+                    last.data.resize(end, 0);
+                }
+                last.data[start..end].copy_from_slice(&chunk.data);
+            }
+            _ => segments.push(Segment::new(chunk.address, chunk.data.clone())),
+        }
+    }
+
+    segments
+}
+
+/// A firmware image opened for editing. Instructions are patched in memory
+/// and the image is written back in the format it was opened from. Only the
+/// records (or bytes for ELF) that a patch changed are rewritten so the
+/// rest of the file is preserved byte for byte, and the checksums of
+/// rewritten Intel HEX records are recomputed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    format: Format,
+    source: Vec<u8>,
+    /// The bytes as they were loaded
+    chunks: Vec<Chunk>,
+    /// The bytes with the patches applied
+    segments: Vec<Segment>,
+    patches: Vec<PatchRecord>,
+}
+
+impl Image {
+    /// Opens an Intel HEX, TI-TXT or ELF file. The format is detected from
+    /// the contents
+    pub fn open(data: &[u8]) -> Result<Image, ImageError> {
+        let (format, chunks) = if data.starts_with(b"\x7fELF") {
+            (Format::Elf, elf(data)?)
+        } else {
+            let text = std::str::from_utf8(data).map_err(|_| ImageError::UnknownFormat)?;
+            match text.trim_start().chars().next() {
+                Some(':') => (Format::IntelHex, intel_hex(text)?),
+                Some('@') => (Format::TiTxt, ti_txt(text)?),
+                _ => return Err(ImageError::UnknownFormat),
+            }
+        };
+
+        Ok(Image {
+            format,
+            source: data.to_vec(),
+            segments: segments(&chunks),
+            chunks,
+            patches: vec![],
+        })
+    }
+
+    /// Returns the format the image was opened from
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns the contents of the image with the patches applied
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Returns a record of every patch in the order they were applied.
+    /// patch::export_map serializes them as a map of the changes
+    pub fn patches(&self) -> &[PatchRecord] {
+        &self.patches
+    }
+
+    /// Replaces the instruction at address the same way as patch::patch
+    /// and returns a record of the change. The image is left untouched on
+    /// error
+    pub fn patch(&mut self, address: u32, inst: &Instruction) -> Result<PatchRecord, ImageError> {
+        let segment = self
+            .segments
+            .iter_mut()
+            .find(|segment| {
+                (segment.address..segment.address + segment.data.len() as u32).contains(&address)
+            })
+            .ok_or(ImageError::Unmapped(address))?;
+
+        let offset = (address - segment.address) as usize;
+        let record = patch::patch_mapped(&mut segment.data, segment.address, offset, inst)
+            .map_err(ImageError::Patch)?;
+        self.patches.push(record.clone());
+        Ok(record)
+    }
+
+    /// Returns the current contents of a chunk
+    fn current(&self, chunk: &Chunk) -> &[u8] {
+        self.segments
+            .iter()
+            .find_map(|segment| {
+                let start = chunk.address.checked_sub(segment.address)? as usize;
+                segment.data.get(start..start + chunk.data.len())
+            })
+            .expect("chunks are always in a segment")
+    }
+
+    /// Writes the image back in the format it was opened from. The file is
+    /// unchanged apart from the bytes that were patched
+    pub fn write(&self) -> Vec<u8> {
+        let changed = self
+            .chunks
+            .iter()
+            .map(|chunk| (chunk, self.current(chunk)))
+            .filter(|(chunk, current)| chunk.data != *current);
+
+        if self.format == Format::Elf {
+            let mut output = self.source.clone();
+            for (chunk, current) in changed {
+                output[chunk.location..chunk.location + current.len()].copy_from_slice(current);
+            }
+            return output;
+        }
+
+        let text = std::str::from_utf8(&self.source).expect("text images are utf-8");
+        let mut lines: Vec<Cow<str>> = text.split_inclusive('\n').map(Cow::Borrowed).collect();
+        for (chunk, current) in changed {
+            let line = &lines[chunk.location];
+            let content = line.trim_end_matches(['\r', '\n']);
+            let record = match self.format {
+                Format::IntelHex => {
+                    let mut record = String::new();
+                    writer::record(&mut record, chunk.address as u16, DATA_RECORD, current);
+                    record.trim_end().to_string()
+                }
+                _ => {
+                    let bytes: Vec<String> = current.iter().map(|b| format!("{:02X}", b)).collect();
+                    bytes.join(" ")
+                }
+            };
+            // keep the case of the hex digits and the surrounding whitespace
+            let record = if content.bytes().any(|b| b.is_ascii_lowercase()) {
+                record.to_ascii_lowercase()
+            } else {
+                record
+            };
+            let trimmed = content.trim();
+            let start = content.len() - content.trim_start().len();
+            let replaced = format!(
+                "{}{}{}",
+                &line[..start],
+                record,
+                &line[start + trimmed.len()..]
+            );
+            lines[chunk.location] = Cow::Owned(replaced);
+        }
+
+        lines.concat().into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::{assemble, parse};
+
+    /// A 32 bit little endian ELF file with one loadable segment holding
+    /// data at address
+    fn elf_file(address: u32, data: &[u8]) -> Vec<u8> {
+        let mut file = vec![0; 0x54];
+        file[..6].copy_from_slice(b"\x7fELF\x01\x01");
+        file[0x1c..0x20].copy_from_slice(&0x34u32.to_le_bytes());
+        file[0x2a..0x2c].copy_from_slice(&0x20u16.to_le_bytes());
+        file[0x2c..0x2e].copy_from_slice(&1u16.to_le_bytes());
+
+        let header = 0x34;
+        file[header..header + 4].copy_from_slice(&PT_LOAD.to_le_bytes());
+        file[header + 4..header + 8].copy_from_slice(&0x54u32.to_le_bytes());
+        file[header + 12..header + 16].copy_from_slice(&address.to_le_bytes());
+        file[header + 16..header + 20].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        file.extend(data);
+        file
+    }
+
+    #[test]
+    fn intel_hex_round_trip() {
+        let program = assemble("mov #0x4400, sp\ncall #0x4410\nret", 0x4400).unwrap();
+        let hex = program.intel_hex();
+        let image = Image::open(hex.as_bytes()).unwrap();
+        assert_eq!(image.format(), Format::IntelHex);
+        assert_eq!(image.segments(), program.segments());
+        assert_eq!(image.write(), hex.as_bytes());
+    }
+
+    #[test]
+    fn intel_hex_patch() {
+        // mov #0x4400, sp; call #0x4410 with the call split across two
+        // records, lowercase digits and crlf line endings
+        let hex = ":0644000031400044b0123f\r\n:02440600104460\r\n:00000001FF\r\n";
+        let mut image = Image::open(hex.as_bytes()).unwrap();
+        image.patch(0x4404, &parse("ret").unwrap()).unwrap();
+
+        let written = String::from_utf8(image.write()).unwrap();
+        assert_eq!(
+            written,
+            ":0644000031400044304190\r\n:0244060003436E\r\n:00000001FF\r\n"
+        );
+        assert_eq!(
+            Image::open(written.as_bytes()).unwrap().segments(),
+            image.segments()
+        );
+        assert_eq!(
+            patch::export_map(image.patches()),
+            "4404-4408  4404-4406  call #0x4410\n"
+        );
+    }
+
+    #[test]
+    fn ti_txt_patch() {
+        let text = "@4400\n31 40 00 44\n  b0 12 10 44\n@fffe\n00 44\nq\n";
+        let mut image = Image::open(text.as_bytes()).unwrap();
+        assert_eq!(image.format(), Format::TiTxt);
+        assert_eq!(image.write(), text.as_bytes());
+
+        image.patch(0x4404, &parse("ret").unwrap()).unwrap();
+        assert_eq!(
+            String::from_utf8(image.write()).unwrap(),
+            "@4400\n31 40 00 44\n  30 41 03 43\n@fffe\n00 44\nq\n"
+        );
+    }
+
+    #[test]
+    fn elf_patch() {
+        // call #0x4410
+        let file = elf_file(0x4400, &[0xb0, 0x12, 0x10, 0x44]);
+        let mut image = Image::open(&file).unwrap();
+        assert_eq!(image.format(), Format::Elf);
+        assert_eq!(
+            image.segments(),
+            [Segment::new(0x4400, vec![0xb0, 0x12, 0x10, 0x44])]
+        );
+
+        image.patch(0x4400, &parse("ret").unwrap()).unwrap();
+        let written = image.write();
+        assert_eq!(written[..0x54], file[..0x54]);
+        assert_eq!(written[0x54..], [0x30, 0x41, 0x03, 0x43]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Image::open(b"hello"), Err(ImageError::UnknownFormat));
+        assert_eq!(Image::open(b":00000001FE\n"), Err(ImageError::Checksum(1)));
+        assert_eq!(
+            Image::open(b"@4400\n31 4\n"),
+            Err(ImageError::InvalidLine(2))
+        );
+        assert_eq!(Image::open(b"\x7fELF\x01\x01"), Err(ImageError::InvalidElf));
+
+        let mut image = Image::open(b"@4400\n30 41\nq\n").unwrap();
+        let ret = parse("ret").unwrap();
+        assert_eq!(image.patch(0x4500, &ret), Err(ImageError::Unmapped(0x4500)));
+        assert_eq!(
+            image.patch(0x4400, &parse("call #0x4410").unwrap()),
+            Err(ImageError::Patch(PatchError::TooLong((4, 2))))
+        );
+        assert!(image.patches().is_empty());
+        assert_eq!(ImageError::Unmapped(0x4500).code(), 1004);
+    }
+}
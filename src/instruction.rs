@@ -1,5 +1,9 @@
+use crate::address::*;
 use crate::emulate::*;
+use crate::encode::{self, EncodeError, EncodeOptions};
+use crate::extended::{Extended, ExtendedWidth};
 use crate::jxx::*;
+use crate::operand::{constant, Operand, OperandWidth};
 use crate::single_operand::*;
 use crate::two_operand::*;
 
@@ -7,6 +11,7 @@ use std::fmt;
 
 /// A container that holds all types of instructions (including emulated)
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Instruction {
     // single operand instructions
     Rrc(Rrc),
@@ -66,9 +71,179 @@ pub enum Instruction {
     Setn(Setn),
     Setz(Setz),
     Tst(Tst),
+
+    // MSP430X
+    Extended(Extended),
+    Mova(Mova),
+    Cmpa(Cmpa),
+    Adda(Adda),
+    Suba(Suba),
+}
+
+/// Returns the emulated form of an instruction if it has one, otherwise the
+/// instruction wrapped in its variant
+pub(crate) fn emulated<T: Emulate>(inst: T, wrap: fn(T) -> Instruction) -> Instruction {
+    inst.emulate().unwrap_or_else(|| wrap(inst))
+}
+
+macro_rules! normalized {
+    ($inst:expr, $t:ident) => {
+        $t::new(
+            constant(*$inst.source(), Some(*$inst.operand_width())),
+            *$inst.operand_width(),
+            *$inst.destination(),
+        )
+    };
 }
 
 impl Instruction {
+    /// Returns the canonical form of the instruction. Instructions that
+    /// have the same semantics but are encoded differently have the same
+    /// canonical form which allows instructions to be compared or hashed by
+    /// semantics rather than encoding.
+    ///
+    /// In the canonical form immediate sources that can be produced by the
+    /// constant generator are replaced by the equivalent constant, taking
+    /// into account that only the low byte is used for byte operations,
+    /// instructions that can be emulated are always represented by their
+    /// emulated form and instruction word bits the CPU ignores are cleared
+    pub fn normalize(&self) -> Instruction {
+        match self {
+            Self::Rrc(inst) => Self::Rrc(Rrc::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Swpb(inst) => Self::Swpb(Swpb::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Rra(inst) => Self::Rra(Rra::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Sxt(inst) => Self::Sxt(Sxt::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Push(inst) => Self::Push(Push::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Call(inst) => Self::Call(Call::new(
+                constant(*inst.source(), *inst.operand_width()),
+                *inst.operand_width(),
+            )),
+            Self::Reti(_) => Self::Reti(Reti::new()),
+            Self::Mov(inst) => emulated(normalized!(inst, Mov), Self::Mov),
+            Self::Add(inst) => emulated(normalized!(inst, Add), Self::Add),
+            Self::Addc(inst) => emulated(normalized!(inst, Addc), Self::Addc),
+            Self::Subc(inst) => emulated(normalized!(inst, Subc), Self::Subc),
+            Self::Sub(inst) => emulated(normalized!(inst, Sub), Self::Sub),
+            Self::Cmp(inst) => emulated(normalized!(inst, Cmp), Self::Cmp),
+            Self::Dadd(inst) => emulated(normalized!(inst, Dadd), Self::Dadd),
+            Self::Bic(inst) => emulated(normalized!(inst, Bic), Self::Bic),
+            Self::Bis(inst) => emulated(normalized!(inst, Bis), Self::Bis),
+            Self::Xor(inst) => emulated(normalized!(inst, Xor), Self::Xor),
+            Self::Bit(inst) => Self::Bit(normalized!(inst, Bit)),
+            Self::And(inst) => Self::And(normalized!(inst, And)),
+            Self::Adc(inst) => Self::Addc(*inst.original()).normalize(),
+            Self::Br(inst) => Self::Mov(*inst.original()).normalize(),
+            Self::Clr(inst) => Self::Mov(*inst.original()).normalize(),
+            Self::Clrc(inst) => Self::Bic(*inst.original()).normalize(),
+            Self::Clrn(inst) => Self::Bic(*inst.original()).normalize(),
+            Self::Clrz(inst) => Self::Bic(*inst.original()).normalize(),
+            Self::Dadc(inst) => Self::Dadd(*inst.original()).normalize(),
+            Self::Dec(inst) => Self::Sub(*inst.original()).normalize(),
+            Self::Decd(inst) => Self::Sub(*inst.original()).normalize(),
+            Self::Dint(inst) => Self::Bic(*inst.original()).normalize(),
+            Self::Eint(inst) => Self::Bis(*inst.original()).normalize(),
+            Self::Inc(inst) => Self::Add(*inst.original()).normalize(),
+            Self::Incd(inst) => Self::Add(*inst.original()).normalize(),
+            Self::Inv(inst) => Self::Xor(*inst.original()).normalize(),
+            Self::Nop(inst) => Self::Mov(*inst.original()).normalize(),
+            Self::Pop(inst) => Self::Mov(*inst.original()).normalize(),
+            Self::Ret(inst) => Self::Mov(*inst.original()).normalize(),
+            Self::Rla(inst) => Self::Add(*inst.original()).normalize(),
+            Self::Rlc(inst) => Self::Addc(*inst.original()).normalize(),
+            Self::Sbc(inst) => Self::Subc(*inst.original()).normalize(),
+            Self::Setc(inst) => Self::Bis(*inst.original()).normalize(),
+            Self::Setn(inst) => Self::Bis(*inst.original()).normalize(),
+            Self::Setz(inst) => Self::Bis(*inst.original()).normalize(),
+            Self::Tst(inst) => Self::Cmp(*inst.original()).normalize(),
+            Self::Extended(inst) => Self::Extended(inst.normalize()),
+            _ => *self,
+        }
+    }
+
+    /// Returns whether two instructions have the same semantics even if
+    /// they are encoded differently (eg. `add #4, r15` using the constant
+    /// generator or an immediate word). This compares the normalized forms
+    pub fn semantically_eq(&self, other: &Instruction) -> bool {
+        self.normalize() == other.normalize()
+    }
+
+    /// Returns the mnemonic for the instruction. This is operand width aware
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            Self::Rrc(inst) => inst.mnemonic(),
+            Self::Swpb(inst) => inst.mnemonic(),
+            Self::Rra(inst) => inst.mnemonic(),
+            Self::Sxt(inst) => inst.mnemonic(),
+            Self::Push(inst) => inst.mnemonic(),
+            Self::Call(inst) => inst.mnemonic(),
+            Self::Reti(_) => "reti",
+            Self::Jnz(inst) => inst.mnemonic(),
+            Self::Jz(inst) => inst.mnemonic(),
+            Self::Jlo(inst) => inst.mnemonic(),
+            Self::Jc(inst) => inst.mnemonic(),
+            Self::Jn(inst) => inst.mnemonic(),
+            Self::Jge(inst) => inst.mnemonic(),
+            Self::Jl(inst) => inst.mnemonic(),
+            Self::Jmp(inst) => inst.mnemonic(),
+            Self::Mov(inst) => inst.mnemonic(),
+            Self::Add(inst) => inst.mnemonic(),
+            Self::Addc(inst) => inst.mnemonic(),
+            Self::Subc(inst) => inst.mnemonic(),
+            Self::Sub(inst) => inst.mnemonic(),
+            Self::Cmp(inst) => inst.mnemonic(),
+            Self::Dadd(inst) => inst.mnemonic(),
+            Self::Bit(inst) => inst.mnemonic(),
+            Self::Bic(inst) => inst.mnemonic(),
+            Self::Bis(inst) => inst.mnemonic(),
+            Self::Xor(inst) => inst.mnemonic(),
+            Self::And(inst) => inst.mnemonic(),
+            Self::Adc(inst) => inst.mnemonic(),
+            Self::Br(inst) => inst.mnemonic(),
+            Self::Clr(inst) => inst.mnemonic(),
+            Self::Clrc(inst) => inst.mnemonic(),
+            Self::Clrn(inst) => inst.mnemonic(),
+            Self::Clrz(inst) => inst.mnemonic(),
+            Self::Dadc(inst) => inst.mnemonic(),
+            Self::Dec(inst) => inst.mnemonic(),
+            Self::Decd(inst) => inst.mnemonic(),
+            Self::Dint(inst) => inst.mnemonic(),
+            Self::Eint(inst) => inst.mnemonic(),
+            Self::Inc(inst) => inst.mnemonic(),
+            Self::Incd(inst) => inst.mnemonic(),
+            Self::Inv(inst) => inst.mnemonic(),
+            Self::Nop(inst) => inst.mnemonic(),
+            Self::Pop(inst) => inst.mnemonic(),
+            Self::Ret(inst) => inst.mnemonic(),
+            Self::Rla(inst) => inst.mnemonic(),
+            Self::Rlc(inst) => inst.mnemonic(),
+            Self::Sbc(inst) => inst.mnemonic(),
+            Self::Setc(inst) => inst.mnemonic(),
+            Self::Setn(inst) => inst.mnemonic(),
+            Self::Setz(inst) => inst.mnemonic(),
+            Self::Tst(inst) => inst.mnemonic(),
+            Self::Extended(inst) => inst.mnemonic(),
+            Self::Mova(inst) => inst.mnemonic(),
+            Self::Cmpa(inst) => inst.mnemonic(),
+            Self::Adda(inst) => inst.mnemonic(),
+            Self::Suba(inst) => inst.mnemonic(),
+        }
+    }
+
     pub fn size(&self) -> usize {
         match self {
             Self::Rrc(inst) => inst.size(),
@@ -122,8 +297,334 @@ impl Instruction {
             Self::Setn(inst) => inst.size(),
             Self::Setz(inst) => inst.size(),
             Self::Tst(inst) => inst.size(),
+            Self::Extended(inst) => inst.size(),
+            Self::Mova(inst) => inst.size(),
+            Self::Cmpa(inst) => inst.size(),
+            Self::Adda(inst) => inst.size(),
+            Self::Suba(inst) => inst.size(),
+        }
+    }
+
+    /// Returns the source operand as it is encoded. Emulated instructions
+    /// return the source of the instruction they emulate. Extended and
+    /// address instructions return None since their operands can be 20 bits
+    /// (see Extended::source_value and AddressInstruction::source_value)
+    pub fn source(&self) -> Option<&Operand> {
+        match self {
+            Self::Rrc(inst) => Some(inst.source()),
+            Self::Swpb(inst) => Some(inst.source()),
+            Self::Rra(inst) => Some(inst.source()),
+            Self::Sxt(inst) => Some(inst.source()),
+            Self::Push(inst) => Some(inst.source()),
+            Self::Call(inst) => Some(inst.source()),
+            Self::Reti(_) => None,
+            Self::Jnz(_) => None,
+            Self::Jz(_) => None,
+            Self::Jlo(_) => None,
+            Self::Jc(_) => None,
+            Self::Jn(_) => None,
+            Self::Jge(_) => None,
+            Self::Jl(_) => None,
+            Self::Jmp(_) => None,
+            Self::Mov(inst) => Some(inst.source()),
+            Self::Add(inst) => Some(inst.source()),
+            Self::Addc(inst) => Some(inst.source()),
+            Self::Subc(inst) => Some(inst.source()),
+            Self::Sub(inst) => Some(inst.source()),
+            Self::Cmp(inst) => Some(inst.source()),
+            Self::Dadd(inst) => Some(inst.source()),
+            Self::Bit(inst) => Some(inst.source()),
+            Self::Bic(inst) => Some(inst.source()),
+            Self::Bis(inst) => Some(inst.source()),
+            Self::Xor(inst) => Some(inst.source()),
+            Self::And(inst) => Some(inst.source()),
+            Self::Adc(inst) => Some(inst.original().source()),
+            Self::Br(inst) => Some(inst.original().source()),
+            Self::Clr(inst) => Some(inst.original().source()),
+            Self::Clrc(inst) => Some(inst.original().source()),
+            Self::Clrn(inst) => Some(inst.original().source()),
+            Self::Clrz(inst) => Some(inst.original().source()),
+            Self::Dadc(inst) => Some(inst.original().source()),
+            Self::Dec(inst) => Some(inst.original().source()),
+            Self::Decd(inst) => Some(inst.original().source()),
+            Self::Dint(inst) => Some(inst.original().source()),
+            Self::Eint(inst) => Some(inst.original().source()),
+            Self::Inc(inst) => Some(inst.original().source()),
+            Self::Incd(inst) => Some(inst.original().source()),
+            Self::Inv(inst) => Some(inst.original().source()),
+            Self::Nop(inst) => Some(inst.original().source()),
+            Self::Pop(inst) => Some(inst.original().source()),
+            Self::Ret(inst) => Some(inst.original().source()),
+            Self::Rla(inst) => Some(inst.original().source()),
+            Self::Rlc(inst) => Some(inst.original().source()),
+            Self::Sbc(inst) => Some(inst.original().source()),
+            Self::Setc(inst) => Some(inst.original().source()),
+            Self::Setn(inst) => Some(inst.original().source()),
+            Self::Setz(inst) => Some(inst.original().source()),
+            Self::Tst(inst) => Some(inst.original().source()),
+            Self::Extended(_) => None,
+            Self::Mova(_) => None,
+            Self::Cmpa(_) => None,
+            Self::Adda(_) => None,
+            Self::Suba(_) => None,
+        }
+    }
+
+    /// Returns the destination operand as it is encoded. Emulated
+    /// instructions return the destination of the instruction they emulate.
+    /// Extended and address instructions return None
+    pub fn destination(&self) -> Option<&Operand> {
+        match self {
+            Self::Rrc(_) => None,
+            Self::Swpb(_) => None,
+            Self::Rra(_) => None,
+            Self::Sxt(_) => None,
+            Self::Push(_) => None,
+            Self::Call(_) => None,
+            Self::Reti(_) => None,
+            Self::Jnz(_) => None,
+            Self::Jz(_) => None,
+            Self::Jlo(_) => None,
+            Self::Jc(_) => None,
+            Self::Jn(_) => None,
+            Self::Jge(_) => None,
+            Self::Jl(_) => None,
+            Self::Jmp(_) => None,
+            Self::Mov(inst) => Some(inst.destination()),
+            Self::Add(inst) => Some(inst.destination()),
+            Self::Addc(inst) => Some(inst.destination()),
+            Self::Subc(inst) => Some(inst.destination()),
+            Self::Sub(inst) => Some(inst.destination()),
+            Self::Cmp(inst) => Some(inst.destination()),
+            Self::Dadd(inst) => Some(inst.destination()),
+            Self::Bit(inst) => Some(inst.destination()),
+            Self::Bic(inst) => Some(inst.destination()),
+            Self::Bis(inst) => Some(inst.destination()),
+            Self::Xor(inst) => Some(inst.destination()),
+            Self::And(inst) => Some(inst.destination()),
+            Self::Adc(inst) => Some(inst.original().destination()),
+            Self::Br(inst) => Some(inst.original().destination()),
+            Self::Clr(inst) => Some(inst.original().destination()),
+            Self::Clrc(inst) => Some(inst.original().destination()),
+            Self::Clrn(inst) => Some(inst.original().destination()),
+            Self::Clrz(inst) => Some(inst.original().destination()),
+            Self::Dadc(inst) => Some(inst.original().destination()),
+            Self::Dec(inst) => Some(inst.original().destination()),
+            Self::Decd(inst) => Some(inst.original().destination()),
+            Self::Dint(inst) => Some(inst.original().destination()),
+            Self::Eint(inst) => Some(inst.original().destination()),
+            Self::Inc(inst) => Some(inst.original().destination()),
+            Self::Incd(inst) => Some(inst.original().destination()),
+            Self::Inv(inst) => Some(inst.original().destination()),
+            Self::Nop(inst) => Some(inst.original().destination()),
+            Self::Pop(inst) => Some(inst.original().destination()),
+            Self::Ret(inst) => Some(inst.original().destination()),
+            Self::Rla(inst) => Some(inst.original().destination()),
+            Self::Rlc(inst) => Some(inst.original().destination()),
+            Self::Sbc(inst) => Some(inst.original().destination()),
+            Self::Setc(inst) => Some(inst.original().destination()),
+            Self::Setn(inst) => Some(inst.original().destination()),
+            Self::Setz(inst) => Some(inst.original().destination()),
+            Self::Tst(inst) => Some(inst.original().destination()),
+            Self::Extended(_) => None,
+            Self::Mova(_) => None,
+            Self::Cmpa(_) => None,
+            Self::Adda(_) => None,
+            Self::Suba(_) => None,
         }
     }
+
+    /// Returns the operand width as it is encoded. Emulated instructions
+    /// return the width of the instruction they emulate. Address
+    /// instructions and 20 bit extended instructions return None
+    pub fn operand_width(&self) -> Option<OperandWidth> {
+        match self {
+            Self::Rrc(inst) => *inst.operand_width(),
+            Self::Swpb(inst) => *inst.operand_width(),
+            Self::Rra(inst) => *inst.operand_width(),
+            Self::Sxt(inst) => *inst.operand_width(),
+            Self::Push(inst) => *inst.operand_width(),
+            Self::Call(inst) => *inst.operand_width(),
+            Self::Reti(_) => None,
+            Self::Jnz(_) => None,
+            Self::Jz(_) => None,
+            Self::Jlo(_) => None,
+            Self::Jc(_) => None,
+            Self::Jn(_) => None,
+            Self::Jge(_) => None,
+            Self::Jl(_) => None,
+            Self::Jmp(_) => None,
+            Self::Mov(inst) => Some(*inst.operand_width()),
+            Self::Add(inst) => Some(*inst.operand_width()),
+            Self::Addc(inst) => Some(*inst.operand_width()),
+            Self::Subc(inst) => Some(*inst.operand_width()),
+            Self::Sub(inst) => Some(*inst.operand_width()),
+            Self::Cmp(inst) => Some(*inst.operand_width()),
+            Self::Dadd(inst) => Some(*inst.operand_width()),
+            Self::Bit(inst) => Some(*inst.operand_width()),
+            Self::Bic(inst) => Some(*inst.operand_width()),
+            Self::Bis(inst) => Some(*inst.operand_width()),
+            Self::Xor(inst) => Some(*inst.operand_width()),
+            Self::And(inst) => Some(*inst.operand_width()),
+            Self::Adc(inst) => Some(*inst.original().operand_width()),
+            Self::Br(inst) => Some(*inst.original().operand_width()),
+            Self::Clr(inst) => Some(*inst.original().operand_width()),
+            Self::Clrc(inst) => Some(*inst.original().operand_width()),
+            Self::Clrn(inst) => Some(*inst.original().operand_width()),
+            Self::Clrz(inst) => Some(*inst.original().operand_width()),
+            Self::Dadc(inst) => Some(*inst.original().operand_width()),
+            Self::Dec(inst) => Some(*inst.original().operand_width()),
+            Self::Decd(inst) => Some(*inst.original().operand_width()),
+            Self::Dint(inst) => Some(*inst.original().operand_width()),
+            Self::Eint(inst) => Some(*inst.original().operand_width()),
+            Self::Inc(inst) => Some(*inst.original().operand_width()),
+            Self::Incd(inst) => Some(*inst.original().operand_width()),
+            Self::Inv(inst) => Some(*inst.original().operand_width()),
+            Self::Nop(inst) => Some(*inst.original().operand_width()),
+            Self::Pop(inst) => Some(*inst.original().operand_width()),
+            Self::Ret(inst) => Some(*inst.original().operand_width()),
+            Self::Rla(inst) => Some(*inst.original().operand_width()),
+            Self::Rlc(inst) => Some(*inst.original().operand_width()),
+            Self::Sbc(inst) => Some(*inst.original().operand_width()),
+            Self::Setc(inst) => Some(*inst.original().operand_width()),
+            Self::Setn(inst) => Some(*inst.original().operand_width()),
+            Self::Setz(inst) => Some(*inst.original().operand_width()),
+            Self::Tst(inst) => Some(*inst.original().operand_width()),
+            Self::Extended(inst) => match inst.width() {
+                ExtendedWidth::Byte => Some(OperandWidth::Byte),
+                ExtendedWidth::Word => Some(OperandWidth::Word),
+                ExtendedWidth::Address => None,
+            },
+            Self::Mova(_) => None,
+            Self::Cmpa(_) => None,
+            Self::Adda(_) => None,
+            Self::Suba(_) => None,
+        }
+    }
+
+    /// Encodes the instruction to machine code exactly as it is (see
+    /// original_encoding) so decoding and then encoding always returns the
+    /// original bytes. Emulated instructions are encoded as the instruction
+    /// they emulate. Immediates are only encoded with the constant
+    /// generator when they are Operand::Constant; use canonical_encoding
+    /// or encode_with the default options to use it for every immediate it
+    /// can produce
+    pub fn encode(&self) -> std::result::Result<Vec<u8>, EncodeError> {
+        self.original_encoding()
+    }
+
+    /// Encodes the instruction to machine code. The default options give
+    /// the canonical encoding
+    pub fn encode_with(
+        &self,
+        options: &EncodeOptions,
+    ) -> std::result::Result<Vec<u8>, EncodeError> {
+        self.encoding(options)
+            .map(|encoding| encoding.bytes().to_vec())
+    }
+
+    /// Encodes the instruction and appends the machine code to output so
+    /// images can be built up without allocating per instruction. Nothing
+    /// is appended when the instruction can't be encoded
+    pub fn encode_into(
+        &self,
+        output: &mut Vec<u8>,
+        options: &EncodeOptions,
+    ) -> std::result::Result<(), EncodeError> {
+        output.extend_from_slice(self.encoding(options)?.bytes());
+        Ok(())
+    }
+
+    /// Encodes the instruction and writes the machine code to w. Encode
+    /// errors are returned as io errors of kind InvalidInput that wrap the
+    /// EncodeError
+    pub fn encode_to_writer<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        options: &EncodeOptions,
+    ) -> std::io::Result<()> {
+        let encoding = self
+            .encoding(options)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        w.write_all(encoding.bytes())
+    }
+
+    fn encoding(
+        &self,
+        options: &EncodeOptions,
+    ) -> std::result::Result<encode::Encoding, EncodeError> {
+        let reserved = if options.reserved_bits {
+            self.reserved()
+        } else {
+            0
+        };
+
+        if options.literal_immediates {
+            encode::instruction(self, reserved)
+        } else {
+            encode::instruction(&self.normalize(), reserved)
+        }
+    }
+
+    /// Returns the instruction word bits the CPU ignores that were set when
+    /// the instruction was decoded
+    fn reserved(&self) -> u16 {
+        match self {
+            Self::Rrc(inst) => inst.reserved(),
+            Self::Swpb(inst) => inst.reserved(),
+            Self::Rra(inst) => inst.reserved(),
+            Self::Sxt(inst) => inst.reserved(),
+            Self::Push(inst) => inst.reserved(),
+            Self::Call(inst) => inst.reserved(),
+            Self::Reti(inst) => inst.reserved(),
+            _ => 0,
+        }
+    }
+
+    /// Encodes the instruction exactly as it is, keeping literal immediates
+    /// and instruction word bits the CPU ignores. For a decoded instruction
+    /// this is always the bytes it was decoded from
+    pub fn original_encoding(&self) -> std::result::Result<Vec<u8>, EncodeError> {
+        self.encode_with(&EncodeOptions {
+            literal_immediates: true,
+            reserved_bits: true,
+            ..Default::default()
+        })
+    }
+
+    /// Encodes the normalized form of the instruction which is the shortest
+    /// encoding and the one an assembler produces. This is the same as
+    /// encode_with the default options
+    pub fn canonical_encoding(&self) -> std::result::Result<Vec<u8>, EncodeError> {
+        self.encode_with(&EncodeOptions::default())
+    }
+
+    /// Returns the offset (in words) for jxx instructions
+    pub fn jump_offset(&self) -> Option<i16> {
+        match self {
+            Self::Jnz(inst) => Some(inst.offset()),
+            Self::Jz(inst) => Some(inst.offset()),
+            Self::Jlo(inst) => Some(inst.offset()),
+            Self::Jc(inst) => Some(inst.offset()),
+            Self::Jn(inst) => Some(inst.offset()),
+            Self::Jge(inst) => Some(inst.offset()),
+            Self::Jl(inst) => Some(inst.offset()),
+            Self::Jmp(inst) => Some(inst.offset()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "evcxr")]
+impl Instruction {
+    /// Called by evcxr to display an instruction in a notebook
+    pub fn evcxr_display(&self) {
+        let text = crate::listing::escape_html(&self.to_string());
+        println!(
+            "EVCXR_BEGIN_CONTENT text/html\n<code>{}</code>\nEVCXR_END_CONTENT",
+            text
+        );
+    }
 }
 
 impl fmt::Display for Instruction {
@@ -180,6 +681,11 @@ impl fmt::Display for Instruction {
             Self::Setn(inst) => write!(f, "{}", inst),
             Self::Setz(inst) => write!(f, "{}", inst),
             Self::Tst(inst) => write!(f, "{}", inst),
+            Self::Extended(inst) => write!(f, "{}", inst),
+            Self::Mova(inst) => write!(f, "{}", inst),
+            Self::Cmpa(inst) => write!(f, "{}", inst),
+            Self::Adda(inst) => write!(f, "{}", inst),
+            Self::Suba(inst) => write!(f, "{}", inst),
         }
     }
 }
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::decode;
+use crate::instruction::Instruction;
+use crate::operand::{Operand, OperandWidth};
+use crate::Result;
+
+const SINGLE_OPERAND: [&str; 7] = ["rrc", "swpb", "rra", "sxt", "push", "call", "reti"];
+const JXX: [&str; 8] = ["jnz", "jz", "jlo", "jc", "jn", "jge", "jl", "jmp"];
+const TWO_OPERAND: [&str; 12] = [
+    "mov", "add", "addc", "subc", "sub", "cmp", "dadd", "bit", "bic", "bis", "xor", "and",
+];
+
+/// The addressing mode of an operand as it is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mode {
+    Register,
+    Indexed,
+    Indirect,
+    AutoIncrement,
+    Symbolic,
+    Immediate,
+    Absolute,
+    Constant,
+}
+
+const SOURCE_MODES: [Mode; 8] = [
+    Mode::Register,
+    Mode::Indexed,
+    Mode::Indirect,
+    Mode::AutoIncrement,
+    Mode::Symbolic,
+    Mode::Immediate,
+    Mode::Absolute,
+    Mode::Constant,
+];
+
+const DESTINATION_MODES: [Mode; 4] = [
+    Mode::Register,
+    Mode::Indexed,
+    Mode::Symbolic,
+    Mode::Absolute,
+];
+
+impl From<&Operand> for Mode {
+    fn from(operand: &Operand) -> Self {
+        match operand {
+            Operand::RegisterDirect(_) => Mode::Register,
+            Operand::Indexed { .. } => Mode::Indexed,
+            Operand::RegisterIndirect(_) => Mode::Indirect,
+            Operand::RegisterIndirectAutoIncrement(_) => Mode::AutoIncrement,
+            Operand::Symbolic(_) => Mode::Symbolic,
+            Operand::Immediate(_) => Mode::Immediate,
+            Operand::Absolute(_) => Mode::Absolute,
+            Operand::Constant(_) => Mode::Constant,
+        }
+    }
+}
+
+/// An opcode, addressing mode and width combination. Emulated instructions
+/// are counted as the instruction they emulate since that is what is
+/// encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Form {
+    pub opcode: &'static str,
+    pub source: Option<Mode>,
+    pub destination: Option<Mode>,
+    /// Whether the instruction operates on bytes. None for instructions
+    /// that don't have a width
+    pub byte: Option<bool>,
+}
+
+impl Form {
+    /// Returns the form of an instruction or None if it can't be encoded.
+    /// MSP430X extended and address instructions aren't part of the base
+    /// forms
+    pub fn of(inst: &Instruction) -> Option<Form> {
+        if let Instruction::Extended(_)
+        | Instruction::Mova(_)
+        | Instruction::Cmpa(_)
+        | Instruction::Adda(_)
+        | Instruction::Suba(_) = inst
+        {
+            return None;
+        }
+
+        let bytes = inst.encode().ok()?;
+        let word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let byte = inst
+            .operand_width()
+            .map(|width| width == OperandWidth::Byte);
+
+        let form = match word >> 13 {
+            0 => Form {
+                opcode: SINGLE_OPERAND[(word as usize >> 7) & 0b111],
+                source: inst.source().map(Mode::from),
+                destination: None,
+                byte,
+            },
+            1 => Form {
+                opcode: JXX[(word as usize >> 10) & 0b111],
+                source: None,
+                destination: None,
+                byte: None,
+            },
+            _ => Form {
+                opcode: TWO_OPERAND[(word as usize >> 12) - 4],
+                source: inst.source().map(Mode::from),
+                destination: inst.destination().map(Mode::from),
+                byte,
+            },
+        };
+
+        Some(form)
+    }
+
+    /// Returns every form that can be encoded
+    pub fn all() -> Vec<Form> {
+        let mut forms = vec![];
+        for opcode in SINGLE_OPERAND {
+            let widths: &[Option<bool>] = match opcode {
+                "rrc" | "rra" | "push" => &[Some(false), Some(true)],
+                _ => &[None],
+            };
+            let modes: &[Mode] = match opcode {
+                "reti" => &[],
+                _ => &SOURCE_MODES,
+            };
+
+            if modes.is_empty() {
+                forms.push(Form {
+                    opcode,
+                    source: None,
+                    destination: None,
+                    byte: None,
+                });
+            }
+
+            for source in modes {
+                for byte in widths {
+                    forms.push(Form {
+                        opcode,
+                        source: Some(*source),
+                        destination: None,
+                        byte: *byte,
+                    });
+                }
+            }
+        }
+
+        for opcode in JXX {
+            forms.push(Form {
+                opcode,
+                source: None,
+                destination: None,
+                byte: None,
+            });
+        }
+
+        for opcode in TWO_OPERAND {
+            for source in SOURCE_MODES {
+                for destination in DESTINATION_MODES {
+                    for byte in [false, true] {
+                        forms.push(Form {
+                            opcode,
+                            source: Some(source),
+                            destination: Some(destination),
+                            byte: Some(byte),
+                        });
+                    }
+                }
+            }
+        }
+
+        forms
+    }
+}
+
+/// Records which forms were exercised by decoding so gaps in test suites
+/// and corpora can be found
+#[derive(Debug, Clone, Default)]
+pub struct IsaCoverage {
+    hits: BTreeMap<Form, usize>,
+}
+
+impl IsaCoverage {
+    pub fn new() -> IsaCoverage {
+        IsaCoverage::default()
+    }
+
+    /// Decodes data and records the form of the instruction if it decodes
+    pub fn decode(&mut self, data: &[u8]) -> Result<Instruction> {
+        let inst = decode(data)?;
+        self.record(&inst);
+        Ok(inst)
+    }
+
+    /// Records the form of an instruction
+    pub fn record(&mut self, inst: &Instruction) {
+        if let Some(form) = Form::of(inst) {
+            *self.hits.entry(form).or_default() += 1;
+        }
+    }
+
+    /// Returns the number of times a form was recorded
+    pub fn hits(&self, form: &Form) -> usize {
+        self.hits.get(form).copied().unwrap_or(0)
+    }
+
+    /// Returns the forms that were never recorded
+    pub fn gaps(&self) -> Vec<Form> {
+        Form::all()
+            .into_iter()
+            .filter(|form| !self.hits.contains_key(form))
+            .collect()
+    }
+
+    /// Renders a summary of the coverage followed by one line per gap
+    pub fn report(&self) -> String {
+        let gaps = self.gaps();
+        let total = Form::all().len();
+        let mut output = String::new();
+        let _ = writeln!(output, "{} of {} forms covered", total - gaps.len(), total);
+
+        for gap in gaps {
+            let _ = write!(output, "{}", gap.opcode);
+            if gap.byte == Some(true) {
+                output.push_str(".b");
+            }
+
+            let modes: Vec<String> = [gap.source, gap.destination]
+                .into_iter()
+                .flatten()
+                .map(|mode| format!("{:?}", mode).to_lowercase())
+                .collect();
+            if !modes.is_empty() {
+                let _ = write!(output, " {}", modes.join(", "));
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emulated_as_original() {
+        let mut coverage = IsaCoverage::new();
+        // ret
+        coverage.decode(&[0x30, 0x41]).unwrap();
+        assert_eq!(
+            coverage.hits(&Form {
+                opcode: "mov",
+                source: Some(Mode::AutoIncrement),
+                destination: Some(Mode::Register),
+                byte: Some(false),
+            }),
+            1
+        );
+    }
+
+    #[test]
+    fn exhaustive() {
+        let mut coverage = IsaCoverage::new();
+        for word in 0..=u16::MAX {
+            let [a, b] = word.to_le_bytes();
+            let _ = coverage.decode(&[a, b, 0x34, 0x12, 0x78, 0x56]);
+        }
+
+        assert_eq!(coverage.gaps(), vec![]);
+        assert_eq!(coverage.report(), "849 of 849 forms covered\n");
+    }
+
+    #[test]
+    fn report() {
+        let mut coverage = IsaCoverage::new();
+        // jnz #-0x2
+        coverage.decode(&[0xfe, 0x23]).unwrap();
+        let report = coverage.report();
+        assert!(report.starts_with("1 of 849 forms covered\n"));
+        assert!(report.contains("\nrrc.b indexed\n"));
+        assert!(report.contains("\nmov.b constant, absolute\n"));
+        assert!(!report.contains("\njnz\n"));
+        assert!(report.contains("\njz\n"));
+    }
+}
@@ -1,6 +1,14 @@
 use std::fmt;
+use std::ops::RangeInclusive;
 
-pub fn jxx_fix_offset(offset: u16) -> i16 {
+use crate::encode::EncodeError;
+
+/// The range of word offsets that fit in the 10 bit offset field of a jxx
+/// instruction
+pub const OFFSET_RANGE: RangeInclusive<i16> = -512..=511;
+
+/// Sign extends the 10 bit offset field of a jxx instruction word
+pub fn sign_extend_offset(offset: u16) -> i16 {
     if offset & 0b10_0000_0000 > 0 {
         (offset | 0xfc00) as i16
     } else {
@@ -8,6 +16,27 @@ pub fn jxx_fix_offset(offset: u16) -> i16 {
     }
 }
 
+/// Converts a displacement in bytes into the word offset a jxx instruction
+/// is encoded with. Like the offset, the displacement is relative to the
+/// word after the jump so it must be even and within -1024..=1022
+pub fn offset_from_displacement(displacement: i64) -> std::result::Result<i16, EncodeError> {
+    match i16::try_from(displacement / 2) {
+        Ok(offset) if displacement % 2 == 0 && OFFSET_RANGE.contains(&offset) => Ok(offset),
+        _ => Err(EncodeError::InvalidDisplacement(displacement)),
+    }
+}
+
+/// Converts the word offset of a jxx instruction into a displacement in
+/// bytes relative to the word after the jump
+pub fn displacement_from_offset(offset: i16) -> i64 {
+    offset as i64 * 2
+}
+
+#[deprecated(since = "0.3.0", note = "renamed to sign_extend_offset")]
+pub fn jxx_fix_offset(offset: u16) -> i16 {
+    sign_extend_offset(offset)
+}
+
 /// All jxx instructions implement this trait to provide a common interface
 /// and polymorphism
 pub trait Jxx {
@@ -19,6 +48,7 @@ pub trait Jxx {
 macro_rules! jxx {
     ($t:ident, $n:expr) => {
         #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
         pub struct $t {
             offset: i16,
         }
@@ -51,7 +81,7 @@ macro_rules! jxx {
                 // should probably implement a better fix that is more
                 // efficient https://github.com/rust-lang/rust/issues/42860
                 if self.offset < 0 {
-                    write!(f, "{} #-{:#x}", $n, -self.offset)
+                    write!(f, "{} #-{:#x}", $n, self.offset.unsigned_abs())
                 } else {
                     write!(f, "{} #{:#x}", $n, self.offset)
                 }
@@ -68,3 +98,34 @@ jxx!(Jn, "jn");
 jxx!(Jge, "jge");
 jxx!(Jl, "jl");
 jxx!(Jmp, "jmp");
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displacement() {
+        assert_eq!(offset_from_displacement(-1024), Ok(-512));
+        assert_eq!(offset_from_displacement(1022), Ok(511));
+        assert_eq!(displacement_from_offset(-2), -4);
+        assert_eq!(
+            offset_from_displacement(1024),
+            Err(EncodeError::InvalidDisplacement(1024))
+        );
+        assert_eq!(
+            offset_from_displacement(3),
+            Err(EncodeError::InvalidDisplacement(3))
+        );
+        assert_eq!(
+            offset_from_displacement(i64::MIN),
+            Err(EncodeError::InvalidDisplacement(i64::MIN))
+        );
+
+        for offset in OFFSET_RANGE {
+            assert_eq!(
+                offset_from_displacement(displacement_from_offset(offset)),
+                Ok(offset)
+            );
+        }
+    }
+}
@@ -1,70 +1,94 @@
+//! A disassembly engine for the msp430
+//!
+//! Most uses only need [`decode`] and the types in the [`prelude`]. A dead
+//! listing of an image takes a few lines:
+//!
+//! ```
+//! use msp430_asm::prelude::*;
+//!
+//! // mov #0x4400, sp; ret
+//! let image = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+//! let mut offset = 0;
+//! while offset < image.len() {
+//!     match decode(&image[offset..]) {
+//!         Ok(inst) => {
+//!             println!("{:04x}: {}", 0x4400 + offset, inst);
+//!             offset += inst.size();
+//!         }
+//!         Err(e) => {
+//!             println!("{:04x}: ; {}", 0x4400 + offset, e);
+//!             offset += 2;
+//!         }
+//!     }
+//! }
+//!
+//! assert_eq!(
+//!     render(&sweep(&image, 0x4400)),
+//!     "       0  4400:  mov #0x4400, sp\n       0  4404:  ret\n"
+//! );
+//! ```
+//!
+//! # Stability
+//!
+//! [`decode`], the [`prelude`], the instruction and operand types and their
+//! traits make up the stable surface. Byte lengths of instructions and
+//! operands are always named `size`. Error enums are `#[non_exhaustive]` and
+//! provide stable numeric codes. Renamed items are kept for one release with
+//! a deprecation pointing to their replacement. The [`decode::parts`]
+//! parsers and the analysis modules may still change between minor releases
+//! and the `superopt` feature is experimental
+
+pub mod address;
+pub mod asm;
+pub mod cache;
+pub mod canonical;
+pub mod cave;
+pub mod composite;
+pub mod container;
+pub mod coverage;
+pub mod decode;
 pub mod decode_error;
+pub mod diff;
 pub mod emulate;
+pub mod encode;
+pub mod extended;
+pub mod extract;
+pub mod format;
+pub mod globals;
+pub mod heuristics;
+pub mod il;
+pub mod image;
 pub mod instruction;
+pub mod isa_coverage;
 pub mod jxx;
+pub mod listing;
 pub mod operand;
+pub mod overlay;
+pub mod packed;
+pub mod patch;
+pub mod peephole;
+pub mod prelude;
+pub mod provenance;
+pub mod rules;
+pub mod security;
+pub mod semantics;
 pub mod single_operand;
+#[cfg(feature = "superopt")]
+pub mod superopt;
+pub mod symbols;
+pub mod target;
+pub mod terminator;
+pub mod testing;
+pub mod trace;
 pub mod two_operand;
+pub mod vectors;
+pub mod workspace;
+pub mod writer;
 
-use decode_error::DecodeError;
-use emulate::Emulate;
-use instruction::Instruction;
-use jxx::*;
-use operand::{parse_destination, parse_source, OperandWidth};
-use single_operand::*;
-use two_operand::*;
-
-const RRC_OPCODE: u16 = 0;
-const SWPB_OPCODE: u16 = 1;
-const RRA_OPCODE: u16 = 2;
-const SXT_OPCODE: u16 = 3;
-const PUSH_OPCODE: u16 = 4;
-const CALL_OPCODE: u16 = 5;
-const RETI_OPCODE: u16 = 6;
-
-const MOV_OPCODE: u16 = 4;
-const ADD_OPCODE: u16 = 5;
-const ADDC_OPCODE: u16 = 6;
-const SUBC_OPCODE: u16 = 7;
-const SUB_OPCODE: u16 = 8;
-const CMP_OPCODE: u16 = 9;
-const DADD_OPCODE: u16 = 10;
-const BIT_OPCODE: u16 = 11;
-const BIC_OPCODE: u16 = 12;
-const BIS_OPCODE: u16 = 13;
-const XOR_OPCODE: u16 = 14;
-const AND_OPCODE: u16 = 15;
-
-const SINGLE_OPERAND_REGISTER_MASK: u16 = 0b1111;
-
-const SINGLE_OPERAND_OPCODE_MASK: u16 = 0b0000_0011_1000_0000;
-
-const SINGLE_OPERAND_SOURCE_MASK: u16 = 0b11_0000;
-
-const SINGLE_OPERAND_WIDTH_MASK: u16 = 0b100_0000;
-
-const INST_TYPE_MASK: u16 = 0b1110_0000_0000_0000;
-
-const SINGLE_OPERAND_INSTRUCTION: u16 = 0b0000_0000_0000_0000;
-
-/// JMP_MASK masks off the high three bits to check whether the pattern 001
-/// is present. This describes a JMP instruction
-const JMP_INSTRUCTION: u16 = 0b0010_0000_0000_0000;
-
-/// JMP_CONDITION_MASK masks off the three bits used to denote the Jxx condition
-const JMP_CONDITION_MASK: u16 = 0b0001_1100_0000_0000;
-
-/// JMP_OFFSET masks off the lower 10 bits used to represent the offset.
-/// This can be a negative offset and it represented as such in one's
-/// compliment
-const JMP_OFFSET: u16 = 0b0000001111111111;
-
-const TWO_OPERAND_OPCODE_MASK: u16 = 0b1111_0000_0000_0000;
-const TWO_OPERAND_SOURCE_MASK: u16 = 0b1111_0000_0000;
-const TWO_OPERAND_AD_MASK: u16 = 0b1000_0000;
-const TWO_OPERAND_WIDTH: u16 = 0b100_0000;
-const TWO_OPERAND_AS: u16 = 0b11_0000;
-const TWO_OPERAND_DESTINATION: u16 = 0b1111;
+pub use decode_error::DecodeError;
+pub use encode::{EncodeError, EncodeOptions};
+pub use instruction::Instruction;
+pub use operand::{Operand, OperandWidth};
 
 /// Stores the return type for a decode
 pub type Result<T> = std::result::Result<T, DecodeError>;
@@ -74,163 +98,30 @@ pub type Result<T> = std::result::Result<T, DecodeError>;
 /// series of instructions, you keep track of the number of the size of the
 /// last decoded instruction to remove those bytes from the input to correctly
 /// decode the next due to the fact that instructions are not fixed width and
-/// maybe 2, 4 or 6 bytes
+/// maybe 2, 4, 6 or (for MSP430X extended instructions) 8 bytes
 pub fn decode(data: &[u8]) -> Result<Instruction> {
-    if data.len() < 2 {
-        return Err(DecodeError::MissingInstruction);
-    }
-
-    let (int_bytes, remaining_data) = data.split_at(std::mem::size_of::<u16>());
-    let first_word = u16::from_le_bytes(int_bytes.try_into().unwrap());
-
-    let inst_type = first_word & INST_TYPE_MASK;
-    match inst_type {
-        SINGLE_OPERAND_INSTRUCTION => {
-            let opcode = (SINGLE_OPERAND_OPCODE_MASK & first_word) >> 7;
-            let register = (SINGLE_OPERAND_REGISTER_MASK & first_word) as u8;
-            let source_addressing = (SINGLE_OPERAND_SOURCE_MASK & first_word) >> 4;
-            let operand_width =
-                OperandWidth::from(((SINGLE_OPERAND_WIDTH_MASK & first_word) >> 6) as u8);
-
-            let (source, _) = operand::parse_source(register, source_addressing, remaining_data)?;
-
-            match opcode {
-                RRC_OPCODE => Ok(Instruction::Rrc(Rrc::new(source, Some(operand_width)))),
-                SWPB_OPCODE => Ok(Instruction::Swpb(Swpb::new(source, None))),
-                RRA_OPCODE => Ok(Instruction::Rra(Rra::new(source, Some(operand_width)))),
-                SXT_OPCODE => Ok(Instruction::Sxt(Sxt::new(source, None))),
-                PUSH_OPCODE => Ok(Instruction::Push(Push::new(source, Some(operand_width)))),
-                CALL_OPCODE => Ok(Instruction::Call(Call::new(source, None))),
-                RETI_OPCODE => Ok(Instruction::Reti(Reti::new())),
-                _ => Err(DecodeError::InvalidOpcode(opcode)),
-            }
-        }
-        JMP_INSTRUCTION => {
-            let condition = (first_word & JMP_CONDITION_MASK) >> 10;
-            let offset = jxx_fix_offset(first_word & JMP_OFFSET);
-
-            match condition {
-                0 => Ok(Instruction::Jnz(Jnz::new(offset))),
-                1 => Ok(Instruction::Jz(Jz::new(offset))),
-                2 => Ok(Instruction::Jlo(Jlo::new(offset))),
-                3 => Ok(Instruction::Jc(Jc::new(offset))),
-                4 => Ok(Instruction::Jn(Jn::new(offset))),
-                5 => Ok(Instruction::Jge(Jge::new(offset))),
-                6 => Ok(Instruction::Jl(Jl::new(offset))),
-                7 => Ok(Instruction::Jmp(Jmp::new(offset))),
-                _ => Err(DecodeError::InvalidJumpCondition(condition)),
-            }
-        }
-        _ => {
-            // The opcode is the first four bits for this type of
-            // instruction so there isn't a simple mask we can check.
-            // If it doesn't match a single operand or jmp instuction
-            // we'll fall through to here and attempt to match a two
-            // operand. If it doesn't match any we'll return None
-            let opcode = (first_word & TWO_OPERAND_OPCODE_MASK) >> 12;
-            let source_register = ((first_word & TWO_OPERAND_SOURCE_MASK) >> 8) as u8;
-            let ad = (first_word & TWO_OPERAND_AD_MASK) >> 7;
-            let operand_width = OperandWidth::from(((first_word & TWO_OPERAND_WIDTH) >> 6) as u8);
-            let source_addressing = (first_word & TWO_OPERAND_AS) >> 4;
-            let destination_register = (first_word & TWO_OPERAND_DESTINATION) as u8;
-
-            // if source has an additional word it is encoded before the destination
-            let (source, remaining_data) =
-                parse_source(source_register, source_addressing, remaining_data)?;
-
-            let destination = parse_destination(destination_register, ad, remaining_data)?;
-
-            match opcode {
-                MOV_OPCODE => {
-                    let inst = Mov::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Mov(inst)),
-                    }
-                }
-                ADD_OPCODE => {
-                    let inst = Add::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Add(inst)),
-                    }
-                }
-                ADDC_OPCODE => {
-                    let inst = Addc::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Addc(inst)),
-                    }
-                }
-                SUBC_OPCODE => {
-                    let inst = Subc::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Subc(inst)),
-                    }
-                }
-                SUB_OPCODE => {
-                    let inst = Sub::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Sub(inst)),
-                    }
-                }
-                CMP_OPCODE => {
-                    let inst = Cmp::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Cmp(inst)),
-                    }
-                }
-                DADD_OPCODE => {
-                    let inst = Dadd::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Dadd(inst)),
-                    }
-                }
-                BIT_OPCODE => Ok(Instruction::Bit(Bit::new(
-                    source,
-                    operand_width,
-                    destination,
-                ))),
-                BIC_OPCODE => {
-                    let inst = Bic::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Bic(inst)),
-                    }
-                }
-                BIS_OPCODE => {
-                    let inst = Bis::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Bis(inst)),
-                    }
-                }
-                XOR_OPCODE => {
-                    let inst = Xor::new(source, operand_width, destination);
-                    match inst.emulate() {
-                        Some(inst) => Ok(inst),
-                        None => Ok(Instruction::Xor(inst)),
-                    }
-                }
-                AND_OPCODE => Ok(Instruction::And(And::new(
-                    source,
-                    operand_width,
-                    destination,
-                ))),
-                _ => Err(DecodeError::InvalidOpcode(opcode)),
-            }
-        }
+    decode::parts::instruction(decode::Cursor::new(data)).map(|(_, inst)| inst)
+}
+
+/// Decodes the instruction at the start of the slice which is located at
+/// address. Instructions are always word aligned so an odd address returns
+/// UnalignedAddress rather than decoding bytes that the hardware would
+/// never fetch as an instruction
+pub fn decode_at(data: &[u8], address: u32) -> Result<Instruction> {
+    if !address.is_multiple_of(2) {
+        return Err(DecodeError::UnalignedAddress(address));
     }
+
+    decode(data)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::operand::Operand;
+    use crate::jxx::*;
+    use crate::operand::{Operand, OperandWidth};
+    use crate::single_operand::*;
+    use crate::two_operand::*;
 
     #[test]
     fn empty_data() {
@@ -238,6 +129,16 @@ mod tests {
         assert_eq!(decode(&data), Err(DecodeError::MissingInstruction));
     }
 
+    #[test]
+    fn unaligned_address() {
+        let data = [0x30, 0x41];
+        assert_eq!(
+            decode_at(&data, 0x4401),
+            Err(DecodeError::UnalignedAddress(0x4401))
+        );
+        assert_eq!(decode_at(&data, 0x4400), decode(&data));
+    }
+
     #[test]
     fn jnz() {
         let data = [0x00, 0x20];
@@ -334,7 +235,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rrc(Rrc::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -347,7 +251,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rrc(Rrc::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -360,7 +267,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rrc(Rrc::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Byte)
             )))
         );
@@ -373,7 +283,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rrc(Rrc::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Byte)
             )))
         );
@@ -450,7 +363,13 @@ mod tests {
         let inst = decode(&data);
         assert_eq!(
             inst,
-            Ok(Instruction::Swpb(Swpb::new(Operand::Indexed((9, 4)), None)))
+            Ok(Instruction::Swpb(Swpb::new(
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
+                None
+            )))
         );
     }
 
@@ -461,7 +380,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Swpb(Swpb::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 None
             )))
         );
@@ -526,7 +448,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rra(Rra::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -539,7 +464,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rra(Rra::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -552,7 +480,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rra(Rra::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Byte)
             )))
         );
@@ -565,7 +496,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Rra(Rra::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Byte)
             )))
         );
@@ -639,7 +573,13 @@ mod tests {
         let inst = decode(&data);
         assert_eq!(
             inst,
-            Ok(Instruction::Sxt(Sxt::new(Operand::Indexed((9, 4)), None)))
+            Ok(Instruction::Sxt(Sxt::new(
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
+                None
+            )))
         );
     }
 
@@ -649,7 +589,13 @@ mod tests {
         let inst = decode(&data);
         assert_eq!(
             inst,
-            Ok(Instruction::Sxt(Sxt::new(Operand::Indexed((9, -5)), None)))
+            Ok(Instruction::Sxt(Sxt::new(
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
+                None
+            )))
         );
     }
 
@@ -712,7 +658,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Push(Push::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -725,7 +674,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Push(Push::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Word)
             )))
         );
@@ -738,7 +690,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Push(Push::new(
-                Operand::Indexed((9, 4)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
                 Some(OperandWidth::Byte)
             )))
         );
@@ -751,7 +706,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Push(Push::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 Some(OperandWidth::Byte)
             ))),
         );
@@ -919,7 +877,13 @@ mod tests {
         let inst = decode(&data);
         assert_eq!(
             inst,
-            Ok(Instruction::Call(Call::new(Operand::Indexed((9, 4)), None)))
+            Ok(Instruction::Call(Call::new(
+                Operand::Indexed {
+                    register: 9,
+                    offset: 4
+                },
+                None
+            )))
         );
     }
 
@@ -930,7 +894,10 @@ mod tests {
         assert_eq!(
             inst,
             Ok(Instruction::Call(Call::new(
-                Operand::Indexed((9, -5)),
+                Operand::Indexed {
+                    register: 9,
+                    offset: -5
+                },
                 None
             )))
         );
@@ -989,6 +956,14 @@ mod tests {
         assert_eq!(inst, Ok(Instruction::Reti(Reti::new())));
     }
 
+    #[test]
+    fn msp430x_single_operand() {
+        // pushm.a #1, pc, popm.w #1, r15 and calla r4
+        assert_eq!(decode(&[0x00, 0x14]), Err(DecodeError::InvalidOpcode(0x14)));
+        assert_eq!(decode(&[0x0f, 0x17]), Err(DecodeError::InvalidOpcode(0x17)));
+        assert_eq!(decode(&[0x44, 0x13]), Err(DecodeError::InvalidOpcode(0x13)));
+    }
+
     #[test]
     fn push_sr() {
         let data = [0x02, 0x12];
@@ -1001,4 +976,99 @@ mod tests {
             )))
         );
     }
+
+    #[test]
+    fn clrz() {
+        // bic #2, sr
+        let inst = decode(&[0x22, 0xc3]).unwrap();
+        assert!(matches!(inst, Instruction::Clrz(_)));
+        assert_eq!(inst.to_string(), "clrz");
+    }
+
+    #[test]
+    fn clrn() {
+        // bic #4, sr
+        let inst = decode(&[0x22, 0xc2]).unwrap();
+        assert!(matches!(inst, Instruction::Clrn(_)));
+        assert_eq!(inst.to_string(), "clrn");
+    }
+
+    #[test]
+    fn normalize_immediate_constant() {
+        // mov #0, r9 and mov #0 (cg), r9
+        let immediate = decode(&[0x39, 0x40, 0x00, 0x00]).unwrap();
+        let constant = decode(&[0x09, 0x43]).unwrap();
+        assert_ne!(immediate, constant);
+        assert_eq!(immediate.normalize(), constant.normalize());
+    }
+
+    #[test]
+    fn normalize_emulated() {
+        // add #1, r9 and inc r9
+        let add = Instruction::Add(Add::new(
+            Operand::Immediate(1),
+            OperandWidth::Word,
+            Operand::RegisterDirect(9),
+        ));
+        let inc = decode(&[0x19, 0x53]).unwrap();
+        assert_eq!(add.normalize(), inc);
+        assert_eq!(inc.normalize(), inc);
+    }
+
+    #[test]
+    fn emulate_semantic() {
+        use crate::emulate::Emulate;
+        use crate::operand::Equality;
+
+        // mov #0, r15 with an immediate word is still clr
+        assert_eq!(
+            decode(&[0x3f, 0x40, 0x00, 0x00]).unwrap().to_string(),
+            "clr r15"
+        );
+
+        // bic #2, sr with an immediate word
+        let bic = Bic::new(
+            Operand::Immediate(2),
+            OperandWidth::Word,
+            Operand::RegisterDirect(2),
+        );
+        assert_eq!(bic.emulate(), None);
+        assert_eq!(
+            bic.emulate_by(Equality::Semantic)
+                .map(|inst| inst.mnemonic().to_string()),
+            Some("clrz".to_string())
+        );
+    }
+
+    #[test]
+    fn normalize_byte_immediate() {
+        // xor.b #0xff, r9 and inv.b r9
+        let xor = decode(&[0x79, 0xe0, 0xff, 0x00]).unwrap();
+        let inv = decode(&[0x79, 0xe3]).unwrap();
+        assert_eq!(xor.normalize(), inv);
+    }
+
+    #[test]
+    fn normalize_unchanged() {
+        // mov #0x4400, sp
+        let inst = decode(&[0x31, 0x40, 0x00, 0x44]).unwrap();
+        assert_eq!(inst.normalize(), inst);
+    }
+
+    #[test]
+    fn fuzz_display() {
+        // every instruction word with extension words that hit the edges of
+        // the signed and unsigned ranges must decode and format without
+        // panicking
+        for extension in [0x0000u16, 0x7fff, 0x8000, 0xffff] {
+            let [lo, hi] = extension.to_le_bytes();
+            for word in 0..=u16::MAX {
+                let [a, b] = word.to_le_bytes();
+                if let Ok(inst) = decode(&[a, b, lo, hi, lo, hi]) {
+                    let _ = inst.to_string();
+                    let _ = inst.normalize().to_string();
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,437 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::decode_at;
+use crate::decode_error::DecodeError;
+use crate::globals;
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+use crate::provenance::Provenance;
+use crate::terminator::{self, Terminator};
+use crate::Result;
+
+/// A single line of a listing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub address: u32,
+    /// The number of bytes the line covers
+    pub size: usize,
+    /// The instruction decoded at the address or the reason it could not be
+    /// decoded
+    pub instruction: Result<Instruction>,
+    /// The number of times the line was hit (executed or sampled)
+    pub hits: usize,
+    /// Where the instruction came from when it was transformed or assembled
+    pub provenance: Option<Provenance>,
+}
+
+/// Decodes data linearly from start to end. Data that fails to decode is
+/// skipped one word at a time so the sweep can resynchronize. When base is
+/// odd the first byte is reported as UnalignedAddress and skipped so the
+/// rest of the data is decoded at word aligned addresses
+pub fn sweep(data: &[u8], base: u32) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let address = base + offset as u32;
+        let instruction = decode_at(&data[offset..], address);
+        let size = match instruction {
+            Ok(inst) => inst.size(),
+            Err(DecodeError::UnalignedAddress(_)) => 1,
+            Err(_) => 2.min(data.len() - offset),
+        };
+
+        lines.push(Line {
+            address,
+            size,
+            instruction,
+            hits: 0,
+            provenance: None,
+        });
+        offset += size;
+    }
+
+    lines
+}
+
+/// The maximum number of instructions a speculative decode follows before
+/// giving up on joining the sweep
+const SPECULATION_LIMIT: usize = 16;
+
+/// Statistics about how reliable a linear sweep of some data is
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepQuality {
+    /// The lines of the sweep
+    pub lines: Vec<Line>,
+    /// The number of lines that failed to decode
+    pub errors: usize,
+    /// The addresses where the sweep decoded an instruction again after one
+    /// or more lines that failed to decode
+    pub resync_points: Vec<u32>,
+    /// Ranges where decoding from the word after the start of an
+    /// instruction also decodes without errors until it joins the sweep.
+    /// The sweep can't tell which of the two is right in these ranges.
+    /// Overlapping ranges are merged
+    pub ambiguous: Vec<Range<u32>>,
+}
+
+/// Decodes from offset until a line of the sweep starts where the decode is
+/// or the end of the data is reached and returns the offset it joined at.
+/// None is returned when an instruction fails to decode or it doesn't join
+/// within SPECULATION_LIMIT instructions
+fn speculate(data: &[u8], base: u32, lines: &[Line], mut offset: usize) -> Option<usize> {
+    for _ in 0..SPECULATION_LIMIT {
+        let address = base + offset as u32;
+        let inst = decode_at(&data[offset..], address).ok()?;
+        offset += inst.size();
+        let joined = lines
+            .binary_search_by_key(&(base + offset as u32), |line| line.address)
+            .is_ok();
+        if joined || offset >= data.len() {
+            return Some(offset);
+        }
+    }
+
+    None
+}
+
+/// Sweeps data and reports where the sweep resynchronized after errors and
+/// where another decoding is just as plausible. Every instruction with
+/// extension words is decoded again from its second word to look for an
+/// alternate decoding
+pub fn sweep_quality(data: &[u8], base: u32) -> SweepQuality {
+    let lines = sweep(data, base);
+    let mut errors = 0;
+    let mut resync_points = vec![];
+    let mut ambiguous: Vec<Range<u32>> = vec![];
+
+    for (i, line) in lines.iter().enumerate() {
+        let inst = match &line.instruction {
+            Ok(inst) => inst,
+            Err(_) => {
+                errors += 1;
+                continue;
+            }
+        };
+
+        if i > 0 && lines[i - 1].instruction.is_err() {
+            resync_points.push(line.address);
+        }
+
+        if inst.size() <= 2 {
+            continue;
+        }
+
+        let offset = (line.address - base) as usize + 2;
+        if let Some(end) = speculate(data, base, &lines, offset) {
+            let range = line.address..base + end as u32;
+            match ambiguous.last_mut() {
+                Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+                _ => ambiguous.push(range),
+            }
+        }
+    }
+
+    SweepQuality {
+        lines,
+        errors,
+        resync_points,
+        ambiguous,
+    }
+}
+
+/// Increments the hit count of the line that contains each address.
+/// Addresses that aren't covered by any line are ignored
+pub fn count_hits<I: IntoIterator<Item = u32>>(lines: &mut [Line], addresses: I) {
+    for address in addresses {
+        let index = lines.partition_point(|line| line.address <= address);
+        if index == 0 {
+            continue;
+        }
+
+        let line = &mut lines[index - 1];
+        if address < line.address + line.size as u32 {
+            line.hits += 1;
+        }
+    }
+}
+
+/// Increments the hit count of every line that overlaps each range
+pub fn count_range_hits(lines: &mut [Line], ranges: &[Range<u32>]) {
+    for range in ranges {
+        let start = lines.partition_point(|line| line.address + line.size as u32 <= range.start);
+        for line in lines[start..]
+            .iter_mut()
+            .take_while(|line| line.address < range.end)
+        {
+            line.hits += 1;
+        }
+    }
+}
+
+/// Classifies every line as a terminator. Lines that failed to decode
+/// split the listing so an instruction is never paired with one that
+/// doesn't directly follow it
+fn terminators(lines: &[Line]) -> Vec<Option<Terminator>> {
+    let mut terminators = Vec::with_capacity(lines.len());
+    for run in lines.chunk_by(|a, b| a.instruction.is_ok() == b.instruction.is_ok()) {
+        let listing: Vec<(u32, Instruction)> = run
+            .iter()
+            .filter_map(|line| Some((line.address, *line.instruction.as_ref().ok()?)))
+            .collect();
+        if listing.is_empty() {
+            terminators.extend(run.iter().map(|_| None));
+        } else {
+            terminators.extend((0..listing.len()).map(|i| terminator::terminator(&listing, i)));
+        }
+    }
+
+    terminators
+}
+
+/// Returns the notes rendered as a comment after each line. Terminators are
+/// labeled so intentional dead ends aren't mistaken for analysis failures,
+/// absolute operands are labeled with the inferred type of the global and
+/// transformed instructions are labeled with their provenance
+fn notes(lines: &[Line]) -> Vec<Vec<String>> {
+    let listing: Vec<(u32, Instruction)> = lines
+        .iter()
+        .filter_map(|line| Some((line.address, *line.instruction.as_ref().ok()?)))
+        .collect();
+    let globals = globals::infer(&listing);
+
+    lines
+        .iter()
+        .zip(terminators(lines))
+        .map(|(line, terminator)| {
+            let mut notes = vec![];
+            if let Some(terminator) = terminator {
+                notes.push(format!("terminator: {}", terminator));
+            }
+
+            let Ok(inst) = &line.instruction else {
+                return notes;
+            };
+            let mut addresses: Vec<u16> = [inst.source(), inst.destination()]
+                .into_iter()
+                .flatten()
+                .filter_map(|operand| match operand {
+                    Operand::Absolute(address) => Some(*address),
+                    _ => None,
+                })
+                .collect();
+            addresses.dedup();
+            for address in addresses {
+                if let Some(global) = globals.iter().find(|g| g.address == address) {
+                    notes.push(format!("{}: {}", Operand::Absolute(address), global.ty));
+                }
+            }
+
+            if let Some(provenance) = &line.provenance {
+                notes.push(provenance.to_string());
+            }
+
+            notes
+        })
+        .collect()
+}
+
+/// Returns the text of a line followed by its notes
+fn text(line: &Line, notes: &[String]) -> String {
+    match &line.instruction {
+        Ok(inst) if notes.is_empty() => inst.to_string(),
+        Ok(inst) => format!("{}  ; {}", inst, notes.join("; ")),
+        Err(e) => format!("; {}", e),
+    }
+}
+
+/// Renders a listing as text with the hit count for each line in the first
+/// column
+pub fn render(lines: &[Line]) -> String {
+    let mut output = String::new();
+    for (line, notes) in lines.iter().zip(notes(lines)) {
+        let _ = writeln!(
+            output,
+            "{:>8}  {:04x}:  {}",
+            line.hits,
+            line.address,
+            text(line, &notes)
+        );
+    }
+
+    output
+}
+
+/// Escapes text for use in HTML
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Renders a listing as an HTML table with columns for the hit count, the
+/// address and the instruction
+pub fn render_html(lines: &[Line]) -> String {
+    let mut output = String::from("<table>\n");
+    for (line, notes) in lines.iter().zip(notes(lines)) {
+        let text = text(line, &notes);
+        let _ = writeln!(
+            output,
+            "<tr><td>{}</td><td><code>{:04x}</code></td><td><code>{}</code></td></tr>",
+            line.hits,
+            line.address,
+            escape_html(&text)
+        );
+    }
+
+    output.push_str("</table>\n");
+    output
+}
+
+/// Displays a listing as a table in an evcxr notebook
+#[cfg(feature = "evcxr")]
+pub fn evcxr_display(lines: &[Line]) {
+    println!(
+        "EVCXR_BEGIN_CONTENT text/html\n{}EVCXR_END_CONTENT",
+        render_html(lines)
+    );
+}
+
+#[cfg(feature = "evcxr")]
+impl Line {
+    /// Called by evcxr to display a line as a single row table
+    pub fn evcxr_display(&self) {
+        evcxr_display(std::slice::from_ref(self));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality() {
+        // mov #0x4400, sp; <invalid>; <invalid>; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x80, 0x13, 0x80, 0x13, 0x30, 0x41];
+        let quality = sweep_quality(&data, 0x4400);
+        assert_eq!(quality.lines.len(), 4);
+        assert_eq!(quality.errors, 2);
+        assert_eq!(quality.resync_points, vec![0x4408]);
+        // decoding from 0x4402 gives the valid br r4 which joins the
+        // sweep at 0x4404
+        assert_eq!(quality.ambiguous, vec![0x4400..0x4404]);
+
+        // mov #0x1380, r15 is only valid from the start
+        let quality = sweep_quality(&[0x3f, 0x40, 0x80, 0x13], 0x4400);
+        assert_eq!(quality.errors, 0);
+        assert_eq!(quality.resync_points, vec![]);
+        assert_eq!(quality.ambiguous, vec![]);
+    }
+
+    #[test]
+    fn sweep_resync() {
+        // mov #0x4400, sp; <invalid>; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x80, 0x13, 0x30, 0x41];
+        let lines = sweep(&data, 0x4400);
+        assert_eq!(
+            lines.iter().map(|l| l.address).collect::<Vec<_>>(),
+            vec![0x4400, 0x4404, 0x4406]
+        );
+        assert_eq!(lines[1].instruction, Err(DecodeError::InvalidOpcode(7)));
+    }
+
+    #[test]
+    fn sweep_unaligned() {
+        // <padding>; ret
+        let lines = sweep(&[0xff, 0x30, 0x41], 0x4401);
+        assert_eq!(
+            lines.iter().map(|l| l.address).collect::<Vec<_>>(),
+            vec![0x4401, 0x4402]
+        );
+        assert_eq!(
+            lines[0].instruction,
+            Err(DecodeError::UnalignedAddress(0x4401))
+        );
+        assert_eq!(lines[0].size, 1);
+    }
+
+    #[test]
+    fn hits() {
+        // mov #0x4400, sp; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let mut lines = sweep(&data, 0x4400);
+        count_hits(&mut lines, [0x4400, 0x4402, 0x4404, 0x4404, 0x4406, 0x43fe]);
+        assert_eq!(lines.iter().map(|l| l.hits).collect::<Vec<_>>(), vec![2, 2]);
+    }
+
+    #[test]
+    fn render_listing() {
+        // mov #0x4400, sp; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let mut lines = sweep(&data, 0x4400);
+        count_hits(&mut lines, [0x4404]);
+        assert_eq!(
+            render(&lines),
+            "       0  4400:  mov #0x4400, sp\n       1  4404:  ret\n"
+        );
+    }
+
+    #[test]
+    fn render_terminators() {
+        // jmp $; bis #0x10, sr; jmp $-4; <invalid>; jmp $
+        let data = [
+            0xff, 0x3f, 0x32, 0xd0, 0x10, 0x00, 0xfd, 0x3f, 0x80, 0x13, 0xff, 0x3f,
+        ];
+        let lines = sweep(&data, 0x4400);
+        assert_eq!(
+            render(&lines),
+            "       0  4400:  jmp #-0x1  ; terminator: self loop\n\
+             \x20      0  4402:  bis #0x10, sr  ; terminator: low power loop\n\
+             \x20      0  4406:  jmp #-0x3\n\
+             \x20      0  4408:  ; invalid opcode 7\n\
+             \x20      0  440a:  jmp #-0x1  ; terminator: self loop\n"
+        );
+        assert!(render_html(&lines).contains("<code>jmp #-0x1  ; terminator: self loop</code>"));
+    }
+
+    #[test]
+    fn range_hits() {
+        // mov #0x4400, sp; ret; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41, 0x30, 0x41];
+        let mut lines = sweep(&data, 0x4400);
+        count_range_hits(&mut lines, &[0x4402..0x4406, 0x4406..0x4407]);
+        assert_eq!(
+            lines.iter().map(|l| l.hits).collect::<Vec<_>>(),
+            vec![1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn render_listing_html() {
+        // mov &0x0120, r9
+        let data = [0x19, 0x42, 0x20, 0x01];
+        let lines = sweep(&data, 0x4400);
+        assert_eq!(
+            render_html(&lines),
+            "<table>\n<tr><td>0</td><td><code>4400</code></td><td><code>mov &amp;0x120, r9  ; &amp;0x120: word</code></td></tr>\n</table>\n"
+        );
+    }
+
+    #[test]
+    fn render_globals() {
+        // mov &0x0200, r15; mov.b @r15, r14; mov.b r14, &0x0202; rla &0x0202
+        let data = [
+            0x1f, 0x42, 0x00, 0x02, 0x6e, 0x4f, 0xc2, 0x4e, 0x02, 0x02, 0x92, 0x52, 0x02, 0x02,
+            0x02, 0x02,
+        ];
+        let lines = sweep(&data, 0x4400);
+        assert_eq!(
+            render(&lines),
+            "       0  4400:  mov &0x200, r15  ; &0x200: data pointer\n\
+             \x20      0  4404:  mov.b @r15, r14\n\
+             \x20      0  4406:  mov.b r14, &0x202  ; &0x202: word\n\
+             \x20      0  440a:  rla &0x202  ; &0x202: word\n"
+        );
+    }
+}
@@ -1,18 +1,33 @@
 use std::fmt;
 
+use crate::encode::EncodeError;
 use crate::DecodeError;
 use crate::Result;
 
+/// How instructions and operands are compared
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Equality {
+    /// Instructions are compared as they are encoded
+    #[default]
+    Structural,
+    /// Instructions are compared by their normalized form so the same
+    /// operation encoded differently (eg. `add #1, r15` with an immediate
+    /// word, which isn't shown as `inc`) compares equal
+    Semantic,
+}
+
 /// Represents a source or destination operand. This represents all
 /// addressing mode represented by AS/AD with their corresponding register
 /// pairs. In msp430 the valid destination operands are a subset of the
 /// source operands. Due to cases in the implementation where it is necessary
 /// to sometimes use a source as a destination (br emulated instruction) or
 /// compare a source and a destination rather than create separate types for
-/// source and destination they share one. The enforcement that a valid
-/// destination is specified, as all operands are valid for source, is left
-/// to the implementation of the decoding logic or assembling logic.
+/// source and destination they share one. Whether an operand can be used as
+/// a destination is checked with valid_as_destination; the decoder only
+/// produces valid destinations and the checked constructors of two operand
+/// instructions (try_new) reject invalid ones.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Operand {
     /// The operand is stored in the register
     RegisterDirect(u8),
@@ -20,7 +35,7 @@ pub enum Operand {
     /// register.
     ///
     /// This requires an additional word
-    Indexed((u8, i16)),
+    Indexed { register: u8, offset: i16 },
     /// The operand is stored at the address that is in the register
     ///
     /// This requires an additional word
@@ -47,10 +62,139 @@ pub enum Operand {
 }
 
 impl Operand {
+    /// Returns an indexed operand
+    pub fn indexed(register: u8, offset: i16) -> Operand {
+        Self::Indexed { register, offset }
+    }
+
+    /// Returns an indexed operand from the (register, offset) tuple Indexed
+    /// held before it had named fields
+    #[deprecated(since = "0.3.0", note = "use Operand::Indexed { register, offset }")]
+    pub fn from_indexed((register, offset): (u8, i16)) -> Operand {
+        Self::Indexed { register, offset }
+    }
+
+    /// Returns the register of register direct, indexed, register indirect
+    /// and register indirect autoincrement operands
+    pub fn register(&self) -> Option<u8> {
+        match self {
+            Self::RegisterDirect(r)
+            | Self::Indexed { register: r, .. }
+            | Self::RegisterIndirect(r)
+            | Self::RegisterIndirectAutoIncrement(r) => Some(*r),
+            _ => None,
+        }
+    }
+
+    /// Returns the offset of indexed and symbolic operands
+    pub fn offset(&self) -> Option<i16> {
+        match self {
+            Self::Indexed { offset, .. } | Self::Symbolic(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// Returns whether two operands refer to the same value when used with
+    /// the operand width even if they are encoded differently. Immediates
+    /// the constant generator can produce equal the constant (only the low
+    /// byte is compared for byte operations) and an index from pc equals
+    /// the symbolic operand with the same offset. PartialEq compares the
+    /// encoding
+    pub fn semantically_eq(&self, other: &Operand, operand_width: Option<OperandWidth>) -> bool {
+        let canonical = |operand: &Operand| match constant(*operand, operand_width) {
+            Operand::Indexed {
+                register: 0,
+                offset,
+            } => Operand::Symbolic(offset),
+            operand => operand,
+        };
+
+        canonical(self) == canonical(other)
+    }
+
+    /// Compares operands with equality. Structural equality is PartialEq
+    /// and semantic equality is semantically_eq
+    pub fn eq_by(
+        &self,
+        other: &Operand,
+        equality: Equality,
+        operand_width: Option<OperandWidth>,
+    ) -> bool {
+        match equality {
+            Equality::Structural => self == other,
+            Equality::Semantic => self.semantically_eq(other, operand_width),
+        }
+    }
+
+    /// Returns whether the operand can be encoded as a destination (AD).
+    /// Only register direct, indexed (from any register except sr),
+    /// symbolic and absolute operands are valid destinations
+    pub fn valid_as_destination(&self) -> bool {
+        self.encode(false).is_ok()
+    }
+
+    /// Returns the value the operand has when used with the operand width.
+    /// Byte operations only use the low byte of an immediate so the high
+    /// byte is cleared, every other operand is returned unchanged
+    pub fn effective(&self, operand_width: Option<OperandWidth>) -> Operand {
+        match (self, operand_width) {
+            (Self::Immediate(value), Some(OperandWidth::Byte)) => Self::Immediate(value & 0xff),
+            _ => *self,
+        }
+    }
+
+    /// Encodes the operand as a source (AS) or destination (AD) returning
+    /// the addressing mode bits, the additional word if the operand has one
+    /// and the register. Operands that have no encoding, such as a constant
+    /// the constant generator can't produce or an immediate destination, are
+    /// rejected
+    pub fn encode(
+        &self,
+        is_source: bool,
+    ) -> std::result::Result<(u16, Option<u16>, u8), EncodeError> {
+        let encoded = match (*self, is_source) {
+            (Self::RegisterDirect(r), _) if r < 16 => (0, None, r),
+            // indexed, indirect and autoincrement from sr and cg are
+            // constants when used as a source
+            (
+                Self::Indexed {
+                    register: r,
+                    offset: i,
+                },
+                true,
+            ) if matches!(r, 0 | 1 | 4..=15) => (1, Some(i as u16), r),
+            (
+                Self::Indexed {
+                    register: r,
+                    offset: i,
+                },
+                false,
+            ) if r < 16 && r != 2 => (1, Some(i as u16), r),
+            (Self::RegisterIndirect(r), true) if matches!(r, 0 | 1 | 4..=15) => (2, None, r),
+            // @pc+ is an immediate
+            (Self::RegisterIndirectAutoIncrement(r), true) if matches!(r, 1 | 4..=15) => {
+                (3, None, r)
+            }
+            (Self::Symbolic(i), _) => (1, Some(i as u16), 0),
+            (Self::Immediate(i), true) => (3, Some(i), 0),
+            (Self::Absolute(a), _) => (1, Some(a), 2),
+            (Self::Constant(0), true) => (0, None, 3),
+            (Self::Constant(1), true) => (1, None, 3),
+            (Self::Constant(2), true) => (2, None, 3),
+            (Self::Constant(4), true) => (2, None, 2),
+            (Self::Constant(8), true) => (3, None, 2),
+            (Self::Constant(-1), true) => (3, None, 3),
+            (_, true) => return Err(EncodeError::InvalidSource(*self)),
+            (_, false) => return Err(EncodeError::InvalidDestination(*self)),
+        };
+
+        Ok(encoded)
+    }
+
     pub fn size(&self) -> usize {
         match self {
             Self::RegisterDirect(_) => 0,
-            Self::Indexed(_) => 2,
+            Self::Indexed { .. } => 2,
             Self::RegisterIndirect(_) => 0,
             Self::RegisterIndirectAutoIncrement(_) => 0,
             Self::Symbolic(_) => 2,
@@ -71,30 +215,19 @@ impl fmt::Display for Operand {
                 3 => write!(f, "cg"),
                 _ => write!(f, "r{}", r),
             },
-            Self::Indexed((r, i)) => match r {
-                1 => {
-                    if *i >= 0 {
-                        write!(f, "{:#x}(sp)", i)
-                    } else {
-                        write!(f, "-{:#x}(sp)", -i)
-                    }
-                }
-                3 => {
-                    if *i >= 0 {
-                        write!(f, "{:#x}(cg)", i)
-                    } else {
-                        write!(f, "-{:#x}(cg)", -i)
-                    }
-                }
-                4..=15 => {
-                    if *i >= 0 {
-                        write!(f, "{:#x}(r{})", i, r)
-                    } else {
-                        write!(f, "-{:#x}(r{})", -i, r)
-                    }
+            Self::Indexed {
+                register: r,
+                offset: i,
+            } => {
+                // decoding never produces pc or sr here (those are symbolic
+                // and absolute) but hand built operands may use any register
+                let register = Operand::RegisterDirect(*r);
+                if *i >= 0 {
+                    write!(f, "{:#x}({})", i, register)
+                } else {
+                    write!(f, "-{:#x}({})", i.unsigned_abs(), register)
                 }
-                _ => unreachable!(),
-            },
+            }
             Self::RegisterIndirect(r) => {
                 if *r == 1 {
                     write!(f, "@sp")
@@ -113,14 +246,14 @@ impl fmt::Display for Operand {
                 if *i >= 0 {
                     write!(f, "#{:#x}(pc)", i)
                 } else {
-                    write!(f, "#-{:#x}(pc)", -i)
+                    write!(f, "#-{:#x}(pc)", i.unsigned_abs())
                 }
             }
             Self::Immediate(i) => {
                 if *i & 0x8000 == 0 {
                     write!(f, "#{:#x}", i)
                 } else {
-                    write!(f, "#-{:#x}", *i as i16)
+                    write!(f, "#-{:#x}", (*i as i16).unsigned_abs())
                 }
             }
             Self::Absolute(a) => write!(f, "&{:#x}", a),
@@ -128,7 +261,7 @@ impl fmt::Display for Operand {
                 if *i >= 0 {
                     write!(f, "#{:#x}", i)
                 } else {
-                    write!(f, "#-{:#x}", -i)
+                    write!(f, "#-{:#x}", i.unsigned_abs())
                 }
             }
         }
@@ -140,115 +273,140 @@ impl fmt::Display for Operand {
 ///
 /// The operand itself is always stored as a word for alignment reasons
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum OperandWidth {
     Byte,
     Word,
 }
 
+/// Converts the BW bit of an instruction word. Only the lowest bit is
+/// considered
 impl From<u8> for OperandWidth {
     fn from(val: u8) -> Self {
-        match val {
+        match val & 1 {
             0 => OperandWidth::Word,
-            1 => OperandWidth::Byte,
-            _ => unreachable!(),
+            _ => OperandWidth::Byte,
         }
     }
 }
 
+/// Returns the constant generator equivalent of an immediate operand if one
+/// exists, otherwise the operand is returned unchanged
+pub(crate) fn constant(operand: Operand, operand_width: Option<OperandWidth>) -> Operand {
+    let value = match (operand, operand_width) {
+        (Operand::Immediate(value), Some(OperandWidth::Byte)) if value & 0xff == 0xff => 0xffff,
+        (Operand::Immediate(value), Some(OperandWidth::Byte)) => value & 0xff,
+        (Operand::Immediate(value), _) => value,
+        _ => return operand,
+    };
+
+    match value {
+        0 => Operand::Constant(0),
+        1 => Operand::Constant(1),
+        2 => Operand::Constant(2),
+        4 => Operand::Constant(4),
+        8 => Operand::Constant(8),
+        0xffff => Operand::Constant(-1),
+        _ => operand,
+    }
+}
+
+fn extension_word(data: &[u8]) -> Option<u16> {
+    match data {
+        [lo, hi, ..] => Some(u16::from_le_bytes([*lo, *hi])),
+        _ => None,
+    }
+}
+
+/// Parses a source operand for the register and AS (source addressing
+/// mode), reading the additional word from data if the operand has one
+pub(crate) fn source(register: u8, addressing: u8, data: &[u8]) -> Result<Operand> {
+    let invalid = DecodeError::InvalidSource {
+        addressing: addressing as u16,
+        register,
+    };
+    let word = || extension_word(data).ok_or(DecodeError::MissingSource);
+
+    match (addressing, register) {
+        (_, 16..) => Err(invalid),
+        (0, 3) => Ok(Operand::Constant(0)),
+        (0, _) => Ok(Operand::RegisterDirect(register)),
+        (1, 0) => Ok(Operand::Symbolic(word()? as i16)),
+        (1, 2) => Ok(Operand::Absolute(word()?)),
+        (1, 3) => Ok(Operand::Constant(1)),
+        (1, _) => Ok(Operand::Indexed {
+            register,
+            offset: word()? as i16,
+        }),
+        (2, 2) => Ok(Operand::Constant(4)),
+        (2, 3) => Ok(Operand::Constant(2)),
+        (2, _) => Ok(Operand::RegisterIndirect(register)),
+        (3, 0) => Ok(Operand::Immediate(word()?)),
+        (3, 2) => Ok(Operand::Constant(8)),
+        (3, 3) => Ok(Operand::Constant(-1)),
+        (3, _) => Ok(Operand::RegisterIndirectAutoIncrement(register)),
+        _ => Err(invalid),
+    }
+}
+
+/// Parses a destination operand for the register and AD (destination
+/// addressing mode), reading the additional word from data if the operand
+/// has one
+pub(crate) fn destination(register: u8, addressing: u8, data: &[u8]) -> Result<Operand> {
+    let invalid = DecodeError::InvalidDestination {
+        addressing: addressing as u16,
+        register,
+    };
+    let word = || extension_word(data).ok_or(DecodeError::MissingDestination);
+
+    match (addressing, register) {
+        (_, 16..) => Err(invalid),
+        (0, _) => Ok(Operand::RegisterDirect(register)),
+        (1, 0) => Ok(Operand::Symbolic(word()? as i16)),
+        (1, 2) => Ok(Operand::Absolute(word()?)),
+        (1, _) => Ok(Operand::Indexed {
+            register,
+            offset: word()? as i16,
+        }),
+        _ => Err(invalid),
+    }
+}
+
 /// Parses a source operand from an input stream. This is only used for AS
 /// modes where the source operand is stored as an additional word of data.
 /// Otherwise the source operand can be fully decoded from just reading the
 /// the instruction word
+#[deprecated(
+    since = "0.3.0",
+    note = "use decode::parts::source which takes the addressing mode as a u8 and a Cursor"
+)]
 pub fn parse_source(register: u8, source: u16, data: &[u8]) -> Result<(Operand, &[u8])> {
-    match source {
-        0 => match register {
-            3 => Ok((Operand::Constant(0), data)),
-            0..=2 | 4..=15 => Ok((Operand::RegisterDirect(register), data)),
-            _ => Err(DecodeError::InvalidSource((source, register))),
-        },
-        1 => match register {
-            0 => {
-                if data.len() < 2 {
-                    Err(DecodeError::MissingSource)
-                } else {
-                    let (bytes, remaining_data) = data.split_at(std::mem::size_of::<u16>());
-                    let second_word = i16::from_le_bytes(bytes.try_into().unwrap());
-                    Ok((Operand::Symbolic(second_word), remaining_data))
-                }
-            }
-            2 => {
-                if data.len() < 2 {
-                    Err(DecodeError::MissingSource)
-                } else {
-                    let (bytes, remaining_data) = data.split_at(std::mem::size_of::<u16>());
-                    let second_word = u16::from_le_bytes(bytes.try_into().unwrap());
-                    Ok((Operand::Absolute(second_word), remaining_data))
-                }
-            }
-            3 => Ok((Operand::Constant(1), data)),
-            1 | 4..=15 => {
-                if data.len() < 2 {
-                    Err(DecodeError::MissingSource)
-                } else {
-                    let (bytes, remaining_data) = data.split_at(std::mem::size_of::<u16>());
-                    let second_word = i16::from_le_bytes(bytes.try_into().unwrap());
-                    Ok((Operand::Indexed((register, second_word)), remaining_data))
-                }
-            }
-            _ => Err(DecodeError::InvalidSource((source, register))),
-        },
-        2 => match register {
-            2 => Ok((Operand::Constant(4), data)),
-            3 => Ok((Operand::Constant(2), data)),
-            0..=1 | 4..=15 => Ok((Operand::RegisterIndirect(register), data)),
-            _ => Err(DecodeError::InvalidSource((source, register))),
-        },
-        3 => match register {
-            0 => {
-                if data.len() < 2 {
-                    Err(DecodeError::MissingSource)
-                } else {
-                    let (bytes, remaining_data) = data.split_at(std::mem::size_of::<u16>());
-                    let second_word = u16::from_le_bytes(bytes.try_into().unwrap());
-                    Ok((Operand::Immediate(second_word), remaining_data))
-                }
-            }
-            2 => Ok((Operand::Constant(8), data)),
-            3 => Ok((Operand::Constant(-1), data)),
-            1 | 4..=15 => Ok((Operand::RegisterIndirectAutoIncrement(register), data)),
-            _ => Err(DecodeError::InvalidSource((source, register))),
-        },
-        _ => Err(DecodeError::InvalidSource((source, register))),
-    }
+    let addressing = u8::try_from(source).map_err(|_| DecodeError::InvalidSource {
+        addressing: source,
+        register,
+    })?;
+    let operand = self::source(register, addressing, data)?;
+    Ok((operand, &data[operand.size()..]))
 }
 
 /// Parses a destination operand from an input stream. This is only used for
 /// AD modes where the destination operand is stored as an additional word
 /// of data. Otherwise the destination operand can be fully decoded from just
 /// reading the the instruction word
+#[deprecated(
+    since = "0.3.0",
+    note = "use decode::parts::destination which takes the addressing mode as a u8 and a Cursor"
+)]
 pub fn parse_destination(register: u8, source: u16, data: &[u8]) -> Result<Operand> {
-    match source {
-        0 => Ok(Operand::RegisterDirect(register)),
-        1 => {
-            if data.len() < 2 {
-                Err(DecodeError::MissingDestination)
-            } else {
-                let (bytes, _) = data[0..2].split_at(std::mem::size_of::<u16>());
-                let raw_operand = u16::from_le_bytes(bytes.try_into().unwrap());
-                let index = raw_operand;
-                match register {
-                    0 => Ok(Operand::Symbolic(index as i16)),
-                    2 => Ok(Operand::Absolute(raw_operand)),
-                    1 | 3..=15 => Ok(Operand::Indexed((register, index as i16))),
-                    _ => Err(DecodeError::InvalidDestination((source, register))),
-                }
-            }
-        }
-        _ => Err(DecodeError::InvalidDestination((source, register))),
-    }
+    let addressing = u8::try_from(source).map_err(|_| DecodeError::InvalidDestination {
+        addressing: source,
+        register,
+    })?;
+    destination(register, addressing, data)
 }
 
 #[cfg(test)]
+#[allow(deprecated)]
 mod tests {
     use super::*;
 
@@ -291,7 +449,13 @@ mod tests {
     fn source_pc_invalid_source() {
         let data = [0xfe, 0xff];
         let source = parse_source(0, 5, &data);
-        assert_eq!(source, Err(DecodeError::InvalidSource((5, 0))));
+        assert_eq!(
+            source,
+            Err(DecodeError::InvalidSource {
+                addressing: 5,
+                register: 0
+            })
+        );
     }
 
     #[test]
@@ -326,7 +490,13 @@ mod tests {
     fn source_sr_invalid_source() {
         let data = [];
         let source = parse_source(2, 4, &data);
-        assert_eq!(source, Err(DecodeError::InvalidSource((4, 2))));
+        assert_eq!(
+            source,
+            Err(DecodeError::InvalidSource {
+                addressing: 4,
+                register: 2
+            })
+        );
     }
 
     #[test]
@@ -361,7 +531,13 @@ mod tests {
     fn source_cg_invalid_source() {
         let data = [];
         let source = parse_source(3, 4, &data);
-        assert_eq!(source, Err(DecodeError::InvalidSource((4, 3))));
+        assert_eq!(
+            source,
+            Err(DecodeError::InvalidSource {
+                addressing: 4,
+                register: 3
+            })
+        );
     }
 
     #[test]
@@ -375,14 +551,32 @@ mod tests {
     fn source_gp_register_indexed() {
         let data = [0x2, 0x0];
         let source = parse_source(9, 1, &data);
-        assert_eq!(source, Ok((Operand::Indexed((9, 2)), &data[2..])));
+        assert_eq!(
+            source,
+            Ok((
+                Operand::Indexed {
+                    register: 9,
+                    offset: 2
+                },
+                &data[2..]
+            ))
+        );
     }
 
     #[test]
     fn source_gp_register_indexed_negative() {
         let data = [0xfd, 0xff];
         let source = parse_source(9, 1, &data);
-        assert_eq!(source, Ok((Operand::Indexed((9, -3)), &data[2..])));
+        assert_eq!(
+            source,
+            Ok((
+                Operand::Indexed {
+                    register: 9,
+                    offset: -3
+                },
+                &data[2..]
+            ))
+        );
     }
 
     #[test]
@@ -406,7 +600,13 @@ mod tests {
     fn source_gp_invalid_source() {
         let data = [];
         let source = parse_source(9, 4, &data);
-        assert_eq!(source, Err(DecodeError::InvalidSource((4, 9))));
+        assert_eq!(
+            source,
+            Err(DecodeError::InvalidSource {
+                addressing: 4,
+                register: 9
+            })
+        );
     }
 
     #[test]
@@ -420,14 +620,26 @@ mod tests {
     fn destination_register_indexed() {
         let data = [0x2, 0x0];
         let destination = parse_destination(9, 1, &data);
-        assert_eq!(destination, Ok(Operand::Indexed((9, 2))));
+        assert_eq!(
+            destination,
+            Ok(Operand::Indexed {
+                register: 9,
+                offset: 2
+            })
+        );
     }
 
     #[test]
     fn destination_register_indexed_negative() {
         let data = [0xfe, 0xff];
         let destination = parse_destination(9, 1, &data);
-        assert_eq!(destination, Ok(Operand::Indexed((9, -2))));
+        assert_eq!(
+            destination,
+            Ok(Operand::Indexed {
+                register: 9,
+                offset: -2
+            })
+        );
     }
 
     #[test]
@@ -455,6 +667,185 @@ mod tests {
     fn destination_invalid_source() {
         let data = [];
         let destination = parse_destination(9, 3, &data);
-        assert_eq!(destination, Err(DecodeError::InvalidDestination((3, 9))));
+        assert_eq!(
+            destination,
+            Err(DecodeError::InvalidDestination {
+                addressing: 3,
+                register: 9
+            })
+        );
+    }
+
+    #[test]
+    fn display_total() {
+        assert_eq!(
+            Operand::Indexed {
+                register: 0,
+                offset: 4
+            }
+            .to_string(),
+            "0x4(pc)"
+        );
+        assert_eq!(
+            Operand::Indexed {
+                register: 2,
+                offset: -4
+            }
+            .to_string(),
+            "-0x4(sr)"
+        );
+        assert_eq!(
+            Operand::Indexed {
+                register: 9,
+                offset: i16::MIN
+            }
+            .to_string(),
+            "-0x8000(r9)"
+        );
+        assert_eq!(Operand::Symbolic(i16::MIN).to_string(), "#-0x8000(pc)");
+        assert_eq!(Operand::Constant(i8::MIN).to_string(), "#-0x80");
+        assert_eq!(Operand::Immediate(0xfffa).to_string(), "#-0x6");
+        assert_eq!(Operand::Immediate(0x8000).to_string(), "#-0x8000");
+    }
+
+    #[test]
+    fn semantically_eq() {
+        let word = Some(OperandWidth::Word);
+        let byte = Some(OperandWidth::Byte);
+        assert_ne!(Operand::Constant(4), Operand::Immediate(4));
+        assert!(Operand::Constant(4).semantically_eq(&Operand::Immediate(4), word));
+        assert!(Operand::Immediate(0xffff).semantically_eq(&Operand::Constant(-1), word));
+        assert!(!Operand::Immediate(0xff).semantically_eq(&Operand::Constant(-1), word));
+        assert!(Operand::Immediate(0xff).semantically_eq(&Operand::Constant(-1), byte));
+        assert!(Operand::Immediate(0x108).semantically_eq(&Operand::Constant(8), byte));
+        assert!(Operand::Indexed {
+            register: 0,
+            offset: 6
+        }
+        .semantically_eq(&Operand::Symbolic(6), word));
+        assert!(!Operand::Indexed {
+            register: 4,
+            offset: 6
+        }
+        .semantically_eq(&Operand::Symbolic(6), word));
+    }
+
+    #[test]
+    fn indexed_fields() {
+        let operand = Operand::indexed(14, -4);
+        assert_eq!(
+            operand,
+            Operand::Indexed {
+                register: 14,
+                offset: -4
+            }
+        );
+        assert_eq!(operand.register(), Some(14));
+        assert_eq!(operand.offset(), Some(-4));
+        assert_eq!(Operand::Symbolic(6).register(), None);
+        assert_eq!(Operand::Symbolic(6).offset(), Some(6));
+        #[allow(deprecated)]
+        let old = Operand::from_indexed((14, -4));
+        assert_eq!(old, operand);
+        assert_eq!(Operand::RegisterDirect(4).offset(), None);
+    }
+
+    #[test]
+    fn effective() {
+        let byte = Some(OperandWidth::Byte);
+        assert_eq!(
+            Operand::Immediate(0x1234).effective(byte),
+            Operand::Immediate(0x34)
+        );
+        assert_eq!(
+            Operand::Immediate(0x1234).effective(Some(OperandWidth::Word)),
+            Operand::Immediate(0x1234)
+        );
+        assert_eq!(
+            Operand::Absolute(0x1234).effective(byte),
+            Operand::Absolute(0x1234)
+        );
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(Operand::RegisterDirect(15).encode(false), Ok((0, None, 15)));
+        assert_eq!(
+            Operand::Indexed {
+                register: 4,
+                offset: -2
+            }
+            .encode(true),
+            Ok((1, Some(0xfffe), 4))
+        );
+        assert_eq!(
+            Operand::Absolute(0x200).encode(false),
+            Ok((1, Some(0x200), 2))
+        );
+        assert_eq!(
+            Operand::Immediate(0x4400).encode(true),
+            Ok((3, Some(0x4400), 0))
+        );
+        assert_eq!(Operand::Constant(8).encode(true), Ok((3, None, 2)));
+        assert_eq!(
+            Operand::Immediate(0x4400).encode(false),
+            Err(EncodeError::InvalidDestination(Operand::Immediate(0x4400)))
+        );
+        assert_eq!(
+            Operand::RegisterIndirect(4).encode(false),
+            Err(EncodeError::InvalidDestination(Operand::RegisterIndirect(
+                4
+            )))
+        );
+        assert_eq!(
+            Operand::RegisterIndirectAutoIncrement(0).encode(true),
+            Err(EncodeError::InvalidSource(
+                Operand::RegisterIndirectAutoIncrement(0)
+            ))
+        );
+    }
+
+    #[test]
+    fn valid_destinations() {
+        // every destination the decoder produces is valid
+        for addressing in 0..4 {
+            for register in 0..16 {
+                if let Ok(operand) = destination(register, addressing, &[0x00, 0x02]) {
+                    assert!(operand.valid_as_destination(), "{:?}", operand);
+                }
+            }
+        }
+
+        assert!(Operand::indexed(3, 4).valid_as_destination());
+        assert!(!Operand::indexed(2, 4).valid_as_destination());
+        assert!(!Operand::Immediate(0x10).valid_as_destination());
+        assert!(!Operand::RegisterIndirect(4).valid_as_destination());
+    }
+
+    #[test]
+    fn checked_construction() {
+        use crate::two_operand::Mov;
+
+        assert_eq!(
+            Mov::try_new(
+                Operand::RegisterDirect(4),
+                OperandWidth::Word,
+                Operand::Constant(0)
+            ),
+            Err(DecodeError::IllegalDestination(Operand::Constant(0)))
+        );
+        assert!(Mov::try_new(
+            Operand::Constant(0),
+            OperandWidth::Word,
+            Operand::Absolute(0x200)
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn width_from_bit() {
+        assert_eq!(OperandWidth::from(0), OperandWidth::Word);
+        assert_eq!(OperandWidth::from(1), OperandWidth::Byte);
+        assert_eq!(OperandWidth::from(2), OperandWidth::Word);
     }
 }
@@ -0,0 +1,189 @@
+use std::fmt::Write;
+
+use crate::format::operands;
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+
+/// A named field in a struct layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    /// The offset of the field from the start of the struct (in bytes)
+    pub offset: u16,
+    /// The size of the field (in bytes)
+    pub size: u16,
+}
+
+/// A C-like struct layout that can be overlaid on memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+impl Layout {
+    pub fn new(name: &str) -> Layout {
+        Layout {
+            name: name.to_string(),
+            fields: vec![],
+        }
+    }
+
+    /// Appends a field after the last field in the layout
+    pub fn field(mut self, name: &str, size: u16) -> Layout {
+        let offset = self.size();
+        self.fields.push(Field {
+            name: name.to_string(),
+            offset,
+            size,
+        });
+        self
+    }
+
+    /// Returns the size of the layout (in bytes)
+    pub fn size(&self) -> u16 {
+        self.fields
+            .iter()
+            .map(|f| f.offset + f.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the name of the field containing offset, followed by +n when
+    /// offset is not the start of the field
+    fn field_name(&self, offset: u16) -> Option<String> {
+        let field = self
+            .fields
+            .iter()
+            .find(|f| offset >= f.offset && offset < f.offset + f.size)?;
+
+        match offset - field.offset {
+            0 => Some(field.name.clone()),
+            n => Some(format!("{}+{}", field.name, n)),
+        }
+    }
+}
+
+/// Layouts placed at absolute addresses (eg. peripheral register blocks or
+/// structs in RAM) and registers known to point at a layout. Operands that
+/// fall inside a layout are rendered by field name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Overlays {
+    regions: Vec<(u16, Layout)>,
+    registers: Vec<(u8, Layout)>,
+}
+
+impl Overlays {
+    pub fn new() -> Overlays {
+        Overlays::default()
+    }
+
+    /// Places a layout at an absolute address
+    pub fn region(mut self, address: u16, layout: Layout) -> Overlays {
+        self.regions.push((address, layout));
+        self
+    }
+
+    /// Marks a register as pointing at a layout
+    pub fn register(mut self, register: u8, layout: Layout) -> Overlays {
+        self.registers.retain(|(r, _)| *r != register);
+        self.registers.push((register, layout));
+        self
+    }
+
+    /// Returns the field name for an operand if it accesses a layout
+    pub fn operand(&self, operand: &Operand) -> Option<String> {
+        match operand {
+            Operand::Absolute(address) => self.regions.iter().find_map(|(base, layout)| {
+                let offset = address.checked_sub(*base)?;
+                let field = layout.field_name(offset)?;
+                Some(format!("&{}.{}", layout.name, field))
+            }),
+            Operand::Indexed {
+                register,
+                offset: index,
+            } if *index >= 0 => {
+                let (_, layout) = self.registers.iter().find(|(r, _)| r == register)?;
+                let field = layout.field_name(*index as u16)?;
+                Some(format!("{}->{}", Operand::RegisterDirect(*register), field))
+            }
+            Operand::RegisterIndirect(register)
+            | Operand::RegisterIndirectAutoIncrement(register) => {
+                let (_, layout) = self.registers.iter().find(|(r, _)| r == register)?;
+                let field = layout.field_name(0)?;
+                Some(format!("{}->{}", Operand::RegisterDirect(*register), field))
+            }
+            _ => None,
+        }
+    }
+
+    /// Renders an instruction with operands that access a layout replaced by
+    /// their field names
+    pub fn render(&self, inst: &Instruction) -> String {
+        let (source, destination) = operands(inst);
+        if inst.jump_offset().is_some() || (source.is_none() && destination.is_none()) {
+            return inst.to_string();
+        }
+
+        let mut output = inst.mnemonic().to_string();
+        for (i, operand) in [source, destination].iter().flatten().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            let _ = match self.operand(operand) {
+                Some(field) => write!(output, "{}{}", separator, field),
+                None => write!(output, "{}{}", separator, operand),
+            };
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    fn timer() -> Layout {
+        Layout::new("TA0")
+            .field("CTL", 2)
+            .field("CCTL0", 2)
+            .field("CCTL1", 2)
+    }
+
+    #[test]
+    fn layout_size() {
+        assert_eq!(timer().size(), 6);
+    }
+
+    #[test]
+    fn region() {
+        let overlays = Overlays::new().region(0x0160, timer());
+        // mov #0x210, &0x0160
+        let inst = decode(&[0xb2, 0x40, 0x10, 0x02, 0x60, 0x01]).unwrap();
+        assert_eq!(overlays.render(&inst), "mov #0x210, &TA0.CTL");
+        // mov.b &0x0163, r15
+        let inst = decode(&[0x5f, 0x42, 0x63, 0x01]).unwrap();
+        assert_eq!(overlays.render(&inst), "mov.b &TA0.CCTL0+1, r15");
+    }
+
+    #[test]
+    fn register() {
+        let overlays = Overlays::new().register(15, timer());
+        // mov 0x4(r15), 0x2(r15)
+        let inst = decode(&[0x9f, 0x4f, 0x04, 0x00, 0x02, 0x00]).unwrap();
+        assert_eq!(overlays.render(&inst), "mov r15->CCTL1, r15->CCTL0");
+        // tst 0x0(r15)
+        let inst = decode(&[0x8f, 0x93, 0x00, 0x00]).unwrap();
+        assert_eq!(overlays.render(&inst), "tst r15->CTL");
+    }
+
+    #[test]
+    fn unmapped() {
+        let overlays = Overlays::new().register(15, timer());
+        // mov 0x10(r15), r14; jmp $
+        let inst = decode(&[0x1e, 0x4f, 0x10, 0x00]).unwrap();
+        assert_eq!(overlays.render(&inst), "mov 0x10(r15), r14");
+        let inst = decode(&[0xff, 0x3f]).unwrap();
+        assert_eq!(overlays.render(&inst), inst.to_string());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::decode::{parts, Cursor};
+use crate::instruction::Instruction;
+use crate::Result;
+
+/// A compact representation of a decoded instruction that stores the
+/// instruction word and any extension words. This takes less than half of
+/// the memory of Instruction which makes it suitable for keeping whole
+/// images in memory. The rich Instruction is decoded again on demand
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PackedInstruction {
+    words: [u16; 4],
+    len: u8,
+}
+
+impl PackedInstruction {
+    /// Decodes the next instruction in data and packs the words that were
+    /// consumed while decoding it
+    pub fn decode(data: &[u8]) -> Result<PackedInstruction> {
+        let (input, _) = parts::instruction(Cursor::new(data))?;
+        let mut words = [0; 4];
+        let len = input.offset() / 2;
+        for (word, bytes) in words.iter_mut().zip(data[..input.offset()].chunks_exact(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        Ok(PackedInstruction {
+            words,
+            len: len as u8,
+        })
+    }
+
+    /// Returns the instruction word followed by the extension words
+    pub fn words(&self) -> &[u16] {
+        &self.words[..self.len as usize]
+    }
+
+    /// Returns the size of the encoding (in bytes)
+    pub fn size(&self) -> usize {
+        self.len as usize * 2
+    }
+
+    /// Decodes the packed words back into an Instruction
+    pub fn unpack(&self) -> Instruction {
+        let mut data = [0; 8];
+        for (bytes, word) in data.chunks_exact_mut(2).zip(self.words()) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+
+        // the words were validated when the instruction was packed and
+        // decoding is pure so this can't fail
+        crate::decode(&data[..self.size()]).expect("packed instruction is a valid encoding")
+    }
+}
+
+/// Decodes data linearly and packs every instruction along with its offset
+/// in data. Data that fails to decode is skipped one word at a time
+pub fn pack(data: &[u8]) -> Vec<(usize, PackedInstruction)> {
+    let mut instructions = vec![];
+    let mut offset = 0;
+
+    while offset < data.len() {
+        match PackedInstruction::decode(&data[offset..]) {
+            Ok(inst) => {
+                instructions.push((offset, inst));
+                offset += inst.size();
+            }
+            Err(_) => offset += 2,
+        }
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact() {
+        // four words to fit MSP430X extended instructions
+        assert!(std::mem::size_of::<PackedInstruction>() <= 10);
+    }
+
+    #[test]
+    fn round_trip() {
+        for word in 0..=u16::MAX {
+            let [a, b] = word.to_le_bytes();
+            let data = [a, b, 0x34, 0x12, 0x78, 0x56];
+            if let Ok(inst) = crate::decode(&data) {
+                let packed = PackedInstruction::decode(&data).unwrap();
+                assert_eq!(packed.unpack(), inst);
+            }
+        }
+    }
+
+    #[test]
+    fn pack_image() {
+        // mov #0x4400, sp; <invalid>; ret
+        let data = [0x31, 0x40, 0x00, 0x44, 0x80, 0x13, 0x30, 0x41];
+        let packed = pack(&data);
+        assert_eq!(
+            packed.iter().map(|(o, _)| *o).collect::<Vec<_>>(),
+            vec![0, 6]
+        );
+        assert_eq!(packed[0].1.words(), &[0x4031, 0x4400]);
+        assert_eq!(packed[1].1.unpack().to_string(), "ret");
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+use crate::decode;
+use crate::decode_error::DecodeError;
+use crate::encode::EncodeError;
+use crate::instruction::Instruction;
+use crate::provenance::Provenance;
+
+/// The encoding of nop (mov #0, r3)
+const NOP: [u8; 2] = [0x03, 0x43];
+
+/// Errors that can occur while patching an instruction in an image
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum PatchError {
+    /// Present when the instruction being replaced doesn't decode
+    Decode(DecodeError),
+    /// Present when the replacement can't be encoded
+    Encode(EncodeError),
+    /// Present when the replacement is longer than the instruction it
+    /// replaces. Contains the size of the replacement and of the original
+    TooLong((usize, usize)),
+}
+
+impl PatchError {
+    /// Returns a stable numeric code for the error. Patch errors use the
+    /// 900-999 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Decode(_) => 900,
+            Self::Encode(_) => 901,
+            Self::TooLong(_) => 902,
+        }
+    }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "original instruction: {}", e),
+            Self::Encode(e) => write!(f, "replacement: {}", e),
+            Self::TooLong((size, original)) => write!(
+                f,
+                "replacement is {} bytes but the original is {} bytes",
+                size, original
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode(e) => Some(e),
+            Self::Encode(e) => Some(e),
+            Self::TooLong(_) => None,
+        }
+    }
+}
+
+/// What a patch replaced. A list of these is a map from the original image
+/// to the patched one
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchRecord {
+    /// The addresses of the instruction the patch replaced
+    pub original: Range<u32>,
+    /// The addresses of the replacement. The rest of the original range is
+    /// padded with nops
+    pub replacement: Range<u32>,
+    /// The instruction the patch displaced
+    pub displaced: Instruction,
+}
+
+/// Replaces the instruction at offset in image with inst. The replacement
+/// is encoded in its shortest form and must fit in the original
+/// instruction. Any bytes left over are filled with nops so the following
+/// instruction isn't disturbed. The image is left untouched on error
+pub fn patch(image: &mut [u8], offset: usize, inst: &Instruction) -> Result<(), PatchError> {
+    apply(image, offset, inst).map(|_| ())
+}
+
+/// Patches the instruction at offset the same way as patch and returns a
+/// record of the ranges it changed and the instruction it displaced. base is
+/// the address the image is loaded at
+pub fn patch_mapped(
+    image: &mut [u8],
+    base: u32,
+    offset: usize,
+    inst: &Instruction,
+) -> Result<PatchRecord, PatchError> {
+    let (displaced, padding) = apply(image, offset, inst)?;
+    let start = base + offset as u32;
+    let end = start + displaced.size() as u32;
+    Ok(PatchRecord {
+        original: start..end,
+        replacement: start..end - padding as u32 * 2,
+        displaced,
+    })
+}
+
+/// Serializes patch records with one line per patch giving the original
+/// range, the replacement range and the displaced instruction (eg.
+/// `4400-4404  4400-4402  call #0x4410`). Like the provenance export this
+/// is written alongside the patched image to debug it
+pub fn export_map(records: &[PatchRecord]) -> String {
+    let mut output = String::new();
+    for record in records {
+        let _ = writeln!(
+            output,
+            "{:04x}-{:04x}  {:04x}-{:04x}  {}",
+            record.original.start,
+            record.original.end,
+            record.replacement.start,
+            record.replacement.end,
+            record.displaced
+        );
+    }
+
+    output
+}
+
+/// Patches the instruction at offset the same way as patch and returns the
+/// instructions that now occupy the original instruction with their
+/// provenance. base is the address the image is loaded at. The replacement
+/// is tagged "patch" with the address of the original and padding is tagged
+/// "patch-padding"
+pub fn patch_tagged(
+    image: &mut [u8],
+    base: u32,
+    offset: usize,
+    inst: &Instruction,
+) -> Result<Vec<(Instruction, Provenance)>, PatchError> {
+    let (_, padding) = apply(image, offset, inst)?;
+    let nop = decode(&NOP).expect("nop always decodes");
+
+    let mut patched = vec![(
+        *inst,
+        Provenance::original(base + offset as u32).with_rule("patch"),
+    )];
+    patched.extend(
+        std::iter::repeat_n(nop, padding)
+            .map(|nop| (nop, Provenance::default().with_rule("patch-padding"))),
+    );
+    Ok(patched)
+}
+
+/// Patches the instruction at offset and returns the instruction that was
+/// replaced and the number of nops that were needed to pad the replacement
+fn apply(
+    image: &mut [u8],
+    offset: usize,
+    inst: &Instruction,
+) -> Result<(Instruction, usize), PatchError> {
+    let original = decode(image.get(offset..).unwrap_or_default()).map_err(PatchError::Decode)?;
+    let bytes = inst.canonical_encoding().map_err(PatchError::Encode)?;
+    if bytes.len() > original.size() {
+        return Err(PatchError::TooLong((bytes.len(), original.size())));
+    }
+
+    let target = &mut image[offset..offset + original.size()];
+    let (replacement, padding) = target.split_at_mut(bytes.len());
+    replacement.copy_from_slice(&bytes);
+    for nop in padding.chunks_exact_mut(2) {
+        nop.copy_from_slice(&NOP);
+    }
+
+    Ok((original, padding.len() / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse;
+    use crate::listing::{render, sweep};
+    use crate::provenance;
+
+    #[test]
+    fn pads_with_nops() {
+        // call #0x4410; ret
+        let mut image = [0xb0, 0x12, 0x10, 0x44, 0x30, 0x41];
+        patch(&mut image, 0, &parse("clr r15").unwrap()).unwrap();
+        assert_eq!(
+            render(&sweep(&image, 0x4400)),
+            "       0  4400:  clr r15\n       0  4402:  nop\n       0  4404:  ret\n"
+        );
+    }
+
+    #[test]
+    fn same_size() {
+        // jnz #-0x2
+        let mut image = [0xfe, 0x23];
+        patch(&mut image, 0, &parse("jmp #-0x2").unwrap()).unwrap();
+        assert_eq!(image, [0xfe, 0x3f]);
+    }
+
+    #[test]
+    fn tagged() {
+        // call #0x4410
+        let mut image = [0xb0, 0x12, 0x10, 0x44];
+        let patched = patch_tagged(&mut image, 0x4400, 0, &parse("ret").unwrap()).unwrap();
+        assert_eq!(
+            provenance::render(0x4400, &patched),
+            "4400:  ret  ; from 0x4400 by patch\n4402:  nop  ; added by patch-padding\n"
+        );
+    }
+
+    #[test]
+    fn mapped() {
+        // call #0x4410; ret
+        let mut image = [0xb0, 0x12, 0x10, 0x44, 0x30, 0x41];
+        let records = vec![
+            patch_mapped(&mut image, 0x4400, 0, &parse("clr r15").unwrap()).unwrap(),
+            patch_mapped(&mut image, 0x4400, 4, &parse("reti").unwrap()).unwrap(),
+        ];
+        assert_eq!(
+            records[0],
+            PatchRecord {
+                original: 0x4400..0x4404,
+                replacement: 0x4400..0x4402,
+                displaced: parse("call #0x4410").unwrap(),
+            }
+        );
+        assert_eq!(
+            export_map(&records),
+            "4400-4404  4400-4402  call #0x4410\n4404-4406  4404-4406  ret\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        // ret; <invalid>
+        let mut image = [0x30, 0x41, 0x80, 0x13];
+        let call = parse("call #0x4410").unwrap();
+        assert_eq!(
+            patch(&mut image, 0, &call),
+            Err(PatchError::TooLong((4, 2)))
+        );
+        assert_eq!(
+            patch(&mut image, 2, &call),
+            Err(PatchError::Decode(DecodeError::InvalidOpcode(7)))
+        );
+        assert_eq!(
+            patch(&mut image, 8, &call),
+            Err(PatchError::Decode(DecodeError::MissingInstruction))
+        );
+        assert_eq!(image, [0x30, 0x41, 0x80, 0x13]);
+
+        let error = PatchError::Decode(DecodeError::InvalidOpcode(7));
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(
+            source.to_string(),
+            DecodeError::InvalidOpcode(7).to_string()
+        );
+        assert!(std::error::Error::source(&PatchError::TooLong((4, 2))).is_none());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::instruction::{emulated, Instruction};
+use crate::operand::Operand;
+use crate::provenance::Provenance;
+use crate::single_operand::{Push, SingleOperand};
+use crate::two_operand::{Mov, TwoOperand};
+
+/// Applies a set of standard msp430 peephole optimizations to a sequence of
+/// instructions and returns the optimized sequence. This is intended to be
+/// run over generated stubs to shrink them. The following rewrites are
+/// performed:
+///
+/// * instructions are normalized (see `Instruction::normalize`) so that
+///   immediate sources that can be produced by the constant generator no
+///   longer need an additional word and `mov #0, rN` becomes `clr rN`
+/// * `push src` immediately followed by `pop dst` is collapsed into
+///   `mov src, dst`, or removed entirely if the source and destination are
+///   the same register
+pub fn optimize(instructions: &[Instruction]) -> Vec<Instruction> {
+    let tagged: Vec<(Instruction, Provenance)> = instructions
+        .iter()
+        .map(|inst| (*inst, Provenance::default()))
+        .collect();
+    optimize_tagged(&tagged)
+        .into_iter()
+        .map(|(inst, _)| inst)
+        .collect()
+}
+
+/// Applies the same optimizations as optimize and records the rules that
+/// changed each instruction in its provenance. Normalizing is recorded as
+/// "normalize" and collapsing a push and pop as "push-pop" with the
+/// origins of both
+pub fn optimize_tagged(
+    instructions: &[(Instruction, Provenance)],
+) -> Vec<(Instruction, Provenance)> {
+    let mut optimized: Vec<(Instruction, Provenance)> = Vec::with_capacity(instructions.len());
+
+    for (original, provenance) in instructions {
+        let inst = original.normalize();
+        let provenance = if inst != *original {
+            provenance.clone().with_rule("normalize")
+        } else {
+            provenance.clone()
+        };
+
+        if let Some((Instruction::Push(push), pushed)) = optimized.last() {
+            if let Instruction::Pop(pop) = &inst {
+                if let Some(replacement) = combine_push_pop(push, pop.original()) {
+                    let provenance = pushed.merge(&provenance).with_rule("push-pop");
+                    optimized.pop();
+                    optimized.extend(replacement.map(|inst| (inst, provenance)));
+                    continue;
+                }
+            }
+        }
+
+        optimized.push((inst, provenance));
+    }
+
+    optimized
+}
+
+/// Combines a push that is immediately followed by a pop into a single mov.
+/// This is only done when neither operand touches memory or the stack
+/// pointer so that the rewrite can't change which memory is accessed.
+/// Returns None if the pair can't be combined, Some(None) if the pair can be
+/// removed entirely, and Some(Some(inst)) with the replacement otherwise
+fn combine_push_pop(push: &Push, pop: &Mov) -> Option<Option<Instruction>> {
+    let width = (*push.operand_width())?;
+    if width != *pop.operand_width() {
+        return None;
+    }
+
+    let source = *push.source();
+    match source {
+        Operand::RegisterDirect(r) if r != 1 => {}
+        Operand::Constant(_) | Operand::Immediate(_) => {}
+        _ => return None,
+    }
+
+    let destination = *pop.destination();
+    match destination {
+        Operand::RegisterDirect(r) if r != 1 => {}
+        _ => return None,
+    }
+
+    if source == destination {
+        return Some(None);
+    }
+
+    Some(Some(emulated(
+        Mov::new(source, width, destination),
+        Instruction::Mov,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+    use crate::emulate::{Clr, Pop};
+    use crate::operand::OperandWidth;
+    use crate::two_operand::Add;
+
+    #[test]
+    fn immediate_to_constant() {
+        let inst = Instruction::Add(Add::new(
+            Operand::Immediate(8),
+            OperandWidth::Word,
+            Operand::RegisterDirect(9),
+        ));
+        assert_eq!(
+            optimize(&[inst]),
+            vec![Instruction::Add(Add::new(
+                Operand::Constant(8),
+                OperandWidth::Word,
+                Operand::RegisterDirect(9),
+            ))]
+        );
+    }
+
+    #[test]
+    fn immediate_not_constant() {
+        let inst = Instruction::Add(Add::new(
+            Operand::Immediate(3),
+            OperandWidth::Word,
+            Operand::RegisterDirect(9),
+        ));
+        assert_eq!(optimize(&[inst]), vec![inst]);
+    }
+
+    #[test]
+    fn immediate_reemulated() {
+        // add #1, r9
+        let inst = decode(&[0x39, 0x50, 0x01, 0x00]).unwrap();
+        let optimized = optimize(&[inst]);
+        assert_eq!(optimized.len(), 1);
+        assert_eq!(optimized[0].to_string(), "inc r9");
+        assert_eq!(optimized[0].size(), 2);
+    }
+
+    #[test]
+    fn clr_immediate() {
+        // mov #0, r9
+        let inst = decode(&[0x39, 0x40, 0x00, 0x00]).unwrap();
+        let mov = Mov::new(
+            Operand::Constant(0),
+            OperandWidth::Word,
+            Operand::RegisterDirect(9),
+        );
+        assert_eq!(
+            optimize(&[inst]),
+            vec![Instruction::Clr(Clr::new(
+                Some(Operand::RegisterDirect(9)),
+                Some(OperandWidth::Word),
+                mov
+            ))]
+        );
+    }
+
+    #[test]
+    fn push_pop_same_register() {
+        let push = Instruction::Push(Push::new(
+            Operand::RegisterDirect(9),
+            Some(OperandWidth::Word),
+        ));
+        let pop = Instruction::Pop(Pop::new(
+            Some(Operand::RegisterDirect(9)),
+            Some(OperandWidth::Word),
+            Mov::new(
+                Operand::RegisterIndirectAutoIncrement(1),
+                OperandWidth::Word,
+                Operand::RegisterDirect(9),
+            ),
+        ));
+        assert_eq!(optimize(&[push, pop]), vec![]);
+    }
+
+    #[test]
+    fn push_pop_different_register() {
+        let push = Instruction::Push(Push::new(
+            Operand::RegisterDirect(9),
+            Some(OperandWidth::Word),
+        ));
+        let pop = Instruction::Pop(Pop::new(
+            Some(Operand::RegisterDirect(10)),
+            Some(OperandWidth::Word),
+            Mov::new(
+                Operand::RegisterIndirectAutoIncrement(1),
+                OperandWidth::Word,
+                Operand::RegisterDirect(10),
+            ),
+        ));
+        assert_eq!(
+            optimize(&[push, pop]),
+            vec![Instruction::Mov(Mov::new(
+                Operand::RegisterDirect(9),
+                OperandWidth::Word,
+                Operand::RegisterDirect(10),
+            ))]
+        );
+    }
+
+    #[test]
+    fn push_pop_memory() {
+        let push = Instruction::Push(Push::new(
+            Operand::Indexed {
+                register: 9,
+                offset: 2,
+            },
+            Some(OperandWidth::Word),
+        ));
+        let pop = Instruction::Pop(Pop::new(
+            Some(Operand::RegisterDirect(10)),
+            Some(OperandWidth::Word),
+            Mov::new(
+                Operand::RegisterIndirectAutoIncrement(1),
+                OperandWidth::Word,
+                Operand::RegisterDirect(10),
+            ),
+        ));
+        assert_eq!(optimize(&[push, pop]), vec![push, pop]);
+    }
+
+    #[test]
+    fn tagged() {
+        // push r9; pop r10; add #1, r9
+        let listing = [
+            (decode(&[0x09, 0x12]).unwrap(), Provenance::original(0x4400)),
+            (decode(&[0x3a, 0x41]).unwrap(), Provenance::original(0x4402)),
+            (
+                decode(&[0x39, 0x50, 0x01, 0x00]).unwrap(),
+                Provenance::original(0x4404),
+            ),
+        ];
+        let optimized = optimize_tagged(&listing);
+        assert_eq!(optimized.len(), 2);
+        assert_eq!(optimized[0].0.to_string(), "mov r9, r10");
+        assert_eq!(
+            optimized[0].1.to_string(),
+            "from 0x4400, 0x4402 by push-pop"
+        );
+        assert_eq!(optimized[1].0.to_string(), "inc r9");
+        assert_eq!(optimized[1].1.to_string(), "from 0x4404 by normalize");
+    }
+}
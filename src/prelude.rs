@@ -0,0 +1,28 @@
+//! Re-exports the types and traits that are needed for most uses of the
+//! crate so they can be brought into scope with a single import
+//!
+//! ```
+//! use msp430_asm::prelude::*;
+//!
+//! // mov #0x4400, sp; call #0x4410
+//! let image = [0x31, 0x40, 0x00, 0x44, 0xb0, 0x12, 0x10, 0x44];
+//! for line in sweep(&image, 0x4400) {
+//!     if let Ok(Instruction::Call(call)) = &line.instruction {
+//!         assert_eq!(call.source(), &Operand::Immediate(0x4410));
+//!     }
+//! }
+//! ```
+
+pub use crate::address::AddressInstruction;
+pub use crate::decode;
+pub use crate::decode_at;
+pub use crate::decode_error::DecodeError;
+pub use crate::emulate::{Emulate, Emulated};
+pub use crate::encode::{EncodeError, EncodeOptions};
+pub use crate::format::{write_instruction, FormatOptions};
+pub use crate::instruction::Instruction;
+pub use crate::jxx::Jxx;
+pub use crate::listing::{render, sweep, Line};
+pub use crate::operand::{Operand, OperandWidth};
+pub use crate::single_operand::SingleOperand;
+pub use crate::two_operand::TwoOperand;
@@ -0,0 +1,190 @@
+use std::fmt::{self, Write};
+
+use crate::instruction::Instruction;
+use crate::listing::Line;
+
+/// Where an instruction came from and what changed it. Transformations such
+/// as the peephole optimizer, the patcher and the assembler attach this to
+/// their output so every byte of modified firmware can be traced back to the
+/// original instructions or source lines and the rules that rewrote them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    /// The addresses of the original instructions in address order. Empty
+    /// for instructions that were added rather than derived
+    pub origins: Vec<u32>,
+    /// The source lines (starting at 1) of assembled instructions in line
+    /// order. Empty for instructions that weren't assembled
+    pub lines: Vec<usize>,
+    /// The names of the rules that were applied in the order they were
+    /// applied
+    pub rules: Vec<String>,
+}
+
+impl Provenance {
+    /// Returns the provenance of an unmodified instruction at address
+    pub fn original(address: u32) -> Provenance {
+        Provenance {
+            origins: vec![address],
+            ..Default::default()
+        }
+    }
+
+    /// Returns the provenance of an instruction assembled from a line of
+    /// source
+    pub fn assembled(line: usize) -> Provenance {
+        Provenance {
+            lines: vec![line],
+            ..Default::default()
+        }
+    }
+
+    /// Returns the provenance with rule appended
+    pub fn with_rule(mut self, rule: &str) -> Provenance {
+        self.rules.push(rule.to_string());
+        self
+    }
+
+    /// Returns the provenance of an instruction derived from both self and
+    /// other. Origins are merged and the rules of self come first
+    pub fn merge(&self, other: &Provenance) -> Provenance {
+        let mut origins: Vec<u32> = self.origins.iter().chain(&other.origins).copied().collect();
+        origins.sort_unstable();
+        origins.dedup();
+        let mut lines: Vec<usize> = self.lines.iter().chain(&other.lines).copied().collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        Provenance {
+            origins,
+            lines,
+            rules: self.rules.iter().chain(&other.rules).cloned().collect(),
+        }
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let origins: Vec<String> = self
+            .origins
+            .iter()
+            .map(|a| format!("{:#x}", a))
+            .chain(self.lines.iter().map(|line| format!("line {}", line)))
+            .collect();
+        match (origins.is_empty(), self.rules.is_empty()) {
+            (true, true) => write!(f, "added"),
+            (true, false) => write!(f, "added by {}", self.rules.join(", ")),
+            (false, true) => write!(f, "from {}", origins.join(", ")),
+            (false, false) => write!(
+                f,
+                "from {} by {}",
+                origins.join(", "),
+                self.rules.join(", ")
+            ),
+        }
+    }
+}
+
+/// Lays out instructions from base as listing lines so the provenance is
+/// shown by listing::render. Unmodified instructions that are still at
+/// their original address have no provenance
+pub fn lines(base: u32, instructions: &[(Instruction, Provenance)]) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = base;
+    for (inst, provenance) in instructions {
+        lines.push(Line {
+            address,
+            size: inst.size(),
+            instruction: Ok(*inst),
+            hits: 0,
+            provenance: (provenance != &Provenance::original(address)).then(|| provenance.clone()),
+        });
+        address += inst.size() as u32;
+    }
+
+    lines
+}
+
+/// Serializes the provenance of lines with one line per address that has
+/// provenance. Intel HEX and TI-TXT can't carry comments so this is written
+/// alongside them to explain the modified bytes of the image
+pub fn export(lines: &[Line]) -> String {
+    let mut output = String::new();
+    for line in lines {
+        if let Some(provenance) = &line.provenance {
+            let _ = writeln!(output, "{:04x}  {}", line.address, provenance);
+        }
+    }
+
+    output
+}
+
+/// Renders instructions laid out from base with the provenance of each one
+/// as a comment. Unmodified instructions that are still at their original
+/// address have no comment
+pub fn render(base: u32, instructions: &[(Instruction, Provenance)]) -> String {
+    let mut output = String::new();
+    let mut address = base;
+    for (inst, provenance) in instructions {
+        let _ = write!(output, "{:04x}:  {}", address, inst);
+        if provenance != &Provenance::original(address) {
+            let _ = write!(output, "  ; {}", provenance);
+        }
+        output.push('\n');
+        address += inst.size() as u32;
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm::parse;
+    use crate::listing;
+
+    #[test]
+    fn display() {
+        assert_eq!(Provenance::default().to_string(), "added");
+        assert_eq!(Provenance::original(0x4400).to_string(), "from 0x4400");
+        let merged = Provenance::original(0x4402)
+            .merge(&Provenance::original(0x4400).with_rule("normalize"))
+            .with_rule("push-pop");
+        assert_eq!(merged.origins, vec![0x4400, 0x4402]);
+        assert_eq!(
+            merged.to_string(),
+            "from 0x4400, 0x4402 by normalize, push-pop"
+        );
+        assert_eq!(
+            Provenance::assembled(3).with_rule("macro").to_string(),
+            "from line 3 by macro"
+        );
+    }
+
+    #[test]
+    fn rendered() {
+        let instructions = [
+            (parse("clr r15").unwrap(), Provenance::original(0x4400)),
+            (
+                parse("nop").unwrap(),
+                Provenance::default().with_rule("patch-padding"),
+            ),
+            (parse("ret").unwrap(), Provenance::original(0x4408)),
+        ];
+        assert_eq!(
+            render(0x4400, &instructions),
+            "4400:  clr r15\n4402:  nop  ; added by patch-padding\n4404:  ret  ; from 0x4408\n"
+        );
+
+        let lines = lines(0x4400, &instructions);
+        assert_eq!(
+            listing::render(&lines),
+            "       0  4400:  clr r15\n\
+             \x20      0  4402:  nop  ; added by patch-padding\n\
+             \x20      0  4404:  ret  ; from 0x4408\n"
+        );
+        assert_eq!(
+            export(&lines),
+            "4402  added by patch-padding\n4404  from 0x4408\n"
+        );
+    }
+}
@@ -0,0 +1,785 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::instruction::Instruction;
+use crate::operand::{Operand, OperandWidth};
+use crate::target;
+use crate::two_operand::TwoOperand;
+
+/// Watchdog timer control register on 1xx/2xx/4xx devices
+pub const WDTCTL: u16 = 0x0120;
+
+/// Flash memory controller registers (FCTL1 - FCTL3) on 1xx/2xx/4xx devices
+pub const FCTL: Range<u16> = 0x0128..0x012e;
+
+const WDTPW: u16 = 0x5a00;
+const WDTHOLD: u16 = 0x0080;
+
+/// A problem reported by a rule at a specific address
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Finding {
+    pub address: u32,
+    /// The name of the rule that reported the finding
+    pub rule: String,
+    pub message: String,
+}
+
+/// A check that runs over a listing of decoded instructions. Each entry in
+/// the listing is the address an instruction was decoded from and the
+/// instruction itself
+pub trait Rule {
+    /// Returns the name of the rule which is attached to every finding
+    fn name(&self) -> &str;
+    /// Returns the findings for the listing
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding>;
+}
+
+/// Runs all rules over a listing and returns their findings ordered by
+/// address, then rule name and message, with duplicates removed. The order
+/// doesn't depend on the order of the rules so the output can be diffed
+/// between runs
+pub fn run(rules: &[&dyn Rule], listing: &[(u32, Instruction)]) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = rules.iter().flat_map(|r| r.check(listing)).collect();
+    findings.sort();
+    findings.dedup();
+    findings
+}
+
+/// Returns whether the instruction writes to its destination operand
+pub(crate) fn writes_destination(inst: &Instruction) -> bool {
+    !matches!(
+        inst,
+        Instruction::Cmp(_) | Instruction::Bit(_) | Instruction::Tst(_)
+    ) && inst.destination().is_some()
+}
+
+/// Reports when the watchdog is stopped (WDTPW | WDTHOLD written to WDTCTL)
+/// and never restarted later in the listing
+pub struct WatchdogDisabled {
+    /// The address of the watchdog control register for the target device
+    pub wdtctl: u16,
+}
+
+impl Default for WatchdogDisabled {
+    fn default() -> Self {
+        WatchdogDisabled { wdtctl: WDTCTL }
+    }
+}
+
+impl Rule for WatchdogDisabled {
+    fn name(&self) -> &str {
+        "watchdog-disabled"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        let mut stopped = None;
+        for (address, inst) in listing {
+            if !matches!(inst, Instruction::Mov(_))
+                || inst.destination() != Some(&Operand::Absolute(self.wdtctl))
+            {
+                continue;
+            }
+
+            match inst.source() {
+                Some(Operand::Immediate(value))
+                    if value & 0xff00 == WDTPW && value & WDTHOLD != 0 =>
+                {
+                    if stopped.is_none() {
+                        stopped = Some(*address);
+                    }
+                }
+                _ => stopped = None,
+            }
+        }
+
+        match stopped {
+            Some(address) => vec![Finding {
+                address,
+                rule: self.name().to_string(),
+                message: "watchdog is stopped and never restarted".to_string(),
+            }],
+            None => vec![],
+        }
+    }
+}
+
+/// Reports when the stack pointer is initialized to an address below the
+/// start of RAM
+pub struct StackBelowRam {
+    /// The RAM address range of the target device
+    pub ram: Range<u16>,
+}
+
+impl Rule for StackBelowRam {
+    fn name(&self) -> &str {
+        "stack-below-ram"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        listing
+            .iter()
+            .filter_map(|(address, inst)| {
+                if !matches!(inst, Instruction::Mov(_))
+                    || inst.destination() != Some(&Operand::RegisterDirect(1))
+                {
+                    return None;
+                }
+
+                match inst.source() {
+                    Some(Operand::Immediate(sp)) if *sp < self.ram.start => Some(Finding {
+                        address: *address,
+                        rule: self.name().to_string(),
+                        message: format!(
+                            "stack pointer set to {:#x} which is below RAM ({:#x})",
+                            sp, self.ram.start
+                        ),
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports writes to the flash controller registers from within interrupt
+/// service routines
+pub struct FlashWriteFromIsr {
+    /// The address ranges of the interrupt service routines
+    pub isrs: Vec<Range<u32>>,
+    /// The address range of the flash controller registers for the target
+    /// device
+    pub registers: Range<u16>,
+}
+
+impl FlashWriteFromIsr {
+    /// Creates the rule for 1xx/2xx/4xx devices
+    pub fn new(isrs: Vec<Range<u32>>) -> FlashWriteFromIsr {
+        FlashWriteFromIsr {
+            isrs,
+            registers: FCTL,
+        }
+    }
+}
+
+impl Rule for FlashWriteFromIsr {
+    fn name(&self) -> &str {
+        "flash-write-from-isr"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        listing
+            .iter()
+            .filter_map(|(address, inst)| {
+                if !self.isrs.iter().any(|isr| isr.contains(address)) || !writes_destination(inst) {
+                    return None;
+                }
+
+                match inst.destination() {
+                    Some(Operand::Absolute(register)) if self.registers.contains(register) => {
+                        Some(Finding {
+                            address: *address,
+                            rule: self.name().to_string(),
+                            message: format!(
+                                "flash controller register {:#x} written from an interrupt",
+                                register
+                            ),
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports byte copy loops (`mov.b @rN+, X(rM)`) that aren't bounded by a
+/// compare. Loops are identified as the range between a backwards jxx and
+/// its target. A loop is considered bounded if its body contains a `cmp` or
+/// if it is a counted loop, where the flags that a backwards `jnz` tests
+/// come from decrementing a counter (`dec rN; jnz loop`). `tst` is
+/// intentionally not considered a bound because it is how copies that run
+/// until a terminator (eg. strcpy) are implemented which is exactly the
+/// pattern that leads to memory corruption
+#[derive(Default)]
+pub struct UnboundedCopyLoop {}
+
+impl Rule for UnboundedCopyLoop {
+    fn name(&self) -> &str {
+        "unbounded-copy-loop"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        let mut findings = vec![];
+        for (end, (address, inst)) in listing.iter().enumerate() {
+            let offset = match inst.jump_offset() {
+                Some(offset) if offset < 0 => offset,
+                _ => continue,
+            };
+
+            let target = target::jump_target(*address, offset);
+            let start = match listing[..end].iter().position(|(a, _)| *a == target) {
+                Some(start) => start,
+                None => continue,
+            };
+
+            let body = &listing[start..end];
+            if body.iter().any(|(_, i)| matches!(i, Instruction::Cmp(_))) {
+                continue;
+            }
+
+            let counted = matches!(inst, Instruction::Jnz(_))
+                && matches!(
+                    body.iter().rev().find(|(_, i)| sets_flags(i)),
+                    Some((_, Instruction::Dec(_) | Instruction::Decd(_)))
+                );
+            if counted {
+                continue;
+            }
+
+            for (address, inst) in body {
+                let copy = match inst {
+                    Instruction::Mov(mov) => mov,
+                    _ => continue,
+                };
+
+                if let (
+                    OperandWidth::Byte,
+                    Operand::RegisterIndirectAutoIncrement(_),
+                    Operand::Indexed { .. },
+                ) = (copy.operand_width(), copy.source(), copy.destination())
+                {
+                    findings.push(Finding {
+                        address: *address,
+                        rule: self.name().to_string(),
+                        message: format!("byte copy loop without a bound ({})", inst),
+                    });
+                }
+            }
+        }
+
+        findings
+    }
+}
+
+/// Reports byte instructions with an immediate that has a nonzero high
+/// byte. Only the low byte is used so the high byte usually indicates a
+/// compiler quirk or hand written code
+pub struct ByteImmediate {}
+
+impl Rule for ByteImmediate {
+    fn name(&self) -> &str {
+        "byte-immediate"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        listing
+            .iter()
+            .filter_map(|(address, inst)| {
+                let width = inst.operand_width();
+                match inst.source()? {
+                    source @ Operand::Immediate(value)
+                        if width == Some(OperandWidth::Byte) && value & 0xff00 != 0 =>
+                    {
+                        Some(Finding {
+                            address: *address,
+                            rule: self.name().to_string(),
+                            message: format!(
+                                "byte operation with 16 bit immediate {:#06x} (effective {})",
+                                value,
+                                source.effective(width)
+                            ),
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports jumps, calls and branches to targets that are at an odd address
+/// or outside of the executable memory of the device
+pub struct InvalidTarget {
+    /// The address ranges of executable memory (eg. flash) for the target
+    /// device
+    pub executable: Vec<Range<u32>>,
+}
+
+impl Rule for InvalidTarget {
+    fn name(&self) -> &str {
+        "invalid-target"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        target::validate_listing(listing, &self.executable)
+            .into_iter()
+            .map(|(address, e)| Finding {
+                address,
+                rule: self.name().to_string(),
+                message: e.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Taint state for ConstantTime. Taint only ever grows which makes the
+/// analysis flow insensitive: a location is secret if any instruction in the
+/// function can make it secret
+#[derive(Default, PartialEq)]
+struct Taint {
+    registers: [bool; 16],
+    globals: HashSet<u16>,
+    /// Set once a secret is stored through a pointer, after which all
+    /// memory that isn't a known global is considered secret
+    memory: bool,
+}
+
+impl Taint {
+    fn register(&self, r: u8) -> bool {
+        self.registers[r as usize & 0xf]
+    }
+
+    fn operand(&self, operand: &Operand) -> bool {
+        match operand {
+            Operand::RegisterDirect(r) => self.register(*r),
+            Operand::Absolute(address) => self.memory || self.globals.contains(address),
+            Operand::Indexed { register: r, .. }
+            | Operand::RegisterIndirect(r)
+            | Operand::RegisterIndirectAutoIncrement(r) => self.memory || self.register(*r),
+            Operand::Symbolic(_) => self.memory,
+            Operand::Immediate(_) | Operand::Constant(_) => false,
+        }
+    }
+
+    fn write(&mut self, operand: &Operand, secret: bool) {
+        if !secret {
+            return;
+        }
+
+        match operand {
+            Operand::RegisterDirect(r) => self.registers[*r as usize & 0xf] = true,
+            Operand::Absolute(address) => {
+                self.globals.insert(*address);
+            }
+            _ => self.memory = true,
+        }
+    }
+
+    /// Returns whether the value an instruction produces is secret
+    fn result(&self, inst: &Instruction) -> bool {
+        let source = inst.source().is_some_and(|s| self.operand(s));
+        match inst {
+            // mov only depends on its source
+            Instruction::Mov(_)
+            | Instruction::Br(_)
+            | Instruction::Clr(_)
+            | Instruction::Nop(_)
+            | Instruction::Pop(_)
+            | Instruction::Ret(_)
+            | Instruction::Push(_)
+            | Instruction::Call(_) => source,
+            // idioms that always produce zero
+            Instruction::Xor(_) | Instruction::Sub(_) if inst.source() == inst.destination() => {
+                false
+            }
+            Instruction::Adc(_)
+            | Instruction::Addc(_)
+            | Instruction::Dadc(_)
+            | Instruction::Dadd(_)
+            | Instruction::Rlc(_)
+            | Instruction::Rrc(_)
+            | Instruction::Sbc(_)
+            | Instruction::Subc(_) => {
+                source || inst.destination().is_some_and(|d| self.operand(d)) || self.registers[2]
+            }
+            _ => source || inst.destination().is_some_and(|d| self.operand(d)),
+        }
+    }
+}
+
+/// Returns whether the instruction updates the status register flags
+fn sets_flags(inst: &Instruction) -> bool {
+    !matches!(
+        inst,
+        Instruction::Mov(_)
+            | Instruction::Bic(_)
+            | Instruction::Bis(_)
+            | Instruction::Push(_)
+            | Instruction::Call(_)
+            | Instruction::Swpb(_)
+            | Instruction::Reti(_)
+            | Instruction::Br(_)
+            | Instruction::Clr(_)
+            | Instruction::Nop(_)
+            | Instruction::Pop(_)
+            | Instruction::Ret(_)
+            | Instruction::Clrc(_)
+            | Instruction::Clrn(_)
+            | Instruction::Clrz(_)
+            | Instruction::Setc(_)
+            | Instruction::Setn(_)
+            | Instruction::Setz(_)
+            | Instruction::Dint(_)
+            | Instruction::Eint(_)
+    ) && inst.jump_offset().is_none()
+}
+
+/// Reports secret dependent branches and secret indexed memory accesses in
+/// a function, for auditing constant time code (eg. crypto). Secrets are
+/// the registers and globals that hold secret inputs when the function is
+/// entered and are propagated through every instruction in the function
+/// regardless of order. Conditional jumps are checked against the closest
+/// preceding instruction that sets the flags
+pub struct ConstantTime {
+    /// The address range of the function
+    pub function: Range<u32>,
+    /// Registers that hold secrets on entry
+    pub registers: Vec<u8>,
+    /// Absolute addresses that hold secrets
+    pub globals: Vec<u16>,
+}
+
+impl ConstantTime {
+    fn propagate(&self, body: &[&(u32, Instruction)]) -> Taint {
+        let mut taint = Taint::default();
+        for register in &self.registers {
+            taint.registers[*register as usize & 0xf] = true;
+        }
+        taint.globals.extend(&self.globals);
+
+        loop {
+            let before = (taint.registers, taint.globals.len(), taint.memory);
+            for (_, inst) in body {
+                let secret = taint.result(inst);
+                match (inst, inst.destination(), inst.source()) {
+                    (Instruction::Push(_), _, _) => taint.memory |= secret,
+                    (
+                        Instruction::Cmp(_)
+                        | Instruction::Bit(_)
+                        | Instruction::Tst(_)
+                        | Instruction::Call(_),
+                        _,
+                        _,
+                    ) => {}
+                    (_, Some(destination), _) => taint.write(destination, secret),
+                    // single operand instructions write their source
+                    (_, None, Some(source)) => taint.write(source, secret),
+                    _ => {}
+                }
+
+                if sets_flags(inst) && secret {
+                    taint.registers[2] = true;
+                }
+            }
+
+            if before == (taint.registers, taint.globals.len(), taint.memory) {
+                return taint;
+            }
+        }
+    }
+}
+
+impl Rule for ConstantTime {
+    fn name(&self) -> &str {
+        "constant-time"
+    }
+
+    fn check(&self, listing: &[(u32, Instruction)]) -> Vec<Finding> {
+        let body: Vec<&(u32, Instruction)> = listing
+            .iter()
+            .filter(|(address, _)| self.function.contains(address))
+            .collect();
+        let taint = self.propagate(&body);
+
+        let mut findings = vec![];
+        let mut flags = false;
+        for (address, inst) in &body {
+            let mut finding = |message: String| {
+                findings.push(Finding {
+                    address: *address,
+                    rule: self.name().to_string(),
+                    message,
+                })
+            };
+
+            for operand in [inst.source(), inst.destination()].into_iter().flatten() {
+                if let Operand::Indexed { register: r, .. }
+                | Operand::RegisterIndirect(r)
+                | Operand::RegisterIndirectAutoIncrement(r) = operand
+                {
+                    if taint.register(*r) {
+                        finding(format!("secret indexed memory access ({})", inst));
+                    }
+                }
+            }
+
+            match inst {
+                Instruction::Jmp(_) => {}
+                _ if inst.jump_offset().is_some() && flags => {
+                    finding(format!("branch on secret dependent flags ({})", inst));
+                }
+                Instruction::Call(_) | Instruction::Br(_) if taint.result(inst) => {
+                    finding(format!("secret dependent branch target ({})", inst));
+                }
+                _ => {}
+            }
+
+            if sets_flags(inst) {
+                flags = taint.result(inst);
+            }
+        }
+
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::listing;
+
+    #[test]
+    fn watchdog_disabled() {
+        // mov #0x5a80, &0x0120; ret
+        let listing = listing(&[0xb2, 0x40, 0x80, 0x5a, 0x20, 0x01, 0x30, 0x41], 0x4400);
+        assert_eq!(
+            WatchdogDisabled::default().check(&listing),
+            vec![Finding {
+                address: 0x4400,
+                rule: "watchdog-disabled".to_string(),
+                message: "watchdog is stopped and never restarted".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn watchdog_restarted() {
+        // mov #0x5a80, &0x0120; mov #0x5a08, &0x0120
+        let listing = listing(
+            &[
+                0xb2, 0x40, 0x80, 0x5a, 0x20, 0x01, 0xb2, 0x40, 0x08, 0x5a, 0x20, 0x01,
+            ],
+            0x4400,
+        );
+        assert_eq!(WatchdogDisabled::default().check(&listing), vec![]);
+    }
+
+    #[test]
+    fn stack_below_ram() {
+        // mov #0x0100, sp; mov #0x0400, sp
+        let listing = listing(&[0x31, 0x40, 0x00, 0x01, 0x31, 0x40, 0x00, 0x04], 0x4400);
+        let rule = StackBelowRam {
+            ram: 0x0200..0x0400,
+        };
+        assert_eq!(
+            rule.check(&listing),
+            vec![Finding {
+                address: 0x4400,
+                rule: "stack-below-ram".to_string(),
+                message: "stack pointer set to 0x100 which is below RAM (0x200)".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn flash_write_from_isr() {
+        // mov #0xa540, &0x0128; mov #0xa540, &0x0128
+        let listing = listing(
+            &[
+                0xb2, 0x40, 0x40, 0xa5, 0x28, 0x01, 0xb2, 0x40, 0x40, 0xa5, 0x28, 0x01,
+            ],
+            0x4400,
+        );
+        let isrs = vec![0x4406..0x440c, 0xc000..0xc010];
+        let rule = FlashWriteFromIsr::new(isrs);
+        assert_eq!(
+            rule.check(&listing),
+            vec![Finding {
+                address: 0x4406,
+                rule: "flash-write-from-isr".to_string(),
+                message: "flash controller register 0x128 written from an interrupt".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn run_ordered() {
+        // mov #0x5a80, &0x0120; mov #0x0100, sp
+        let listing = listing(
+            &[0xb2, 0x40, 0x80, 0x5a, 0x20, 0x01, 0x31, 0x40, 0x00, 0x01],
+            0x4400,
+        );
+        let stack = StackBelowRam {
+            ram: 0x0200..0x0400,
+        };
+        let watchdog = WatchdogDisabled::default();
+        let findings = run(&[&stack, &watchdog], &listing);
+        assert_eq!(
+            findings.iter().map(|f| f.address).collect::<Vec<_>>(),
+            vec![0x4400, 0x4406]
+        );
+    }
+
+    #[test]
+    fn run_deterministic() {
+        // mov #0x5a80, &0x0120; mov #0x0100, sp
+        let listing = listing(
+            &[0xb2, 0x40, 0x80, 0x5a, 0x20, 0x01, 0x31, 0x40, 0x00, 0x01],
+            0x4400,
+        );
+        let stack = StackBelowRam {
+            ram: 0x0200..0x0400,
+        };
+        let watchdog = WatchdogDisabled::default();
+        let findings = run(&[&stack, &watchdog, &stack], &listing);
+        assert_eq!(findings, run(&[&watchdog, &stack], &listing));
+        assert_eq!(findings.len(), 2);
+    }
+
+    #[test]
+    fn invalid_target() {
+        // call #0x0200; jmp #0x0
+        let listing = listing(&[0xb0, 0x12, 0x00, 0x02, 0x00, 0x3c], 0xc000);
+        let rule = InvalidTarget {
+            executable: vec![0xc000..0xff80, 0x10000..0x14400],
+        };
+        assert_eq!(
+            rule.check(&listing),
+            vec![Finding {
+                address: 0xc000,
+                rule: "invalid-target".to_string(),
+                message: "target 0x200 is not in executable memory".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn unbounded_copy_loop() {
+        // loop: mov.b @r15+, 0x0(r14); inc r14; tst.b -0x1(r14); jnz loop
+        let listing = listing(
+            &[
+                0xfe, 0x4f, 0x00, 0x00, 0x1e, 0x53, 0xce, 0x93, 0xff, 0xff, 0xfa, 0x23,
+            ],
+            0x4400,
+        );
+        assert_eq!(
+            UnboundedCopyLoop::default().check(&listing),
+            vec![Finding {
+                address: 0x4400,
+                rule: "unbounded-copy-loop".to_string(),
+                message: "byte copy loop without a bound (mov.b @r15+, 0x0(r14))".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn bounded_copy_loop() {
+        // loop: mov.b @r15+, 0x0(r14); inc r14; cmp r13, r15; jnz loop
+        let listing = listing(
+            &[0xfe, 0x4f, 0x00, 0x00, 0x1e, 0x53, 0x0f, 0x9d, 0xfb, 0x23],
+            0x4400,
+        );
+        assert_eq!(UnboundedCopyLoop::default().check(&listing), vec![]);
+    }
+
+    #[test]
+    fn counted_copy_loop() {
+        // loop: mov.b @r15+, 0x0(r14); inc r14; dec r13; jnz loop
+        let counted = listing(
+            &[0xfe, 0x4f, 0x00, 0x00, 0x1e, 0x53, 0x1d, 0x83, 0xfb, 0x23],
+            0x4400,
+        );
+        assert_eq!(UnboundedCopyLoop::default().check(&counted), vec![]);
+
+        // loop: dec r13; mov.b @r15+, 0x0(r14); inc r14; jnz loop
+        let listing = listing(
+            &[0x1d, 0x83, 0xfe, 0x4f, 0x00, 0x00, 0x1e, 0x53, 0xfb, 0x23],
+            0x4400,
+        );
+        assert_eq!(UnboundedCopyLoop::default().check(&listing).len(), 1);
+    }
+
+    #[test]
+    fn byte_immediate() {
+        // mov.b #0x1234, r15; mov.b #0x34, r15; mov #0x1234, r15
+        let listing = listing(
+            &[
+                0x7f, 0x40, 0x34, 0x12, 0x7f, 0x40, 0x34, 0x00, 0x3f, 0x40, 0x34, 0x12,
+            ],
+            0x4400,
+        );
+        assert_eq!(
+            ByteImmediate {}.check(&listing),
+            vec![Finding {
+                address: 0x4400,
+                rule: "byte-immediate".to_string(),
+                message: "byte operation with 16 bit immediate 0x1234 (effective #0x34)"
+                    .to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn taint_register_mask() {
+        let mut taint = Taint::default();
+        taint.write(&Operand::RegisterDirect(0x1f), true);
+        assert!(taint.registers[0xf]);
+        assert!(taint.operand(&Operand::RegisterDirect(0x1f)));
+        assert!(taint.operand(&Operand::Indexed {
+            register: 0x1f,
+            offset: 0
+        }));
+    }
+
+    #[test]
+    fn constant_time_branch() {
+        // cmp r14, r15; jnz #0x1; mov #0x1, r12; ret
+        let listing = listing(&[0x0f, 0x9e, 0x01, 0x20, 0x1c, 0x43, 0x30, 0x41], 0x4400);
+        let rule = ConstantTime {
+            function: 0x4400..0x4408,
+            registers: vec![15],
+            globals: vec![],
+        };
+        assert_eq!(
+            rule.check(&listing),
+            vec![Finding {
+                address: 0x4402,
+                rule: "constant-time".to_string(),
+                message: "branch on secret dependent flags (jnz #0x1)".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn constant_time_table_lookup() {
+        // mov &0x0200, r15; and #0xf, r15; mov.b 0x4500(r15), r12; xor r14, r14
+        let listing = listing(
+            &[
+                0x1f, 0x42, 0x00, 0x02, 0x3f, 0xf0, 0x0f, 0x00, 0x5c, 0x4f, 0x00, 0x45, 0x0e, 0xee,
+            ],
+            0x4400,
+        );
+        let rule = ConstantTime {
+            function: 0x4400..0x4410,
+            registers: vec![],
+            globals: vec![0x0200],
+        };
+        let findings = rule.check(&listing);
+        assert_eq!(
+            findings.iter().map(|f| f.address).collect::<Vec<_>>(),
+            vec![0x4408]
+        );
+    }
+
+    #[test]
+    fn constant_time_clean() {
+        // xor r15, r14; rla r14; cmp r12, r13; jnz #0x1
+        let listing = listing(&[0x0e, 0xef, 0x0e, 0x5e, 0x0d, 0x9c, 0x01, 0x20], 0x4400);
+        let rule = ConstantTime {
+            function: 0x4400..0x4408,
+            registers: vec![14, 15],
+            globals: vec![],
+        };
+        assert_eq!(rule.check(&listing), vec![]);
+    }
+}
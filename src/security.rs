@@ -0,0 +1,207 @@
+use std::fmt;
+
+/// BSL security key on 2xx/4xx devices
+pub const BSLSKEY: u32 = 0xffde;
+
+/// JTAG lock key on 5xx/6xx devices (two words)
+pub const JTAG_LOCK_KEY: u32 = 0x17fc;
+
+/// JTAG signatures on FRAM devices (two words)
+pub const JTAG_SIGNATURE: u32 = 0xff80;
+
+/// BSL signatures on FRAM devices (two words)
+pub const BSL_SIGNATURE: u32 = 0xff84;
+
+const BSLSKEY_DISABLED: u16 = 0xaa55;
+const BSLSKEY_ERASE: u16 = 0x0000;
+const LOCKED: u16 = 0x5555;
+const PASSWORD: u16 = 0xaaaa;
+
+/// The device family decides where the lock words are stored and how they
+/// are interpreted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Family {
+    /// 1xx/2xx/4xx flash devices. JTAG is locked by blowing a fuse so only
+    /// the BSL configuration can be read from the image
+    Flash,
+    /// 5xx/6xx flash devices
+    Flash5xx,
+    /// FR5xx/FR6xx/FR2xx/FR4xx FRAM devices
+    Fram,
+}
+
+/// Whether a debugger can connect over JTAG/SBW
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JtagAccess {
+    Open,
+    /// Access is permanently disabled
+    Locked,
+    /// Access requires a password. Contains the password length in words
+    Password(u16),
+}
+
+impl fmt::Display for JtagAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open => write!(f, "jtag is open"),
+            Self::Locked => write!(f, "jtag is locked"),
+            Self::Password(len) => write!(f, "jtag requires a {} word password", len),
+        }
+    }
+}
+
+/// Whether the bootstrap loader can be used to read or write memory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BslAccess {
+    /// The BSL is available with the password (the interrupt vector table
+    /// on flash devices)
+    Enabled,
+    Disabled,
+    /// The BSL is available but flash is erased when an invalid password is
+    /// given
+    EraseOnInvalidPassword,
+}
+
+impl fmt::Display for BslAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Enabled => write!(f, "bsl is enabled"),
+            Self::Disabled => write!(f, "bsl is disabled"),
+            Self::EraseOnInvalidPassword => {
+                write!(f, "bsl is enabled and erases flash on an invalid password")
+            }
+        }
+    }
+}
+
+/// The debug access an image would configure when programmed. Fields are
+/// None when the family doesn't store the setting in the image or the image
+/// doesn't cover the lock words
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub jtag: Option<JtagAccess>,
+    pub bsl: Option<BslAccess>,
+}
+
+fn read_word(image: &[u8], base: u32, address: u32) -> Option<u16> {
+    let offset = address.checked_sub(base)? as usize;
+    let bytes = image.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_pair(image: &[u8], base: u32, address: u32) -> Option<(u16, u16)> {
+    Some((
+        read_word(image, base, address)?,
+        read_word(image, base, address + 2)?,
+    ))
+}
+
+/// Inspects the JTAG and BSL lock words of an image loaded at base
+pub fn audit(image: &[u8], base: u32, family: Family) -> Report {
+    match family {
+        Family::Flash => Report {
+            jtag: None,
+            bsl: read_word(image, base, BSLSKEY).map(|key| match key {
+                BSLSKEY_DISABLED => BslAccess::Disabled,
+                BSLSKEY_ERASE => BslAccess::EraseOnInvalidPassword,
+                _ => BslAccess::Enabled,
+            }),
+        },
+        Family::Flash5xx => Report {
+            jtag: read_pair(image, base, JTAG_LOCK_KEY).map(|key| match key {
+                (0x0000, 0x0000) | (0xffff, 0xffff) => JtagAccess::Open,
+                _ => JtagAccess::Locked,
+            }),
+            bsl: None,
+        },
+        Family::Fram => Report {
+            // only the locked and password signatures are acted on, any
+            // other value leaves jtag open
+            jtag: read_pair(image, base, JTAG_SIGNATURE).map(|signature| match signature {
+                (LOCKED, LOCKED) => JtagAccess::Locked,
+                (PASSWORD, len) => JtagAccess::Password(len),
+                _ => JtagAccess::Open,
+            }),
+            bsl: read_pair(image, base, BSL_SIGNATURE).map(|signature| match signature {
+                (LOCKED, LOCKED) => BslAccess::Disabled,
+                _ => BslAccess::Enabled,
+            }),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_bsl() {
+        let mut image = vec![0xff; 0x20];
+        assert_eq!(
+            audit(&image, 0xffc0, Family::Flash),
+            Report {
+                jtag: None,
+                bsl: Some(BslAccess::Enabled)
+            }
+        );
+
+        image[0x1e..0x20].copy_from_slice(&[0x55, 0xaa]);
+        assert_eq!(
+            audit(&image, 0xffc0, Family::Flash).bsl,
+            Some(BslAccess::Disabled)
+        );
+        image[0x1e..0x20].copy_from_slice(&[0x00, 0x00]);
+        assert_eq!(
+            audit(&image, 0xffc0, Family::Flash).bsl,
+            Some(BslAccess::EraseOnInvalidPassword)
+        );
+    }
+
+    #[test]
+    fn flash_5xx_jtag() {
+        assert_eq!(
+            audit(&[0xff; 4], 0x17fc, Family::Flash5xx).jtag,
+            Some(JtagAccess::Open)
+        );
+        assert_eq!(
+            audit(&[0x55; 4], 0x17fc, Family::Flash5xx).jtag,
+            Some(JtagAccess::Locked)
+        );
+    }
+
+    #[test]
+    fn fram() {
+        // jtag password of 4 words and bsl disabled
+        let image = [0xaa, 0xaa, 0x04, 0x00, 0x55, 0x55, 0x55, 0x55];
+        assert_eq!(
+            audit(&image, 0xff80, Family::Fram),
+            Report {
+                jtag: Some(JtagAccess::Password(4)),
+                bsl: Some(BslAccess::Disabled)
+            }
+        );
+        assert_eq!(
+            audit(&[0x55; 4], 0xff80, Family::Fram).jtag,
+            Some(JtagAccess::Locked)
+        );
+        assert_eq!(
+            audit(&[0x34, 0x12, 0x78, 0x56], 0xff80, Family::Fram).jtag,
+            Some(JtagAccess::Open)
+        );
+        assert_eq!(
+            audit(&[0x55, 0x55, 0x00, 0x00], 0xff80, Family::Fram).jtag,
+            Some(JtagAccess::Open)
+        );
+    }
+
+    #[test]
+    fn not_covered() {
+        assert_eq!(
+            audit(&[0xff; 4], 0x4400, Family::Fram),
+            Report {
+                jtag: None,
+                bsl: None
+            }
+        );
+    }
+}
@@ -0,0 +1,564 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::instruction::Instruction;
+use crate::operand::{Operand, OperandWidth};
+use crate::single_operand::SingleOperand;
+use crate::two_operand::TwoOperand;
+
+/// Carry flag in the status register
+pub const C: u16 = 0x0001;
+/// Zero flag in the status register
+pub const Z: u16 = 0x0002;
+/// Negative flag in the status register
+pub const N: u16 = 0x0004;
+/// Overflow flag in the status register
+pub const V: u16 = 0x0100;
+
+/// The number of input states that sequences are compared over
+const INPUTS: u64 = 256;
+
+/// Register and memory state of the CPU that straight-line sequences of
+/// instructions can be executed against.
+///
+/// Memory is sparse. Bytes that have not been written read as a
+/// pseudo-random value derived from the seed and the address so that two
+/// states created with the same seed start with the same memory contents
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub registers: [u16; 16],
+    memory: HashMap<u16, u8>,
+    seed: u64,
+    /// Addresses of bytes that were read before they were written
+    reads: RefCell<BTreeSet<u16>>,
+}
+
+/// Where an operand reads its value from or writes its result to once all
+/// addressing has been resolved
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Location {
+    Register(u8),
+    Memory(u16),
+    Value(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Mov,
+    Add,
+    Addc,
+    Subc,
+    Sub,
+    Cmp,
+    Dadd,
+    Bit,
+    Bic,
+    Bis,
+    Xor,
+    And,
+}
+
+/// Returns the mask that covers all bits for the width and the mask for the
+/// most significant (sign) bit
+fn masks(width: OperandWidth) -> (u16, u16) {
+    match width {
+        OperandWidth::Byte => (0xff, 0x80),
+        OperandWidth::Word => (0xffff, 0x8000),
+    }
+}
+
+pub(crate) fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+impl State {
+    pub fn new(registers: [u16; 16], seed: u64) -> State {
+        State {
+            registers,
+            memory: HashMap::new(),
+            seed,
+            reads: RefCell::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match self.memory.get(&address) {
+            Some(value) => *value,
+            None => {
+                self.reads.borrow_mut().insert(address);
+                splitmix64(self.seed ^ ((address as u64) << 32)) as u8
+            }
+        }
+    }
+
+    /// Returns the addresses of the bytes that were read before they were
+    /// written in ascending order. These are the bytes of the initial memory
+    /// that the executed instructions depend on
+    pub fn initial_reads(&self) -> Vec<u16> {
+        self.reads.borrow().iter().copied().collect()
+    }
+
+    /// Returns the addresses of all bytes that have been written in
+    /// ascending order
+    pub fn written(&self) -> Vec<u16> {
+        let mut written: Vec<u16> = self.memory.keys().copied().collect();
+        written.sort_unstable();
+        written
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        self.memory.insert(address, value);
+    }
+
+    /// Reads a word from memory. Word accesses ignore the low bit of the
+    /// address the same way the hardware does
+    pub fn read_word(&self, address: u16) -> u16 {
+        let address = address & !1;
+        u16::from_le_bytes([self.read_byte(address), self.read_byte(address + 1)])
+    }
+
+    /// Writes a word to memory. Word accesses ignore the low bit of the
+    /// address the same way the hardware does
+    pub fn write_word(&mut self, address: u16, value: u16) {
+        let address = address & !1;
+        let bytes = value.to_le_bytes();
+        self.write_byte(address, bytes[0]);
+        self.write_byte(address + 1, bytes[1]);
+    }
+
+    /// Executes a single instruction and returns whether it could be
+    /// executed. Only instructions that don't depend on their location are
+    /// supported so any instruction that reads or writes the program counter
+    /// (jumps, calls, branches and PC relative operands) is rejected as are
+    /// MSP430X extended instructions. The state is unspecified after an
+    /// instruction is rejected
+    pub fn execute(&mut self, inst: &Instruction) -> bool {
+        self.step(inst).is_some()
+    }
+
+    /// Executes a sequence of instructions and returns whether all of them
+    /// could be executed
+    pub fn run(&mut self, instructions: &[Instruction]) -> bool {
+        instructions.iter().all(|inst| self.execute(inst))
+    }
+
+    fn step(&mut self, inst: &Instruction) -> Option<()> {
+        match inst {
+            Instruction::Rrc(inst) => self.rotate(inst, true),
+            Instruction::Swpb(inst) => {
+                let location = self.location(inst.source(), OperandWidth::Word)?;
+                let value = self.read(location, OperandWidth::Word)?;
+                self.write(location, OperandWidth::Word, value.swap_bytes())
+            }
+            Instruction::Rra(inst) => self.rotate(inst, false),
+            Instruction::Sxt(inst) => {
+                let location = self.location(inst.source(), OperandWidth::Word)?;
+                let value = self.read(location, OperandWidth::Word)? as u8 as i8 as u16;
+                self.write(location, OperandWidth::Word, value)?;
+                self.logic_flags(value, OperandWidth::Word, false);
+                Some(())
+            }
+            Instruction::Push(inst) => {
+                let width = inst.operand_width().unwrap_or(OperandWidth::Word);
+                let location = self.location(inst.source(), width)?;
+                let value = self.read(location, width)?;
+                self.registers[1] = self.registers[1].wrapping_sub(2);
+                self.write(Location::Memory(self.registers[1]), width, value)
+            }
+            Instruction::Call(_) | Instruction::Reti(_) => None,
+            Instruction::Jnz(_)
+            | Instruction::Jz(_)
+            | Instruction::Jlo(_)
+            | Instruction::Jc(_)
+            | Instruction::Jn(_)
+            | Instruction::Jge(_)
+            | Instruction::Jl(_)
+            | Instruction::Jmp(_) => None,
+            Instruction::Mov(inst) => self.two_operand(Op::Mov, inst),
+            Instruction::Add(inst) => self.two_operand(Op::Add, inst),
+            Instruction::Addc(inst) => self.two_operand(Op::Addc, inst),
+            Instruction::Subc(inst) => self.two_operand(Op::Subc, inst),
+            Instruction::Sub(inst) => self.two_operand(Op::Sub, inst),
+            Instruction::Cmp(inst) => self.two_operand(Op::Cmp, inst),
+            Instruction::Dadd(inst) => self.two_operand(Op::Dadd, inst),
+            Instruction::Bit(inst) => self.two_operand(Op::Bit, inst),
+            Instruction::Bic(inst) => self.two_operand(Op::Bic, inst),
+            Instruction::Bis(inst) => self.two_operand(Op::Bis, inst),
+            Instruction::Xor(inst) => self.two_operand(Op::Xor, inst),
+            Instruction::And(inst) => self.two_operand(Op::And, inst),
+            Instruction::Adc(inst) => self.two_operand(Op::Addc, inst.original()),
+            Instruction::Br(inst) => self.two_operand(Op::Mov, inst.original()),
+            Instruction::Clr(inst) => self.two_operand(Op::Mov, inst.original()),
+            Instruction::Clrc(inst) => self.two_operand(Op::Bic, inst.original()),
+            Instruction::Clrn(inst) => self.two_operand(Op::Bic, inst.original()),
+            Instruction::Clrz(inst) => self.two_operand(Op::Bic, inst.original()),
+            Instruction::Dadc(inst) => self.two_operand(Op::Dadd, inst.original()),
+            Instruction::Dec(inst) => self.two_operand(Op::Sub, inst.original()),
+            Instruction::Decd(inst) => self.two_operand(Op::Sub, inst.original()),
+            Instruction::Dint(inst) => self.two_operand(Op::Bic, inst.original()),
+            Instruction::Eint(inst) => self.two_operand(Op::Bis, inst.original()),
+            Instruction::Inc(inst) => self.two_operand(Op::Add, inst.original()),
+            Instruction::Incd(inst) => self.two_operand(Op::Add, inst.original()),
+            Instruction::Inv(inst) => self.two_operand(Op::Xor, inst.original()),
+            Instruction::Nop(inst) => self.two_operand(Op::Mov, inst.original()),
+            Instruction::Pop(inst) => self.two_operand(Op::Mov, inst.original()),
+            Instruction::Ret(inst) => self.two_operand(Op::Mov, inst.original()),
+            Instruction::Rla(inst) => self.two_operand(Op::Add, inst.original()),
+            Instruction::Rlc(inst) => self.two_operand(Op::Addc, inst.original()),
+            Instruction::Sbc(inst) => self.two_operand(Op::Subc, inst.original()),
+            Instruction::Setc(inst) => self.two_operand(Op::Bis, inst.original()),
+            Instruction::Setn(inst) => self.two_operand(Op::Bis, inst.original()),
+            Instruction::Setz(inst) => self.two_operand(Op::Bis, inst.original()),
+            Instruction::Tst(inst) => self.two_operand(Op::Cmp, inst.original()),
+            Instruction::Extended(_)
+            | Instruction::Mova(_)
+            | Instruction::Cmpa(_)
+            | Instruction::Adda(_)
+            | Instruction::Suba(_) => None,
+        }
+    }
+
+    /// Resolves the addressing mode of an operand, applying any
+    /// autoincrement
+    fn location(&mut self, operand: &Operand, width: OperandWidth) -> Option<Location> {
+        match *operand {
+            Operand::RegisterDirect(r) => Some(Location::Register(r)),
+            Operand::Indexed {
+                register: r,
+                offset: index,
+            } => {
+                let base = self.register(r)?;
+                Some(Location::Memory(base.wrapping_add(index as u16)))
+            }
+            Operand::RegisterIndirect(r) => Some(Location::Memory(self.register(r)?)),
+            Operand::RegisterIndirectAutoIncrement(r) => {
+                let address = self.register(r)?;
+                // the stack pointer is always kept word aligned
+                let increment = match width {
+                    OperandWidth::Byte if r != 1 => 1,
+                    _ => 2,
+                };
+                self.registers[r as usize] = address.wrapping_add(increment);
+                Some(Location::Memory(address))
+            }
+            Operand::Symbolic(_) => None,
+            Operand::Immediate(value) => Some(Location::Value(value)),
+            Operand::Absolute(address) => Some(Location::Memory(address)),
+            Operand::Constant(value) => Some(Location::Value(value as i16 as u16)),
+        }
+    }
+
+    /// Reads a register that is used for addressing
+    fn register(&self, r: u8) -> Option<u16> {
+        match r {
+            0 => None,
+            3 => Some(0),
+            _ => Some(self.registers[r as usize]),
+        }
+    }
+
+    fn read(&self, location: Location, width: OperandWidth) -> Option<u16> {
+        let (mask, _) = masks(width);
+        match (location, width) {
+            (Location::Register(r), _) => Some(self.register(r)? & mask),
+            (Location::Memory(address), OperandWidth::Byte) => Some(self.read_byte(address) as u16),
+            (Location::Memory(address), OperandWidth::Word) => Some(self.read_word(address)),
+            (Location::Value(value), _) => Some(value & mask),
+        }
+    }
+
+    fn write(&mut self, location: Location, width: OperandWidth, value: u16) -> Option<()> {
+        let (mask, _) = masks(width);
+        match (location, width) {
+            (Location::Register(0), _) => return None,
+            // writes to the constant generator are discarded
+            (Location::Register(3), _) => {}
+            // byte writes to registers clear the high byte
+            (Location::Register(r), _) => self.registers[r as usize] = value & mask,
+            (Location::Memory(address), OperandWidth::Byte) => {
+                self.write_byte(address, value as u8)
+            }
+            (Location::Memory(address), OperandWidth::Word) => self.write_word(address, value),
+            (Location::Value(_), _) => return None,
+        }
+
+        Some(())
+    }
+
+    fn set_flags(&mut self, c: bool, z: bool, n: bool, v: bool) {
+        let mut sr = self.registers[2] & !(C | Z | N | V);
+        for (set, flag) in [(c, C), (z, Z), (n, N), (v, V)] {
+            if set {
+                sr |= flag;
+            }
+        }
+
+        self.registers[2] = sr;
+    }
+
+    fn carry(&self) -> u16 {
+        self.registers[2] & C
+    }
+
+    /// Sets the flags the way the logical instructions (and, bit, xor and
+    /// sxt) do
+    fn logic_flags(&mut self, result: u16, width: OperandWidth, v: bool) {
+        let (_, msb) = masks(width);
+        self.set_flags(result != 0, result == 0, result & msb != 0, v);
+    }
+
+    fn add(&mut self, s: u16, d: u16, carry: u16, width: OperandWidth) -> u16 {
+        let (mask, msb) = masks(width);
+        let sum = (s & mask) as u32 + (d & mask) as u32 + carry as u32;
+        let result = sum as u16 & mask;
+        let v = (s ^ result) & (d ^ result) & msb != 0;
+        self.set_flags(sum > mask as u32, result == 0, result & msb != 0, v);
+        result
+    }
+
+    fn dadd(&mut self, s: u16, d: u16, width: OperandWidth) -> u16 {
+        let (_, msb) = masks(width);
+        let digits = match width {
+            OperandWidth::Byte => 2,
+            OperandWidth::Word => 4,
+        };
+
+        let mut carry = self.carry();
+        let mut result = 0;
+        for i in 0..digits {
+            let mut digit = ((s >> (i * 4)) & 0xf) + ((d >> (i * 4)) & 0xf) + carry;
+            carry = 0;
+            if digit > 9 {
+                digit = (digit - 10) & 0xf;
+                carry = 1;
+            }
+            result |= digit << (i * 4);
+        }
+
+        // the overflow flag is undefined for dadd so it is left unchanged
+        let v = self.registers[2] & V != 0;
+        self.set_flags(carry != 0, result == 0, result & msb != 0, v);
+        result
+    }
+
+    fn rotate<T: SingleOperand>(&mut self, inst: &T, through_carry: bool) -> Option<()> {
+        let width = inst.operand_width().unwrap_or(OperandWidth::Word);
+        let (_, msb) = masks(width);
+        let location = self.location(inst.source(), width)?;
+        let value = self.read(location, width)?;
+        let high = if through_carry {
+            if self.carry() != 0 {
+                msb
+            } else {
+                0
+            }
+        } else {
+            value & msb
+        };
+
+        let result = high | (value >> 1);
+        self.write(location, width, result)?;
+        self.set_flags(value & 1 != 0, result == 0, result & msb != 0, false);
+        Some(())
+    }
+
+    fn two_operand<T: TwoOperand>(&mut self, op: Op, inst: &T) -> Option<()> {
+        let width = *inst.operand_width();
+        let (mask, msb) = masks(width);
+        let source = self.location(inst.source(), width)?;
+        let s = self.read(source, width)?;
+        let destination = self.location(inst.destination(), width)?;
+
+        // mov is the only instruction that doesn't read its destination
+        let d = match op {
+            Op::Mov => 0,
+            _ => self.read(destination, width)?,
+        };
+
+        let result = match op {
+            Op::Mov => s,
+            Op::Add => self.add(s, d, 0, width),
+            Op::Addc => self.add(s, d, self.carry(), width),
+            Op::Subc => self.add(!s & mask, d, self.carry(), width),
+            Op::Sub | Op::Cmp => self.add(!s & mask, d, 1, width),
+            Op::Dadd => self.dadd(s, d, width),
+            Op::Bit | Op::And => {
+                let result = s & d;
+                self.logic_flags(result, width, false);
+                result
+            }
+            Op::Bic => d & !s,
+            Op::Bis => d | s,
+            Op::Xor => {
+                let result = s ^ d;
+                self.logic_flags(result, width, s & d & msb != 0);
+                result
+            }
+        };
+
+        match op {
+            Op::Cmp | Op::Bit => Some(()),
+            _ => self.write(destination, width, result),
+        }
+    }
+
+    /// Returns whether the registers and all memory that was written in
+    /// either state are the same
+    fn same(&self, other: &State) -> bool {
+        self.registers == other.registers
+            && self
+                .memory
+                .keys()
+                .chain(other.memory.keys())
+                .all(|address| self.read_byte(*address) == other.read_byte(*address))
+    }
+}
+
+/// Returns the input states that sequences are compared over. The first few
+/// states use edge case register values and the rest are pseudo-random
+fn inputs() -> impl Iterator<Item = State> {
+    (0..INPUTS).map(|i| {
+        let mut registers = [0; 16];
+        for (r, register) in registers.iter_mut().enumerate() {
+            *register = match i {
+                0 => 0,
+                1 => 0xffff,
+                2 => 0x8000,
+                3 => 0x7fff,
+                _ => splitmix64(i << 8 | r as u64) as u16,
+            };
+        }
+
+        State::new(registers, i)
+    })
+}
+
+/// Returns whether two straight-line sequences of instructions have the same
+/// effect on registers (including flags) and memory.
+///
+/// The sequences are executed from a fixed set of edge case and
+/// pseudo-random input states and compared afterwards. A result of false
+/// means that a counterexample was found or that one of the sequences
+/// contains an instruction that can't be executed (see `State::execute`).
+/// A result of true means no counterexample was found which is strong
+/// evidence, but not a proof, that the sequences are equivalent
+pub fn equivalent(a: &[Instruction], b: &[Instruction]) -> bool {
+    inputs().all(|input| {
+        let mut state_a = input.clone();
+        let mut state_b = input;
+        state_a.run(a) && state_b.run(b) && state_a.same(&state_b)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::listing;
+
+    fn disassemble(data: &[u8]) -> Vec<Instruction> {
+        listing(data, 0).into_iter().map(|(_, inst)| inst).collect()
+    }
+
+    fn state() -> State {
+        State::new([0; 16], 0)
+    }
+
+    #[test]
+    fn add_flags() {
+        // mov #0xffff, r15; add #1, r15
+        let mut state = state();
+        assert!(state.run(&disassemble(&[0x3f, 0x43, 0x1f, 0x53])));
+        assert_eq!(state.registers[15], 0);
+        assert_eq!(state.registers[2], C | Z);
+    }
+
+    #[test]
+    fn sub_overflow() {
+        // mov #0x8000, r15; sub #1, r15
+        let mut state = state();
+        assert!(state.run(&disassemble(&[0x3f, 0x40, 0x00, 0x80, 0x1f, 0x83])));
+        assert_eq!(state.registers[15], 0x7fff);
+        assert_eq!(state.registers[2], C | V);
+    }
+
+    #[test]
+    fn byte_register_write() {
+        // mov #0x1234, r15; mov.b r15, r14
+        let mut state = state();
+        assert!(state.run(&disassemble(&[0x3f, 0x40, 0x34, 0x12, 0x4e, 0x4f])));
+        assert_eq!(state.registers[14], 0x34);
+    }
+
+    #[test]
+    fn push_pop() {
+        // mov #0x0400, sp; push #0x1234; pop r15
+        let mut state = state();
+        assert!(state.run(&disassemble(&[
+            0x31, 0x40, 0x00, 0x04, 0x30, 0x12, 0x34, 0x12, 0x3f, 0x41
+        ])));
+        assert_eq!(state.registers[15], 0x1234);
+        assert_eq!(state.registers[1], 0x0400);
+        assert_eq!(state.read_word(0x03fe), 0x1234);
+    }
+
+    #[test]
+    fn dadd() {
+        // mov #0x0199, r15; clrc; dadd #0x0001, r15
+        let mut state = state();
+        assert!(state.run(&disassemble(&[
+            0x3f, 0x40, 0x99, 0x01, 0x12, 0xc3, 0x1f, 0xa3
+        ])));
+        assert_eq!(state.registers[15], 0x0200);
+    }
+
+    #[test]
+    fn control_flow_rejected() {
+        // ret
+        let mut state = state();
+        assert!(!state.run(&disassemble(&[0x30, 0x41])));
+    }
+
+    #[test]
+    fn equivalent_encodings() {
+        // mov #0, r15 and clr r15
+        let a = disassemble(&[0x3f, 0x40, 0x00, 0x00]);
+        let b = disassemble(&[0x0f, 0x43]);
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn equivalent_push_pop() {
+        // push r14; pop r15 and mov r14, r15
+        let a = disassemble(&[0x0e, 0x12, 0x3f, 0x41]);
+        let b = disassemble(&[0x0f, 0x4e]);
+        // the push leaves r14 on the stack below sp so memory differs
+        assert!(!equivalent(&a, &b));
+    }
+
+    #[test]
+    fn equivalent_nop() {
+        // rla r15 and rla r15; nop
+        let a = disassemble(&[0x0f, 0x5f]);
+        let b = disassemble(&[0x0f, 0x5f, 0x03, 0x43]);
+        assert!(equivalent(&a, &b));
+    }
+
+    #[test]
+    fn not_equivalent() {
+        // inc r15 and incd r15
+        let a = disassemble(&[0x1f, 0x53]);
+        let b = disassemble(&[0x2f, 0x53]);
+        assert!(!equivalent(&a, &b));
+    }
+
+    #[test]
+    fn not_equivalent_flags() {
+        // mov #0, r15 and xor r15, r15 (sets flags)
+        let a = disassemble(&[0x0f, 0x43]);
+        let b = disassemble(&[0x0f, 0xef]);
+        assert!(!equivalent(&a, &b));
+    }
+}
@@ -13,14 +13,23 @@ pub trait SingleOperand {
     fn size(&self) -> usize;
     /// Returns the operand width if one is specified
     fn operand_width(&self) -> &Option<OperandWidth>;
+    /// Returns the bits of the instruction word that the CPU ignores but
+    /// were set when the instruction was decoded. They are xored with the
+    /// canonical instruction word so the instruction encodes to its
+    /// original bytes
+    fn reserved(&self) -> u16 {
+        0
+    }
 }
 
 macro_rules! single_operand {
     ($t:ident, $n:expr) => {
         #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
         pub struct $t {
             source: Operand,
             operand_width: Option<OperandWidth>,
+            reserved: u16,
         }
 
         impl $t {
@@ -28,8 +37,13 @@ macro_rules! single_operand {
                 $t {
                     source,
                     operand_width,
+                    reserved: 0,
                 }
             }
+
+            pub(crate) fn with_reserved(self, reserved: u16) -> $t {
+                $t { reserved, ..self }
+            }
         }
 
         impl SingleOperand for $t {
@@ -51,6 +65,10 @@ macro_rules! single_operand {
             fn operand_width(&self) -> &Option<OperandWidth> {
                 &self.operand_width
             }
+
+            fn reserved(&self) -> u16 {
+                self.reserved
+            }
         }
 
         impl fmt::Display for $t {
@@ -69,16 +87,30 @@ single_operand!(Push, "push");
 single_operand!(Call, "call");
 
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
-pub struct Reti {}
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Reti {
+    reserved: u16,
+}
 
 impl Reti {
     pub fn new() -> Reti {
-        Reti {}
+        Reti { reserved: 0 }
+    }
+
+    pub(crate) fn with_reserved(self, reserved: u16) -> Reti {
+        Reti { reserved }
     }
 
     pub fn size(&self) -> usize {
         2
     }
+
+    /// Returns the bits of the instruction word that the CPU ignores but
+    /// were set when the instruction was decoded. See
+    /// SingleOperand::reserved
+    pub fn reserved(&self) -> u16 {
+        self.reserved
+    }
 }
 
 impl fmt::Display for Reti {
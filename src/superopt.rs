@@ -0,0 +1,162 @@
+use crate::instruction::{emulated, Instruction};
+use crate::operand::{Operand, OperandWidth};
+use crate::semantics;
+use crate::single_operand::*;
+use crate::two_operand::*;
+
+/// The source operands that are available without reading a register
+const CONSTANTS: [i8; 6] = [0, 1, 2, 4, 8, -1];
+
+/// Searches for the smallest sequence of at most two instructions that is
+/// equivalent (see `semantics::equivalent`) to target and smaller than it.
+/// Returns None if no smaller sequence exists.
+///
+/// Candidates are built from every two operand instruction and the rotate,
+/// swap and sign extend single operand instructions using only the
+/// registers the target reads or writes directly and constant generator
+/// sources. This keeps the search space small enough to be exhaustive but
+/// means sequences that need a different register or a memory operand are
+/// never found
+pub fn search(target: &[Instruction]) -> Option<Vec<Instruction>> {
+    let size: usize = target.iter().map(|inst| inst.size()).sum();
+    let candidates = candidates(&registers(target));
+
+    let mut best: Option<Vec<Instruction>> = None;
+    let mut best_size = size;
+
+    for first in &candidates {
+        if first.size() < best_size && semantics::equivalent(target, &[*first]) {
+            best_size = first.size();
+            best = Some(vec![*first]);
+        }
+    }
+
+    for first in &candidates {
+        for second in &candidates {
+            if first.size() + second.size() < best_size
+                && semantics::equivalent(target, &[*first, *second])
+            {
+                best_size = first.size() + second.size();
+                best = Some(vec![*first, *second]);
+            }
+        }
+    }
+
+    best
+}
+
+/// Returns the registers that are directly used by operands in the target
+fn registers(target: &[Instruction]) -> Vec<u8> {
+    let mut registers = vec![];
+    for inst in target {
+        for operand in [inst.source(), inst.destination()].into_iter().flatten() {
+            if let Operand::RegisterDirect(r) = operand {
+                if !registers.contains(r) {
+                    registers.push(*r);
+                }
+            }
+        }
+    }
+
+    registers.sort();
+    registers
+}
+
+macro_rules! two_operand_candidates {
+    ($candidates:expr, $source:expr, $width:expr, $destination:expr, $($t:ident),*) => {
+        $(
+            $candidates.push(emulated($t::new($source, $width, $destination), Instruction::$t));
+        )*
+    };
+}
+
+fn candidates(registers: &[u8]) -> Vec<Instruction> {
+    let mut candidates = vec![];
+    let sources: Vec<Operand> = registers
+        .iter()
+        .map(|r| Operand::RegisterDirect(*r))
+        .chain(CONSTANTS.iter().map(|c| Operand::Constant(*c)))
+        .collect();
+
+    for r in registers {
+        let destination = Operand::RegisterDirect(*r);
+        for width in [OperandWidth::Word, OperandWidth::Byte] {
+            candidates.push(Instruction::Rrc(Rrc::new(destination, Some(width))));
+            candidates.push(Instruction::Rra(Rra::new(destination, Some(width))));
+
+            for source in &sources {
+                two_operand_candidates!(
+                    candidates,
+                    *source,
+                    width,
+                    destination,
+                    Mov,
+                    Add,
+                    Addc,
+                    Subc,
+                    Sub,
+                    Cmp,
+                    Dadd,
+                    Bic,
+                    Bis,
+                    Xor
+                );
+                candidates.push(Instruction::Bit(Bit::new(*source, width, destination)));
+                candidates.push(Instruction::And(And::new(*source, width, destination)));
+            }
+        }
+
+        candidates.push(Instruction::Swpb(Swpb::new(destination, None)));
+        candidates.push(Instruction::Sxt(Sxt::new(destination, None)));
+    }
+
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_generator() {
+        let target = [Instruction::Mov(Mov::new(
+            Operand::Immediate(2),
+            OperandWidth::Word,
+            Operand::RegisterDirect(15),
+        ))];
+        let found = search(&target).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].to_string(), "mov #0x2, r15");
+        assert_eq!(found[0].size(), 2);
+    }
+
+    #[test]
+    fn two_instructions() {
+        // mov #0xff, r15; swpb r15
+        let target = [
+            Instruction::Mov(Mov::new(
+                Operand::Immediate(0x00ff),
+                OperandWidth::Word,
+                Operand::RegisterDirect(15),
+            )),
+            Instruction::Swpb(Swpb::new(Operand::RegisterDirect(15), None)),
+        ];
+        let found = search(&target).unwrap();
+        assert_eq!(
+            found
+                .iter()
+                .map(|inst| inst.to_string())
+                .collect::<Vec<_>>(),
+            vec!["mov.b #-0x1, r15", "swpb r15"]
+        );
+    }
+
+    #[test]
+    fn already_smallest() {
+        let target = [Instruction::Swpb(Swpb::new(
+            Operand::RegisterDirect(15),
+            None,
+        ))];
+        assert_eq!(search(&target), None);
+    }
+}
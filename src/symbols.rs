@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+/// A named value imported from a device header or linker command file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u32,
+}
+
+/// Removes // and /* */ comments from source text
+fn strip_comments(source: &str) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("/*") {
+            rest = after.find("*/").map_or("", |end| &after[end + 2..]);
+            output.push(' ');
+        } else if let Some(after) = rest.strip_prefix("//") {
+            rest = after.find('\n').map_or("", |end| &after[end..]);
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            output.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    output
+}
+
+/// Parses a C integer literal with optional parentheses and suffixes, eg.
+/// (0x0021u)
+fn parse_literal(value: &str) -> Option<u32> {
+    let value = value
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .trim()
+        .trim_end_matches(['u', 'U', 'l', 'L']);
+
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn push(symbols: &mut Vec<Symbol>, name: &str, value: u32) {
+    symbols.retain(|s| s.name != name);
+    symbols.push(Symbol {
+        name: name.to_string(),
+        value,
+    });
+}
+
+/// Parses a device header into symbols. Defines with literal values
+/// (#define P1OUT_ (0x0021u)) are imported as is and register declarations
+/// using the mspgcc and TI macros (sfrb, sfrw, DEFC and DEFW) resolve to the
+/// value of the define they reference. Anything else is ignored
+pub fn parse_header(header: &str) -> Vec<Symbol> {
+    let mut defines = HashMap::new();
+    let mut symbols = vec![];
+
+    for line in strip_comments(header).lines() {
+        let line = line.trim();
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.trim().splitn(2, char::is_whitespace);
+            let (Some(name), Some(value)) = (parts.next(), parts.next()) else {
+                continue;
+            };
+
+            if let (true, Some(value)) = (is_identifier(name), parse_literal(value)) {
+                defines.insert(name.to_string(), value);
+                push(&mut symbols, name, value);
+            }
+        } else if let Some((_, arguments)) = ["sfrb", "sfrw", "DEFC", "DEFW"]
+            .iter()
+            .find_map(|m| Some((m, line.strip_prefix(m)?.trim().strip_prefix('(')?)))
+        {
+            let arguments = arguments.split(')').next().unwrap_or_default();
+            let Some((name, address)) = arguments.split_once(',') else {
+                continue;
+            };
+
+            let (name, address) = (name.trim(), address.trim());
+            let value = parse_literal(address).or_else(|| defines.get(address).copied());
+            if let (true, Some(value)) = (is_identifier(name), value) {
+                push(&mut symbols, name, value);
+            }
+        }
+    }
+
+    symbols
+}
+
+/// Parses symbol assignments from a linker command file (NAME = 0x0021;)
+/// including GNU ld PROVIDE(NAME = 0x0021); statements. Anything else is
+/// ignored
+pub fn parse_linker(linker: &str) -> Vec<Symbol> {
+    let mut symbols = vec![];
+
+    for statement in strip_comments(linker).split(';') {
+        let statement = statement.trim();
+        let statement = statement
+            .strip_prefix("PROVIDE")
+            .map(|s| s.trim().trim_start_matches('(').trim_end_matches(')'))
+            .unwrap_or(statement);
+
+        let Some((name, value)) = statement.split_once('=') else {
+            continue;
+        };
+
+        let name = name.trim();
+        if let (true, Some(value)) = (is_identifier(name), parse_literal(value)) {
+            push(&mut symbols, name, value);
+        }
+    }
+
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, value: u32) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn header() {
+        let header = "
+/* Port 1 */
+#define P1OUT_              (0x0021u)  /* Port 1 Output */
+DEFC(   P1OUT             , P1OUT_)
+#define WDTCTL_             0x0120
+sfrw(WDTCTL, WDTCTL_);
+sfrb(P1DIR, 0x0022);
+#define BIT0                (0x0001)
+#define WDTPW               (0x5A00u)
+#define WDTHOLD_WDTPW       (WDTPW + 0x0080) // not a literal
+";
+        assert_eq!(
+            parse_header(header),
+            vec![
+                symbol("P1OUT_", 0x21),
+                symbol("P1OUT", 0x21),
+                symbol("WDTCTL_", 0x120),
+                symbol("WDTCTL", 0x120),
+                symbol("P1DIR", 0x22),
+                symbol("BIT0", 1),
+                symbol("WDTPW", 0x5a00),
+            ]
+        );
+    }
+
+    #[test]
+    fn linker() {
+        let linker = "
+/* peripherals */
+P1OUT              = 0x0021;
+PROVIDE(WDTCTL = 0x0120);
+MEMORY { RAM : ORIGIN = 0x0200, LENGTH = 0x0200 }
+__stack = ORIGIN(RAM) + LENGTH(RAM);
+";
+        assert_eq!(
+            parse_linker(linker),
+            vec![symbol("P1OUT", 0x21), symbol("WDTCTL", 0x120)]
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::fmt;
+use std::ops::Range;
+
+use crate::instruction::Instruction;
+use crate::jxx;
+use crate::operand::Operand;
+
+/// Reasons a jump, call or branch target can't be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum TargetError {
+    /// Present when the target is at an odd address. Contains the target
+    Unaligned(u32),
+    /// Present when the target is outside of executable memory. Contains
+    /// the target
+    NotExecutable(u32),
+}
+
+impl TargetError {
+    /// Returns a stable numeric code for the error. Target errors use the
+    /// 700-799 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Unaligned(_) => 700,
+            Self::NotExecutable(_) => 701,
+        }
+    }
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unaligned(target) => write!(f, "target {:#x} is not word aligned", target),
+            Self::NotExecutable(target) => {
+                write!(f, "target {:#x} is not in executable memory", target)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+/// Returns the target of a jxx instruction at address with a word offset.
+/// The program counter wraps around the 16 bit address space, or the 20 bit
+/// one for an address above it, so a jump back from near 0 lands at the top
+pub fn jump_target(address: u32, offset: i16) -> u32 {
+    let displacement = jxx::displacement_from_offset(offset) as u32;
+    let target = address.wrapping_add(2).wrapping_add(displacement);
+    match u16::try_from(address) {
+        Ok(_) => target & 0xffff,
+        Err(_) => target & 0xfffff,
+    }
+}
+
+/// Returns the target of a jump, call or branch at address when it is known
+/// from the instruction alone (jxx, `call #imm` and `br #imm`). Indirect
+/// calls and branches return None
+pub fn target(address: u32, inst: &Instruction) -> Option<u32> {
+    if let Some(offset) = inst.jump_offset() {
+        return Some(jump_target(address, offset));
+    }
+
+    match (inst, inst.source()) {
+        (Instruction::Call(_) | Instruction::Br(_), Some(Operand::Immediate(target))) => {
+            Some(*target as u32)
+        }
+        _ => None,
+    }
+}
+
+/// Checks that a target is word aligned and within one of the executable
+/// memory ranges of the device
+pub fn validate(target: u32, executable: &[Range<u32>]) -> Result<(), TargetError> {
+    if !target.is_multiple_of(2) {
+        return Err(TargetError::Unaligned(target));
+    }
+
+    if !executable.iter().any(|range| range.contains(&target)) {
+        return Err(TargetError::NotExecutable(target));
+    }
+
+    Ok(())
+}
+
+/// Validates the target of every jump, call and branch in a listing with a
+/// known target. Each entry in the listing is the address an instruction
+/// was decoded from and the instruction itself. The errors are returned
+/// with the address of the instruction
+pub fn validate_listing(
+    listing: &[(u32, Instruction)],
+    executable: &[Range<u32>],
+) -> Vec<(u32, TargetError)> {
+    listing
+        .iter()
+        .filter_map(|(address, inst)| {
+            let target = target(*address, inst)?;
+            validate(target, executable).err().map(|e| (*address, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    #[test]
+    fn targets() {
+        // jnz #-0x2; call #0x4410; br #0x4400; call r15
+        let jnz = decode(&[0xfe, 0x23]).unwrap();
+        assert_eq!(target(0x4400, &jnz), Some(0x43fe));
+        let call = decode(&[0xb0, 0x12, 0x10, 0x44]).unwrap();
+        assert_eq!(target(0x4400, &call), Some(0x4410));
+        let br = decode(&[0x30, 0x40, 0x00, 0x44]).unwrap();
+        assert_eq!(target(0x4400, &br), Some(0x4400));
+        let indirect = decode(&[0x8f, 0x12]).unwrap();
+        assert_eq!(target(0x4400, &indirect), None);
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!(jump_target(0x0002, -3), 0xfffe);
+        assert_eq!(jump_target(0xfffc, 2), 0x0002);
+        assert_eq!(jump_target(0x10000, -2), 0xfffe);
+        assert_eq!(jump_target(0xffffe, 1), 0x00002);
+    }
+
+    #[test]
+    fn validation() {
+        let flash = [0xc000..0xff80, 0x10000..0x14400];
+        assert_eq!(validate(0xc000, &flash), Ok(()));
+        assert_eq!(
+            validate(0xc001, &flash),
+            Err(TargetError::Unaligned(0xc001))
+        );
+        assert_eq!(
+            validate(0x0200, &flash),
+            Err(TargetError::NotExecutable(0x0200))
+        );
+    }
+
+    #[test]
+    fn listing() {
+        // call #0x0201; jmp #0x0
+        let listing = [
+            (0xc000, decode(&[0xb0, 0x12, 0x01, 0x02]).unwrap()),
+            (0xc004, decode(&[0x00, 0x3c]).unwrap()),
+        ];
+        assert_eq!(
+            validate_listing(&listing, &[0xc000..0xff80, 0x10000..0x14400]),
+            vec![(0xc000, TargetError::Unaligned(0x201))]
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+use crate::target;
+
+/// The low power mode bits in the status register (CPUOFF, OSCOFF, SCG0
+/// and SCG1). Every LPMx sets some of them
+const LOW_POWER: u16 = 0x00f0;
+
+/// Describes why an instruction ends execution along a path. Terminators
+/// are intentional dead ends (eg. panic handlers or low power idle loops)
+/// so they should not be treated as analysis failures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Terminator {
+    /// A jump to itself (`jmp $`)
+    SelfLoop,
+    /// A low power mode is entered (eg. `bis #CPUOFF, sr`) followed,
+    /// possibly after nops, by a jump back to itself or before it
+    LowPowerLoop,
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SelfLoop => write!(f, "self loop"),
+            Self::LowPowerLoop => write!(f, "low power loop"),
+        }
+    }
+}
+
+/// Classifies the instruction at index in a listing as a terminator. Each
+/// entry in the listing is the address an instruction was decoded from and
+/// the instruction itself. For a low power loop the terminator is reported
+/// on the instruction that turns off the CPU
+pub fn terminator(listing: &[(u32, Instruction)], index: usize) -> Option<Terminator> {
+    let (address, inst) = listing.get(index)?;
+
+    if let Instruction::Jmp(_) = inst {
+        if target::target(*address, inst) == Some(*address) {
+            return Some(Terminator::SelfLoop);
+        }
+    }
+
+    if let Instruction::Bis(_) = inst {
+        if inst.destination() != Some(&Operand::RegisterDirect(2)) {
+            return None;
+        }
+
+        match inst.source() {
+            Some(Operand::Immediate(bits)) if bits & LOW_POWER != 0 => {}
+            _ => return None,
+        }
+
+        // a nop usually follows the bis so the CPU has stopped before the
+        // jump is fetched
+        let jump = listing[index + 1..]
+            .iter()
+            .find(|(_, next)| !matches!(next, Instruction::Nop(_)));
+        if let Some((jump_address, jump @ Instruction::Jmp(_))) = jump {
+            match target::target(*jump_address, jump) {
+                Some(target) if target <= *jump_address => return Some(Terminator::LowPowerLoop),
+                _ => {}
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::listing;
+
+    #[test]
+    fn self_loop() {
+        // jmp $
+        let listing = listing(&[0xff, 0x3f], 0x4400);
+        assert_eq!(terminator(&listing, 0), Some(Terminator::SelfLoop));
+    }
+
+    #[test]
+    fn jump() {
+        // jmp $+2
+        let listing = listing(&[0x00, 0x3c], 0x4400);
+        assert_eq!(terminator(&listing, 0), None);
+    }
+
+    #[test]
+    fn low_power_loop() {
+        // bis #0x10, sr; jmp $-4
+        let listing = listing(&[0x32, 0xd0, 0x10, 0x00, 0xfd, 0x3f], 0x4400);
+        assert_eq!(terminator(&listing, 0), Some(Terminator::LowPowerLoop));
+        assert_eq!(terminator(&listing, 1), None);
+    }
+
+    #[test]
+    fn low_power_nop() {
+        // bis #0xd8, sr (LPM3 with GIE); nop; jmp $
+        let idle = listing(&[0x32, 0xd0, 0xd8, 0x00, 0x03, 0x43, 0xff, 0x3f], 0x4400);
+        assert_eq!(terminator(&idle, 0), Some(Terminator::LowPowerLoop));
+        assert_eq!(terminator(&idle, 2), Some(Terminator::SelfLoop));
+
+        // bis #0x80, sr (SCG1); nop; nop; jmp $-8
+        let data = [0x32, 0xd0, 0x80, 0x00, 0x03, 0x43, 0x03, 0x43, 0xfb, 0x3f];
+        let nops = listing(&data, 0x4400);
+        assert_eq!(terminator(&nops, 0), Some(Terminator::LowPowerLoop));
+    }
+
+    #[test]
+    fn low_power_fallthrough() {
+        // bis #0x10, sr; ret
+        let listing = listing(&[0x32, 0xd0, 0x10, 0x00, 0x30, 0x41], 0x4400);
+        assert_eq!(terminator(&listing, 0), None);
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(terminator(&[], 0), None);
+    }
+}
@@ -0,0 +1,137 @@
+//! Helpers for asserting on disassembly in tests
+//!
+//! ```
+//! use msp430_asm::assert_disasm;
+//!
+//! assert_disasm!([0x0b, 0x12, 0x31, 0x40, 0x00, 0x44], ["push r11", "mov #0x4400, sp"]);
+//! ```
+
+use std::fmt::Write;
+
+use crate::format::{write_instruction, FormatOptions};
+#[cfg(test)]
+use crate::instruction::Instruction;
+use crate::listing::sweep;
+
+/// Disassembles data linearly into one line per instruction using the
+/// format options. Data that fails to decode is rendered as a comment with
+/// the error and skipped in the same way as sweep
+pub fn disasm(data: &[u8], options: &FormatOptions) -> Vec<String> {
+    sweep(data, 0)
+        .into_iter()
+        .map(|line| {
+            let mut output = String::new();
+            let _ = match &line.instruction {
+                Ok(inst) => write_instruction(&mut output, inst, options),
+                Err(e) => write!(output, "; {}", e),
+            };
+            output
+        })
+        .collect()
+}
+
+/// Returns a line by line diff of the expected and actual disassembly or
+/// None when they match. Matching lines are prefixed with a space, expected
+/// lines with - and actual lines with +
+pub fn diff<S: AsRef<str>>(expected: &[S], actual: &[String]) -> Option<String> {
+    if expected.len() == actual.len() && expected.iter().zip(actual).all(|(e, a)| e.as_ref() == a) {
+        return None;
+    }
+
+    let mut output = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i).map(AsRef::as_ref), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {
+                let _ = writeln!(output, "  {}", e);
+            }
+            (e, a) => {
+                if let Some(e) = e {
+                    let _ = writeln!(output, "- {}", e);
+                }
+                if let Some(a) = a {
+                    let _ = writeln!(output, "+ {}", a);
+                }
+            }
+        }
+    }
+
+    Some(output)
+}
+
+/// Panics with a diff when data doesn't disassemble to the expected lines.
+/// Used by assert_disasm
+#[track_caller]
+pub fn check<S: AsRef<str>>(data: &[u8], expected: &[S], options: &FormatOptions) {
+    if let Some(diff) = diff(expected, &disasm(data, options)) {
+        panic!(
+            "disassembly does not match (- expected, + actual)\n{}",
+            diff
+        );
+    }
+}
+
+/// Decodes data linearly from base into the address and instruction pairs
+/// that the analyses take. Panics when data doesn't decode
+#[cfg(test)]
+pub(crate) fn listing(data: &[u8], base: u32) -> Vec<(u32, Instruction)> {
+    sweep(data, base)
+        .into_iter()
+        .map(|line| (line.address, line.instruction.unwrap()))
+        .collect()
+}
+
+/// Asserts that bytes disassemble to the expected lines, optionally with
+/// FormatOptions, and panics with a diff when they don't
+#[macro_export]
+macro_rules! assert_disasm {
+    ($data:expr, [$($line:expr),* $(,)?]) => {
+        $crate::assert_disasm!($data, [$($line),*], $crate::format::FormatOptions::default())
+    };
+    ($data:expr, [$($line:expr),* $(,)?], $options:expr) => {
+        $crate::testing::check(&$data, &[$($line),*] as &[&str], &$options)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        assert_disasm!(
+            [0x0b, 0x12, 0x31, 0x40, 0x00, 0x44, 0x30, 0x41],
+            ["push r11", "mov #0x4400, sp", "ret"]
+        );
+    }
+
+    #[test]
+    fn options() {
+        let options = FormatOptions {
+            uppercase: true,
+            numeric_registers: true,
+            ..Default::default()
+        };
+        assert_disasm!([0x0b, 0x12], ["PUSH r11"], options);
+    }
+
+    #[test]
+    fn errors() {
+        assert_disasm!(vec![0x80, 0x13, 0x30, 0x41], ["; invalid opcode 7", "ret"]);
+    }
+
+    #[test]
+    fn diff_lines() {
+        let actual = disasm(&[0x0b, 0x12, 0x30, 0x41], &FormatOptions::default());
+        assert_eq!(diff(&["push r11", "ret"], &actual), None);
+        assert_eq!(
+            diff(&["push r10", "ret", "nop"], &actual),
+            Some("- push r10\n+ push r11\n  ret\n- nop\n".to_string())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "- push r10\n+ push r11")]
+    fn mismatch() {
+        assert_disasm!([0x0b, 0x12], ["push r10"]);
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use crate::listing::{count_hits, sweep, Line};
+
+/// Errors that can occur while parsing a PC trace
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum TraceError {
+    /// Present when a PC value can't be parsed as a hex address. Contains the
+    /// line number (starting at 1) and the value
+    InvalidAddress((usize, String)),
+    /// Present when a record doesn't have the PC column. Contains the line
+    /// number (starting at 1)
+    MissingColumn(usize),
+}
+
+impl TraceError {
+    /// Returns a stable numeric code for the error. Trace errors use the
+    /// 100-199 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InvalidAddress(_) => 100,
+            Self::MissingColumn(_) => 101,
+        }
+    }
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidAddress((line, value)) => {
+                write!(f, "line {}: invalid address {}", line, value)
+            }
+            Self::MissingColumn(line) => {
+                write!(f, "line {}: missing pc column", line)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+fn parse_address(value: &str) -> Option<u32> {
+    let value = value.trim().trim_matches('"');
+    let value = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u32::from_str_radix(value, 16).ok()
+}
+
+/// Parses a PC trace in CSV form, as exported by TI tooling, into the list
+/// of sampled PCs. If the first record is a header the column named "pc"
+/// (case insensitive) is used, otherwise the PC is taken from the first
+/// column. Values are hexadecimal with an optional 0x prefix. Empty lines
+/// and lines starting with # are ignored
+pub fn parse(trace: &str) -> Result<Vec<u32>, TraceError> {
+    let mut column = 0;
+    let mut pcs = vec![];
+    let mut first = true;
+
+    for (i, line) in trace.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').collect();
+        if first {
+            first = false;
+            if parse_address(fields[0]).is_none() {
+                if let Some(pc) = fields
+                    .iter()
+                    .position(|f| f.trim().trim_matches('"').eq_ignore_ascii_case("pc"))
+                {
+                    column = pc;
+                }
+                continue;
+            }
+        }
+
+        let value = fields.get(column).ok_or(TraceError::MissingColumn(i + 1))?;
+        let pc = parse_address(value)
+            .ok_or_else(|| TraceError::InvalidAddress((i + 1, value.trim().to_string())))?;
+        pcs.push(pc);
+    }
+
+    Ok(pcs)
+}
+
+/// Decodes an image loaded at base and counts how many of the sampled PCs
+/// fall on each instruction, producing an execution heat map over the
+/// listing
+pub fn heat_map(image: &[u8], base: u32, pcs: &[u32]) -> Vec<Line> {
+    let mut lines = sweep(image, base);
+    count_hits(&mut lines, pcs.iter().copied());
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_header() {
+        let trace = "time,PC,current\n0,0x4400,1.2\n1,4404,1.1\n";
+        assert_eq!(parse(trace), Ok(vec![0x4400, 0x4404]));
+    }
+
+    #[test]
+    fn parse_no_header() {
+        let trace = "# comment\n\n0x4400\n0x4404\n";
+        assert_eq!(parse(trace), Ok(vec![0x4400, 0x4404]));
+    }
+
+    #[test]
+    fn parse_invalid_address() {
+        let trace = "pc\n0x4400\nzzz\n";
+        assert_eq!(
+            parse(trace),
+            Err(TraceError::InvalidAddress((3, "zzz".to_string())))
+        );
+    }
+
+    #[test]
+    fn parse_missing_column() {
+        let trace = "time,pc\n0,0x4400\n1\n";
+        assert_eq!(parse(trace), Err(TraceError::MissingColumn(3)));
+    }
+
+    #[test]
+    fn heat() {
+        // mov #0x4400, sp; ret
+        let image = [0x31, 0x40, 0x00, 0x44, 0x30, 0x41];
+        let lines = heat_map(&image, 0x4400, &[0x4400, 0x4404, 0x4404]);
+        assert_eq!(
+            lines
+                .iter()
+                .map(|l| (l.address, l.hits))
+                .collect::<Vec<_>>(),
+            vec![(0x4400, 1), (0x4404, 2)]
+        );
+    }
+}
@@ -3,7 +3,8 @@ use std::fmt;
 use crate::emulate;
 use crate::emulate::Emulate;
 use crate::instruction::Instruction;
-use crate::operand::{Operand, OperandWidth};
+use crate::operand::{Equality, Operand, OperandWidth};
+use crate::{DecodeError, Result};
 
 /// All two operand instructions implement this trait to provide a common
 /// interface and polymorphism
@@ -20,9 +21,17 @@ pub trait TwoOperand {
     fn operand_width(&self) -> &OperandWidth;
 }
 
+/// Returns whether the source of an instruction is operand when compared
+/// with equality
+fn source_is(inst: &dyn TwoOperand, operand: Operand, equality: Equality) -> bool {
+    inst.source()
+        .eq_by(&operand, equality, Some(*inst.operand_width()))
+}
+
 macro_rules! two_operand {
     ($t:ident, $n:expr) => {
         #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(feature = "defmt", derive(defmt::Format))]
         pub struct $t {
             source: Operand,
             operand_width: OperandWidth,
@@ -37,6 +46,20 @@ macro_rules! two_operand {
                     destination,
                 }
             }
+
+            /// Creates the instruction if the destination is a valid
+            /// destination operand
+            pub fn try_new(
+                source: Operand,
+                operand_width: OperandWidth,
+                destination: Operand,
+            ) -> Result<$t> {
+                if !destination.valid_as_destination() {
+                    return Err(DecodeError::IllegalDestination(destination));
+                }
+
+                Ok($t::new(source, operand_width, destination))
+            }
         }
 
         impl TwoOperand for $t {
@@ -81,23 +104,30 @@ macro_rules! two_operand {
 two_operand!(Mov, "mov");
 
 impl Emulate for Mov {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(0) && self.destination == Operand::RegisterDirect(3) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        // the emulated instructions without operands or a width of their
+        // own only stand for the word form so the byte forms stay as mov.b
+        let word = self.operand_width == OperandWidth::Word;
+
+        if word
+            && source_is(self, Operand::Constant(0), equality)
+            && self.destination == Operand::RegisterDirect(3)
+        {
             return Some(Instruction::Nop(emulate::Nop::new(None, None, *self)));
         }
 
-        if self.source == Operand::Constant(0) || self.source == Operand::Immediate(0) {
+        if source_is(self, Operand::Constant(0), equality) || self.source == Operand::Immediate(0) {
             if let Operand::RegisterDirect(_) = self.destination {
                 return Some(Instruction::Clr(emulate::Clr::new(
                     Some(self.destination),
-                    None,
+                    Some(self.operand_width),
                     *self,
                 )));
             }
         }
 
-        if self.source == Operand::RegisterIndirectAutoIncrement(1) {
-            if self.destination == Operand::RegisterDirect(0) {
+        if source_is(self, Operand::RegisterIndirectAutoIncrement(1), equality) {
+            if word && self.destination == Operand::RegisterDirect(0) {
                 return Some(Instruction::Ret(emulate::Ret::new(None, None, *self)));
             } else {
                 return Some(Instruction::Pop(emulate::Pop::new(
@@ -108,7 +138,7 @@ impl Emulate for Mov {
             }
         }
 
-        if self.destination == Operand::RegisterDirect(0) {
+        if word && self.destination == Operand::RegisterDirect(0) {
             return Some(Instruction::Br(emulate::Br::new(
                 Some(self.source),
                 None,
@@ -123,20 +153,20 @@ impl Emulate for Mov {
 two_operand!(Add, "add");
 
 impl Emulate for Add {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(1) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(1), equality) {
             Some(Instruction::Inc(emulate::Inc::new(
                 Some(self.destination),
-                None,
+                Some(self.operand_width),
                 *self,
             )))
-        } else if self.source == Operand::Constant(2) {
+        } else if source_is(self, Operand::Constant(2), equality) {
             Some(Instruction::Incd(emulate::Incd::new(
                 Some(self.destination),
-                None,
+                Some(self.operand_width),
                 *self,
             )))
-        } else if self.source == self.destination {
+        } else if source_is(self, self.destination, equality) {
             Some(Instruction::Rla(emulate::Rla::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -151,14 +181,14 @@ impl Emulate for Add {
 two_operand!(Addc, "addc");
 
 impl Emulate for Addc {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(0) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(0), equality) {
             Some(Instruction::Adc(emulate::Adc::new(
                 Some(self.destination),
                 Some(self.operand_width),
                 *self,
             )))
-        } else if self.source == self.destination {
+        } else if source_is(self, self.destination, equality) {
             Some(Instruction::Rlc(emulate::Rlc::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -173,8 +203,8 @@ impl Emulate for Addc {
 two_operand!(Subc, "subc");
 
 impl Emulate for Subc {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(0) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(0), equality) {
             Some(Instruction::Sbc(emulate::Sbc::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -189,14 +219,14 @@ impl Emulate for Subc {
 two_operand!(Sub, "sub");
 
 impl Emulate for Sub {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(1) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(1), equality) {
             Some(Instruction::Dec(emulate::Dec::new(
                 Some(self.destination),
                 Some(self.operand_width),
                 *self,
             )))
-        } else if self.source == Operand::Constant(2) {
+        } else if source_is(self, Operand::Constant(2), equality) {
             Some(Instruction::Decd(emulate::Decd::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -211,8 +241,8 @@ impl Emulate for Sub {
 two_operand!(Cmp, "cmp");
 
 impl Emulate for Cmp {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(0) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(0), equality) {
             Some(Instruction::Tst(emulate::Tst::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -227,8 +257,8 @@ impl Emulate for Cmp {
 two_operand!(Dadd, "dadd");
 
 impl Emulate for Dadd {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(0) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(0), equality) {
             Some(Instruction::Dadc(emulate::Dadc::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -244,22 +274,22 @@ two_operand!(Bit, "bit");
 two_operand!(Bic, "bic");
 
 impl Emulate for Bic {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.destination == Operand::RegisterDirect(2) {
-            match self.source {
-                Operand::Constant(1) => {
-                    return Some(Instruction::Clrc(emulate::Clrc::new(None, None, *self)))
-                }
-                Operand::Constant(2) => {
-                    return Some(Instruction::Clrn(emulate::Clrn::new(None, None, *self)))
-                }
-                Operand::Constant(4) => {
-                    return Some(Instruction::Clrz(emulate::Clrz::new(None, None, *self)))
-                }
-                Operand::Constant(8) => {
-                    return Some(Instruction::Dint(emulate::Dint::new(None, None, *self)))
-                }
-                _ => {}
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        // the status register aliases only stand for the word form
+        if self.operand_width == OperandWidth::Word
+            && self.destination == Operand::RegisterDirect(2)
+        {
+            if source_is(self, Operand::Constant(1), equality) {
+                return Some(Instruction::Clrc(emulate::Clrc::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(2), equality) {
+                return Some(Instruction::Clrz(emulate::Clrz::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(4), equality) {
+                return Some(Instruction::Clrn(emulate::Clrn::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(8), equality) {
+                return Some(Instruction::Dint(emulate::Dint::new(None, None, *self)));
             }
         }
 
@@ -270,22 +300,21 @@ impl Emulate for Bic {
 two_operand!(Bis, "bis");
 
 impl Emulate for Bis {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.destination == Operand::RegisterDirect(2) {
-            match self.source {
-                Operand::Constant(1) => {
-                    return Some(Instruction::Setc(emulate::Setc::new(None, None, *self)))
-                }
-                Operand::Constant(2) => {
-                    return Some(Instruction::Setz(emulate::Setz::new(None, None, *self)))
-                }
-                Operand::Constant(4) => {
-                    return Some(Instruction::Setn(emulate::Setn::new(None, None, *self)))
-                }
-                Operand::Constant(8) => {
-                    return Some(Instruction::Eint(emulate::Eint::new(None, None, *self)))
-                }
-                _ => {}
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if self.operand_width == OperandWidth::Word
+            && self.destination == Operand::RegisterDirect(2)
+        {
+            if source_is(self, Operand::Constant(1), equality) {
+                return Some(Instruction::Setc(emulate::Setc::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(2), equality) {
+                return Some(Instruction::Setz(emulate::Setz::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(4), equality) {
+                return Some(Instruction::Setn(emulate::Setn::new(None, None, *self)));
+            }
+            if source_is(self, Operand::Constant(8), equality) {
+                return Some(Instruction::Eint(emulate::Eint::new(None, None, *self)));
             }
         }
 
@@ -296,8 +325,8 @@ impl Emulate for Bis {
 two_operand!(Xor, "xor");
 
 impl Emulate for Xor {
-    fn emulate(&self) -> Option<Instruction> {
-        if self.source == Operand::Constant(-1) {
+    fn emulate_by(&self, equality: Equality) -> Option<Instruction> {
+        if source_is(self, Operand::Constant(-1), equality) {
             Some(Instruction::Inv(emulate::Inv::new(
                 Some(self.destination),
                 Some(self.operand_width),
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::decode;
+use crate::semantics::{splitmix64, State, C, N, V, Z};
+
+/// Registers and the memory bytes that are relevant to a test vector
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: [u16; 16],
+    /// Memory bytes ordered by address
+    pub memory: BTreeMap<u16, u8>,
+}
+
+/// The effect of executing a single instruction. The pre-state memory holds
+/// every byte the instruction reads and the post-state memory holds those
+/// bytes along with every byte it writes
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vector {
+    pub bytes: Vec<u8>,
+    pub pre: Snapshot,
+    pub post: Snapshot,
+}
+
+/// Errors that can occur while parsing a corpus of test vectors
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum VectorError {
+    /// Present when a line is not a valid vector. Contains the line number
+    /// (starting at 1) and the line
+    InvalidVector((usize, String)),
+}
+
+impl VectorError {
+    /// Returns a stable numeric code for the error. Vector errors use the
+    /// 300-399 range
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::InvalidVector(_) => 300,
+        }
+    }
+}
+
+impl fmt::Display for VectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVector((line, value)) => {
+                write!(f, "line {}: invalid vector {}", line, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VectorError {}
+
+/// Writes a snapshot as comma separated registers followed by comma
+/// separated address=value memory bytes (or - when there are none)
+fn write_snapshot(f: &mut fmt::Formatter<'_>, snapshot: &Snapshot) -> fmt::Result {
+    for (i, register) in snapshot.registers.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        write!(f, "{}{:04x}", separator, register)?;
+    }
+
+    if snapshot.memory.is_empty() {
+        return write!(f, " -");
+    }
+
+    for (i, (address, value)) in snapshot.memory.iter().enumerate() {
+        let separator = if i == 0 { " " } else { "," };
+        write!(f, "{}{:04x}={:02x}", separator, address, value)?;
+    }
+
+    Ok(())
+}
+
+/// Vectors are written on a single line as the instruction bytes in hex,
+/// the pre-state and the post-state separated by spaces
+impl fmt::Display for Vector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in &self.bytes {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        write!(f, " ")?;
+        write_snapshot(f, &self.pre)?;
+        write!(f, " ")?;
+        write_snapshot(f, &self.post)
+    }
+}
+
+fn parse_snapshot(registers: &str, memory: &str) -> Option<Snapshot> {
+    let mut snapshot = Snapshot {
+        registers: [0; 16],
+        memory: BTreeMap::new(),
+    };
+
+    let values: Vec<&str> = registers.split(',').collect();
+    if values.len() != snapshot.registers.len() {
+        return None;
+    }
+
+    for (register, value) in snapshot.registers.iter_mut().zip(values) {
+        *register = u16::from_str_radix(value, 16).ok()?;
+    }
+
+    if memory != "-" {
+        for byte in memory.split(',') {
+            let (address, value) = byte.split_once('=')?;
+            snapshot.memory.insert(
+                u16::from_str_radix(address, 16).ok()?,
+                u8::from_str_radix(value, 16).ok()?,
+            );
+        }
+    }
+
+    Some(snapshot)
+}
+
+fn parse_vector(line: &str) -> Option<Vector> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let [bytes, pre_registers, pre_memory, post_registers, post_memory] = fields[..] else {
+        return None;
+    };
+
+    if bytes.is_empty() || bytes.len() % 2 != 0 {
+        return None;
+    }
+
+    let bytes = (0..bytes.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(bytes.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(Vector {
+        bytes,
+        pre: parse_snapshot(pre_registers, pre_memory)?,
+        post: parse_snapshot(post_registers, post_memory)?,
+    })
+}
+
+/// Parses a corpus with one vector per line. Empty lines and lines
+/// starting with # are ignored
+pub fn parse(corpus: &str) -> Result<Vec<Vector>, VectorError> {
+    let mut vectors = vec![];
+    for (i, line) in corpus.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let vector = parse_vector(line)
+            .ok_or_else(|| VectorError::InvalidVector((i + 1, line.to_string())))?;
+        vectors.push(vector);
+    }
+
+    Ok(vectors)
+}
+
+/// Generates count vectors from random instruction words and states using
+/// the semantics model. Only instructions the model can execute (see
+/// `State::execute`) are included. The same seed always produces the same
+/// vectors
+pub fn generate(seed: u64, count: usize) -> Vec<Vector> {
+    let mut vectors = vec![];
+    let mut counter = seed;
+    let mut random = || {
+        counter = counter.wrapping_add(1);
+        splitmix64(counter)
+    };
+
+    while vectors.len() < count {
+        let words = [random() as u16, random() as u16, random() as u16];
+        let data: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let inst = match decode(&data) {
+            Ok(inst) => inst,
+            Err(_) => continue,
+        };
+
+        let mut registers = [0; 16];
+        for register in registers.iter_mut() {
+            *register = random() as u16;
+        }
+
+        // the constant generator always reads as zero and only the flags of
+        // the status register are modeled
+        registers[3] = 0;
+        registers[2] &= C | Z | N | V;
+
+        let memory_seed = random();
+        let mut state = State::new(registers, memory_seed);
+        if !state.execute(&inst) {
+            continue;
+        }
+
+        let mut pre = Snapshot {
+            registers,
+            memory: BTreeMap::new(),
+        };
+        let mut post = Snapshot {
+            registers: state.registers,
+            memory: BTreeMap::new(),
+        };
+
+        let reads = state.initial_reads();
+        for address in reads.iter().chain(state.written().iter()) {
+            post.memory.insert(*address, state.read_byte(*address));
+        }
+
+        let initial = State::new(registers, memory_seed);
+        for address in reads {
+            pre.memory.insert(address, initial.read_byte(address));
+        }
+
+        vectors.push(Vector {
+            bytes: data[..inst.size()].to_vec(),
+            pre,
+            post,
+        });
+    }
+
+    vectors
+}
+
+/// Runs an external implementation against vectors and returns the indices
+/// of the vectors it fails. The implementation is given the instruction
+/// bytes and the pre-state and returns the post-state. Registers must match
+/// exactly and every byte in the expected post-state memory must match
+pub fn verify<F: FnMut(&[u8], &Snapshot) -> Snapshot>(
+    vectors: &[Vector],
+    mut execute: F,
+) -> Vec<usize> {
+    vectors
+        .iter()
+        .enumerate()
+        .filter(|(_, vector)| {
+            let post = execute(&vector.bytes, &vector.pre);
+            post.registers != vector.post.registers
+                || vector
+                    .post
+                    .memory
+                    .iter()
+                    .any(|(address, value)| post.memory.get(address) != Some(value))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Executes a vector with the semantics model starting from the
+    /// pre-state alone
+    fn model(bytes: &[u8], pre: &Snapshot) -> Snapshot {
+        let mut state = State::new(pre.registers, 0);
+        for (address, value) in &pre.memory {
+            state.write_byte(*address, *value);
+        }
+
+        assert!(state.execute(&decode(bytes).unwrap()));
+        let memory = state
+            .written()
+            .into_iter()
+            .map(|address| (address, state.read_byte(address)))
+            .collect();
+
+        Snapshot {
+            registers: state.registers,
+            memory,
+        }
+    }
+
+    #[test]
+    fn corpus() {
+        // the published corpus is the output of the generator so changes to
+        // the semantics model show up as changes to the corpus
+        let corpus = include_str!("../data/semantics.txt");
+        let vectors = parse(corpus).unwrap();
+        assert_eq!(vectors, generate(0, vectors.len()));
+        assert_eq!(verify(&vectors, model), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn round_trip() {
+        for vector in generate(1, 32) {
+            assert_eq!(parse(&vector.to_string()), Ok(vec![vector]));
+        }
+    }
+
+    #[test]
+    fn detects_failures() {
+        let vectors = generate(2, 8);
+        let failures = verify(&vectors, |bytes, pre| {
+            let mut post = model(bytes, pre);
+            post.registers[2] ^= C;
+            post
+        });
+        assert_eq!(failures, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(
+            parse("# comment\n0e5f 0000 -"),
+            Err(VectorError::InvalidVector((2, "0e5f 0000 -".to_string())))
+        );
+    }
+}
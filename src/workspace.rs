@@ -0,0 +1,418 @@
+use crate::container;
+use crate::instruction::Instruction;
+use crate::operand::Operand;
+use crate::symbols::Symbol;
+use crate::target;
+use crate::writer::Segment;
+
+/// The address of the reset vector, the last entry of the interrupt vector
+/// table
+pub const RESET_VECTOR: u32 = 0xfffe;
+
+/// Returns the little endian word at address if segments contain both of
+/// its bytes
+fn word(segments: &[Segment], address: u32) -> Option<u16> {
+    let byte = |address: u32| {
+        segments.iter().find_map(|segment| {
+            let offset = address.checked_sub(segment.address)? as usize;
+            segment.data.get(offset).copied()
+        })
+    };
+
+    Some(u16::from_le_bytes([byte(address)?, byte(address + 1)?]))
+}
+
+/// A named image (eg. a bootloader, an application or a library blob at a
+/// fixed address) with the symbols it defines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub name: String,
+    pub segments: Vec<Segment>,
+    pub symbols: Vec<Symbol>,
+    /// The addresses execution starts at (eg. the targets of the vector
+    /// table of the image)
+    pub entries: Vec<u32>,
+}
+
+impl Image {
+    pub fn new(name: &str, segments: Vec<Segment>, symbols: Vec<Symbol>) -> Image {
+        Image {
+            name: name.to_string(),
+            segments,
+            symbols,
+            entries: vec![],
+        }
+    }
+
+    /// Returns the image with entries as its entry points
+    pub fn with_entries(mut self, entries: Vec<u32>) -> Image {
+        self.entries = entries;
+        self
+    }
+
+    /// Returns whether one of the segments of the image is loaded at
+    /// address
+    pub fn contains(&self, address: u32) -> bool {
+        self.segments.iter().any(|segment| {
+            address >= segment.address && address - segment.address < segment.data.len() as u32
+        })
+    }
+
+    /// Returns the symbol of the image whose value is address
+    pub fn symbol(&self, address: u32) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.value == address)
+    }
+
+    /// Returns the word at address when the image contains it
+    pub fn word(&self, address: u32) -> Option<u16> {
+        word(&self.segments, address)
+    }
+}
+
+/// A call, branch or jump in one image whose target is in another
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrossReference<'a> {
+    /// The name of the image the instruction is in
+    pub image: &'a str,
+    /// The address of the instruction
+    pub address: u32,
+    pub target: u32,
+    /// The name of the image the target is in
+    pub target_image: &'a str,
+    /// The symbol of the target image at the target if it defines one
+    pub symbol: Option<&'a Symbol>,
+}
+
+/// Several related images that are loaded on the same device so references
+/// from one image can be resolved against the others
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Workspace {
+    images: Vec<Image>,
+}
+
+impl Workspace {
+    pub fn new() -> Workspace {
+        Workspace::default()
+    }
+
+    /// Adds an image to the workspace. When images overlap the one that was
+    /// added first owns the overlapping addresses
+    pub fn add(&mut self, image: Image) {
+        self.images.push(image);
+    }
+
+    pub fn images(&self) -> &[Image] {
+        &self.images
+    }
+
+    /// Returns the image that address is loaded from
+    pub fn image_at(&self, address: u32) -> Option<&Image> {
+        self.images.iter().find(|image| image.contains(address))
+    }
+
+    /// Returns the symbol at address defined by the image the address is
+    /// loaded from
+    pub fn symbol_at(&self, address: u32) -> Option<&Symbol> {
+        self.image_at(address)?.symbol(address)
+    }
+
+    /// Returns the target of a jump, call or branch at address. Calls and
+    /// branches through an absolute address (`br &0xeffe`) are resolved
+    /// when one of the images contains the address, which is how
+    /// bootloaders hand over to the vector table of an application
+    fn target(&self, address: u32, inst: &Instruction) -> Option<u32> {
+        if let Some(target) = target::target(address, inst) {
+            return Some(target);
+        }
+
+        match (inst, inst.source()) {
+            (Instruction::Call(_) | Instruction::Br(_), Some(Operand::Absolute(pointer))) => {
+                let pointer = *pointer as u32;
+                self.image_at(pointer)?.word(pointer).map(u32::from)
+            }
+            _ => None,
+        }
+    }
+
+    /// Sweeps every image and returns the calls, branches and jumps with a
+    /// known target that lands in a different image. Targets outside of
+    /// every image and references within an image aren't included
+    pub fn cross_references(&self) -> Vec<CrossReference<'_>> {
+        let mut references = vec![];
+        for (index, image) in self.images.iter().enumerate() {
+            for line in container::sweep(&image.segments) {
+                let Ok(inst) = &line.instruction else {
+                    continue;
+                };
+                let Some(target) = self.target(line.address, inst) else {
+                    continue;
+                };
+                let Some(owner) = self.images.iter().position(|image| image.contains(target))
+                else {
+                    continue;
+                };
+                if owner == index {
+                    continue;
+                }
+
+                let target_image = &self.images[owner];
+                references.push(CrossReference {
+                    image: &image.name,
+                    address: line.address,
+                    target,
+                    target_image: &target_image.name,
+                    symbol: target_image.symbol(target),
+                });
+            }
+        }
+
+        references
+    }
+}
+
+/// A bootloader and an application that share one flash image. The
+/// bootloader owns the hardware vector table and hands over to the
+/// application with a stub that branches through the reset vector of a
+/// second vector table that belongs to the application
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    /// The address of the reset vector of the application. The vector table
+    /// of the application ends here and the bootloader starts right after
+    pub app_reset_vector: u32,
+    /// The addresses of the stubs in the bootloader that jump to the
+    /// application
+    pub stubs: Vec<u32>,
+    /// The targets of the hardware vector table
+    pub bootloader_entries: Vec<u32>,
+    /// The targets of the vector table of the application
+    pub app_entries: Vec<u32>,
+}
+
+/// Returns the targets of the vector table with vectors entries that ends
+/// with the vector at last. Unprogrammed (0xffff) vectors and vectors that
+/// point outside of the segments are skipped
+fn entries(segments: &[Segment], last: u32, vectors: u32) -> Vec<u32> {
+    let mut entries: Vec<u32> = (0..vectors)
+        .filter_map(|i| word(segments, last.checked_sub(i * 2)?))
+        .map(u32::from)
+        .filter(|target| *target != 0xffff && word(segments, *target).is_some())
+        .collect();
+    entries.sort_unstable();
+    entries.dedup();
+    entries
+}
+
+/// Detects the common bootloader and application layout in an image: the
+/// application has its own vector table of vectors entries (eg. 16 on
+/// F1xx) right below the bootloader and the bootloader jumps to the
+/// application with `br &vector` where vector is the reset vector of the
+/// application. Returns None when the image has no reset vector or no stub
+/// or when a table of vectors entries doesn't fit below the reset vector
+pub fn detect_split(segments: &[Segment], vectors: u32) -> Option<Split> {
+    let reset = word(segments, RESET_VECTOR)?;
+    let hardware = vectors
+        .checked_mul(2)
+        .and_then(|size| (RESET_VECTOR + 2).checked_sub(size))?;
+
+    let mut app_reset_vector = None;
+    let mut stubs = vec![];
+    for line in container::sweep(segments) {
+        let Ok(inst @ Instruction::Br(_)) = &line.instruction else {
+            continue;
+        };
+        let Some(Operand::Absolute(vector)) = inst.source() else {
+            continue;
+        };
+
+        // the stub is in the bootloader above the table it branches through
+        let vector = *vector as u32;
+        if !vector.is_multiple_of(2) || vector >= hardware || line.address <= vector {
+            continue;
+        }
+        if app_reset_vector.is_some_and(|reset| reset != vector) {
+            continue;
+        }
+
+        match word(segments, vector) {
+            Some(target) if word(segments, target as u32).is_some() => {
+                app_reset_vector = Some(vector);
+                stubs.push(line.address);
+            }
+            _ => {}
+        }
+    }
+
+    let app_reset_vector = app_reset_vector?;
+    let mut bootloader_entries = entries(segments, RESET_VECTOR, vectors);
+    if !bootloader_entries.contains(&(reset as u32)) {
+        bootloader_entries.push(reset as u32);
+    }
+
+    Some(Split {
+        app_reset_vector,
+        stubs,
+        bootloader_entries,
+        app_entries: entries(segments, app_reset_vector, vectors),
+    })
+}
+
+impl Split {
+    /// Returns a workspace with the application and the bootloader as
+    /// separate images. Segments are split after the reset vector of the
+    /// application so the application owns everything below it
+    pub fn workspace(&self, segments: &[Segment]) -> Workspace {
+        let boundary = self.app_reset_vector + 2;
+        let mut app = vec![];
+        let mut bootloader = vec![];
+        for segment in segments {
+            let split = (boundary.saturating_sub(segment.address) as usize).min(segment.data.len());
+            let (low, high) = segment.data.split_at(split);
+            if !low.is_empty() {
+                app.push(Segment::new(segment.address, low.to_vec()));
+            }
+            if !high.is_empty() {
+                bootloader.push(Segment::new(segment.address + split as u32, high.to_vec()));
+            }
+        }
+
+        let mut workspace = Workspace::new();
+        workspace.add(
+            Image::new("bootloader", bootloader, vec![])
+                .with_entries(self.bootloader_entries.clone()),
+        );
+        workspace.add(Image::new("app", app, vec![]).with_entries(self.app_entries.clone()));
+        workspace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, value: u32) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    fn workspace() -> Workspace {
+        let mut workspace = Workspace::new();
+        // call #0xc000; call #0x1000; br #0x2000
+        workspace.add(Image::new(
+            "bootloader",
+            vec![Segment::new(
+                0x1000,
+                vec![
+                    0xb0, 0x12, 0x00, 0xc0, 0xb0, 0x12, 0x00, 0x10, 0x30, 0x40, 0x00, 0x20,
+                ],
+            )],
+            vec![],
+        ));
+        // call #0xe000; ret
+        workspace.add(Image::new(
+            "app",
+            vec![Segment::new(
+                0xc000,
+                vec![0xb0, 0x12, 0x00, 0xe0, 0x30, 0x41],
+            )],
+            vec![symbol("main", 0xc000)],
+        ));
+        // ret
+        workspace.add(Image::new(
+            "library",
+            vec![Segment::new(0xe000, vec![0x30, 0x41])],
+            vec![symbol("memcpy", 0xe000)],
+        ));
+        workspace
+    }
+
+    #[test]
+    fn resolve() {
+        let workspace = workspace();
+        assert_eq!(workspace.images().len(), 3);
+        assert_eq!(workspace.image_at(0xc005).unwrap().name, "app");
+        assert_eq!(workspace.image_at(0xc006), None);
+        assert_eq!(workspace.symbol_at(0xe000), Some(&symbol("memcpy", 0xe000)));
+        assert_eq!(workspace.symbol_at(0xe001), None);
+    }
+
+    #[test]
+    fn cross_references() {
+        let workspace = workspace();
+        let main = symbol("main", 0xc000);
+        let memcpy = symbol("memcpy", 0xe000);
+        assert_eq!(
+            workspace.cross_references(),
+            vec![
+                CrossReference {
+                    image: "bootloader",
+                    address: 0x1000,
+                    target: 0xc000,
+                    target_image: "app",
+                    symbol: Some(&main),
+                },
+                CrossReference {
+                    image: "app",
+                    address: 0xc000,
+                    target: 0xe000,
+                    target_image: "library",
+                    symbol: Some(&memcpy),
+                },
+            ]
+        );
+    }
+
+    /// Returns a vector table of 16 unprogrammed vectors except for reset
+    fn vector_table(reset: u16) -> Vec<u8> {
+        let mut table = vec![0xff; 0x20];
+        table[0x1e..].copy_from_slice(&reset.to_le_bytes());
+        table
+    }
+
+    #[test]
+    fn split() {
+        let segments = vec![
+            // ret
+            Segment::new(0xc000, vec![0x30, 0x41]),
+            Segment::new(0xefe0, vector_table(0xc000)),
+            // br &0xeffe
+            Segment::new(0xf000, vec![0x10, 0x42, 0xfe, 0xef]),
+            Segment::new(0xffe0, vector_table(0xf000)),
+        ];
+        let split = detect_split(&segments, 16).unwrap();
+        assert_eq!(
+            split,
+            Split {
+                app_reset_vector: 0xeffe,
+                stubs: vec![0xf000],
+                bootloader_entries: vec![0xf000],
+                app_entries: vec![0xc000],
+            }
+        );
+
+        let workspace = split.workspace(&segments);
+        let bootloader = workspace.image_at(0xf000).unwrap();
+        assert_eq!(bootloader.name, "bootloader");
+        assert_eq!(bootloader.entries, vec![0xf000]);
+        let app = workspace.image_at(0xeffe).unwrap();
+        assert_eq!(app.name, "app");
+        assert_eq!(app.entries, vec![0xc000]);
+        assert_eq!(
+            workspace.cross_references(),
+            vec![CrossReference {
+                image: "bootloader",
+                address: 0xf000,
+                target: 0xc000,
+                target_image: "app",
+                symbol: None,
+            }]
+        );
+
+        // without a stub there is nothing to split
+        assert_eq!(detect_split(&segments[3..], 16), None);
+        // a vector table larger than the address space
+        assert_eq!(detect_split(&segments, 0x8001), None);
+        assert_eq!(detect_split(&segments, u32::MAX), None);
+    }
+}
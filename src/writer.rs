@@ -0,0 +1,143 @@
+use std::fmt::Write;
+
+/// The number of data bytes written per record (Intel HEX) or line (TI-TXT)
+const BYTES_PER_LINE: usize = 16;
+
+const DATA_RECORD: u8 = 0x00;
+const END_OF_FILE_RECORD: u8 = 0x01;
+const EXTENDED_LINEAR_ADDRESS_RECORD: u8 = 0x04;
+
+/// A contiguous run of bytes that is loaded at an address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    pub fn new(address: u32, data: Vec<u8>) -> Segment {
+        Segment { address, data }
+    }
+}
+
+pub(crate) fn record(output: &mut String, address: u16, kind: u8, data: &[u8]) {
+    let mut checksum = data.len() as u8;
+    checksum = checksum
+        .wrapping_add((address >> 8) as u8)
+        .wrapping_add(address as u8)
+        .wrapping_add(kind);
+
+    let _ = write!(output, ":{:02X}{:04X}{:02X}", data.len(), address, kind);
+    for byte in data {
+        checksum = checksum.wrapping_add(*byte);
+        let _ = write!(output, "{:02X}", byte);
+    }
+
+    let _ = writeln!(output, "{:02X}", checksum.wrapping_neg());
+}
+
+/// Serializes segments to Intel HEX. Extended linear address records are
+/// emitted whenever data is above 64KB (eg. upper flash on MSP430X devices)
+/// and records never cross a 64KB boundary
+pub fn intel_hex(segments: &[Segment]) -> String {
+    let mut output = String::new();
+    let mut upper = 0;
+
+    for segment in segments {
+        let mut offset = 0;
+        while offset < segment.data.len() {
+            let address = segment.address + offset as u32;
+            if address >> 16 != upper {
+                upper = address >> 16;
+                record(
+                    &mut output,
+                    0,
+                    EXTENDED_LINEAR_ADDRESS_RECORD,
+                    &(upper as u16).to_be_bytes(),
+                );
+            }
+
+            let to_boundary = 0x10000 - (address & 0xffff) as usize;
+            let len = BYTES_PER_LINE
+                .min(segment.data.len() - offset)
+                .min(to_boundary);
+            record(
+                &mut output,
+                address as u16,
+                DATA_RECORD,
+                &segment.data[offset..offset + len],
+            );
+            offset += len;
+        }
+    }
+
+    record(&mut output, 0, END_OF_FILE_RECORD, &[]);
+    output
+}
+
+/// Serializes segments to the TI-TXT format used by TI's flashing tools
+pub fn ti_txt(segments: &[Segment]) -> String {
+    let mut output = String::new();
+
+    for segment in segments {
+        if segment.data.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(output, "@{:04X}", segment.address);
+        for line in segment.data.chunks(BYTES_PER_LINE) {
+            let bytes: Vec<String> = line.iter().map(|b| format!("{:02X}", b)).collect();
+            let _ = writeln!(output, "{}", bytes.join(" "));
+        }
+    }
+
+    output.push_str("q\n");
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intel_hex_records() {
+        let segments = [
+            Segment::new(0x4400, vec![0x31, 0x40, 0x00, 0x44]),
+            Segment::new(0xfffe, vec![0x00, 0x44]),
+        ];
+        assert_eq!(
+            intel_hex(&segments),
+            ":044400003140004403\n:02FFFE000044BD\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn intel_hex_long_segment() {
+        let segments = [Segment::new(0x4400, vec![0xff; 17])];
+        let hex = intel_hex(&segments);
+        let records: Vec<&str> = hex.lines().collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], ":01441000FFAC");
+    }
+
+    #[test]
+    fn intel_hex_extended_address() {
+        let segments = [Segment::new(0xfffe, vec![0x01, 0x02, 0x03, 0x04])];
+        assert_eq!(
+            intel_hex(&segments),
+            ":02FFFE000102FE\n:020000040001F9\n:020000000304F7\n:00000001FF\n"
+        );
+    }
+
+    #[test]
+    fn ti_txt_lines() {
+        let segments = [
+            Segment::new(0x4400, (0..18).collect()),
+            Segment::new(0xfffe, vec![0x00, 0x44]),
+        ];
+        assert_eq!(
+            ti_txt(&segments),
+            "@4400\n00 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F\n10 11\n@FFFE\n00 44\nq\n"
+        );
+    }
+}